@@ -0,0 +1,172 @@
+//! The `biggest-files` subcommand, the largest single files across every target found.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use humanize_bytes::humanize_bytes_decimal;
+use rayon::prelude::*;
+
+use crate::unc::shown;
+
+/// How many files `biggest-files` lists without `--top`.
+pub const DEFAULT_TOP: usize = 20;
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BigFile {
+    pub size: u64,
+    pub path: PathBuf,
+}
+
+/// The `top` biggest files seen so far, kept as a min-heap so the smallest is the one to drop.
+struct Biggest {
+    top: usize,
+    heap: BinaryHeap<Reverse<BigFile>>,
+}
+
+impl Biggest {
+    fn new(top: usize) -> Self {
+        Self {
+            top,
+            heap: BinaryHeap::with_capacity(top + 1),
+        }
+    }
+
+    fn offer(&mut self, file: BigFile) {
+        if self.top == 0 {
+            return;
+        }
+        if self.heap.len() < self.top {
+            self.heap.push(Reverse(file));
+        } else if self
+            .heap
+            .peek()
+            .is_some_and(|Reverse(smallest)| file > *smallest)
+        {
+            self.heap.pop();
+            self.heap.push(Reverse(file));
+        }
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        for Reverse(file) in other.heap {
+            self.offer(file);
+        }
+        self
+    }
+
+    /// Biggest first, by path among files of the same size.
+    fn into_sorted(self) -> Vec<BigFile> {
+        let mut files: Vec<BigFile> = self.heap.into_iter().map(|Reverse(file)| file).collect();
+        files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        files
+    }
+}
+
+/// Offers every file under `dir` to `biggest`. Symlinks aren't followed, as in sizing.
+fn collect(dir: &Path, biggest: &mut Biggest) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect(&entry.path(), biggest)?;
+        } else if file_type.is_file() {
+            biggest.offer(BigFile {
+                size: entry.metadata()?.len(),
+                path: entry.path(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The `top` biggest files across all of `targets`, biggest first.
+pub fn biggest_files(targets: &[PathBuf], top: usize) -> std::io::Result<Vec<BigFile>> {
+    let biggest = targets
+        .par_iter()
+        .map(|target| {
+            let mut biggest = Biggest::new(top);
+            collect(target, &mut biggest).map(|()| biggest)
+        })
+        .try_reduce(|| Biggest::new(top), |a, b| Ok(a.merge(b)))?;
+    Ok(biggest.into_sorted())
+}
+
+/// Writes `files` as a table of size and path.
+pub fn render_biggest_files(out: &mut dyn Write, files: &[BigFile]) -> std::io::Result<()> {
+    writeln!(out, "      SIZE  PATH")?;
+    for file in files {
+        writeln!(
+            out,
+            "{:>10}  {}",
+            humanize_bytes_decimal!(file.size),
+            shown(&file.path)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_biggest_files_across_targets() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut targets = Vec::new();
+        // (project, file under target, size)
+        let files = [
+            ("app", "debug/app", 9000),
+            ("app", "debug/deps/libfoo.rlib", 300),
+            ("app", "debug/build/x/out/fixture.bin", 7000),
+            ("lib", "release/lib.so", 8000),
+            ("lib", "release/lib.d", 10),
+            ("tool", "debug/tool.pdb", 500),
+            ("tool", "debug/deep/er/still/huge.o", 8500),
+        ];
+        for (project, file, size) in files {
+            let target = temp_dir.path().join(project).join("target");
+            let path = target.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, vec![0u8; size]).unwrap();
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+        let path =
+            |project: &str, file: &str| temp_dir.path().join(project).join("target").join(file);
+
+        let top = biggest_files(&targets, 3).unwrap();
+        assert_eq!(
+            top,
+            [
+                BigFile {
+                    size: 9000,
+                    path: path("app", "debug/app")
+                },
+                BigFile {
+                    size: 8500,
+                    path: path("tool", "debug/deep/er/still/huge.o")
+                },
+                BigFile {
+                    size: 8000,
+                    path: path("lib", "release/lib.so")
+                },
+            ]
+        );
+        assert_eq!(biggest_files(&targets, 100).unwrap().len(), files.len());
+        assert!(biggest_files(&targets, 0).unwrap().is_empty());
+
+        let mut out = Vec::new();
+        render_biggest_files(&mut out, &top[..1]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("      SIZE  PATH\n      9 kB  {}\n", top[0].path.display())
+        );
+    }
+}
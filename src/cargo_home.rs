@@ -0,0 +1,167 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use humanize_bytes::humanize_bytes_decimal;
+
+use crate::{calculate_dir_size, output::ncdu::escape_json};
+
+/// The parts of `CARGO_HOME` worth knowing the size of, as (label, path under CARGO_HOME).
+pub const CARGO_HOME_COMPONENTS: [(&str, &str); 5] = [
+    ("Registry cache", "registry/cache"),
+    ("Registry sources", "registry/src"),
+    ("Git database", "git/db"),
+    ("Git checkouts", "git/checkouts"),
+    ("Installed binaries", "bin"),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CargoHomeComponent {
+    pub label: &'static str,
+    pub path: PathBuf,
+    /// Zero if the directory doesn't exist.
+    pub size: u64,
+}
+
+/// How much space `CARGO_HOME` takes, similar to what `cargo cache` reports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CargoHomeSummary {
+    pub cargo_home: PathBuf,
+    pub components: Vec<CargoHomeComponent>,
+}
+
+impl CargoHomeSummary {
+    /// Sizes each of the [`CARGO_HOME_COMPONENTS`] under `cargo_home`.
+    pub fn measure(cargo_home: &Path) -> std::io::Result<Self> {
+        let components = CARGO_HOME_COMPONENTS
+            .iter()
+            .map(|&(label, dir)| {
+                let path = cargo_home.join(dir);
+                Ok(CargoHomeComponent {
+                    label,
+                    size: calculate_dir_size(&path)?,
+                    path,
+                })
+            })
+            .collect::<std::io::Result<_>>()?;
+        Ok(Self {
+            cargo_home: cargo_home.to_path_buf(),
+            components,
+        })
+    }
+
+    pub fn total(&self) -> u64 {
+        self.components.iter().map(|component| component.size).sum()
+    }
+
+    /// The summary as a JSON object for `--format json`, with the same totals as the section
+    /// [`render_cargo_home_summary`] writes.
+    pub fn to_json(&self, targets_total: u64) -> String {
+        let path = |path: &Path| format!("\"{}\"", escape_json(&path.to_string_lossy()));
+        let components: Vec<String> = self
+            .components
+            .iter()
+            .map(|component| {
+                format!(
+                    "{{\"label\":\"{}\",\"path\":{},\"size\":{}}}",
+                    escape_json(component.label),
+                    path(&component.path),
+                    component.size
+                )
+            })
+            .collect();
+        format!(
+            "{{\"path\":{},\"components\":[{}],\"total\":{},\"targets_total\":{},\"combined_total\":{}}}",
+            path(&self.cargo_home),
+            components.join(","),
+            self.total(),
+            targets_total,
+            self.total() + targets_total
+        )
+    }
+}
+
+/// Writes the summary as a section after the results, with `targets_total` for the combined
+/// total.
+pub fn render_cargo_home_summary(
+    out: &mut dyn Write,
+    summary: &CargoHomeSummary,
+    targets_total: u64,
+) -> std::io::Result<()> {
+    writeln!(out, "\nCargo home ({}):", summary.cargo_home.display())?;
+    for component in &summary.components {
+        writeln!(
+            out,
+            "{:>10}  {}",
+            humanize_bytes_decimal!(component.size),
+            component.label
+        )?;
+    }
+    writeln!(out, "{}", "-".repeat(80))?;
+    writeln!(
+        out,
+        "{:>10}  Cargo home total",
+        humanize_bytes_decimal!(summary.total())
+    )?;
+    writeln!(
+        out,
+        "{:>10}  Target directories total",
+        humanize_bytes_decimal!(targets_total)
+    )?;
+    writeln!(
+        out,
+        "{:>10}  Combined total",
+        humanize_bytes_decimal!(summary.total() + targets_total)
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cargo_home_summary() {
+        let temp_dir = TempDir::new().unwrap();
+        let cargo_home = temp_dir.path();
+        for (dir, size) in [
+            ("registry/cache/index.crates.io", 3000),
+            ("registry/src/index.crates.io", 2000),
+            ("git/db/repo", 500),
+            ("bin", 1500),
+        ] {
+            fs::create_dir_all(cargo_home.join(dir)).unwrap();
+            fs::write(cargo_home.join(dir).join("file"), vec![0u8; size]).unwrap();
+        }
+
+        let summary = CargoHomeSummary::measure(cargo_home).unwrap();
+        let sizes: Vec<(&str, u64)> = summary
+            .components
+            .iter()
+            .map(|component| (component.label, component.size))
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![
+                ("Registry cache", 3000),
+                ("Registry sources", 2000),
+                ("Git database", 500),
+                ("Git checkouts", 0),
+                ("Installed binaries", 1500),
+            ]
+        );
+        assert_eq!(summary.total(), 7000);
+
+        let mut out = Vec::new();
+        render_cargo_home_summary(&mut out, &summary, 3000).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with(&format!("\nCargo home ({}):\n", cargo_home.display())));
+        assert!(out.contains("      7 kB  Cargo home total\n"));
+        assert!(
+            out.ends_with("      3 kB  Target directories total\n     10 kB  Combined total\n")
+        );
+    }
+}
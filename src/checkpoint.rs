@@ -0,0 +1,183 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::paths::{Dirs, create_parent};
+
+/// A directory size saved by an earlier, interrupted scan.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckpointEntry {
+    pub size: u64,
+    pub accessed: Option<SystemTime>,
+}
+
+/// Where `--resume-scan` keeps sizes by default, `scan-checkpoint` in the cache directory.
+pub fn default_checkpoint_file(dirs: &Dirs) -> Option<PathBuf> {
+    Some(dirs.cache.as_ref()?.join("scan-checkpoint"))
+}
+
+fn parse_line(line: &str) -> Option<(PathBuf, CheckpointEntry)> {
+    let mut fields = line.splitn(3, '\t');
+    let size = fields.next()?.parse().ok()?;
+    let accessed = match fields.next()? {
+        "-" => None,
+        secs => Some(UNIX_EPOCH + Duration::from_secs(secs.parse().ok()?)),
+    };
+    let path = fields.next().filter(|path| !path.is_empty())?;
+    Some((PathBuf::from(path), CheckpointEntry { size, accessed }))
+}
+
+/// Reads the sizes saved in `file`, which not existing just means there's nothing to resume.
+///
+/// Lines that don't parse, such as one cut short when the scan was interrupted, are ignored
+/// and those directories get sized again.
+pub fn load_checkpoint(file: &Path) -> std::io::Result<HashMap<PathBuf, CheckpointEntry>> {
+    let contents = match fs::read_to_string(file) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+    // Only complete lines, the last one may have been cut off mid-write
+    let complete = contents.rfind('\n').map_or("", |end| &contents[..end]);
+    Ok(complete.lines().filter_map(parse_line).collect())
+}
+
+/// Appends each directory's size to the checkpoint file as soon as it's known.
+///
+/// Lines are `<size>\t<accessed secs or ->\t<path>`.
+pub struct Checkpoint {
+    file: PathBuf,
+    writer: Mutex<File>,
+}
+
+impl Checkpoint {
+    /// Opens `file` for appending, starting it afresh unless `resume` is set.
+    pub fn open(file: &Path, resume: bool) -> std::io::Result<Self> {
+        create_parent(file)?;
+        let writer = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .truncate(false)
+            .open(file)?;
+        if !resume {
+            writer.set_len(0)?;
+        } else if fs::read(file)?.last().is_some_and(|&last| last != b'\n') {
+            // Finish off a line cut short by the interruption, so it doesn't run into the next
+            (&writer).write_all(b"\n")?;
+        }
+        Ok(Self {
+            file: file.to_path_buf(),
+            writer: Mutex::new(writer),
+        })
+    }
+
+    /// Saves one directory's size. Paths with newlines in them can't be saved and are skipped.
+    pub fn record(&self, path: &Path, entry: CheckpointEntry) -> std::io::Result<()> {
+        let path = path.to_string_lossy();
+        if path.contains('\n') {
+            return Ok(());
+        }
+        let accessed = entry
+            .accessed
+            .and_then(|accessed| accessed.duration_since(UNIX_EPOCH).ok())
+            .map_or_else(|| "-".to_string(), |since| since.as_secs().to_string());
+        let line = format!("{}\t{accessed}\t{path}\n", entry.size);
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        // One write per line, so an interruption loses at most the line being written
+        writer.write_all(line.as_bytes())?;
+        writer.flush()
+    }
+
+    /// Removes the checkpoint once the scan has finished, as there's nothing left to resume.
+    pub fn finish(self) -> std::io::Result<()> {
+        drop(self.writer);
+        fs::remove_file(&self.file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("state").join("scan-checkpoint");
+        let accessed = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let checkpoint = Checkpoint::open(&file, false).unwrap();
+        let entries = [
+            (
+                PathBuf::from("/code/a/target"),
+                CheckpointEntry {
+                    size: 100,
+                    accessed: Some(accessed),
+                },
+            ),
+            (
+                PathBuf::from("/code/with\ttab/target"),
+                CheckpointEntry {
+                    size: 5,
+                    accessed: None,
+                },
+            ),
+        ];
+        for (path, entry) in &entries {
+            checkpoint.record(path, *entry).unwrap();
+        }
+        checkpoint
+            .record(Path::new("/code/new\nline/target"), entries[0].1)
+            .unwrap();
+        drop(checkpoint);
+        assert_eq!(
+            load_checkpoint(&file).unwrap(),
+            HashMap::from(entries.clone())
+        );
+
+        // Resuming keeps what's there, after a line cut short, starting afresh doesn't
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&file)
+            .unwrap()
+            .write_all(b"20\t-\t/code/cut")
+            .unwrap();
+        let checkpoint = Checkpoint::open(&file, true).unwrap();
+        checkpoint
+            .record(Path::new("/code/c/target"), entries[1].1)
+            .unwrap();
+        drop(checkpoint);
+        assert_eq!(load_checkpoint(&file).unwrap().len(), 4);
+        let checkpoint = Checkpoint::open(&file, false).unwrap();
+        assert!(load_checkpoint(&file).unwrap().is_empty());
+        checkpoint.finish().unwrap();
+        assert!(!file.exists());
+        assert!(load_checkpoint(&file).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_load_checkpoint_ignores_partial_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("scan-checkpoint");
+        fs::write(
+            &file,
+            "100\t-\t/code/a/target\nnot a line\n20\t-\t/code/b/tar",
+        )
+        .unwrap();
+        assert_eq!(
+            load_checkpoint(&file).unwrap(),
+            HashMap::from([(
+                PathBuf::from("/code/a/target"),
+                CheckpointEntry {
+                    size: 100,
+                    accessed: None
+                }
+            )])
+        );
+    }
+}
@@ -0,0 +1,285 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use regex::Regex;
+
+use crate::{
+    AgeSource, CASE_INSENSITIVE_DEFAULT, Cli, Command, DEFAULT_DELETE_JOBS, DeleteOrder, SortOrder,
+    cargo_cache_dirs,
+    checkpoint::default_checkpoint_file,
+    drives::fixed_drives,
+    keep::default_keep_file,
+    output::{OutputFormat, ncdu::NCDU_TREE_DEPTH},
+    paths::Dirs,
+    priority::DEFAULT_AGE_WEIGHT,
+    protect::Protected,
+    resolve_roots,
+    rustc::RustcVersion,
+    rustup_home_dir,
+    size_cache::SizeCache,
+};
+
+/// Everything that controls a scan and deletion, independent of the command line.
+///
+/// Build one with [`Config::default`] and adjust the fields, or convert from a parsed [`Cli`].
+/// The default doesn't look at the environment, so the cargo caches aren't skipped and
+/// rustup's toolchains aren't known unless they're set.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Directories to scan for target directories.
+    pub roots: Vec<PathBuf>,
+    /// Stop discovery once this many target directories have been found.
+    pub max_results: Option<usize>,
+    /// Stop discovery and sizing once this long has passed since the scan started.
+    pub timeout: Option<Duration>,
+    pub sort: SortOrder,
+    /// How much age counts for in [`SortOrder::Priority`], see
+    /// [`priority_score`](crate::priority::priority_score).
+    pub priority_age_weight: f64,
+    /// Which timestamp a target's `modified` time comes from.
+    pub age_from: AgeSource,
+    /// Target directories inside these are skipped, leave empty to include them.
+    /// [`cargo_cache_dirs`](crate::cargo_cache_dirs) gives the usual ones.
+    pub cargo_cache_dirs: Vec<PathBuf>,
+    /// Threads used for sizing, `None` uses one per CPU.
+    pub threads: Option<usize>,
+    /// Most directory handles open at once while sizing, `None` for one per thread.
+    pub max_open_fds: Option<usize>,
+    /// Work out a grand total that counts hardlinked files once.
+    pub dedupe_hardlinks: bool,
+    /// Size with the Linux fast walk where it can be used.
+    pub fast_scan: bool,
+    /// Estimate sizes by sampling, see [`approximate_size`](crate::estimate::approximate_size).
+    pub approximate: bool,
+    /// Shared by every scan made with this configuration, so each only lists what changed.
+    pub size_cache: Option<Arc<SizeCache>>,
+    /// Keep a [`SizeTree`](crate::tree::SizeTree) of each target this many levels deep.
+    pub tree_depth: Option<usize>,
+    /// Look up the crate name and version, and the rustc that built it, for each target.
+    pub details: bool,
+    /// Fingerprint each target's contents while sizing it, for `--dedup-report`.
+    pub dedup_report: bool,
+    /// Only keep targets on the same filesystem as this path.
+    pub mount: Option<PathBuf>,
+    /// Only keep targets known to be built by an older rustc than this.
+    pub built_before_rustc: Option<RustcVersion>,
+    /// Only keep targets built by a toolchain that's no longer installed.
+    pub stale_toolchains_only: bool,
+    /// Where rustup keeps its toolchains, for telling which targets were built by a removed one.
+    pub rustup_home: Option<PathBuf>,
+    /// Save sizes here as they're worked out, reusing any already there from an interrupted scan.
+    pub checkpoint: Option<PathBuf>,
+    /// Also find wasm-pack and trunk output next to each target directory.
+    pub wasm: bool,
+    /// Look at these `target/<TRIPLE>` subdirectories instead of whole target directories.
+    pub triples: Vec<String>,
+    /// Look at just the `target/doc` subdirectories instead of whole target directories.
+    pub docs_only: bool,
+    /// Only clean up when a root's filesystem has less than this many bytes available.
+    pub when_free_below: Option<u64>,
+    /// Drop targets smaller than this many bytes.
+    pub min_size: Option<u64>,
+    /// Drop targets bigger than this many bytes.
+    pub max_size: Option<u64>,
+    /// Drop targets with fewer files than this.
+    pub min_files: Option<u64>,
+    /// Drop targets with more files than this.
+    pub max_files: Option<u64>,
+    /// Drop targets with files accessed more recently than this.
+    pub unused_for: Option<Duration>,
+    /// Leave out targets with a file modified after this one was.
+    pub older_than_file: Option<PathBuf>,
+    /// How many directories to delete at once, in a pool separate from the sizing one.
+    pub delete_jobs: usize,
+    /// Delete without prompting.
+    pub force: bool,
+    /// Delete targets git tracks files in, instead of skipping them with `force` or asking again.
+    pub delete_tracked: bool,
+    /// Never delete targets bigger than this many bytes.
+    pub exclude_larger_than: Option<u64>,
+    /// Delete targets in projects with a keep marker too.
+    pub ignore_keep_markers: bool,
+    /// Drop targets in projects with a keep marker from the scan.
+    pub hide_kept: bool,
+    /// Order to delete in, `None` keeps the order targets are given in.
+    pub delete_order: Option<DeleteOrder>,
+    /// Instead of removing whole targets, keep this many of the newest subdirectories in each.
+    pub keep_newest: Option<usize>,
+    /// Move targets into this directory rather than deleting them, which the remover does.
+    pub move_to: Option<PathBuf>,
+    /// Only say what would be deleted.
+    pub dry_run: bool,
+    /// Where to append a line for each deletion, see [`DeletionLog`](crate::deletion_log::DeletionLog).
+    pub log_file: Option<PathBuf>,
+    /// Say how the free inodes changed after deleting.
+    pub report_inodes: bool,
+    /// When prompting, delete targets with fewer files than this without asking.
+    pub auto_under_files: Option<u64>,
+    /// Entries matching any of these start selected in the deletion prompt.
+    pub preselect: Vec<Regex>,
+    /// Match `target` names ignoring ASCII case.
+    pub case_insensitive: bool,
+    /// Match [`PatternSet`](crate::PatternSet) globs ignoring case.
+    pub glob_ignore_case: bool,
+    /// Look in directories whose names start with a dot while discovering.
+    pub hidden: bool,
+    /// Only keep targets whose project is in a git work tree within its root.
+    pub git_only: bool,
+    /// Ask delete, keep or skip for each directory instead of one multi-select.
+    pub review: bool,
+    /// Prompt again after each round of deleting, with a Quit item in the prompt.
+    pub loop_mode: bool,
+    /// Directories listed here are never deleted, `--review` adds to it.
+    pub keep_file: Option<PathBuf>,
+    /// Where anything kept between runs goes, none of which is set by default.
+    pub dirs: Dirs,
+    /// Directories never deleted, from the config file rather than a flag.
+    pub protected: Protected,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            roots: vec![PathBuf::from(".")],
+            max_results: None,
+            timeout: None,
+            sort: SortOrder::default(),
+            priority_age_weight: DEFAULT_AGE_WEIGHT,
+            age_from: AgeSource::default(),
+            cargo_cache_dirs: Vec::new(),
+            threads: None,
+            max_open_fds: None,
+            dedupe_hardlinks: false,
+            fast_scan: false,
+            approximate: false,
+            size_cache: None,
+            tree_depth: None,
+            details: false,
+            dedup_report: false,
+            mount: None,
+            built_before_rustc: None,
+            stale_toolchains_only: false,
+            rustup_home: None,
+            checkpoint: None,
+            wasm: false,
+            triples: Vec::new(),
+            docs_only: false,
+            when_free_below: None,
+            min_size: None,
+            max_size: None,
+            min_files: None,
+            max_files: None,
+            unused_for: None,
+            older_than_file: None,
+            delete_jobs: DEFAULT_DELETE_JOBS,
+            force: false,
+            delete_tracked: false,
+            exclude_larger_than: None,
+            ignore_keep_markers: false,
+            hide_kept: false,
+            delete_order: None,
+            keep_newest: None,
+            move_to: None,
+            dry_run: false,
+            log_file: None,
+            report_inodes: false,
+            auto_under_files: None,
+            preselect: Vec::new(),
+            case_insensitive: CASE_INSENSITIVE_DEFAULT,
+            glob_ignore_case: false,
+            hidden: false,
+            git_only: false,
+            review: false,
+            loop_mode: false,
+            keep_file: None,
+            dirs: Dirs::default(),
+            protected: Protected::default(),
+        }
+    }
+}
+
+impl From<&Cli> for Config {
+    fn from(cli: &Cli) -> Self {
+        let dirs = Dirs::resolve(cli.cache_dir.as_deref(), cli.data_dir.as_deref(), |name| {
+            std::env::var(name).ok()
+        });
+        // Subcommands that scan take their own directories, as top level arguments can't be given
+        let target_dirs = match &cli.command {
+            Some(Command::BiggestFiles { target_dirs, .. }) => target_dirs,
+            _ => &cli.target_dirs,
+        };
+        Self {
+            roots: match cli.all_drives.then(fixed_drives).flatten() {
+                Some(drives) => drives,
+                None => resolve_roots(
+                    target_dirs,
+                    std::env::var_os("CLEAN_BIG_TARGETS_DIRS").as_deref(),
+                    std::env::var_os("CLEAN_BIG_TARGETS_DIR").as_deref(),
+                ),
+            },
+            max_results: cli.max_results,
+            timeout: cli.timeout,
+            sort: cli.sort,
+            priority_age_weight: cli.priority_age_weight,
+            age_from: cli.age_from,
+            cargo_cache_dirs: if cli.include_cargo_cache {
+                Vec::new()
+            } else {
+                cargo_cache_dirs(|name| std::env::var(name).ok())
+            },
+            threads: cli.threads,
+            max_open_fds: cli.max_open_fds,
+            dedupe_hardlinks: cli.dedupe_hardlinks_across_dirs,
+            fast_scan: cli.fast_scan,
+            approximate: cli.approximate,
+            size_cache: cli.size_cache.then(|| Arc::new(SizeCache::new())),
+            details: cli.details,
+            dedup_report: cli.dedup_report,
+            mount: cli.mount.clone(),
+            built_before_rustc: cli.built_before_rustc.clone(),
+            stale_toolchains_only: cli.stale_toolchains_only,
+            rustup_home: rustup_home_dir(|name| std::env::var(name).ok()),
+            checkpoint: cli
+                .resume_scan
+                .then(|| {
+                    cli.checkpoint
+                        .clone()
+                        .or_else(|| default_checkpoint_file(&dirs))
+                })
+                .flatten(),
+            wasm: cli.wasm,
+            triples: cli.triple.clone(),
+            docs_only: cli.docs_only,
+            when_free_below: cli.when_free_below,
+            min_size: cli.min_size,
+            max_size: cli.max_size,
+            min_files: cli.min_files,
+            max_files: cli.max_files,
+            unused_for: cli.unused_for,
+            older_than_file: cli.older_than_file.clone(),
+            tree_depth: (cli.output_format() == OutputFormat::Ncdu).then_some(NCDU_TREE_DEPTH),
+            delete_jobs: cli.delete_jobs,
+            force: cli.force,
+            delete_tracked: cli.delete_tracked,
+            exclude_larger_than: cli.exclude_larger_than,
+            ignore_keep_markers: cli.ignore_keep_markers,
+            hide_kept: cli.hide_kept,
+            delete_order: cli.delete_order,
+            keep_newest: cli.keep_newest,
+            move_to: cli.move_to.clone(),
+            dry_run: cli.dry_run,
+            log_file: cli.log_file.clone(),
+            report_inodes: cli.report_inodes,
+            auto_under_files: cli.auto_under_files,
+            preselect: cli.preselect.clone(),
+            case_insensitive: cli.case_insensitive || CASE_INSENSITIVE_DEFAULT,
+            glob_ignore_case: cli.glob_ignore_case,
+            hidden: cli.hidden,
+            git_only: cli.git_only,
+            review: cli.review,
+            loop_mode: cli.loop_mode,
+            keep_file: cli.state_file.clone().or_else(|| default_keep_file(&dirs)),
+            dirs,
+            protected: Protected::default(),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! `--timeout`, a deadline the walk and the sizing workers check as they go, so a scan on a
+//! slow filesystem stops with what it has rather than running on.
+
+use std::time::{Duration, Instant};
+
+/// What the process exits with when the deadline cut the scan short, the same as `timeout(1)`.
+pub const TIMED_OUT_EXIT: u8 = 124;
+
+/// When to stop, if ever. Checked cooperatively, so whatever is being looked at when it passes
+/// is finished or given up on at the next check.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Deadline(Option<Instant>);
+
+/// The error a walk is unwound with once the deadline has passed, see [`is_expired`].
+#[derive(Debug)]
+struct Expired;
+
+impl std::fmt::Display for Expired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the --timeout deadline passed")
+    }
+}
+
+impl std::error::Error for Expired {}
+
+impl Deadline {
+    /// A deadline `timeout` from now, or none without one.
+    pub fn after(timeout: Option<Duration>) -> Self {
+        Self(timeout.map(|timeout| Instant::now() + timeout))
+    }
+
+    pub fn passed(&self) -> bool {
+        self.0.is_some_and(|at| Instant::now() >= at)
+    }
+
+    /// An error once the deadline has passed, so a walk stops like it would for any other.
+    pub fn check(&self) -> std::io::Result<()> {
+        if self.passed() {
+            return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, Expired));
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if `error` is from [`Deadline::check`], rather than a filesystem timing out.
+pub fn is_expired(error: &std::io::Error) -> bool {
+    error.get_ref().is_some_and(|inner| inner.is::<Expired>())
+}
+
+/// The notice for a scan the deadline cut short, with `not_sized` of the directories found
+/// left unsized.
+pub fn timed_out_warning(timeout: Duration, not_sized: usize) -> String {
+    format!(
+        "Scan truncated after {}s (--timeout), {} directories not yet sized, results are \
+         incomplete",
+        timeout.as_secs_f64(),
+        not_sized
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline() {
+        let never = Deadline::after(None);
+        assert!(!never.passed());
+        assert!(never.check().is_ok());
+
+        let passed = Deadline::after(Some(Duration::ZERO));
+        assert!(passed.passed());
+        let error = passed.check().unwrap_err();
+        assert!(is_expired(&error));
+        assert!(!is_expired(&std::io::Error::from(
+            std::io::ErrorKind::TimedOut
+        )));
+
+        assert!(!Deadline::after(Some(Duration::from_secs(3600))).passed());
+        assert_eq!(
+            timed_out_warning(Duration::from_secs(60), 3),
+            "Scan truncated after 60s (--timeout), 3 directories not yet sized, results are \
+             incomplete"
+        );
+    }
+}
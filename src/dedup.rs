@@ -0,0 +1,120 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use crate::TargetDirInfo;
+
+/// A quick fingerprint of a directory's contents, from the name and size of every file in it.
+///
+/// The files aren't read, so it's cheap enough to gather while sizing, but two directories
+/// only match when they have the same files at the same places with the same sizes.
+pub struct Fingerprint {
+    root: PathBuf,
+    sum: Cell<u64>,
+}
+
+impl Fingerprint {
+    /// An empty fingerprint for the directory at `root`.
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            sum: Cell::new(0),
+        }
+    }
+
+    /// Adds the file at `path` inside the root, `size` bytes long.
+    pub fn add(&self, path: &Path, size: u64) {
+        let mut hasher = DefaultHasher::new();
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .hash(&mut hasher);
+        size.hash(&mut hasher);
+        // Summed so the order the files were seen in doesn't matter
+        self.sum.set(self.sum.get().wrapping_add(hasher.finish()));
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.sum.get()
+    }
+}
+
+/// Groups the targets with the same fingerprint, for `--dedup-report`, in the order each group
+/// is first seen. Targets without a fingerprint, or without any files, aren't in any group.
+pub fn duplicate_groups(target_info: &[TargetDirInfo]) -> Vec<Vec<&TargetDirInfo>> {
+    let mut groups: Vec<Vec<&TargetDirInfo>> = Vec::new();
+    let mut by_fingerprint: HashMap<(u64, u64), usize> = HashMap::new();
+    for info in target_info {
+        let Some(fingerprint) = info.fingerprint.filter(|_| info.file_count != Some(0)) else {
+            continue;
+        };
+        let key = (fingerprint, info.size);
+        match by_fingerprint.get(&key) {
+            Some(&index) => groups[index].push(info),
+            None => {
+                by_fingerprint.insert(key, groups.len());
+                groups.push(vec![info]);
+            }
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, report::NoopReporter, scan};
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn write_layout(target: &Path, files: &[(&str, usize)]) {
+        for (name, len) in files {
+            let file = target.join(name);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, vec![0u8; *len]).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_identical_layouts_are_grouped() {
+        let temp_dir = TempDir::new().unwrap();
+        let layout = [("debug/app", 300), ("debug/deps/libfoo.rlib", 200)];
+        write_layout(&temp_dir.path().join("a").join("target"), &layout);
+        write_layout(&temp_dir.path().join("b").join("target"), &layout);
+        // Same total size, but the files are laid out differently
+        write_layout(
+            &temp_dir.path().join("c").join("target"),
+            &[("debug/app", 200), ("debug/deps/libfoo.rlib", 300)],
+        );
+        write_layout(
+            &temp_dir.path().join("d").join("target"),
+            &[("release/app", 500)],
+        );
+
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            dedup_report: true,
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        assert_eq!(result.targets.len(), 4);
+        let groups: Vec<Vec<PathBuf>> = duplicate_groups(&result.targets)
+            .into_iter()
+            .map(|group| {
+                let mut paths: Vec<PathBuf> = group.iter().map(|info| info.path.clone()).collect();
+                paths.sort();
+                paths
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            [vec![
+                temp_dir.path().join("a").join("target"),
+                temp_dir.path().join("b").join("target"),
+            ]]
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    time::SystemTime,
+};
+
+use crate::{TargetDirInfo, meta::format_utc, paths::create_parent};
+
+/// What happened to a target, as recorded in the `--log-file`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogKind {
+    /// `--dry-run` would have deleted it.
+    Dry,
+    Deleted,
+    /// Moved by `--move-to` rather than deleted.
+    Moved,
+    /// Only its old subdirectories were removed, for `--keep-newest`.
+    Trimmed,
+}
+
+impl LogKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Dry => "DRY",
+            Self::Deleted => "DELETED",
+            Self::Moved => "MOVED",
+            Self::Trimmed => "TRIMMED",
+        }
+    }
+}
+
+/// One line of the log: the time, the [`LogKind`], the size in bytes and the path, tab
+/// separated so it's easy to `cut`.
+pub fn log_line(time: SystemTime, kind: LogKind, info: &TargetDirInfo) -> String {
+    format!(
+        "{}\t{}\t{}\t{}",
+        format_utc(time),
+        kind.as_str(),
+        info.size,
+        info.path.display()
+    )
+}
+
+/// The `--log-file` deletions are appended to, one line each as they happen.
+pub struct DeletionLog {
+    file: File,
+}
+
+impl DeletionLog {
+    /// Opens the log at `path` for appending, creating it and its directory if need be.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        create_parent(path)?;
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| {
+                std::io::Error::new(
+                    e.kind(),
+                    format!("Couldn't open the log file {}: {}", path.display(), e),
+                )
+            })?;
+        Ok(Self { file })
+    }
+
+    pub fn record(&mut self, kind: LogKind, info: &TargetDirInfo) -> std::io::Result<()> {
+        writeln!(self.file, "{}", log_line(SystemTime::now(), kind, info))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, handle_deletion_with, output::Output};
+    use std::{path::PathBuf, sync::Mutex, time::UNIX_EPOCH};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_log_line() {
+        let info = TargetDirInfo::new(PathBuf::from("/code/app/target"), 1000);
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1_714_564_800);
+        assert_eq!(
+            log_line(time, LogKind::Dry, &info),
+            "2024-05-01T12:00:00Z\tDRY\t1000\t/code/app/target"
+        );
+    }
+
+    #[test]
+    fn test_dry_run_logs_without_deleting() {
+        let temp_dir = TempDir::new().unwrap();
+        let targets: Vec<TargetDirInfo> = ["one", "two"]
+            .iter()
+            .map(|name| {
+                let path = temp_dir.path().join(name).join("target");
+                fs::create_dir_all(&path).unwrap();
+                TargetDirInfo::new(path, 10)
+            })
+            .collect();
+        let log_file = temp_dir.path().join("logs").join("deletions.log");
+        let removed = Mutex::new(Vec::new());
+        let remover = |path: &Path| {
+            removed.lock().unwrap().push(path.to_path_buf());
+            Ok(())
+        };
+        let run = |dry_run| {
+            let config = Config {
+                force: true,
+                delete_jobs: 2,
+                delete_tracked: true,
+                dry_run,
+                log_file: Some(log_file.clone()),
+                ..Config::default()
+            };
+            let (mut out, mut err) = (Vec::new(), Vec::new());
+            handle_deletion_with(
+                &targets,
+                &config,
+                &remover,
+                &mut Output::new(&mut out, &mut err, false),
+            )
+            .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let out = run(true);
+        assert!(removed.lock().unwrap().is_empty());
+        assert!(targets.iter().all(|info| info.path.is_dir()));
+        assert!(
+            out.contains(&format!(
+                "Would delete '{}', (10 B)",
+                targets[0].path.display()
+            )),
+            "{out}"
+        );
+        let log = fs::read_to_string(&log_file).unwrap();
+        let kinds: Vec<(&str, &str)> = log
+            .lines()
+            .map(|line| {
+                let fields: Vec<&str> = line.split('\t').collect();
+                (fields[1], fields[3])
+            })
+            .collect();
+        let paths: Vec<String> = targets
+            .iter()
+            .map(|info| info.path.display().to_string())
+            .collect();
+        assert_eq!(
+            kinds,
+            [("DRY", paths[0].as_str()), ("DRY", paths[1].as_str())]
+        );
+
+        // The real thing is appended after
+        run(false);
+        assert_eq!(removed.lock().unwrap().len(), 2);
+        let log = fs::read_to_string(&log_file).unwrap();
+        assert_eq!(log.lines().count(), 4);
+        assert!(
+            log.lines()
+                .skip(2)
+                .all(|line| line.contains("\tDELETED\t10\t"))
+        );
+    }
+}
@@ -0,0 +1,477 @@
+//! The `doctor` subcommand, a checklist of what in the environment could make a scan slow or
+//! find nothing. Each check is worked out on its own, so one failing doesn't hide the rest.
+
+use std::{
+    ffi::OsStr,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    CASE_INSENSITIVE_DEFAULT, child_target_dir,
+    mounts::{fs_type, is_network_fs},
+    output::{ColorChoice, ncdu::escape_json, use_color},
+    stats::IoStats,
+    unc,
+};
+
+/// Below this many open files a parallel scan can run out of handles.
+pub const LOW_OPEN_FILES: u64 = 256;
+
+/// An entry taking longer than this to stat points at a slow mount.
+pub const SLOW_ENTRY: Duration = Duration::from_millis(50);
+
+/// How deep under the path to look for target directories.
+pub const PROBE_DEPTH: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// Worth knowing, but not a problem.
+    Info,
+    Warn,
+    Fail,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Fail => "fail",
+        }
+    }
+}
+
+/// The outcome of one check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Whether stdout and stderr are terminals and what `TERM` is, and whether the table would be
+/// colored as a result.
+pub fn check_terminal(
+    stdout_tty: bool,
+    stderr_tty: bool,
+    env: impl Fn(&str) -> Option<String>,
+) -> Check {
+    let tty = |is: bool| if is { "a terminal" } else { "not a terminal" };
+    let term = env("TERM").unwrap_or_else(|| "unset".to_string());
+    let color = if use_color(ColorChoice::Auto, &env, stdout_tty) {
+        "colored"
+    } else {
+        "not colored"
+    };
+    let status = if stdout_tty { Status::Ok } else { Status::Info };
+    let detail = format!(
+        "stdout is {}, stderr is {}, TERM is {term}, so tables are {color}",
+        tty(stdout_tty),
+        tty(stderr_tty)
+    );
+    Check::new("Terminal", status, detail)
+}
+
+/// Whether `path` can be canonicalized, which discovery starts with.
+pub fn check_canonical(path: &Path) -> Check {
+    match unc::canonicalize(path) {
+        Ok(canonical) => Check::new(
+            "Path",
+            Status::Ok,
+            format!("{} is {}", path.display(), unc::shown(&canonical)),
+        ),
+        Err(e) => Check::new(
+            "Path",
+            Status::Fail,
+            format!("{} can't be canonicalized: {e}", path.display()),
+        ),
+    }
+}
+
+/// What filesystem `path` is on, going by the mountinfo `contents`, and whether it's a network
+/// one. Where there's no mount table, UNC paths are the only sign of a network share.
+pub fn check_filesystem(path: &Path, mountinfo: Option<&str>) -> Check {
+    let canonical = unc::canonical_or_same(path);
+    if let Some((mount, fs_type)) = mountinfo.and_then(|contents| fs_type(&canonical, contents)) {
+        let detail = format!("{fs_type} at {mount}");
+        return if is_network_fs(&fs_type) {
+            Check::new(
+                "Filesystem",
+                Status::Warn,
+                format!("{detail}, a network filesystem where every stat is a round trip"),
+            )
+        } else {
+            Check::new("Filesystem", Status::Ok, detail)
+        };
+    }
+    let shown = unc::shown(&canonical);
+    if shown.starts_with(r"\\") {
+        return Check::new(
+            "Filesystem",
+            Status::Warn,
+            format!("{shown} is a network share, where every stat is a round trip"),
+        );
+    }
+    Check::new(
+        "Filesystem",
+        Status::Info,
+        "the filesystem type isn't known here",
+    )
+}
+
+/// The soft and hard limits on open files.
+#[cfg(unix)]
+pub fn open_file_limit() -> Option<(u64, u64)> {
+    // SAFETY: `rlimit` is plain data, all zeroes is a valid value for it to overwrite
+    let mut limit: libc::rlimit = unsafe { std::mem::zeroed() };
+    // SAFETY: `limit` is writable and outlives the call
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return None;
+    }
+    #[allow(clippy::unnecessary_cast)]
+    Some((limit.rlim_cur as u64, limit.rlim_max as u64))
+}
+
+/// The soft and hard limits on open files, which Windows doesn't have in the same way.
+#[cfg(not(unix))]
+pub fn open_file_limit() -> Option<(u64, u64)> {
+    None
+}
+
+/// Whether the open files `limit` leaves room for a parallel scan.
+pub fn check_open_files(limit: Option<(u64, u64)>) -> Check {
+    match limit {
+        Some((soft, hard)) if soft < LOW_OPEN_FILES => Check::new(
+            "Open files",
+            Status::Warn,
+            format!(
+                "the limit is {soft} (hard {hard}), raise it with `ulimit -n` or pass --max-open-fds"
+            ),
+        ),
+        Some((soft, hard)) => Check::new(
+            "Open files",
+            Status::Ok,
+            format!("the limit is {soft} (hard {hard})"),
+        ),
+        None => Check::new("Open files", Status::Info, "there's no limit to check here"),
+    }
+}
+
+/// Where `program` is on the search path `path_var`, if it's there.
+pub fn find_on_path(program: &str, path_var: Option<&OsStr>) -> Option<PathBuf> {
+    let names = if cfg!(windows) {
+        vec![format!("{program}.exe"), program.to_string()]
+    } else {
+        vec![program.to_string()]
+    };
+    std::env::split_paths(path_var?)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Whether `program` is on the search path.
+pub fn check_on_path(program: &'static str, path_var: Option<&OsStr>) -> Check {
+    match find_on_path(program, path_var) {
+        Some(found) => Check::new(program, Status::Ok, format!("found at {}", found.display())),
+        // Only git is run by this tool, the others say whether this is where Rust is built
+        None if program == "git" => Check::new(
+            program,
+            Status::Warn,
+            "not on PATH, so targets tracked by git can't be told apart",
+        ),
+        None => Check::new(program, Status::Info, "not on PATH"),
+    }
+}
+
+/// Whether `CARGO_TARGET_DIR` sends builds somewhere other than each project's `target`.
+pub fn check_cargo_target_dir(env: impl Fn(&str) -> Option<String>) -> Check {
+    match env("CARGO_TARGET_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => Check::new(
+            "CARGO_TARGET_DIR",
+            Status::Info,
+            format!("set to {dir}, so builds go there rather than into each project's target"),
+        ),
+        None => Check::new("CARGO_TARGET_DIR", Status::Ok, "not set"),
+    }
+}
+
+/// How long listing `path` and statting each entry in it takes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WalkTiming {
+    pub entries: usize,
+    pub total: Duration,
+    /// The entry that took longest to stat, and how long.
+    pub slowest: Option<(PathBuf, Duration)>,
+}
+
+/// Lists `path` and stats each entry, timing it, to spot slow mounts before a full scan.
+pub fn time_shallow_walk(path: &Path) -> std::io::Result<WalkTiming> {
+    let started = Instant::now();
+    let mut timing = WalkTiming::default();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let stat_started = Instant::now();
+        // Errors are timed too, a slow failure is as telling
+        let _ = fs::symlink_metadata(entry.path());
+        let took = stat_started.elapsed();
+        timing.entries += 1;
+        if timing
+            .slowest
+            .as_ref()
+            .is_none_or(|(_, slowest)| took > *slowest)
+        {
+            timing.slowest = Some((entry.path(), took));
+        }
+    }
+    timing.total = started.elapsed();
+    Ok(timing)
+}
+
+/// Whether the first level of `path` could be listed quickly, from [`time_shallow_walk`].
+pub fn check_shallow_walk(timing: std::io::Result<WalkTiming>) -> Check {
+    let timing = match timing {
+        Ok(timing) => timing,
+        Err(e) => return Check::new("First level", Status::Fail, format!("can't be listed: {e}")),
+    };
+    let mut detail = format!("{} entries in {:?}", timing.entries, timing.total);
+    let mut status = Status::Ok;
+    if let Some((path, took)) = &timing.slowest {
+        detail.push_str(&format!(", slowest {} at {took:?}", unc::shown(path)));
+        if *took >= SLOW_ENTRY {
+            status = Status::Warn;
+            detail.push_str(", which suggests a slow mount");
+        }
+    }
+    Check::new("First level", status, detail)
+}
+
+/// Target directories within [`PROBE_DEPTH`] levels of `path`, the quick look a scan would
+/// start with.
+pub fn probe_targets(path: &Path) -> Vec<PathBuf> {
+    let stats = IoStats::default();
+    let mut found = Vec::new();
+    let mut level = vec![path.to_path_buf()];
+    for depth in 0..=PROBE_DEPTH {
+        let mut next = Vec::new();
+        for dir in &level {
+            if let Some(target) = child_target_dir(dir, CASE_INSENSITIVE_DEFAULT, &stats) {
+                found.push(target);
+                continue;
+            }
+            if depth == PROBE_DEPTH {
+                continue;
+            }
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            next.extend(
+                entries
+                    .flatten()
+                    .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+                    .map(|entry| entry.path()),
+            );
+        }
+        level = next;
+    }
+    found.sort();
+    found
+}
+
+/// Whether [`probe_targets`] found anything.
+pub fn check_targets_nearby(found: &[PathBuf]) -> Check {
+    match found {
+        [] => Check::new(
+            "Targets",
+            Status::Warn,
+            format!(
+                "none within {PROBE_DEPTH} levels, they may be deeper, in hidden directories (see --hidden) or elsewhere"
+            ),
+        ),
+        [first, ..] => Check::new(
+            "Targets",
+            Status::Ok,
+            format!(
+                "{} within {PROBE_DEPTH} levels, such as {}",
+                found.len(),
+                unc::shown(first)
+            ),
+        ),
+    }
+}
+
+/// Runs every check against `path` with this process's environment.
+pub fn diagnose(path: &Path, stdout_tty: bool, stderr_tty: bool) -> Vec<Check> {
+    let env = |name: &str| std::env::var(name).ok();
+    let path_var = std::env::var_os("PATH");
+    let mountinfo = if cfg!(target_os = "linux") {
+        fs::read_to_string("/proc/self/mountinfo").ok()
+    } else {
+        None
+    };
+    vec![
+        check_terminal(stdout_tty, stderr_tty, env),
+        check_canonical(path),
+        check_filesystem(path, mountinfo.as_deref()),
+        check_open_files(open_file_limit()),
+        check_on_path("cargo", path_var.as_deref()),
+        check_on_path("rustup", path_var.as_deref()),
+        check_on_path("git", path_var.as_deref()),
+        check_cargo_target_dir(env),
+        check_shallow_walk(time_shallow_walk(path)),
+        check_targets_nearby(&probe_targets(path)),
+    ]
+}
+
+/// Writes `checks` as a checklist, such as `[warn] git: not on PATH, ...`.
+pub fn render_checks(out: &mut dyn Write, checks: &[Check]) -> std::io::Result<()> {
+    for check in checks {
+        let status = format!("[{}]", check.status.as_str());
+        writeln!(out, "{status:<6} {}: {}", check.name, check.detail)?;
+    }
+    Ok(())
+}
+
+/// Writes `checks` as a JSON object with a `checks` array, for `doctor --json`.
+pub fn render_checks_json(out: &mut dyn Write, checks: &[Check]) -> std::io::Result<()> {
+    let checks: Vec<String> = checks
+        .iter()
+        .map(|check| {
+            format!(
+                "{{\"name\":\"{}\",\"status\":\"{}\",\"detail\":\"{}\"}}",
+                escape_json(check.name),
+                check.status.as_str(),
+                escape_json(&check.detail)
+            )
+        })
+        .collect();
+    writeln!(out, "{{\"checks\":[{}]}}", checks.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_check_terminal_and_env() {
+        let env = |name: &str| match name {
+            "TERM" => Some("xterm-256color".to_string()),
+            "NO_COLOR" => Some("1".to_string()),
+            _ => None,
+        };
+        let check = check_terminal(true, false, env);
+        assert_eq!(check.status, Status::Ok);
+        assert_eq!(
+            check.detail,
+            "stdout is a terminal, stderr is not a terminal, TERM is xterm-256color, so tables are not colored"
+        );
+        assert_eq!(check_terminal(false, false, |_| None).status, Status::Info);
+
+        assert_eq!(check_cargo_target_dir(|_| None).status, Status::Ok);
+        let set = check_cargo_target_dir(|_| Some("/shared/target".to_string()));
+        assert_eq!(set.status, Status::Info);
+        assert!(set.detail.contains("/shared/target"));
+
+        assert_eq!(check_open_files(Some((1024, 4096))).status, Status::Ok);
+        assert_eq!(check_open_files(Some((64, 4096))).status, Status::Warn);
+        assert_eq!(check_open_files(None).status, Status::Info);
+    }
+
+    #[test]
+    fn test_check_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let bin = temp_dir.path().join("bin");
+        fs::create_dir(&bin).unwrap();
+        let name = if cfg!(windows) { "git.exe" } else { "git" };
+        fs::write(bin.join(name), "").unwrap();
+        let path_var = std::env::join_paths([temp_dir.path().join("empty"), bin.clone()]).unwrap();
+        assert_eq!(find_on_path("git", Some(&path_var)), Some(bin.join(name)));
+        assert_eq!(check_on_path("git", Some(&path_var)).status, Status::Ok);
+        assert_eq!(
+            check_on_path("rustup", Some(&path_var)).status,
+            Status::Info
+        );
+        assert_eq!(check_on_path("git", None).status, Status::Warn);
+        assert_eq!(find_on_path("git", None), None);
+
+        assert_eq!(check_canonical(temp_dir.path()).status, Status::Ok);
+        assert_eq!(
+            check_canonical(&temp_dir.path().join("missing")).status,
+            Status::Fail
+        );
+        assert_eq!(
+            check_shallow_walk(time_shallow_walk(&temp_dir.path().join("missing"))).status,
+            Status::Fail
+        );
+        let timing = time_shallow_walk(temp_dir.path()).unwrap();
+        assert_eq!(timing.entries, 1);
+        assert_eq!(timing.slowest.map(|(path, _)| path), Some(bin));
+    }
+
+    #[test]
+    fn test_check_filesystem() {
+        let mountinfo = "22 1 259:2 / / rw - ext4 /dev/nvme0n1p2 rw\n\
+                         50 22 0:50 / /srv/builds rw - nfs4 fileserver:/builds rw\n";
+        let local = check_filesystem(Path::new("/home/me"), Some(mountinfo));
+        assert_eq!(local.status, Status::Ok);
+        assert_eq!(local.detail, "ext4 at / (/dev/nvme0n1p2)");
+        let network = check_filesystem(Path::new("/srv/builds/ci"), Some(mountinfo));
+        assert_eq!(network.status, Status::Warn);
+        assert!(network.detail.starts_with("nfs4 at /srv/builds"));
+        assert_eq!(check_filesystem(Path::new("/x"), None).status, Status::Info);
+    }
+
+    #[test]
+    fn test_probe_targets() {
+        let temp_dir = TempDir::new().unwrap();
+        for project in ["app", "group/lib", "a/b/too-deep"] {
+            fs::create_dir_all(temp_dir.path().join(project).join("target")).unwrap();
+        }
+        let found = probe_targets(temp_dir.path());
+        assert_eq!(
+            found,
+            [
+                temp_dir.path().join("app").join("target"),
+                temp_dir.path().join("group").join("lib").join("target"),
+            ]
+        );
+        assert_eq!(check_targets_nearby(&found).status, Status::Ok);
+        assert_eq!(check_targets_nearby(&[]).status, Status::Warn);
+    }
+
+    #[test]
+    fn test_render_checks() {
+        let checks = [
+            Check::new("git", Status::Warn, "not on PATH"),
+            Check::new("Path", Status::Ok, "C:\\code is \"fine\""),
+        ];
+        let mut out = Vec::new();
+        render_checks(&mut out, &checks).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "[warn] git: not on PATH\n[ok]   Path: C:\\code is \"fine\"\n"
+        );
+        let mut out = Vec::new();
+        render_checks_json(&mut out, &checks).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"checks\":[{\"name\":\"git\",\"status\":\"warn\",\"detail\":\"not on PATH\"},\
+             {\"name\":\"Path\",\"status\":\"ok\",\"detail\":\"C:\\\\code is \\\"fine\\\"\"}]}\n"
+        );
+    }
+}
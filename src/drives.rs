@@ -0,0 +1,130 @@
+use std::path::{Path, PathBuf};
+
+/// The roots of the drives set in `mask`, a [`GetLogicalDrives`] style bitmask where bit 0 is
+/// `A:`, that `is_fixed` accepts.
+///
+/// [`GetLogicalDrives`]: https://learn.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-getlogicaldrives
+pub fn drive_roots(mask: u32, is_fixed: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+    (b'A'..=b'Z')
+        .enumerate()
+        .filter(|(bit, _)| mask & (1 << bit) != 0)
+        .map(|(_, letter)| PathBuf::from(format!("{}:\\", letter as char)))
+        .filter(|root| is_fixed(root))
+        .collect()
+}
+
+/// The fixed local drives, leaving out removable, network, optical and RAM drives, for
+/// `--all-drives`.
+///
+/// `None` outside Windows, where there are no drive letters to enumerate.
+pub fn fixed_drives() -> Option<Vec<PathBuf>> {
+    #[cfg(windows)]
+    {
+        Some(windows::fixed_drives())
+    }
+    #[cfg(not(windows))]
+    {
+        None
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{os::windows::ffi::OsStrExt, path::PathBuf};
+
+    use windows_sys::Win32::{
+        Storage::FileSystem::{GetDriveTypeW, GetLogicalDrives},
+        System::WindowsProgramming::DRIVE_FIXED,
+    };
+
+    pub(super) fn fixed_drives() -> Vec<PathBuf> {
+        // SAFETY: takes no arguments, and returns 0 on failure which means no drives
+        let mask = unsafe { GetLogicalDrives() };
+        super::drive_roots(mask, |root| {
+            let wide: Vec<u16> = root.as_os_str().encode_wide().chain(Some(0)).collect();
+            // SAFETY: `wide` is a NUL terminated UTF-16 string that outlives the call
+            unsafe { GetDriveTypeW(wide.as_ptr()) == DRIVE_FIXED }
+        })
+    }
+}
+
+/// How much of the total is on each of `roots`, in the order given, for the per-drive
+/// subtotals of `--all-drives`.
+pub fn subtotals_by_root(
+    target_info: &[crate::TargetDirInfo],
+    roots: &[PathBuf],
+) -> Vec<(PathBuf, u64)> {
+    roots
+        .iter()
+        .map(|root| {
+            // Discovered paths are canonical, which on Windows adds a `\\?\` prefix
+            let canonical = crate::unc::canonical_or_same(root);
+            let size = target_info
+                .iter()
+                .filter(|info| info.path.starts_with(root) || info.path.starts_with(&canonical))
+                .map(|info| info.size)
+                .sum();
+            (root.clone(), size)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TargetDirInfo;
+
+    #[test]
+    fn test_drive_roots() {
+        // A:, C:, D: and Z:, with D: removable
+        let mask = 1 | 1 << 2 | 1 << 3 | 1 << 25;
+        assert_eq!(
+            drive_roots(mask, |root| root != Path::new("D:\\")),
+            [
+                PathBuf::from("A:\\"),
+                PathBuf::from("C:\\"),
+                PathBuf::from("Z:\\")
+            ]
+        );
+        assert!(drive_roots(0, |_| true).is_empty());
+    }
+
+    #[test]
+    fn test_subtotals_by_root() {
+        let target_info = [
+            TargetDirInfo::new(PathBuf::from("/c/a/target"), 10),
+            TargetDirInfo::new(PathBuf::from("/c/b/target"), 5),
+            TargetDirInfo::new(PathBuf::from("/d/a/target"), 1),
+        ];
+        let roots = [
+            PathBuf::from("/c"),
+            PathBuf::from("/d"),
+            PathBuf::from("/e"),
+        ];
+        assert_eq!(
+            subtotals_by_root(&target_info, &roots),
+            [
+                (PathBuf::from("/c"), 15),
+                (PathBuf::from("/d"), 1),
+                (PathBuf::from("/e"), 0)
+            ]
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_fixed_drives_includes_system_drive() {
+        let system_drive = std::env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string());
+        let drives = fixed_drives().unwrap();
+        assert!(
+            drives.contains(&PathBuf::from(format!("{system_drive}\\"))),
+            "{drives:?}"
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_fixed_drives_outside_windows() {
+        assert_eq!(fixed_drives(), None);
+    }
+}
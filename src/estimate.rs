@@ -0,0 +1,260 @@
+//! `--approximate`, sizing a directory from a sample of what's in it rather than all of it.
+
+use std::{fs, path::Path};
+
+/// How many of a directory's files, and of its subdirectories, are looked at before the rest
+/// are estimated from them.
+pub const SAMPLE_SIZE: usize = 32;
+
+/// A size worked out by sampling, with how far out it could be.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Estimate {
+    pub bytes: u64,
+    /// The true size is within this many bytes either way about 95% of the time. It's 0 when
+    /// everything was looked at.
+    pub margin: u64,
+}
+
+impl Estimate {
+    pub fn exact(bytes: u64) -> Self {
+        Self { bytes, margin: 0 }
+    }
+
+    /// The estimate for two independent parts together, whose margins add in quadrature.
+    pub fn combine(self, other: Self) -> Self {
+        let margin = (self.margin as f64).hypot(other.margin as f64);
+        Self {
+            bytes: self.bytes + other.bytes,
+            margin: margin.round() as u64,
+        }
+    }
+
+    /// How far out it could be, as a percentage of the size.
+    pub fn margin_percent(&self) -> f64 {
+        if self.bytes == 0 {
+            return 0.0;
+        }
+        self.margin as f64 * 100.0 / self.bytes as f64
+    }
+}
+
+/// Picks `count` of `population` items spread evenly through them, as indices in order.
+pub fn sample_indices(population: usize, count: usize) -> Vec<usize> {
+    if count >= population {
+        return (0..population).collect();
+    }
+    (0..count).map(|i| i * population / count).collect()
+}
+
+/// Estimates the total of `population` sizes from `sample`, some of them picked without
+/// bias: the sample's mean times the population, with a margin of two standard errors.
+///
+/// A sample of everything is exact. The margin shrinks as the sample gets closer to the whole
+/// population, by the finite population correction.
+pub fn extrapolate(population: u64, sample: &[u64]) -> Estimate {
+    let n = sample.len() as u64;
+    if n >= population {
+        return Estimate::exact(sample.iter().sum());
+    }
+    if n == 0 {
+        return Estimate::default();
+    }
+    let mean = sample.iter().sum::<u64>() as f64 / n as f64;
+    let variance = if n > 1 {
+        sample
+            .iter()
+            .map(|&size| (size as f64 - mean).powi(2))
+            .sum::<f64>()
+            / (n - 1) as f64
+    } else {
+        // One observation says nothing about the spread, so allow for as much again
+        mean * mean
+    };
+    let correction = 1.0 - n as f64 / population as f64;
+    let standard_error = population as f64 * (variance / n as f64 * correction).sqrt();
+    Estimate {
+        bytes: (mean * population as f64).round() as u64,
+        margin: (2.0 * standard_error).round() as u64,
+    }
+}
+
+/// Estimates the size of the directory at `path`, looking at up to `sample` of the files and of
+/// the subdirectories in each directory and extrapolating from those to the rest.
+///
+/// Listing a directory is cheap next to looking at every file in it, so every listed entry is
+/// counted, only the sizes are sampled. Symlinks are counted as themselves, like the exact walk.
+pub fn approximate_size(path: &Path, sample: usize) -> std::io::Result<Estimate> {
+    let mut files = Vec::new();
+    let mut subdirs = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            subdirs.push(entry.path());
+        } else {
+            files.push(entry);
+        }
+    }
+
+    let mut sampled_files = Vec::new();
+    for i in sample_indices(files.len(), sample) {
+        sampled_files.push(files[i].metadata()?.len());
+    }
+    let estimate = extrapolate(files.len() as u64, &sampled_files);
+
+    let indices = sample_indices(subdirs.len(), sample);
+    let mut sizes = Vec::with_capacity(indices.len());
+    let mut inner = Estimate::default();
+    for i in &indices {
+        let subdir = approximate_size(&subdirs[*i], sample)?;
+        sizes.push(subdir.bytes);
+        inner = inner.combine(Estimate {
+            bytes: 0,
+            margin: subdir.margin,
+        });
+    }
+    let across = extrapolate(subdirs.len() as u64, &sizes);
+    // Each sampled subdirectory's own uncertainty stands for the ones not looked at too
+    let scale = subdirs.len() as f64 / indices.len().max(1) as f64;
+    let inner = Estimate {
+        bytes: 0,
+        margin: (inner.margin as f64 * scale).round() as u64,
+    };
+    Ok(estimate.combine(across).combine(inner))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_extrapolate() {
+        // Everything looked at is exact
+        assert_eq!(extrapolate(3, &[1, 2, 3]), Estimate::exact(6));
+        assert_eq!(extrapolate(0, &[]), Estimate::exact(0));
+        // No spread, so no margin
+        assert_eq!(extrapolate(100, &[10, 10, 10, 10]), Estimate::exact(1000));
+        // Mean 20, sample variance 200/3, standard error 100 * sqrt(200/3 / 4 * 0.96)
+        let estimate = extrapolate(100, &[10, 20, 30, 20]);
+        assert_eq!(estimate.bytes, 2000);
+        assert_eq!(estimate.margin, 800);
+        assert_eq!(estimate.margin_percent(), 40.0);
+        // A single observation is taken to be as spread as it is big, 2 * 2 * sqrt(100 / 2)
+        assert_eq!(
+            extrapolate(2, &[10]),
+            Estimate {
+                bytes: 20,
+                margin: 28
+            }
+        );
+    }
+
+    #[test]
+    fn test_sample_indices_and_combine() {
+        assert_eq!(sample_indices(10, 4), [0, 2, 5, 7]);
+        assert_eq!(sample_indices(3, 4), [0, 1, 2]);
+        assert_eq!(sample_indices(0, 4), Vec::<usize>::new());
+        assert_eq!(
+            Estimate {
+                bytes: 100,
+                margin: 3
+            }
+            .combine(Estimate {
+                bytes: 50,
+                margin: 4
+            }),
+            Estimate {
+                bytes: 150,
+                margin: 5
+            }
+        );
+    }
+
+    #[test]
+    fn test_approximate_uniform_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        for crate_dir in 0..60 {
+            let dir = target
+                .join("debug")
+                .join("build")
+                .join(format!("dep-{crate_dir}"));
+            fs::create_dir_all(&dir).unwrap();
+            for file in 0..3 {
+                fs::write(dir.join(format!("out-{file}")), vec![0u8; 900 + file * 100]).unwrap();
+            }
+        }
+        let deps = target.join("debug").join("deps");
+        fs::create_dir_all(&deps).unwrap();
+        for file in 0..200 {
+            fs::write(
+                deps.join(format!("lib{file}.rlib")),
+                vec![0u8; 500 + file % 3 * 250],
+            )
+            .unwrap();
+        }
+
+        let exact = crate::calculate_dir_size(&target).unwrap();
+        let estimate = approximate_size(&target, 8).unwrap();
+        assert!(estimate.margin > 0);
+        let off = estimate.bytes.abs_diff(exact);
+        assert!(
+            off * 20 <= exact,
+            "{estimate:?} is more than 5% from {exact}"
+        );
+        assert!(off <= estimate.margin, "{estimate:?} vs {exact}");
+
+        // With enough of a sample it's everything
+        assert_eq!(
+            approximate_size(&target, 1000).unwrap(),
+            Estimate::exact(exact)
+        );
+    }
+
+    #[test]
+    fn test_approximate_scan_deletes_exactly() {
+        use crate::{
+            Config, handle_deletion_with,
+            output::{Output, TableOptions, format_row},
+            report::NoopReporter,
+            scan,
+        };
+
+        let temp_dir = TempDir::new().unwrap();
+        let deps = temp_dir.path().join("app").join("target").join("deps");
+        fs::create_dir_all(&deps).unwrap();
+        for file in 0..100 {
+            fs::write(deps.join(format!("{file}.o")), vec![0u8; file * 10]).unwrap();
+        }
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            cargo_cache_dirs: Vec::new(),
+            approximate: true,
+            force: true,
+            delete_jobs: 1,
+            delete_tracked: true,
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        let info = &result.targets[0];
+        assert!(info.size_margin.is_some_and(|margin| margin > 0));
+        assert!(
+            format_row(info, &TableOptions::default())
+                .trim_start()
+                .starts_with('~')
+        );
+
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        handle_deletion_with(
+            &result.targets,
+            &config,
+            &|_| Ok(()),
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Sizing the estimated directories exactly before deleting"));
+        // 10 * (0 + 1 + ... + 99)
+        assert!(out.contains("successfully, (49.5 kB)"), "{out}");
+    }
+}
@@ -0,0 +1,580 @@
+//! `--explain` and `--explain-all`, tracing why a directory was or wasn't listed through each
+//! rule the scan applies.
+//!
+//! Discovery and the filters report every directory they drop as an [`Event::DirPruned`], so
+//! the scan is run with a [`Recorder`] and the explanation is put together from what it kept.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use humanize_bytes::humanize_bytes_decimal;
+
+use crate::{
+    KEEP_MARKER, ScanResult, TargetDirInfo,
+    config::Config,
+    has_keep_marker, is_target_name, path_contains,
+    protect::{PROTECT_MARKER, TARGET_KEEP_FILE, has_protect_marker},
+    report::{Event, PruneReason, Reporter},
+    unc,
+};
+
+/// Keeps every event while passing it on to another reporter.
+pub struct Recorder<'a> {
+    inner: &'a dyn Reporter,
+    events: Mutex<Vec<Event>>,
+}
+
+impl<'a> Recorder<'a> {
+    pub fn new(inner: &'a dyn Reporter) -> Self {
+        Self {
+            inner,
+            events: Mutex::default(),
+        }
+    }
+
+    /// The events seen so far, in the order they were reported.
+    pub fn events(&self) -> Vec<Event> {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl Reporter for Recorder<'_> {
+    fn report(&self, event: Event) {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(event.clone());
+        self.inner.report(event);
+    }
+}
+
+/// What one rule made of a directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The rule let it through.
+    Passed,
+    /// Listed, but worth knowing, such as it never being deleted.
+    Noted,
+    /// The rule left it out.
+    Excluded,
+}
+
+impl Verdict {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Passed => "pass",
+            Self::Noted => "note",
+            Self::Excluded => "excluded",
+        }
+    }
+}
+
+/// One rule evaluated against a directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// The rule, named after its option where it has one, such as `--min-size`.
+    pub rule: &'static str,
+    pub verdict: Verdict,
+    pub detail: String,
+}
+
+impl Step {
+    fn new(rule: &'static str, verdict: Verdict, detail: impl Into<String>) -> Self {
+        Self {
+            rule,
+            verdict,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// The rule behind `reason`, and what it found, with `size` where it was sized.
+fn pruned_step(reason: &PruneReason, config: &Config, size: Option<u64>) -> Step {
+    let size = size.map_or_else(
+        || "its size".to_string(),
+        |size| humanize_bytes_decimal!(size).to_string(),
+    );
+    let excluded = |rule, detail: String| Step::new(rule, Verdict::Excluded, detail);
+    match reason {
+        PruneReason::NotADirectory => excluded("directory", "not a directory".to_string()),
+        PruneReason::NoTargetDir => excluded(
+            "name",
+            "the project has no directory named target".to_string(),
+        ),
+        PruneReason::CargoCache => excluded(
+            "cargo cache",
+            "inside the cargo registry or git checkouts, which aren't projects".to_string(),
+        ),
+        PruneReason::OutsideRoot => excluded(
+            "root",
+            "reached through a symlink leading outside the scanned directory".to_string(),
+        ),
+        PruneReason::AlreadyVisited => {
+            excluded("alias", "already reached through another path".to_string())
+        }
+        PruneReason::AliasOf(kept) => excluded(
+            "alias",
+            format!(
+                "the same directory as {}, which is listed instead",
+                unc::shown(kept)
+            ),
+        ),
+        PruneReason::RecentlyAccessed => excluded(
+            "--unused-for",
+            "files inside were read more recently than it allows".to_string(),
+        ),
+        PruneReason::NewerThanReference => excluded(
+            "--older-than-file",
+            match &config.older_than_file {
+                Some(file) => format!("a file inside is newer than {}", file.display()),
+                None => "a file inside is newer than the reference file".to_string(),
+            },
+        ),
+        PruneReason::BelowMinSize => excluded(
+            "--min-size",
+            format!(
+                "{size} is below the minimum of {}",
+                humanize_bytes_decimal!(config.min_size.unwrap_or_default())
+            ),
+        ),
+        PruneReason::AboveMaxSize => excluded(
+            "--max-size",
+            format!(
+                "{size} is above the maximum of {}",
+                humanize_bytes_decimal!(config.max_size.unwrap_or_default())
+            ),
+        ),
+        PruneReason::BelowMinFiles => excluded(
+            "--min-files",
+            format!(
+                "fewer files than the minimum of {}",
+                config.min_files.unwrap_or_default()
+            ),
+        ),
+        PruneReason::AboveMaxFiles => excluded(
+            "--max-files",
+            format!(
+                "more files than the maximum of {}",
+                config.max_files.unwrap_or_default()
+            ),
+        ),
+        PruneReason::NotBuiltBeforeRustc => excluded(
+            "--built-before-rustc",
+            match &config.built_before_rustc {
+                Some(version) => format!("not known to be built by a rustc older than {version}"),
+                None => "not known to be built by an older rustc".to_string(),
+            },
+        ),
+        PruneReason::ToolchainInstalled => excluded(
+            "--stale-toolchains-only",
+            "the toolchain that built it is still installed, or isn't known".to_string(),
+        ),
+        PruneReason::OtherMount => excluded(
+            "--mount",
+            "on a different filesystem from the one asked for".to_string(),
+        ),
+        PruneReason::KeepMarker => excluded(
+            "--hide-kept",
+            format!("the project has a {KEEP_MARKER} file"),
+        ),
+        PruneReason::NotInGitRepo => excluded(
+            "--git-only",
+            "its project isn't in a git work tree within the scanned directory".to_string(),
+        ),
+        PruneReason::Hidden => excluded(
+            "--hidden",
+            "its project's directory starts with a dot, which is skipped without --hidden"
+                .to_string(),
+        ),
+    }
+}
+
+/// Returns true if `event_path`, as reported, is `path` once canonicalized.
+fn same_dir(event_path: &Path, path: &Path) -> bool {
+    event_path == path || unc::canonical_or_same(event_path) == path
+}
+
+/// Traces `path` through the rules of a scan with `config`, from the `result` and the `events`
+/// a [`Recorder`] kept during it. The last step says whether it was left out or listed.
+pub fn explain(path: &Path, config: &Config, result: &ScanResult, events: &[Event]) -> Vec<Step> {
+    let canonical = match unc::canonicalize(path) {
+        Ok(canonical) => canonical,
+        Err(e) => return vec![Step::new("exists", Verdict::Excluded, e.to_string())],
+    };
+    let mut steps = Vec::new();
+    let Some(root) = config
+        .roots
+        .iter()
+        .find(|root| path_contains(root, &canonical))
+        .map(|root| unc::canonical_or_same(root))
+    else {
+        steps.push(Step::new(
+            "root",
+            Verdict::Excluded,
+            "not inside any of the directories being scanned",
+        ));
+        return steps;
+    };
+    steps.push(Step::new(
+        "root",
+        Verdict::Passed,
+        format!("inside {}", unc::shown(&root)),
+    ));
+
+    // With `--docs-only` and `--triple` what's listed is inside the target directory
+    let target_dir = match canonical.parent() {
+        Some(parent)
+            if (config.docs_only || !config.triples.is_empty())
+                && parent
+                    .file_name()
+                    .is_some_and(|name| is_target_name(name, config.case_insensitive)) =>
+        {
+            parent.to_path_buf()
+        }
+        _ => canonical.clone(),
+    };
+    let name = target_dir.file_name().unwrap_or_default();
+    if is_target_name(name, config.case_insensitive) {
+        steps.push(Step::new("name", Verdict::Passed, "named target"));
+    } else {
+        let hint = if is_target_name(name, true) {
+            ", which only matches ignoring case (see --case-insensitive)"
+        } else {
+            ", only directories named target are looked for"
+        };
+        steps.push(Step::new(
+            "name",
+            Verdict::Excluded,
+            format!("named {}{hint}", name.to_string_lossy()),
+        ));
+        return steps;
+    }
+
+    let project = target_dir.parent().unwrap_or(&target_dir);
+    if project.join("Cargo.toml").is_file() {
+        steps.push(Step::new("Cargo.toml", Verdict::Passed, "next to it"));
+    } else {
+        steps.push(Step::new(
+            "Cargo.toml",
+            Verdict::Noted,
+            "not next to it, it's found by its name alone",
+        ));
+    }
+
+    let found = events
+        .iter()
+        .any(|event| matches!(event, Event::TargetFound(found) if same_dir(found, &target_dir)));
+    if project == root || project.parent() == Some(root.as_path()) {
+        steps.push(Step::new(
+            "depth",
+            Verdict::Passed,
+            "its project is a scanned directory or directly inside one",
+        ));
+    } else if found {
+        steps.push(Step::new(
+            "depth",
+            Verdict::Passed,
+            "found as a git submodule",
+        ));
+    } else {
+        steps.push(Step::new(
+            "depth",
+            Verdict::Excluded,
+            "its project is deeper than directly inside a scanned directory, scan its parent instead",
+        ));
+        return steps;
+    }
+
+    let size = events.iter().find_map(|event| match event {
+        Event::SizeComputed(sized, size) | Event::SizeResumed(sized, size)
+            if same_dir(sized, &canonical) =>
+        {
+            Some(*size)
+        }
+        _ => None,
+    });
+    let pruned = events.iter().find_map(|event| match event {
+        Event::DirPruned(pruned, reason)
+            if same_dir(pruned, &canonical)
+                || same_dir(pruned, &target_dir)
+                || same_dir(pruned, project) =>
+        {
+            Some(reason)
+        }
+        _ => None,
+    });
+    if let Some(reason) = pruned {
+        steps.push(pruned_step(reason, config, size));
+        return steps;
+    }
+    if let Some((_, e)) = result
+        .errors
+        .iter()
+        .find(|(errored, _)| same_dir(errored, &canonical))
+    {
+        steps.push(Step::new(
+            "size",
+            Verdict::Excluded,
+            format!("couldn't be sized: {e}"),
+        ));
+        return steps;
+    }
+    let Some(info) = result
+        .targets
+        .iter()
+        .find(|info| same_dir(&info.path, &canonical))
+    else {
+        let detail = if result.truncated {
+            "discovery stopped at --max-results before reaching it"
+        } else {
+            "not found by the scan"
+        };
+        steps.push(Step::new("discovery", Verdict::Excluded, detail));
+        return steps;
+    };
+    steps.extend(protection_steps(info, config));
+    steps.push(Step::new(
+        "listed",
+        Verdict::Passed,
+        humanize_bytes_decimal!(info.size),
+    ));
+    steps
+}
+
+/// What keeps `info` from ever being deleted, though it's listed.
+fn protection_steps(info: &TargetDirInfo, config: &Config) -> Vec<Step> {
+    let mut steps = Vec::new();
+    if let Some(glob) = config.protected.protected_by(&info.path) {
+        steps.push(Step::new(
+            "protected",
+            Verdict::Noted,
+            format!("matches `{glob}` in the config file, so it's never deleted"),
+        ));
+    }
+    if has_protect_marker(info) {
+        steps.push(Step::new(
+            "protected",
+            Verdict::Noted,
+            format!(
+                "has a {TARGET_KEEP_FILE} inside or a {PROTECT_MARKER} in its project, so it's never deleted"
+            ),
+        ));
+    }
+    if has_keep_marker(info) && !config.ignore_keep_markers {
+        steps.push(Step::new(
+            "keep marker",
+            Verdict::Noted,
+            format!(
+                "its project has a {KEEP_MARKER} file, so it's only deleted with --ignore-keep-markers"
+            ),
+        ));
+    }
+    steps
+}
+
+/// Every target directory the scan considered, from the `events`, each with the step that
+/// decided it. Directories dropped while looking for projects aren't targets, so they're left
+/// out.
+pub fn explain_all(config: &Config, result: &ScanResult, events: &[Event]) -> Vec<(PathBuf, Step)> {
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    for event in events {
+        let path = match event {
+            Event::TargetFound(path) => path,
+            Event::DirPruned(path, reason)
+                if !matches!(
+                    reason,
+                    PruneReason::NotADirectory
+                        | PruneReason::NoTargetDir
+                        | PruneReason::Hidden
+                        | PruneReason::OutsideRoot
+                        | PruneReason::AlreadyVisited
+                ) =>
+            {
+                path
+            }
+            _ => continue,
+        };
+        if !candidates.contains(path) {
+            candidates.push(path.clone());
+        }
+    }
+    candidates
+        .into_iter()
+        .filter_map(|path| {
+            // The last step is the one that left it out, or that it's listed
+            let deciding = explain(&path, config, result, events).pop()?;
+            Some((path, deciding))
+        })
+        .collect()
+}
+
+/// Writes the `steps` explaining `path`, one rule to a line.
+pub fn render_explanation(out: &mut dyn Write, path: &Path, steps: &[Step]) -> std::io::Result<()> {
+    writeln!(out, "{}", unc::shown(path))?;
+    for step in steps {
+        let verdict = format!("[{}]", step.verdict.as_str());
+        writeln!(out, "  {verdict:<10} {}: {}", step.rule, step.detail)?;
+    }
+    Ok(())
+}
+
+/// Writes one line for each directory from [`explain_all`].
+pub fn render_explain_all(out: &mut dyn Write, decided: &[(PathBuf, Step)]) -> std::io::Result<()> {
+    for (path, step) in decided {
+        match step.verdict {
+            Verdict::Excluded => writeln!(
+                out,
+                "Excluded {} by {}: {}",
+                unc::shown(path),
+                step.rule,
+                step.detail
+            )?,
+            _ => writeln!(out, "Included {}", unc::shown(path))?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{protect::Protected, report::NoopReporter, scan};
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// The last line of the explanation of `path`.
+    fn decided(path: &Path, config: &Config, result: &ScanResult, events: &[Event]) -> String {
+        let step = explain(path, config, result, events).pop().unwrap();
+        format!("[{}] {}: {}", step.verdict.as_str(), step.rule, step.detail)
+    }
+
+    #[test]
+    fn test_explain_each_rule() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("code");
+        let outside = temp_dir.path().join("elsewhere").join("target");
+        fs::create_dir_all(&outside).unwrap();
+        let sizes = [
+            ("app/target", 1000),
+            ("tiny/target", 10),
+            (".hidden/target", 1000),
+            ("group/nested/target", 1000),
+            ("registry/target", 1000),
+            ("vendored/target", 1000),
+            ("kept/target", 1000),
+        ];
+        for (dir, size) in sizes {
+            let dir = root.join(dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("file"), vec![0u8; size]).unwrap();
+        }
+        fs::write(root.join("app").join("Cargo.toml"), "").unwrap();
+        fs::create_dir_all(root.join("other").join("build")).unwrap();
+        fs::write(root.join("kept").join(KEEP_MARKER), "").unwrap();
+        let config = Config {
+            roots: vec![root.clone()],
+            cargo_cache_dirs: vec![root.join("registry")],
+            min_size: Some(100),
+            protected: Protected::parse("protected = [\"vendored\"]", false).unwrap(),
+            ..Config::default()
+        };
+        let recorder = Recorder::new(&NoopReporter);
+        let result = scan(&config, &recorder).unwrap();
+        let events = recorder.events();
+        let decided = |path: &Path| decided(path, &config, &result, &events);
+
+        let app = root.join("app").join("target");
+        let mut out = Vec::new();
+        render_explanation(&mut out, &app, &explain(&app, &config, &result, &events)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "{}\n  [pass]     root: inside {}\n  [pass]     name: named target\n  \
+                 [pass]     Cargo.toml: next to it\n  [pass]     depth: its project is a scanned \
+                 directory or directly inside one\n  [pass]     listed: 1 kB\n",
+                app.display(),
+                unc::canonical_or_same(&root).display()
+            )
+        );
+
+        assert_eq!(
+            decided(&root.join("tiny").join("target")),
+            "[excluded] --min-size: 10 B is below the minimum of 100 B"
+        );
+        assert_eq!(
+            decided(&root.join(".hidden").join("target")),
+            "[excluded] --hidden: its project's directory starts with a dot, which is skipped \
+             without --hidden"
+        );
+        assert_eq!(
+            decided(&root.join("group").join("nested").join("target")),
+            "[excluded] depth: its project is deeper than directly inside a scanned directory, \
+             scan its parent instead"
+        );
+        assert_eq!(
+            decided(&root.join("registry").join("target")),
+            "[excluded] cargo cache: inside the cargo registry or git checkouts, which aren't \
+             projects"
+        );
+        assert_eq!(
+            decided(&root.join("other").join("build")),
+            "[excluded] name: named build, only directories named target are looked for"
+        );
+        assert_eq!(
+            decided(&outside),
+            "[excluded] root: not inside any of the directories being scanned"
+        );
+        assert!(decided(&root.join("missing")).starts_with("[excluded] exists: "));
+
+        // Listed, but never deleted
+        let vendored = explain(
+            &root.join("vendored").join("target"),
+            &config,
+            &result,
+            &events,
+        );
+        assert!(vendored.contains(&Step::new(
+            "protected",
+            Verdict::Noted,
+            "matches `vendored` in the config file, so it's never deleted"
+        )));
+        let kept = explain(&root.join("kept").join("target"), &config, &result, &events);
+        assert_eq!(kept[kept.len() - 2].rule, "keep marker");
+        assert_eq!(kept.last().unwrap().verdict, Verdict::Passed);
+
+        let all = explain_all(&config, &result, &events);
+        let mut out = Vec::new();
+        render_explain_all(&mut out, &all).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(&format!("Included {}\n", app.display())));
+        assert!(out.contains("by --min-size: 10 B is below the minimum of 100 B\n"));
+        assert!(out.contains("by cargo cache: "));
+        assert!(!out.contains(".hidden"));
+    }
+
+    #[test]
+    fn test_explain_hide_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("app").join("target");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(temp_dir.path().join("app").join(KEEP_MARKER), "").unwrap();
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            cargo_cache_dirs: Vec::new(),
+            hide_kept: true,
+            ..Config::default()
+        };
+        let recorder = Recorder::new(&NoopReporter);
+        let result = scan(&config, &recorder).unwrap();
+        assert_eq!(
+            decided(&target, &config, &result, &recorder.events()),
+            format!("[excluded] --hide-kept: the project has a {KEEP_MARKER} file")
+        );
+    }
+}
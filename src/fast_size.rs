@@ -0,0 +1,234 @@
+//! The `--fast-scan` sizing walk for Linux, which stats each entry relative to its open
+//! directory rather than by its full path, so the kernel doesn't walk the path again for every
+//! file.
+
+use std::{
+    ffi::{CStr, CString},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{fds::FdBudget, stats::IoStats};
+
+/// What sizing needs to know about each file the walk finds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStat {
+    pub len: u64,
+    pub accessed: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+    pub dev: u64,
+    pub ino: u64,
+    pub nlink: u64,
+}
+
+fn timestamp(secs: i64, nanos: i64) -> Option<SystemTime> {
+    let secs = u64::try_from(secs).ok()?;
+    let nanos = u32::try_from(nanos).ok()?;
+    UNIX_EPOCH.checked_add(Duration::new(secs, nanos))
+}
+
+/// An open directory stream, closed when dropped.
+struct Dir(*mut libc::DIR);
+
+impl Dir {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        // SAFETY: `path` is NUL terminated
+        let fd = unsafe {
+            libc::open(
+                path.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // SAFETY: `fd` is an open directory, which the stream takes ownership of
+        let dir = unsafe { libc::fdopendir(fd) };
+        if dir.is_null() {
+            let error = std::io::Error::last_os_error();
+            // SAFETY: fdopendir failed, so `fd` is still ours to close
+            unsafe { libc::close(fd) };
+            return Err(error);
+        }
+        Ok(Self(dir))
+    }
+
+    /// Stats `name` in this directory, without following it if it's a symlink.
+    fn stat(&self, name: &CStr) -> std::io::Result<libc::stat> {
+        // SAFETY: stat is plain old data, which it's fine to start out zeroed
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        // SAFETY: the stream is open, `name` is NUL terminated and `stat` is valid for writes
+        let result = unsafe {
+            libc::fstatat(
+                libc::dirfd(self.0),
+                name.as_ptr(),
+                &mut stat,
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(stat)
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        // SAFETY: the stream is open, and is never used again
+        unsafe { libc::closedir(self.0) };
+    }
+}
+
+/// Calls `visit` with every file under the directory at `path`, returning the total size.
+/// Entries removed between being listed and being looked at are skipped, calling `vanished`.
+///
+/// Like the portable walk this holds one directory handle at a time within `fds`, listing a
+/// directory completely before going into its subdirectories, and doesn't follow symlinks.
+pub fn walk(
+    path: &Path,
+    fds: &FdBudget,
+    stats: &IoStats,
+    visit: &mut dyn FnMut(&FileStat),
+    vanished: &mut dyn FnMut(),
+) -> std::io::Result<u64> {
+    let mut total = 0;
+    let mut subdirs = Vec::new();
+    {
+        let _permit = fds.acquire();
+        stats.count_read_dir();
+        let dir = Dir::open(path)?;
+        loop {
+            // SAFETY: errno is thread local, it's cleared to tell the end from an error
+            unsafe { *libc::__errno_location() = 0 };
+            // SAFETY: the stream is open, the entry is only used before the next readdir
+            let entry = unsafe { libc::readdir(dir.0) };
+            if entry.is_null() {
+                let error = std::io::Error::last_os_error();
+                if error.raw_os_error().is_some_and(|code| code != 0) {
+                    return Err(error);
+                }
+                break;
+            }
+            // SAFETY: readdir returned an entry, whose name is NUL terminated
+            let (name, kind) =
+                unsafe { (CStr::from_ptr((*entry).d_name.as_ptr()), (*entry).d_type) };
+            if matches!(name.to_bytes(), b"." | b"..") {
+                continue;
+            }
+            if kind == libc::DT_DIR {
+                subdirs.push(name.to_bytes().to_vec());
+                continue;
+            }
+            stats.count_metadata();
+            let stat = match dir.stat(name) {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    vanished();
+                    continue;
+                }
+                stat => stat?,
+            };
+            // Filesystems that don't fill in the type need the stat to tell
+            if kind == libc::DT_UNKNOWN && stat.st_mode & libc::S_IFMT == libc::S_IFDIR {
+                subdirs.push(name.to_bytes().to_vec());
+                continue;
+            }
+            // The field types differ between architectures
+            #[allow(clippy::useless_conversion)]
+            let file = FileStat {
+                len: u64::try_from(stat.st_size).unwrap_or(0),
+                accessed: timestamp(stat.st_atime, stat.st_atime_nsec),
+                modified: timestamp(stat.st_mtime, stat.st_mtime_nsec),
+                dev: u64::from(stat.st_dev),
+                ino: u64::from(stat.st_ino),
+                nlink: u64::from(stat.st_nlink),
+            };
+            visit(&file);
+            total += file.len;
+        }
+    }
+    for name in subdirs {
+        let subdir: PathBuf = path.join(std::ffi::OsStr::from_bytes(&name));
+        match walk(&subdir, fds, stats, visit, vanished) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vanished(),
+            walked => total += walked?,
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, report::NoopReporter, scan};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_fast_scan_matches_portable() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("app").join("target");
+        fs::create_dir_all(target.join("debug").join("deps").join("empty")).unwrap();
+        fs::write(target.join("CACHEDIR.TAG"), "Signature").unwrap();
+        fs::write(target.join("debug").join("app"), vec![1u8; 4096]).unwrap();
+        fs::write(target.join("debug").join("deps").join("none"), "").unwrap();
+        fs::write(
+            target.join("debug").join("deps").join("libapp.rlib"),
+            vec![2u8; 12_345],
+        )
+        .unwrap();
+        fs::hard_link(
+            target.join("debug").join("app"),
+            target.join("debug").join("deps").join("app-linked"),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(temp_dir.path(), target.join("debug").join("outside")).unwrap();
+
+        let scan_with = |fast_scan| {
+            let config = Config {
+                roots: vec![temp_dir.path().to_path_buf()],
+                fast_scan,
+                dedupe_hardlinks: true,
+                ..Config::default()
+            };
+            let result = scan(&config, &NoopReporter).unwrap();
+            let info = &result.targets[0];
+            (
+                info.size,
+                info.file_count,
+                info.modified,
+                info.accessed,
+                result.deduplicated_total,
+            )
+        };
+        let portable = scan_with(false);
+        assert_eq!(
+            portable.0,
+            9 + 4096 + 12_345 + 4096 + temp_dir.path().as_os_str().len() as u64
+        );
+        assert_eq!(scan_with(true), portable);
+
+        let mut seen = 0;
+        let total = walk(
+            &target,
+            &FdBudget::default(),
+            &IoStats::new(),
+            &mut |_| seen += 1,
+            &mut || {},
+        )
+        .unwrap();
+        assert_eq!((total, Some(seen)), (portable.0, portable.1));
+        assert!(
+            walk(
+                &target.join("missing"),
+                &FdBudget::default(),
+                &IoStats::new(),
+                &mut |_| {},
+                &mut || {}
+            )
+            .is_err()
+        );
+    }
+}
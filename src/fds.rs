@@ -0,0 +1,71 @@
+use std::sync::{Condvar, Mutex, PoisonError};
+
+/// Limits how many directory handles are open at once across sizing threads.
+///
+/// Each open handle holds a permit, threads wanting one past the limit wait for another to
+/// finish listing its directory.
+#[derive(Debug, Default)]
+pub struct FdBudget {
+    limit: Option<usize>,
+    open: Mutex<Open>,
+    freed: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Open {
+    current: usize,
+    peak: usize,
+}
+
+impl FdBudget {
+    /// A budget of `limit` handles, `None` doesn't limit anything.
+    ///
+    /// A limit of 0 would never let anything through, so it's treated as 1.
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit: limit.map(|limit| limit.max(1)),
+            ..Self::default()
+        }
+    }
+
+    /// Waits until a handle can be opened, the permit gives it back when dropped.
+    pub fn acquire(&self) -> FdPermit<'_> {
+        let mut open = self.open.lock().unwrap_or_else(PoisonError::into_inner);
+        while self.limit.is_some_and(|limit| open.current >= limit) {
+            open = self
+                .freed
+                .wait(open)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        open.current += 1;
+        open.peak = open.peak.max(open.current);
+        FdPermit { budget: self }
+    }
+
+    /// The most handles that were open at the same time.
+    pub fn peak(&self) -> usize {
+        self.open
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .peak
+    }
+}
+
+/// Held while a directory handle is open, see [`FdBudget::acquire`].
+#[derive(Debug)]
+pub struct FdPermit<'a> {
+    budget: &'a FdBudget,
+}
+
+impl Drop for FdPermit<'_> {
+    fn drop(&mut self) {
+        let mut open = self
+            .budget
+            .open
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        open.current -= 1;
+        drop(open);
+        self.budget.freed.notify_one();
+    }
+}
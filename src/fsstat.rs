@@ -0,0 +1,64 @@
+//! What's left on a filesystem, from `statvfs` on Unix: the space `--when-free-below` and
+//! `--loop` check and the free inodes `--report-inodes` shows.
+
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FsStats {
+    /// Bytes an unprivileged user can still write.
+    pub available_bytes: u64,
+    /// Inodes an unprivileged user can still create, where the platform says.
+    pub free_inodes: Option<u64>,
+}
+
+/// The [`FsStats`] of the filesystem holding `path`.
+#[cfg(unix)]
+pub fn fs_stats(path: &Path) -> std::io::Result<FsStats> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: `statvfs` is plain data, all zeroes is a valid value for it to overwrite
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL terminated and `stats` is writable, both outlive the call
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // The field types vary between platforms, they all fit
+    #[allow(clippy::unnecessary_cast)]
+    Ok(FsStats {
+        available_bytes: stats.f_bavail as u64 * stats.f_frsize as u64,
+        free_inodes: Some(stats.f_favail as u64),
+    })
+}
+
+/// The [`FsStats`] of the filesystem holding `path`. There's no inode count to be had here.
+#[cfg(not(unix))]
+pub fn fs_stats(path: &Path) -> std::io::Result<FsStats> {
+    Ok(FsStats {
+        available_bytes: fs4::available_space(path)?,
+        free_inodes: None,
+    })
+}
+
+/// The bytes available on the filesystem holding `path`, as a
+/// [`FreeSpace`](crate::FreeSpace).
+pub fn available_space(path: &Path) -> std::io::Result<u64> {
+    fs_stats(path).map(|stats| stats.available_bytes)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_fs_stats() {
+        let temp_dir = TempDir::new().unwrap();
+        let stats = fs_stats(temp_dir.path()).unwrap();
+        assert!(stats.free_inodes.is_some());
+        let fs4_available = fs4::available_space(temp_dir.path()).unwrap();
+        // Others are writing to the same filesystem, so only roughly the same
+        assert!(stats.available_bytes.abs_diff(fs4_available) < 1 << 30);
+        assert!(fs_stats(&temp_dir.path().join("missing")).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::{fs, path::Path, process::Command};
+
+/// Whether git tracks anything under `path`, such as a `target/` committed by mistake.
+///
+/// Asks `git ls-files` in the parent directory. Without git, or outside a repository, nothing
+/// is tracked.
+pub fn is_tracked(path: &Path) -> bool {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return false;
+    };
+    Command::new("git")
+        .arg("-C")
+        .arg(parent)
+        .args(["ls-files", "-z", "--"])
+        .arg(name)
+        .output()
+        .is_ok_and(|output| output.status.success() && !output.stdout.is_empty())
+}
+
+/// Returns true if `path` makes the directory it's in a work tree: a `.git` directory, or the
+/// `.git` file of a worktree or submodule saying where the repository is.
+fn is_git_marker(path: &Path) -> bool {
+    path.is_dir() || fs::read_to_string(path).is_ok_and(|contents| contents.starts_with("gitdir:"))
+}
+
+/// Whether `project` is inside a git work tree, for `--git-only`, looking for a `.git` in it
+/// and each directory above it up to `root`. Directories inside a repository's own `.git`
+/// aren't in a work tree.
+///
+/// Both paths should be canonical. This looks at the files rather than asking git, so it
+/// costs a stat or two per directory and works without git installed.
+pub fn in_work_tree(project: &Path, root: &Path) -> bool {
+    for dir in project.ancestors() {
+        if dir.file_name().is_some_and(|name| name == ".git") {
+            return false;
+        }
+        if is_git_marker(&dir.join(".git")) {
+            return true;
+        }
+        if dir == root {
+            break;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// Runs git in `dir`, returning false if git isn't installed.
+    fn git(dir: &Path, args: &[&str]) -> bool {
+        Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+
+    #[test]
+    fn test_is_tracked() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path();
+        for name in ["tracked", "ignored"] {
+            fs::create_dir_all(project.join(name).join("debug")).unwrap();
+            fs::write(project.join(name).join("debug").join("artifact"), "x").unwrap();
+        }
+        assert!(!is_tracked(&project.join("tracked")));
+        if !git(project, &["init", "-q"]) {
+            // No git here, which is the no-check case already covered above
+            return;
+        }
+        fs::write(project.join(".gitignore"), "/ignored\n").unwrap();
+        assert!(git(project, &["add", "."]));
+
+        assert!(is_tracked(&project.join("tracked")));
+        assert!(!is_tracked(&project.join("ignored")));
+        assert!(!is_tracked(&project.join("missing")));
+    }
+
+    #[test]
+    fn test_in_work_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        // A repository, with a project nested inside it
+        fs::create_dir_all(root.join("repo").join(".git")).unwrap();
+        fs::create_dir_all(root.join("repo").join("crates").join("app")).unwrap();
+        // A worktree checked out from it, whose `.git` is a file
+        fs::create_dir_all(root.join("feature")).unwrap();
+        fs::write(
+            root.join("feature").join(".git"),
+            format!(
+                "gitdir: {}\n",
+                root.join("repo/.git/worktrees/feature").display()
+            ),
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("scratch")).unwrap();
+        fs::write(root.join("scratch").join(".git"), "not a pointer\n").unwrap();
+        fs::create_dir_all(root.join("repo").join(".git").join("modules").join("dep")).unwrap();
+
+        assert!(in_work_tree(&root.join("repo"), root));
+        assert!(in_work_tree(
+            &root.join("repo").join("crates").join("app"),
+            root
+        ));
+        assert!(in_work_tree(&root.join("feature"), root));
+        assert!(!in_work_tree(&root.join("scratch"), root));
+        assert!(!in_work_tree(
+            &root.join("repo").join(".git").join("modules").join("dep"),
+            root
+        ));
+        // Only up to the root, a repository above it doesn't count
+        assert!(!in_work_tree(
+            &root.join("repo").join("crates").join("app"),
+            &root.join("repo").join("crates")
+        ));
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    collections::HashSet,
+    fs::Metadata,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// Remembers which hardlinked files have been counted, so a grand total can count each inode
+/// once even when its links are spread across several target directories.
+///
+/// Only files with more than one link are tracked. On platforms without inode numbers nothing
+/// is tracked and the deduplicated total is the same as the naive one.
+#[derive(Debug, Default)]
+pub struct Hardlinks {
+    #[cfg_attr(not(unix), allow(dead_code))]
+    seen: Mutex<HashSet<(u64, u64)>>,
+    duplicate_bytes: AtomicU64,
+}
+
+impl Hardlinks {
+    /// Records a file, noting its size as a duplicate if another link was already counted.
+    #[cfg(unix)]
+    pub(crate) fn record(&self, metadata: &Metadata) {
+        use std::os::unix::fs::MetadataExt;
+
+        self.record_link(
+            metadata.dev(),
+            metadata.ino(),
+            metadata.nlink(),
+            metadata.len(),
+        );
+    }
+
+    /// Like [`Hardlinks::record`], for a file `len` bytes long at inode `ino` on `dev`.
+    #[cfg(unix)]
+    pub(crate) fn record_link(&self, dev: u64, ino: u64, nlink: u64, len: u64) {
+        if nlink <= 1 {
+            return;
+        }
+        let first = self
+            .seen
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert((dev, ino));
+        if !first {
+            self.duplicate_bytes.fetch_add(len, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn record(&self, _metadata: &Metadata) {}
+
+    /// Bytes counted more than once by the per-directory sizes.
+    pub fn duplicate_bytes(&self) -> u64 {
+        self.duplicate_bytes.load(Ordering::Relaxed)
+    }
+}
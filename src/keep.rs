@@ -0,0 +1,117 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::paths::{Dirs, create_parent};
+
+/// Target directories the user chose to keep during `--review`, which later runs leave alone.
+///
+/// Stored one path per line in the state file.
+#[derive(Debug, Default)]
+pub struct KeepList {
+    file: PathBuf,
+    paths: BTreeSet<PathBuf>,
+}
+
+impl KeepList {
+    /// Reads the list from `file`, which not existing yet just means nothing is kept.
+    pub fn load(file: &Path) -> std::io::Result<Self> {
+        let contents = match fs::read_to_string(file) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            file: file.to_path_buf(),
+            paths: contents
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(PathBuf::from)
+                .collect(),
+        })
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.paths.contains(&canonical(path))
+    }
+
+    pub fn insert(&mut self, path: &Path) {
+        self.paths.insert(canonical(path));
+    }
+
+    /// Writes the list back, creating the state directory if needed.
+    pub fn save(&self) -> std::io::Result<()> {
+        create_parent(&self.file)?;
+        let mut contents = String::new();
+        for path in &self.paths {
+            contents.push_str(&path.to_string_lossy());
+            contents.push('\n');
+        }
+        // Write then rename, so an interrupted save can't lose earlier decisions
+        let partial = self.file.with_extension("partial");
+        fs::write(&partial, contents)?;
+        fs::rename(&partial, &self.file)
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    crate::unc::canonical_or_same(path)
+}
+
+/// Where keep decisions are stored by default, `keep` in the state directory.
+pub fn default_keep_file(dirs: &Dirs) -> Option<PathBuf> {
+    Some(dirs.state.as_ref()?.join("keep"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_keep_list_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("state").join("keep");
+        let kept = temp_dir.path().join("project").join("target");
+        fs::create_dir_all(&kept).unwrap();
+
+        let mut list = KeepList::load(&file).unwrap();
+        assert!(!list.contains(&kept));
+        list.insert(&kept);
+        list.save().unwrap();
+
+        let list = KeepList::load(&file).unwrap();
+        assert!(list.contains(&kept));
+        assert!(!list.contains(&temp_dir.path().join("other").join("target")));
+    }
+
+    #[test]
+    fn test_default_keep_file() {
+        let env = |name: &str| match name {
+            "XDG_STATE_HOME" => Some("/state".to_string()),
+            "HOME" => Some("/home/user".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            default_keep_file(&Dirs::resolve(None, None, env)),
+            Some(PathBuf::from("/state/clean-big-targets/keep"))
+        );
+        let env = |name: &str| (name == "HOME").then(|| "/home/user".to_string());
+        assert_eq!(
+            default_keep_file(&Dirs::resolve(None, None, env)),
+            Some(PathBuf::from(
+                "/home/user/.local/state/clean-big-targets/keep"
+            ))
+        );
+        assert_eq!(
+            default_keep_file(&Dirs::resolve(None, Some(Path::new("/data")), env)),
+            Some(PathBuf::from("/data/keep"))
+        );
+        assert_eq!(
+            default_keep_file(&Dirs::resolve(None, None, |_| None)),
+            None
+        );
+    }
+}
@@ -1,270 +1,5927 @@
 use std::{
+    cell::Cell,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
     fs,
-    io::IsTerminal,
+    io::Write,
     path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::{Duration, SystemTime},
 };
 
-use clap::Parser;
-use dialoguer::MultiSelect;
+use clap::{ArgAction, CommandFactory, Parser, Subcommand, ValueEnum, builder::BoolishValueParser};
+use dialoguer::{Confirm, MultiSelect, Select};
 use humanize_bytes::humanize_bytes_decimal;
+use rayon::prelude::*;
+use regex::Regex;
+
+pub mod biggest;
+pub mod cargo_home;
+pub mod checkpoint;
+pub mod config;
+pub mod deadline;
+pub mod dedup;
+pub mod deletion_log;
+pub mod doctor;
+pub mod drives;
+pub mod estimate;
+pub mod explain;
+#[cfg(target_os = "linux")]
+pub mod fast_size;
+pub mod fds;
+pub mod fsstat;
+pub mod git;
+pub mod hardlinks;
+pub mod keep;
+pub mod manifest;
+pub mod meta;
+pub mod mounts;
+pub mod output;
+pub mod paths;
+pub mod permissions;
+pub mod plan;
+pub mod priority;
+pub mod progress;
+pub mod project;
+pub mod protect;
+pub mod relocate;
+pub mod report;
+pub mod restore;
+pub mod rustc;
+pub mod session;
+pub mod size_cache;
+pub mod stats;
+pub mod status;
+pub mod submodules;
+pub mod systemd;
+pub mod tree;
+pub mod triples;
+pub mod unc;
+pub mod walk;
+pub mod wasm;
+
+use checkpoint::{Checkpoint, CheckpointEntry, load_checkpoint};
+use config::Config;
+use deadline::{Deadline, is_expired, timed_out_warning};
+use dedup::Fingerprint;
+use deletion_log::{DeletionLog, LogKind};
+use estimate::{SAMPLE_SIZE, approximate_size};
+use fds::FdBudget;
+use fsstat::{FsStats, fs_stats};
+use hardlinks::Hardlinks;
+use keep::KeepList;
+use manifest::{CrateDetails, crate_details};
+use mounts::{Mount, MountResolver, noatime_mount};
+use output::{
+    AgeFormat, ColorChoice, Output, OutputFormat, StripPrefix,
+    fixed::{DEFAULT_PATH_WIDTH, parse_path_width},
+    tiers::{TierBounds, parse_tier_bounds},
+};
+use permissions::{Identity, skip_without_permission};
+use priority::{parse_age_weight, sort_by_priority};
+use progress::{SizeProgress, Tracker};
+use protect::{PROTECT_MARKER, mark_protected, refuse_protected};
+use report::{Event, PruneReason, Reporter};
+use rustc::{RustcInfo, RustcVersion, installed_toolchains, is_stale, read_rustc_info};
+use size_cache::{DirListing, SizeCache};
+use stats::IoStats;
+use status::StatusLineMode;
+use submodules::find_submodule_targets;
+use tree::SizeTree;
+use unc::shown;
+use walk::{ScanOptions, TargetWalker};
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Cli {
-    #[clap(short, long)]
-    pub debug: bool,
-    #[clap(default_value = ".", env = "CLEAN_BIG_TARGETS_DIR")]
-    pub target_dir: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Print what the scan does to stderr, given twice (`-vv`) also as --explain-all
+    #[clap(short, long, short_alias = 'v', alias = "verbose", action = ArgAction::Count)]
+    pub debug: u8,
+    /// Directories to scan, added to any in CLEAN_BIG_TARGETS_DIRS (falls back to
+    /// CLEAN_BIG_TARGETS_DIR, then the current directory)
+    #[clap(value_name = "TARGET_DIR")]
+    pub target_dirs: Vec<PathBuf>,
     #[clap(short = 'D', long)]
     pub delete: bool,
     #[clap(long, requires = "delete")]
     pub force: bool,
+    /// Ask about each directory in turn: delete it, keep it (and never ask again) or skip it
+    /// for now
+    #[clap(long, requires = "delete", conflicts_with = "force")]
+    pub review: bool,
+    /// After each round of deleting, scan again and prompt again with the new sizes and free
+    /// space, until Quit is picked or Escape is pressed
+    #[clap(long = "loop", requires = "delete", conflicts_with = "force")]
+    pub loop_mode: bool,
+    /// Where `--review` remembers directories to keep (defaults to `keep` in `--data-dir`, or
+    /// `$XDG_STATE_HOME/clean-big-targets/keep`)
+    #[clap(long, value_name = "PATH", env = "CLEAN_BIG_TARGETS_STATE_FILE")]
+    pub state_file: Option<PathBuf>,
+    /// Where to keep things that can be worked out again, such as the `--resume-scan`
+    /// checkpoint [default: $XDG_CACHE_HOME/clean-big-targets]
+    #[clap(long, value_name = "DIR", env = "CLEAN_BIG_TARGETS_CACHE_DIR")]
+    pub cache_dir: Option<PathBuf>,
+    /// Where to keep records and state between runs, such as `--review`'s keep list
+    /// [default: $XDG_DATA_HOME/clean-big-targets, with state in $XDG_STATE_HOME/clean-big-targets]
+    #[clap(long, value_name = "DIR", env = "CLEAN_BIG_TARGETS_DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+    /// Stop scanning after this many target directories have been found
+    #[clap(long, value_name = "N", env = "CLEAN_BIG_TARGETS_MAX_RESULTS")]
+    pub max_results: Option<usize>,
+    /// Stop finding and sizing target directories after this long, such as `60s`, showing those
+    /// sized in full and exiting with 124. Nothing is deleted after a scan cut short
+    #[clap(long, value_name = "DURATION", value_parser = parse_duration, env = "CLEAN_BIG_TARGETS_TIMEOUT")]
+    pub timeout: Option<Duration>,
+    /// Order to list (and delete) target directories in
+    #[clap(long, alias = "rank-by", value_enum, default_value_t = SortOrder::SizeDesc, env = "CLEAN_BIG_TARGETS_SORT")]
+    pub sort: SortOrder,
+    /// For `--sort priority`, the power age in days is raised to before multiplying by the size:
+    /// 0 is size alone, higher favours stale targets more
+    #[clap(
+        long,
+        value_name = "WEIGHT",
+        default_value_t = priority::DEFAULT_AGE_WEIGHT,
+        value_parser = parse_age_weight,
+        env = "CLEAN_BIG_TARGETS_PRIORITY_AGE_WEIGHT"
+    )]
+    pub priority_age_weight: f64,
+    /// Which timestamp says how old a target is, as `--show-extremes` and `--porcelain` show it
+    #[clap(long, value_enum, default_value_t = AgeSource::NewestArtifact, env = "CLEAN_BIG_TARGETS_AGE_FROM")]
+    pub age_from: AgeSource,
+    /// When to colorize output, overrides CLICOLOR_FORCE and NO_COLOR
+    #[clap(long, value_enum, default_value_t = ColorChoice::Auto, env = "CLEAN_BIG_TARGETS_COLOR")]
+    pub color: ColorChoice,
+    /// How to show times in the table, as how long ago or as UTC timestamps
+    #[clap(long, value_enum, default_value_t = AgeFormat::Relative, env = "CLEAN_BIG_TARGETS_AGE_FORMAT")]
+    pub age_format: AgeFormat,
+    /// How to present the results
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Table,
+        conflicts_with = "delete",
+        alias = "output"
+    )]
+    pub format: OutputFormat,
+    /// Group the results into size tiers with subtotals, short for `--format tiers`
+    #[clap(long, conflicts_with_all = ["delete", "format", "porcelain"])]
+    pub tiers: bool,
+    /// Do nothing unless a scanned directory's filesystem has less than SIZE free, such as
+    /// `20GB`, for scheduled cleanups
+    #[clap(long, value_name = "SIZE", value_parser = parse_size, env = "CLEAN_BIG_TARGETS_WHEN_FREE_BELOW")]
+    pub when_free_below: Option<u64>,
+    /// Only show target directories of at least SIZE, such as `100MB`
+    #[clap(long, value_name = "SIZE", value_parser = parse_size, env = "CLEAN_BIG_TARGETS_MIN_SIZE")]
+    pub min_size: Option<u64>,
+    /// Only show target directories of at most SIZE, such as `1GB`, to clean up lots of small
+    /// ones while leaving the big ones alone
+    #[clap(long, value_name = "SIZE", value_parser = parse_size, env = "CLEAN_BIG_TARGETS_MAX_SIZE")]
+    pub max_size: Option<u64>,
+    /// Only show target directories with at least N files, leaving out stubs from a single
+    /// `cargo check`
+    #[clap(long, value_name = "N", env = "CLEAN_BIG_TARGETS_MIN_FILES")]
+    pub min_files: Option<u64>,
+    /// Only show target directories with at most N files
+    #[clap(long, value_name = "N", env = "CLEAN_BIG_TARGETS_MAX_FILES")]
+    pub max_files: Option<u64>,
+    /// Only show target directories whose files haven't been read for this long, such as `30d`
+    /// (directories on filesystems without access times are always shown)
+    #[clap(long, value_name = "DURATION", value_parser = parse_duration, env = "CLEAN_BIG_TARGETS_UNUSED_FOR")]
+    pub unused_for: Option<Duration>,
+    /// Only show target directories with no file modified since PATH was, such as a file
+    /// touched when the last release was tagged
+    #[clap(long, value_name = "PATH", env = "CLEAN_BIG_TARGETS_OLDER_THAN_FILE")]
+    pub older_than_file: Option<PathBuf>,
+    /// Add a LAST ACCESSED column to the table, from the newest file access time in each target
+    #[clap(long, env = "CLEAN_BIG_TARGETS_SHOW_ACCESSED", value_parser = BoolishValueParser::new())]
+    pub show_accessed: bool,
+    /// Only show target directories built by a rustc older than this, such as `1.80.0`
+    /// (directories with no `.rustc_info.json` are left out)
+    #[clap(long, value_name = "VERSION", value_parser = RustcVersion::parse, env = "CLEAN_BIG_TARGETS_BUILT_BEFORE_RUSTC")]
+    pub built_before_rustc: Option<RustcVersion>,
+    /// Only show target directories built by a rustc that rustup no longer has installed
+    #[clap(long, env = "CLEAN_BIG_TARGETS_STALE_TOOLCHAINS_ONLY", value_parser = BoolishValueParser::new())]
+    pub stale_toolchains_only: bool,
+    /// Add NAME and VERSION columns from the `Cargo.toml` next to each target, RUSTC from the
+    /// compiler that built it and MOUNT from the filesystem it's on
+    #[clap(long, env = "CLEAN_BIG_TARGETS_DETAILS", value_parser = BoolishValueParser::new())]
+    pub details: bool,
+    /// Only show target directories on the same filesystem as PATH
+    #[clap(long, value_name = "PATH", env = "CLEAN_BIG_TARGETS_MOUNT")]
+    pub mount: Option<PathBuf>,
+    /// After each path in the table, show where it really is when symlinks are involved
+    #[clap(long, env = "CLEAN_BIG_TARGETS_SHOW_CANONICAL", value_parser = BoolishValueParser::new())]
+    pub show_canonical: bool,
+    /// In the table, du, tiers and HTML output, show paths under PREFIX with it replaced by
+    /// REPLACEMENT, or removed if there's no `=REPLACEMENT`, such as `/home/alice=~`. Can be
+    /// given multiple times, the longest matching prefix is used
+    #[clap(long, value_name = "PREFIX[=REPLACEMENT]", value_parser = StripPrefix::parse)]
+    pub strip_prefix: Vec<StripPrefix>,
+    /// Head the table with TEXT instead of "Target directories (sorted by size, ...):"
+    #[clap(long, value_name = "TEXT", env = "CLEAN_BIG_TARGETS_TITLE")]
+    pub title: Option<String>,
+    /// Leave the title, column names and the rule under them off the table
+    #[clap(long, env = "CLEAN_BIG_TARGETS_NO_HEADER", value_parser = BoolishValueParser::new())]
+    pub no_header: bool,
+    /// End `--format du` and `du-bytes` output with a total line, like `du -c`
+    #[clap(long)]
+    pub du_total: bool,
+    /// Width of the path column for `--format table-no-color-width`, longer paths lose their
+    /// start
+    #[clap(
+        long,
+        value_name = "CHARS",
+        value_parser = parse_path_width,
+        default_value_t = DEFAULT_PATH_WIDTH,
+        env = "CLEAN_BIG_TARGETS_PATH_WIDTH"
+    )]
+    pub path_width: usize,
+    /// Lower bounds of the Huge, Large and Medium tiers for `--format tiers`, largest first
+    #[clap(
+        long,
+        value_name = "SIZES",
+        value_parser = parse_tier_bounds,
+        default_value = "10GB,1GB,100MB",
+        env = "CLEAN_BIG_TARGETS_TIER_BOUNDS"
+    )]
+    pub tier_bounds: TierBounds,
+    /// Delete the directories in FILE, a plan written by `--format jsonl-plan`, without
+    /// prompting but with the usual safety checks
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["delete", "format", "porcelain"])]
+    pub execute_plan: Option<PathBuf>,
+    /// Use the targets in FILE, written by `--format json`, instead of scanning. Each is checked
+    /// again and sized again first, so a scan can be reviewed before a later `--delete`
+    #[clap(long, value_name = "FILE", conflicts_with = "execute_plan")]
+    pub from_json: Option<PathBuf>,
+    /// Print just the number of target directories found, without sizing them. Options that
+    /// need sizes or access times, such as `--min-size` and `--unused-for`, don't apply
+    #[clap(long, conflicts_with_all = ["delete", "format", "porcelain", "list_triples", "from_json"])]
+    pub count_only: bool,
+    /// Print just the size of the directory at PATH, like `du -sh`, without looking for targets
+    #[clap(long, value_name = "PATH", conflicts_with_all = ["delete", "execute_plan", "from_json"])]
+    pub size_of: Option<PathBuf>,
+    /// Warn about targets from `--from-json` that have grown by more than PERCENT since the scan
+    #[clap(
+        long,
+        value_name = "PERCENT",
+        default_value_t = 10,
+        requires = "from_json"
+    )]
+    pub max_growth: u64,
+    /// Leave the `meta` object with the hostname, username, roots, version, time and options
+    /// out of `--format json`
+    #[clap(long, env = "CLEAN_BIG_TARGETS_NO_META", value_parser = BoolishValueParser::new())]
+    pub no_meta: bool,
+    /// On Windows, don't clear the read-only attribute on files in a target and retry when
+    /// they stop it being deleted
+    #[clap(
+        long,
+        env = "CLEAN_BIG_TARGETS_NO_CLEAR_READONLY",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub no_clear_readonly: bool,
+    /// When to end with a single `clean-big-targets: found=N ...` line on stderr for scripts
+    #[clap(long, value_enum, default_value_t = StatusLineMode::Auto, env = "CLEAN_BIG_TARGETS_STATUS_LINE")]
+    pub status_line: StatusLineMode,
+    /// End with a single JSON object on stderr saying what was found and deleted, whatever the
+    /// output format
+    #[clap(long, env = "CLEAN_BIG_TARGETS_SUMMARY_JSON", value_parser = BoolishValueParser::new())]
+    pub summary_json: bool,
+    /// Print results in a stable, script-friendly format.
+    ///
+    /// Each line is `<size_bytes>\t<epoch_mtime>\t<kind>\t<path>`, where `epoch_mtime` is
+    /// seconds since the Unix epoch (or `-` if unknown) and backslash, tab, newline, carriage
+    /// return and invalid UTF-8 bytes in the path are escaped as `\\`, `\t`, `\n`, `\r` and
+    /// `\xNN`. This format will not change between releases and ignores `--color`.
+    #[clap(long, conflicts_with_all = ["delete", "format"])]
+    pub porcelain: bool,
+    /// Label target directories with no entries at all as "(empty)" rather than "0 B"
+    #[clap(long, env = "CLEAN_BIG_TARGETS_REPORT_EMPTY", value_parser = BoolishValueParser::new())]
+    pub report_empty: bool,
+    /// Add size distribution statistics (median, p90, standard deviation, buckets) to the summary,
+    /// or a `stats` object with --format json
+    #[clap(long, env = "CLEAN_BIG_TARGETS_STATS", value_parser = BoolishValueParser::new())]
+    pub stats: bool,
+    /// After the table, show the least and most recently built target directories
+    #[clap(long, env = "CLEAN_BIG_TARGETS_SHOW_EXTREMES", value_parser = BoolishValueParser::new())]
+    pub show_extremes: bool,
+    /// After the table, group target directories whose files all have the same names and
+    /// sizes, as they're likely copies. Slower, as every file's name is hashed while sizing
+    #[clap(long, env = "CLEAN_BIG_TARGETS_DEDUP_REPORT", value_parser = BoolishValueParser::new())]
+    pub dedup_report: bool,
+    /// Number of threads to use when calculating sizes (defaults to one per CPU)
+    #[clap(long, value_name = "N", env = "CLEAN_BIG_TARGETS_THREADS")]
+    pub threads: Option<usize>,
+    /// Most directory handles to keep open at once while sizing, for systems with a low
+    /// `ulimit -n` (defaults to one per sizing thread)
+    #[clap(long, value_name = "N", env = "CLEAN_BIG_TARGETS_MAX_OPEN_FDS")]
+    pub max_open_fds: Option<usize>,
+    /// Number of directories to delete in parallel, separate from the sizing threads. They're
+    /// still started in the order they're deleted in
+    #[clap(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_DELETE_JOBS,
+        alias = "delete-threads",
+        env = "CLEAN_BIG_TARGETS_DELETE_JOBS"
+    )]
+    pub delete_jobs: usize,
+    /// Order to delete in, instead of following `--sort`
+    #[clap(long, value_enum, requires = "delete")]
+    pub delete_order: Option<DeleteOrder>,
+    /// When deleting, keep the N most recently modified subdirectories of each target (such as
+    /// build profiles) and only remove the rest
+    #[clap(long, value_name = "N", requires = "delete")]
+    pub keep_newest: Option<usize>,
+    /// Move the selected targets into DIR instead of deleting them, each named after its
+    /// project such as `app-target`
+    #[clap(
+        long,
+        value_name = "DIR",
+        requires = "delete",
+        conflicts_with = "keep_newest"
+    )]
+    pub move_to: Option<PathBuf>,
+    /// Go through deleting without removing anything, saying (and logging with `--log-file`)
+    /// what would have been deleted
+    #[clap(long, requires = "delete")]
+    pub dry_run: bool,
+    /// Append a line for each directory deleted to FILE, with the time, what was done, the
+    /// size and the path, tab separated
+    #[clap(long, value_name = "FILE", env = "CLEAN_BIG_TARGETS_LOG_FILE")]
+    pub log_file: Option<PathBuf>,
+    /// After deleting, show the free inodes on the filesystem before and after, where the
+    /// platform can say (not on Windows)
+    #[clap(long, requires = "delete")]
+    pub report_inodes: bool,
+    /// Include directories inside the cargo and rustup caches (registry, git checkouts, toolchains)
+    #[clap(
+        long,
+        env = "CLEAN_BIG_TARGETS_INCLUDE_CARGO_CACHE",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub include_cargo_cache: bool,
+    /// When prompting, delete directories with fewer than N files without asking, as they're
+    /// cheap to rebuild
+    #[clap(long, value_name = "N", env = "CLEAN_BIG_TARGETS_AUTO_UNDER_FILES")]
+    pub auto_under_files: Option<u64>,
+    /// Start the deletion prompt with directories whose path matches this regex selected, can be
+    /// given multiple times
+    #[clap(long, value_name = "REGEX", value_parser = Regex::new)]
+    pub preselect: Vec<Regex>,
+    /// Match `target` directory names ignoring case (the default on Windows and macOS)
+    #[clap(
+        long,
+        visible_alias = "ignore-case",
+        env = "CLEAN_BIG_TARGETS_CASE_INSENSITIVE",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub case_insensitive: bool,
+    /// Match the globs in the config file's `protected` list ignoring case
+    #[clap(
+        long,
+        env = "CLEAN_BIG_TARGETS_GLOB_IGNORE_CASE",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub glob_ignore_case: bool,
+    /// Also look in directories whose names start with a dot, such as `.local` or `.backup`.
+    /// A hidden directory given as a root is always looked in
+    #[clap(long, env = "CLEAN_BIG_TARGETS_HIDDEN", value_parser = BoolishValueParser::new())]
+    pub hidden: bool,
+    /// Only consider targets whose project is in a git work tree, with a `.git` directory (or
+    /// a worktree's `.git` file) in it or above it within the scanned directory
+    #[clap(long, env = "CLEAN_BIG_TARGETS_GIT_ONLY", value_parser = BoolishValueParser::new())]
+    pub git_only: bool,
+    /// Also report a grand total that counts files hardlinked between target directories once
+    /// (each directory's own size still counts all its files)
+    #[clap(
+        long,
+        env = "CLEAN_BIG_TARGETS_DEDUPE_HARDLINKS_ACROSS_DIRS",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub dedupe_hardlinks_across_dirs: bool,
+    /// Size directories with a faster walk that stats each file relative to its directory
+    /// (Linux only, elsewhere this does nothing)
+    #[clap(long, env = "CLEAN_BIG_TARGETS_FAST_SCAN", value_parser = BoolishValueParser::new())]
+    pub fast_scan: bool,
+    /// Estimate each size from a sample of the files and subdirectories in every directory,
+    /// for a quick look at a slow filesystem. Estimates are marked `~`, and are sized exactly
+    /// before anything is deleted
+    #[clap(long, env = "CLEAN_BIG_TARGETS_APPROXIMATE", value_parser = BoolishValueParser::new())]
+    pub approximate: bool,
+    /// Remember what each directory held between the scans of one run, so `--loop` only looks
+    /// inside directories whose modification time changed
+    #[clap(long, env = "CLEAN_BIG_TARGETS_SIZE_CACHE", value_parser = BoolishValueParser::new())]
+    pub size_cache: bool,
+    /// Add the sizes of the caches in CARGO_HOME to the report, with a total covering them and
+    /// the target directories, or a `cargo_home` object with --format json
+    #[clap(long, env = "CLEAN_BIG_TARGETS_WITH_CARGO_HOME_SUMMARY", value_parser = BoolishValueParser::new())]
+    pub with_cargo_home_summary: bool,
+    /// Also find wasm-pack `pkg` and trunk `dist` output next to each target directory
+    #[clap(long, env = "CLEAN_BIG_TARGETS_WASM", value_parser = BoolishValueParser::new())]
+    pub wasm: bool,
+    /// Only look at the generated documentation in `target/doc`, so listing and deleting
+    /// reclaim the docs but leave compiled artifacts alone, like `cargo clean --doc`
+    #[clap(long, env = "CLEAN_BIG_TARGETS_DOCS_ONLY", value_parser = BoolishValueParser::new())]
+    pub docs_only: bool,
+    /// Only look at the `target/<TRIPLE>` subdirectories for this target triple, such as
+    /// `wasm32-unknown-unknown`, can be given multiple times
+    #[clap(long, value_name = "TRIPLE", value_parser = triples::parse_triple)]
+    pub triple: Vec<String>,
+    /// List the target triples built for across the target directories, with their sizes
+    #[clap(long, conflicts_with_all = ["delete", "triple"])]
+    pub list_triples: bool,
+    /// Save each directory's size to the checkpoint file as it's worked out, and skip the ones
+    /// already there from an interrupted run of the same scan
+    #[clap(long, env = "CLEAN_BIG_TARGETS_RESUME_SCAN", value_parser = BoolishValueParser::new())]
+    pub resume_scan: bool,
+    /// Where --resume-scan keeps sizes [default: scan-checkpoint in --cache-dir]
+    #[clap(long, value_name = "FILE", requires = "resume_scan")]
+    pub checkpoint: Option<PathBuf>,
+    /// Delete target directories even if git tracks files in them, which --force otherwise skips
+    #[clap(long, requires = "delete")]
+    pub delete_tracked: bool,
+    /// Never delete target directories bigger than SIZE, such as `100GB`, to protect a big
+    /// shared cache while sweeping the rest. They're still listed
+    #[clap(long, value_name = "SIZE", value_parser = parse_size, env = "CLEAN_BIG_TARGETS_EXCLUDE_LARGER_THAN")]
+    pub exclude_larger_than: Option<u64>,
+    /// Delete targets in projects with a `.clean-big-targets-keep` file too, which are otherwise
+    /// never deleted
+    #[clap(long, env = "CLEAN_BIG_TARGETS_IGNORE_KEEP_MARKERS", value_parser = BoolishValueParser::new())]
+    pub ignore_keep_markers: bool,
+    /// Don't list targets in projects with a `.clean-big-targets-keep` file at all
+    #[clap(long, env = "CLEAN_BIG_TARGETS_HIDE_KEPT", value_parser = BoolishValueParser::new())]
+    pub hide_kept: bool,
+    /// Scan every fixed local drive instead of the given directories, with a subtotal for each
+    /// (Windows only)
+    #[clap(long, env = "CLEAN_BIG_TARGETS_ALL_DRIVES", value_parser = BoolishValueParser::new())]
+    pub all_drives: bool,
+    /// Print the environment variables that can stand in for flags, then exit
+    #[clap(long)]
+    pub list_env: bool,
+    /// Scan as usual, then print each rule `PATH` went through and whether it was left out or
+    /// listed, instead of the listing
+    #[clap(long, value_name = "PATH", conflicts_with_all = ["delete", "count_only", "from_json"])]
+    pub explain: Option<PathBuf>,
+    /// Print to stderr why each target directory found was left out or listed
+    #[clap(long)]
+    pub explain_all: bool,
+}
+
+impl Cli {
+    /// How to present the results, from `--format` or `--tiers`.
+    pub fn output_format(&self) -> OutputFormat {
+        if self.tiers {
+            OutputFormat::Tiers
+        } else {
+            self.format
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Print (or install) a systemd service and timer that run this tool on a schedule
+    GenerateUnit {
+        /// When to run, as a systemd calendar expression such as `weekly` or `Sun *-*-* 03:00`
+        #[clap(long, default_value = "weekly")]
+        schedule: String,
+        /// Arguments to run the tool with, as a single shell-quoted string
+        #[clap(long, default_value = "", allow_hyphen_values = true)]
+        args: String,
+        /// Write the units to the systemd user unit directory instead of printing them
+        #[clap(long)]
+        install_user: bool,
+    },
+    /// Delete the target directory of one project, without scanning for others
+    ///
+    /// Honors CARGO_TARGET_DIR and `build.target-dir` in `.cargo/config.toml`. Asks before
+    /// deleting unless given --force, as --delete does.
+    CleanProject {
+        /// The project directory, the one containing `Cargo.toml`
+        path: PathBuf,
+        /// Delete without asking
+        #[clap(long)]
+        force: bool,
+        /// Ask about the directory as --review does, and remember a keep
+        #[clap(long, conflicts_with = "force")]
+        review: bool,
+        /// Show what would be deleted without deleting anything
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// List the biggest single files across every target directory found, biggest first
+    BiggestFiles {
+        /// How many files to list
+        #[clap(long, value_name = "N", default_value_t = biggest::DEFAULT_TOP)]
+        top: usize,
+        /// Directories to scan, as for the command without a subcommand
+        #[clap(value_name = "TARGET_DIR")]
+        target_dirs: Vec<PathBuf>,
+    },
+    /// Check the environment for what could make a scan slow or find nothing
+    ///
+    /// Covers the terminal, the filesystem and whether it's a network mount, the open files
+    /// limit, the tools on PATH, CARGO_TARGET_DIR, how quickly the first level lists and
+    /// whether any target directories are near.
+    Doctor {
+        /// The directory a scan would start from
+        #[clap(default_value = ".")]
+        path: PathBuf,
+        /// Print the checks as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Move back everything the last run with --move-to moved, where it was
+    ///
+    /// Each run's moves are recorded in `moves` in the state directory, so running this again
+    /// undoes the run before.
+    RestoreLast {
+        /// Where the moves were recorded, as for the command without a subcommand
+        #[clap(long, value_name = "DIR", env = "CLEAN_BIG_TARGETS_DATA_DIR")]
+        data_dir: Option<PathBuf>,
+    },
+}
+
+/// An environment variable that stands in for a command-line option, see [`env_vars`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnvVar {
+    pub name: String,
+    /// The option it stands in for, such as `--threads`.
+    pub option: String,
+    pub help: String,
+}
+
+/// Every environment variable this tool reads in place of an option.
+///
+/// Built from the [`Cli`] definitions, so it can't drift from them. An option given on the
+/// command line always wins over its variable, which wins over the default. Deleting can't be
+/// turned on from the environment, and options that require or conflict with others (like
+/// `--format`) have no variable since clap would treat it as given, even as `0`. The conflicts
+/// between options that do have one are left to [`conflicting_options`].
+pub fn env_vars() -> Vec<EnvVar> {
+    // Roots are merged by `resolve_roots` rather than clap, so they're the only ones listed here
+    let mut vars = vec![
+        EnvVar {
+            name: "CLEAN_BIG_TARGETS_DIRS".to_string(),
+            option: "TARGET_DIR".to_string(),
+            help: "Directories to scan as well as TARGET_DIR, separated like PATH".to_string(),
+        },
+        EnvVar {
+            name: "CLEAN_BIG_TARGETS_DIR".to_string(),
+            option: "TARGET_DIR".to_string(),
+            help: "Directory to scan when neither TARGET_DIR nor CLEAN_BIG_TARGETS_DIRS is set"
+                .to_string(),
+        },
+    ];
+    let command = Cli::command();
+    for arg in command.get_arguments() {
+        let (Some(name), Some(long)) = (arg.get_env(), arg.get_long()) else {
+            continue;
+        };
+        let help = arg
+            .get_help()
+            .map(|help| help.to_string())
+            .unwrap_or_default();
+        vars.push(EnvVar {
+            name: name.to_string_lossy().into_owned(),
+            option: format!("--{long}"),
+            help: help.lines().next().unwrap_or_default().to_string(),
+        });
+    }
+    vars
+}
+
+/// The first pair of options in `cli` that can't be used together, checked once their values
+/// are known so one turned off in the environment doesn't count.
+pub fn conflicting_options(cli: &Cli) -> Option<(&'static str, &'static str)> {
+    // Whether an option was given, and its name
+    type Given = (bool, &'static str);
+    let exact_sizing = [
+        (cli.fast_scan, "--fast-scan"),
+        (cli.dedup_report, "--dedup-report"),
+        (
+            cli.dedupe_hardlinks_across_dirs,
+            "--dedupe-hardlinks-across-dirs",
+        ),
+    ];
+    let approximate = [(cli.approximate, "--approximate")];
+    let options: [(Given, &[Given]); 6] = [
+        (
+            (cli.docs_only, "--docs-only"),
+            &[(!cli.triple.is_empty(), "--triple"), (cli.wasm, "--wasm")],
+        ),
+        (
+            (cli.no_header, "--no-header"),
+            &[(cli.title.is_some(), "--title")],
+        ),
+        (
+            (cli.older_than_file.is_some(), "--older-than-file"),
+            &approximate,
+        ),
+        (
+            (cli.approximate, "--approximate"),
+            &[
+                exact_sizing.as_slice(),
+                &[(cli.resume_scan, "--resume-scan")],
+            ]
+            .concat(),
+        ),
+        (
+            (cli.size_cache, "--size-cache"),
+            &[approximate.as_slice(), &exact_sizing].concat(),
+        ),
+        (
+            (cli.hide_kept, "--hide-kept"),
+            &[(cli.ignore_keep_markers, "--ignore-keep-markers")],
+        ),
+    ];
+    options
+        .iter()
+        .filter(|((given, _), _)| *given)
+        .find_map(|&((_, option), others)| {
+            let &(_, other) = others.iter().find(|(given, _)| *given)?;
+            Some((option, other))
+        })
+}
+
+/// The order directories are handed to the remover in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DeleteOrder {
+    /// Reclaim the most space first, in case the run is interrupted
+    LargestFirst,
+    SmallestFirst,
+    /// Alphabetically by path
+    Path,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SortOrder {
+    /// Largest first
+    #[default]
+    SizeDesc,
+    /// Smallest first
+    SizeAsc,
+    /// Biggest and stalest first, by size × age in days ^ `--priority-age-weight`
+    Priority,
+    /// Most files first, for filesystems that run out of inodes before space
+    Files,
+    /// Most bytes per file first, the quickest to delete for the space they free
+    ReclaimablePerFile,
+}
+
+/// Which timestamp says how old a target is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum AgeSource {
+    /// The target directory's own modification time, which trivial operations change too
+    Dir,
+    /// The newest file inside, which is when it was last built
+    #[default]
+    NewestArtifact,
+    /// The project's `Cargo.lock`, which is when its dependencies last changed
+    CargoLock,
+    /// The newest access time inside, which running without rebuilding changes too, though
+    /// only on filesystems that update access times
+    Accessed,
+}
+
+/// What sort of build output directory this is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TargetKind {
+    /// A cargo `target` directory
+    #[default]
+    Cargo,
+    /// The `<TRIPLE>` subdirectory of a cargo `target` directory, from `--triple`
+    Triple,
+    /// wasm-pack's `pkg` output next to a `target` directory, from `--wasm`
+    WasmPack,
+    /// trunk's `dist` output next to a `target` directory, from `--wasm`
+    Trunk,
+    /// The generated documentation in a cargo `target` directory, from `--docs-only`
+    Doc,
+}
+
+impl TargetKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TargetKind::Cargo => "cargo",
+            TargetKind::Triple => "triple",
+            TargetKind::WasmPack => "wasm-pack",
+            TargetKind::Trunk => "trunk",
+            TargetKind::Doc => "doc",
+        }
+    }
+
+    /// The kind [`TargetKind::as_str`] gives `name` for.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            TargetKind::Cargo,
+            TargetKind::Triple,
+            TargetKind::WasmPack,
+            TargetKind::Trunk,
+            TargetKind::Doc,
+        ]
+        .into_iter()
+        .find(|kind| kind.as_str() == name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TargetDirInfo {
+    pub path: PathBuf,
+    pub size: u64,
+    /// When the directory itself was last modified, if known.
+    pub modified: Option<SystemTime>,
+    /// The newest access time of any file inside, if the filesystem records them.
+    pub accessed: Option<SystemTime>,
+    pub kind: TargetKind,
+    /// The directory has no entries at all, as opposed to just adding up to zero bytes.
+    pub empty: bool,
+    /// How many files are inside, when it was sized rather than resumed from a checkpoint.
+    pub file_count: Option<u64>,
+    /// What the size is made up of, when the scan was asked to keep it.
+    pub tree: Option<SizeTree>,
+    /// The crate the directory belongs to, when the scan was asked for details.
+    pub crate_details: Option<CrateDetails>,
+    /// The compiler that built it, when the scan was asked for details and it's recorded.
+    pub rustc: Option<RustcInfo>,
+    /// The compiler that built it is no longer installed, so it will be rebuilt from scratch.
+    pub stale_toolchain: bool,
+    /// It's on the config file's `protected` list, so it's shown but never deleted.
+    pub protected: bool,
+    /// The size is an [`Estimate`](estimate::Estimate) for `--approximate`, within this many
+    /// bytes either way.
+    pub size_margin: Option<u64>,
+    /// The filesystem it's on, when the scan was asked for details or to filter by mount.
+    pub mount: Option<Mount>,
+    /// A [`Fingerprint`] of what's inside, when the scan was asked for `--dedup-report`.
+    pub fingerprint: Option<u64>,
+    /// When sizing it started, so what the size is a snapshot of.
+    pub snapshot_at: Option<SystemTime>,
+    /// Its modification time moved while it was being sized, so it may be mid-build and the
+    /// size a mix of before and after.
+    pub changed_while_sizing: bool,
+    /// Files and directories that disappeared between being listed and being sized, such as
+    /// to a running build, and so aren't in the size. It's approximate when there were any.
+    pub vanished: u64,
+}
+
+impl TargetDirInfo {
+    pub fn new(path: PathBuf, size: u64) -> Self {
+        Self {
+            path,
+            size,
+            modified: None,
+            accessed: None,
+            kind: TargetKind::default(),
+            empty: false,
+            file_count: None,
+            tree: None,
+            crate_details: None,
+            rustc: None,
+            stale_toolchain: false,
+            protected: false,
+            size_margin: None,
+            mount: None,
+            fingerprint: None,
+            snapshot_at: None,
+            changed_while_sizing: false,
+            vanished: 0,
+        }
+    }
+
+    /// The cargo `target` directory this is or is inside of, or for output kept beside the
+    /// target directory, this directory itself. Either way its parent is the project.
+    pub fn target_dir(&self) -> &Path {
+        match self.kind {
+            TargetKind::Cargo | TargetKind::WasmPack | TargetKind::Trunk => &self.path,
+            TargetKind::Triple | TargetKind::Doc => self.path.parent().unwrap_or(&self.path),
+        }
+    }
+}
+
+/// Returns true if `path` is a directory with no entries.
+pub fn is_empty_dir(path: &Path) -> bool {
+    fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none())
+}
+
+/// Returns the modification time of `path`, if it can be read.
+pub fn dir_modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Runs `walk`, which sizes `path`, along with when it started and whether the modification
+/// time of `path` itself moved meanwhile, as it does when a build adds or removes files there.
+fn watch_for_changes<T>(path: &Path, walk: impl FnOnce() -> T) -> (T, SystemTime, bool) {
+    let before = dir_modified(path);
+    let started = SystemTime::now();
+    let walked = walk();
+    let changed = before.is_some() && dir_modified(path) != before;
+    (walked, started, changed)
+}
+
+/// How deep [`newest_artifact_modified`] looks, enough for `target/<profile>/deps/<file>`.
+pub const NEWEST_ARTIFACT_DEPTH: usize = 3;
+
+/// The newest modification time of any file within `depth` levels of `path`.
+///
+/// Sizing finds this for free, this is for targets that weren't sized, so it doesn't look at
+/// the whole tree.
+pub fn newest_artifact_modified(path: &Path, depth: usize) -> Option<SystemTime> {
+    let mut newest = None;
+    for entry in fs::read_dir(path).ok()?.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let modified = if file_type.is_dir() {
+            match depth {
+                0 => None,
+                _ => newest_artifact_modified(&entry.path(), depth - 1),
+            }
+        } else {
+            entry.metadata().and_then(|m| m.modified()).ok()
+        };
+        newest = newest.max(modified);
+    }
+    newest
+}
+
+/// Returns the modification time of the `Cargo.lock` in `project`, if there is one.
+pub fn cargo_lock_modified(project: &Path) -> Option<SystemTime> {
+    fs::metadata(project.join("Cargo.lock"))
+        .and_then(|m| m.modified())
+        .ok()
+}
+
+/// When `info` was last built, going by `source`. `newest_artifact` is the newest file
+/// modification time seen while sizing it, if it was sized and had any files.
+///
+/// A target with no files at all goes by its directory's time instead.
+pub fn target_age(
+    info: &TargetDirInfo,
+    source: AgeSource,
+    newest_artifact: Option<SystemTime>,
+) -> Option<SystemTime> {
+    match source {
+        AgeSource::Dir => dir_modified(&info.path),
+        AgeSource::NewestArtifact => newest_artifact
+            .or_else(|| newest_artifact_modified(&info.path, NEWEST_ARTIFACT_DEPTH))
+            .or_else(|| dir_modified(&info.path)),
+        AgeSource::CargoLock => info.target_dir().parent().and_then(cargo_lock_modified),
+        AgeSource::Accessed => info.accessed.or_else(|| dir_modified(&info.path)),
+    }
+}
+
+/// The result of scanning for target directories.
+#[derive(Debug, Default)]
+pub struct Discovery {
+    pub target_dirs: Vec<PathBuf>,
+    /// Set when the scan stopped early because it hit the result limit.
+    pub truncated: bool,
+    /// Set when the scan stopped early because the deadline passed.
+    pub timed_out: bool,
+}
+
+/// Parses a size such as `1024`, `100MB`, `1.5 GB` or `2GiB` into bytes.
+///
+/// Units are case-insensitive, `kB`/`MB`/`GB`/`TB` are powers of 1000 and `KiB`/`MiB`/`GiB`/`TiB`
+/// powers of 1024.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{value}'"))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "t" | "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => return Err(format!("unknown size unit in '{value}'")),
+    };
+    Ok((number * multiplier as f64).round() as u64)
+}
+
+/// Parses a duration such as `90`, `30d`, `2w` or `12h`, in seconds if there's no unit.
+///
+/// Units are `s`, `m` (minutes), `h`, `d` and `w`.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{value}'"))?;
+    let seconds: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("unknown duration unit in '{value}'")),
+    };
+    Ok(Duration::from_secs(number.saturating_mul(seconds)))
+}
+
+/// Globs matched against paths. Every list of globs is compiled by this, so they all match
+/// the same way:
+///
+/// - `*` and `?` match within one component, never across a `/`.
+/// - `**` as a whole component matches any number of components, including none, so `a/**/b`
+///   matches `a/b` and `a/x/y/b` and `a/**` everything inside `a`. Anywhere else it's just `*`.
+/// - `{vendor,third_party}` matches either alternative. Braces don't nest.
+/// - `\` is a separator like `/`, in globs and paths alike, so there's no escaping.
+/// - A glob starting with `/` matches from the root, any other from any component on, and
+///   either has to match up to the end of the path.
+/// - With `ignore_case` (`--glob-ignore-case`), `Vendor`, `VENDOR` and `vendor` all match.
+#[derive(Clone, Debug, Default)]
+pub struct PatternSet {
+    globs: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl PatternSet {
+    pub fn new<S: AsRef<str>>(globs: &[S], ignore_case: bool) -> Result<Self, String> {
+        let patterns = globs
+            .iter()
+            .map(|glob| glob_regex(glob.as_ref(), ignore_case))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            globs: globs.iter().map(|glob| glob.as_ref().to_string()).collect(),
+            patterns,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Returns true if any glob matches `path`.
+    pub fn is_match(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy().replace('\\', "/");
+        self.patterns.iter().any(|pattern| pattern.is_match(&path))
+    }
+
+    /// Returns true if any glob matches `path` or a directory it's in.
+    pub fn matches_within(&self, path: &Path) -> bool {
+        self.match_within(path).is_some()
+    }
+
+    /// The first glob, as given, that matches `path` or a directory it's in.
+    pub fn match_within(&self, path: &Path) -> Option<&str> {
+        path.ancestors().find_map(|dir| {
+            let dir = dir.to_string_lossy().replace('\\', "/");
+            self.patterns
+                .iter()
+                .position(|pattern| pattern.is_match(&dir))
+                .map(|i| self.globs[i].as_str())
+        })
+    }
+}
+
+/// Turns a glob into a regex as [`PatternSet`] describes.
+fn glob_regex(glob: &str, ignore_case: bool) -> Result<Regex, String> {
+    let invalid = |reason: &str| format!("invalid pattern `{glob}`: {reason}");
+    let normalized = glob.replace('\\', "/");
+    let normalized = normalized.trim_end_matches('/');
+    let mut pattern = String::from(if ignore_case { "(?i)" } else { "" });
+    pattern.push_str(if normalized.starts_with('/') {
+        "^"
+    } else {
+        "(^|/)"
+    });
+    let mut in_braces = false;
+    let mut component_start = true;
+    let mut chars = normalized.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                match chars.peek() {
+                    Some('/') if component_start => {
+                        chars.next();
+                        pattern.push_str("(.*/)?");
+                        continue;
+                    }
+                    None if component_start => pattern.push_str(".*"),
+                    _ => pattern.push_str("[^/]*"),
+                }
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            '{' if in_braces => return Err(invalid("braces can't be nested")),
+            '{' => {
+                in_braces = true;
+                pattern.push_str("(?:");
+            }
+            ',' if in_braces => pattern.push('|'),
+            '}' if in_braces => {
+                in_braces = false;
+                pattern.push(')');
+            }
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+        component_start = c == '/';
+    }
+    if in_braces {
+        return Err(invalid("`{` isn't closed"));
+    }
+    pattern.push('$');
+    Regex::new(&pattern).map_err(|e| invalid(&e.to_string()))
+}
+
+/// Works out which directories to scan.
+///
+/// Positional roots come first, followed by those in `dirs_env` (`CLEAN_BIG_TARGETS_DIRS`, split
+/// on the platform's path separator). Only if neither gives any roots is `dir_env`
+/// (`CLEAN_BIG_TARGETS_DIR`) used, and then the current directory. Duplicates are dropped,
+/// keeping the first.
+pub fn resolve_roots(
+    positional: &[PathBuf],
+    dirs_env: Option<&OsStr>,
+    dir_env: Option<&OsStr>,
+) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = positional.to_vec();
+    if let Some(dirs) = dirs_env {
+        roots.extend(std::env::split_paths(dirs).filter(|path| !path.as_os_str().is_empty()));
+    }
+    if roots.is_empty() {
+        roots.push(
+            dir_env
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from(".")),
+        );
+    }
+
+    let mut seen = HashSet::new();
+    roots.retain(|root| seen.insert(unc::canonical_or_same(root)));
+    roots
+}
+
+/// Reports the space available on the filesystem holding a path, see [`free_space_below`].
+pub type FreeSpace<'a> = dyn Fn(&Path) -> std::io::Result<u64> + 'a;
+
+/// Finds the first of `roots` on a filesystem with less than `threshold` bytes available,
+/// returning it with the space available, or `None` when every root has enough.
+///
+/// `free_space` is usually [`fsstat::available_space`].
+pub fn free_space_below<'a>(
+    roots: &'a [PathBuf],
+    threshold: u64,
+    free_space: &FreeSpace,
+) -> std::io::Result<Option<(&'a Path, u64)>> {
+    for root in roots {
+        let available = free_space(root)?;
+        if available < threshold {
+            return Ok(Some((root, available)));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns `CARGO_HOME`, falling back to `~/.cargo`.
+pub fn cargo_home_dir(env: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    env("CARGO_HOME").map(PathBuf::from).or_else(|| {
+        env("HOME")
+            .or_else(|| env("USERPROFILE"))
+            .map(|home| Path::new(&home).join(".cargo"))
+    })
+}
+
+/// Returns `RUSTUP_HOME`, falling back to `~/.rustup`.
+pub fn rustup_home_dir(env: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    env("RUSTUP_HOME").map(PathBuf::from).or_else(|| {
+        env("HOME")
+            .or_else(|| env("USERPROFILE"))
+            .map(|home| Path::new(&home).join(".rustup"))
+    })
+}
+
+/// Returns the well-known cargo and rustup cache directories, which are expensive to rebuild.
+///
+/// Uses `CARGO_HOME` and `RUSTUP_HOME` if set, falling back to `~/.cargo` and `~/.rustup`.
+pub fn cargo_cache_dirs(env: impl Fn(&str) -> Option<String>) -> Vec<PathBuf> {
+    let home = env("HOME")
+        .or_else(|| env("USERPROFILE"))
+        .map(PathBuf::from);
+    let cargo_home = cargo_home_dir(&env);
+    let rustup_home = env("RUSTUP_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".rustup")));
+
+    let mut dirs = Vec::new();
+    if let Some(cargo_home) = cargo_home {
+        dirs.push(cargo_home.join("registry"));
+        dirs.push(cargo_home.join("git"));
+    }
+    if let Some(rustup_home) = rustup_home {
+        dirs.push(rustup_home.join("toolchains"));
+        dirs.push(rustup_home.join("downloads"));
+    }
+    dirs
+}
+
+/// Drops any target directories that are inside one of `cache_dirs`.
+pub fn exclude_cargo_caches(
+    target_dirs: Vec<PathBuf>,
+    cache_dirs: &[PathBuf],
+    reporter: &dyn Reporter,
+) -> Vec<PathBuf> {
+    let cache_dirs: Vec<PathBuf> = cache_dirs
+        .iter()
+        .filter_map(|dir| unc::canonicalize(dir).ok())
+        .collect();
+    target_dirs
+        .into_iter()
+        .filter(|path| {
+            let canonical = unc::canonical_or_same(path);
+            if cache_dirs.iter().any(|cache| canonical.starts_with(cache)) {
+                reporter.report(Event::DirPruned(path.clone(), PruneReason::CargoCache));
+                false
+            } else {
+                true
+            }
+        })
+        .collect()
+}
+
+/// The device and inode of `path`, which are the same through every bind mount of it.
+#[cfg(unix)]
+pub fn dir_id(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path)
+        .ok()
+        .map(|metadata| (metadata.dev(), metadata.ino()))
+}
+
+/// Without inode numbers there's no telling bind mounts apart, so nothing is collapsed.
+#[cfg(not(unix))]
+pub fn dir_id(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+/// Collapses target directories that are the same directory reached through different paths,
+/// such as through a bind mount, where canonicalizing doesn't help.
+///
+/// Directories are the same if `dir_id` gives the same `(device, inode)`. The shortest path is
+/// kept, in the place the first of them was found, and the others are reported as aliases.
+pub fn collapse_aliases(
+    target_dirs: Vec<PathBuf>,
+    dir_id: impl Fn(&Path) -> Option<(u64, u64)>,
+    reporter: &dyn Reporter,
+) -> Vec<PathBuf> {
+    let mut groups: Vec<Vec<PathBuf>> = Vec::new();
+    let mut by_id: HashMap<(u64, u64), usize> = HashMap::new();
+    for path in target_dirs {
+        match dir_id(&path).map(|id| *by_id.entry(id).or_insert(groups.len())) {
+            Some(group) if group < groups.len() => groups[group].push(path),
+            _ => groups.push(vec![path]),
+        }
+    }
+    groups
+        .into_iter()
+        .filter_map(|mut group| {
+            group.sort_by_key(|path| (path.components().count(), path.as_os_str().len()));
+            let mut group = group.into_iter();
+            let kept = group.next()?;
+            for alias in group {
+                reporter.report(Event::DirPruned(alias, PruneReason::AliasOf(kept.clone())));
+            }
+            Some(kept)
+        })
+        .collect()
+}
+
+/// The outcome of [`scan`].
+#[derive(Debug, Default)]
+pub struct ScanResult {
+    /// Target directories found and sized, sorted as configured.
+    pub targets: Vec<TargetDirInfo>,
+    /// Set when discovery stopped early because it hit `max_results`.
+    pub truncated: bool,
+    /// Set when discovery or sizing stopped early because `timeout` passed.
+    pub timed_out: bool,
+    /// How many of the directories found weren't sized before `timeout` passed.
+    pub not_sized: usize,
+    /// Directories whose size couldn't be calculated.
+    pub errors: Vec<(PathBuf, std::io::Error)>,
+    /// Filesystem calls made during discovery and sizing.
+    pub io_stats: IoStats,
+    /// The total size counting each hardlinked file once, when `dedupe_hardlinks` is set.
+    pub deduplicated_total: Option<u64>,
+    /// Things the user should know about, where the scan carried on in a degraded way.
+    pub warnings: Vec<String>,
+    /// What the size and file count bounds left out, for each option that left any out.
+    pub left_out: Vec<LeftOut>,
+}
+
+/// Targets a size or file count bound left out of a [`ScanResult`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LeftOut {
+    /// The option that left them out, such as `--max-size`.
+    pub option: &'static str,
+    pub count: usize,
+    pub size: u64,
+}
+
+/// Which of the size and file count bounds `info` falls outside of, if any.
+///
+/// The bounds are inclusive. Targets without a file count, such as ones resumed from a
+/// checkpoint, aren't left out by the file count bounds.
+fn outside_bounds(info: &TargetDirInfo, config: &Config) -> Option<(PruneReason, &'static str)> {
+    let files = info.file_count;
+    if config.min_size.is_some_and(|min| info.size < min) {
+        Some((PruneReason::BelowMinSize, "--min-size"))
+    } else if config.max_size.is_some_and(|max| info.size > max) {
+        Some((PruneReason::AboveMaxSize, "--max-size"))
+    } else if config
+        .min_files
+        .is_some_and(|min| files.is_some_and(|files| files < min))
+    {
+        Some((PruneReason::BelowMinFiles, "--min-files"))
+    } else if config
+        .max_files
+        .is_some_and(|max| files.is_some_and(|files| files > max))
+    {
+        Some((PruneReason::AboveMaxFiles, "--max-files"))
+    } else {
+        None
+    }
+}
+
+/// Finds the directories to size under each of the configured roots, noting on `result`
+/// whether discovery stopped early.
+fn discover_all(
+    config: &Config,
+    deadline: Deadline,
+    reporter: &dyn Reporter,
+    result: &mut ScanResult,
+) -> std::io::Result<Vec<(PathBuf, TargetKind)>> {
+    let mut target_dirs = Vec::new();
+    for root in &config.roots {
+        if deadline.passed() {
+            result.timed_out = true;
+            break;
+        }
+        let remaining = config
+            .max_results
+            .map(|max| max.saturating_sub(target_dirs.len()));
+        if remaining == Some(0) {
+            result.truncated = true;
+            break;
+        }
+        let opts = ScanOptions::new()
+            .max_results(remaining)
+            .case_insensitive(config.case_insensitive)
+            .hidden(config.hidden)
+            .deadline(deadline);
+        let discovery = discover_targets(root, &opts, reporter, &result.io_stats)?;
+        result.truncated |= discovery.truncated;
+        result.timed_out |= discovery.timed_out;
+        let canonical_root = unc::canonical_or_same(root);
+        target_dirs.extend(discovery.target_dirs.into_iter().filter(|target| {
+            if !config.git_only {
+                return true;
+            }
+            let project = unc::canonical_or_same(target.parent().unwrap_or(target));
+            let in_git = git::in_work_tree(&project, &canonical_root);
+            if !in_git {
+                reporter.report(Event::DirPruned(target.clone(), PruneReason::NotInGitRepo));
+            }
+            in_git
+        }));
+        if result.truncated || result.timed_out {
+            continue;
+        }
+        // Submodules can be checked out anywhere in a project, not just where the walk looks
+        let Ok(root) = unc::canonicalize(root) else {
+            continue;
+        };
+        for target in find_submodule_targets(
+            &root,
+            config.case_insensitive,
+            config.hidden,
+            &result.io_stats,
+        ) {
+            if target_dirs.contains(&target) {
+                continue;
+            }
+            if config
+                .max_results
+                .is_some_and(|max| target_dirs.len() >= max)
+            {
+                result.truncated = true;
+                break;
+            }
+            reporter.report(Event::TargetFound(target.clone()));
+            target_dirs.push(target);
+        }
+    }
+    let target_dirs = collapse_aliases(target_dirs, dir_id, reporter);
+    let cargo_targets = exclude_cargo_caches(target_dirs, &config.cargo_cache_dirs, reporter);
+    let mut target_dirs: Vec<(PathBuf, TargetKind)> = if config.docs_only {
+        cargo_targets
+            .iter()
+            .map(|target| target.join("doc"))
+            .filter(|dir| dir.is_dir())
+            .map(|dir| (dir, TargetKind::Doc))
+            .collect()
+    } else if config.triples.is_empty() {
+        cargo_targets
+            .iter()
+            .map(|target| (target.clone(), TargetKind::Cargo))
+            .collect()
+    } else {
+        cargo_targets
+            .iter()
+            .flat_map(|target| {
+                config
+                    .triples
+                    .iter()
+                    .map(|triple| target.join(triple))
+                    .filter(|dir| dir.is_dir())
+                    .map(|dir| (dir, TargetKind::Triple))
+            })
+            .collect()
+    };
+    if config.wasm {
+        target_dirs.extend(
+            cargo_targets
+                .iter()
+                .flat_map(|target| wasm::wasm_output_dirs(target)),
+        );
+    }
+    Ok(target_dirs)
+}
+
+/// Finds target directories under each of the configured roots, sizes them in parallel and
+/// sorts them.
+pub fn scan(config: &Config, reporter: &dyn Reporter) -> std::io::Result<ScanResult> {
+    let mut result = ScanResult::default();
+    let deadline = Deadline::after(config.timeout);
+    let target_dirs = discover_all(config, deadline, reporter, &mut result)?;
+
+    // Loaded before opening, which completes any line cut short so it can be appended to
+    let (resumed, checkpoint) = match &config.checkpoint {
+        Some(file) => (load_checkpoint(file)?, Some(Checkpoint::open(file, true)?)),
+        None => (Default::default(), None),
+    };
+
+    let reference = match &config.older_than_file {
+        Some(file) => Some(reference_time(file)?),
+        None => None,
+    };
+    let fds = FdBudget::new(config.max_open_fds);
+    let hardlinks = Hardlinks::default();
+    let sizing = Sizing {
+        stats: &result.io_stats,
+        fds: &fds,
+        hardlinks: config.dedupe_hardlinks.then_some(&hardlinks),
+        newest_access: None,
+        newest_modified: None,
+        file_count: None,
+        vanished: None,
+        fingerprint: None,
+        progress: None,
+        cache: config.size_cache.as_deref(),
+        deadline,
+        fast: config.fast_scan,
+    };
+    // With the newest file's modification time, when it's wanted for `--older-than-file`
+    type Sized = Result<(TargetDirInfo, Option<SystemTime>), (PathBuf, std::io::Error)>;
+    let sized: Vec<Sized> = build_thread_pool(config.threads)?.install(|| {
+        target_dirs
+            .par_iter()
+            .map(|(path, kind)| {
+                deadline.check().map_err(|e| (path.clone(), e))?;
+                if let Some(entry) = resumed.get(path) {
+                    reporter.report(Event::SizeResumed(path.clone(), entry.size));
+                    let mut info = TargetDirInfo {
+                        accessed: entry.accessed,
+                        empty: entry.size == 0 && is_empty_dir(path),
+                        kind: *kind,
+                        ..TargetDirInfo::new(path.clone(), entry.size)
+                    };
+                    info.modified = target_age(&info, config.age_from, None);
+                    return Ok((info, None));
+                }
+                if config.approximate {
+                    return match approximate_size(path, SAMPLE_SIZE) {
+                        Ok(estimate) => {
+                            reporter.report(Event::SizeComputed(path.clone(), estimate.bytes));
+                            let mut info = TargetDirInfo {
+                                empty: estimate.bytes == 0 && is_empty_dir(path),
+                                kind: *kind,
+                                size_margin: Some(estimate.margin),
+                                ..TargetDirInfo::new(path.clone(), estimate.bytes)
+                            };
+                            info.modified = target_age(&info, config.age_from, None);
+                            Ok((info, None))
+                        }
+                        Err(e) => Err((path.clone(), e)),
+                    };
+                }
+                let newest_access = Cell::new(None);
+                let newest_modified = Cell::new(None);
+                let file_count = Cell::new(0);
+                let vanished = Cell::new(0);
+                let fingerprint = Fingerprint::new(path);
+                let sizing = Sizing {
+                    newest_access: Some(&newest_access),
+                    newest_modified: (config.age_from == AgeSource::NewestArtifact
+                        || config.older_than_file.is_some())
+                    .then_some(&newest_modified),
+                    file_count: Some(&file_count),
+                    vanished: Some(&vanished),
+                    fingerprint: config.dedup_report.then_some(&fingerprint),
+                    ..sizing
+                };
+                let (measured, snapshot_at, changed_while_sizing) =
+                    watch_for_changes(path, || sizing.measure(path, config.tree_depth));
+                if changed_while_sizing {
+                    reporter.report(Event::ChangedWhileSizing(path.clone()));
+                }
+                match measured {
+                    Ok((size, tree)) => {
+                        reporter.report(Event::SizeComputed(path.clone(), size));
+                        let entry = CheckpointEntry {
+                            size,
+                            accessed: newest_access.get(),
+                        };
+                        if let Some(Err(e)) = checkpoint.as_ref().map(|c| c.record(path, entry)) {
+                            reporter.report(Event::CheckpointFailed(path.clone(), e.to_string()));
+                        }
+                        let mut info = TargetDirInfo {
+                            accessed: newest_access.get(),
+                            empty: size == 0 && is_empty_dir(path),
+                            file_count: Some(file_count.get()),
+                            fingerprint: config.dedup_report.then(|| fingerprint.finish()),
+                            tree,
+                            kind: *kind,
+                            snapshot_at: Some(snapshot_at),
+                            changed_while_sizing,
+                            vanished: vanished.get(),
+                            ..TargetDirInfo::new(path.clone(), size)
+                        };
+                        info.modified = target_age(&info, config.age_from, newest_modified.get());
+                        Ok((info, newest_modified.get()))
+                    }
+                    Err(e) => Err((path.clone(), e)),
+                }
+            })
+            .collect()
+    });
+    for entry in sized {
+        match entry {
+            Ok((info, newest)) => {
+                // Without any files there's nothing newer, so it's kept
+                if let (Some(reference), Some(newest)) = (reference, newest)
+                    && newest > reference
+                {
+                    reporter.report(Event::DirPruned(
+                        info.path.clone(),
+                        PruneReason::NewerThanReference,
+                    ));
+                    continue;
+                }
+                result.targets.push(info);
+            }
+            Err((_, e)) if is_expired(&e) => result.not_sized += 1,
+            Err(error) => result.errors.push(error),
+        }
+    }
+    // Only what was sized in full is kept, so the deadline can't leave a size short
+    result.timed_out |= result.not_sized > 0;
+    if result.timed_out {
+        result.warnings.push(timed_out_warning(
+            config.timeout.unwrap_or_default(),
+            result.not_sized,
+        ));
+    }
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.finish()?;
+    }
+    let vanished: u64 = result.targets.iter().map(|info| info.vanished).sum();
+    if vanished > 0 {
+        result.warnings.push(format!(
+            "{vanished} files and directories disappeared while being sized, the sizes marked ~ \
+             leave them out"
+        ));
+    }
+    // Said once for the whole scan, the first filesystem found is enough to explain it
+    if config.age_from == AgeSource::Accessed
+        && cfg!(target_os = "linux")
+        && let Ok(contents) = fs::read_to_string("/proc/self/mountinfo")
+        && let Some(mount) = noatime_mount(
+            result.targets.iter().map(|info| info.path.as_path()),
+            &contents,
+        )
+    {
+        result.warnings.push(format!(
+            "{} is mounted noatime, so access times there never change and --age-from accessed \
+             can't tell how old targets on it are",
+            mount.point.display()
+        ));
+    }
+    if let Some(unused_for) = config.unused_for {
+        let now = SystemTime::now();
+        result.targets.retain(|info| {
+            // Without access times (or with `noatime`) there's no telling, so keep it
+            let unused = info.accessed.is_none_or(|accessed| {
+                now.duration_since(accessed)
+                    .is_ok_and(|since| since >= unused_for)
+            });
+            if !unused {
+                reporter.report(Event::DirPruned(
+                    info.path.clone(),
+                    PruneReason::RecentlyAccessed,
+                ));
+            }
+            unused
+        });
+    }
+    if config.hide_kept {
+        hide_kept(&mut result.targets, reporter);
+    }
+    result.targets.retain(|info| {
+        let Some((reason, option)) = outside_bounds(info, config) else {
+            return true;
+        };
+        reporter.report(Event::DirPruned(info.path.clone(), reason));
+        match result
+            .left_out
+            .iter_mut()
+            .find(|left| left.option == option)
+        {
+            Some(left) => {
+                left.count += 1;
+                left.size += info.size;
+            }
+            None => result.left_out.push(LeftOut {
+                option,
+                count: 1,
+                size: info.size,
+            }),
+        }
+        false
+    });
+    result.left_out.sort_by_key(|left| left.option);
+    if config.dedupe_hardlinks {
+        let total: u64 = result.targets.iter().map(|info| info.size).sum();
+        result.deduplicated_total = Some(total.saturating_sub(hardlinks.duplicate_bytes()));
+    }
+
+    check_rustc_and_mounts(&mut result, config, reporter);
+    mark_protected(&mut result.targets, &config.protected);
+
+    sort_configured(&mut result.targets, config);
+    Ok(result)
+}
+
+/// The modification time of `file`, the cutoff for `--older-than-file`.
+fn reference_time(file: &Path) -> std::io::Result<SystemTime> {
+    fs::metadata(file)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| {
+            std::io::Error::new(
+                e.kind(),
+                format!(
+                    "Couldn't read the modification time of {}: {}",
+                    file.display(),
+                    e
+                ),
+            )
+        })
+}
+
+/// Like [`scan`] without sizing anything, for `--count-only`. Every target has a size of 0, and
+/// only the filters that don't need a size or access time are applied.
+pub fn find_targets(config: &Config, reporter: &dyn Reporter) -> std::io::Result<ScanResult> {
+    let mut result = ScanResult::default();
+    result.targets = discover_all(
+        config,
+        Deadline::after(config.timeout),
+        reporter,
+        &mut result,
+    )?
+    .into_iter()
+    .map(|(path, kind)| TargetDirInfo {
+        kind,
+        ..TargetDirInfo::new(path, 0)
+    })
+    .collect();
+    if config.hide_kept {
+        hide_kept(&mut result.targets, reporter);
+    }
+    if result.timed_out {
+        result
+            .warnings
+            .push(timed_out_warning(config.timeout.unwrap_or_default(), 0));
+    }
+    check_rustc_and_mounts(&mut result, config, reporter);
+    sort_configured(&mut result.targets, config);
+    Ok(result)
+}
+
+/// Leaves out the targets whose project has a [`KEEP_MARKER`], for `--hide-kept`.
+fn hide_kept(targets: &mut Vec<TargetDirInfo>, reporter: &dyn Reporter) {
+    targets.retain(|info| {
+        let marked = has_keep_marker(info);
+        if marked {
+            reporter.report(Event::DirPruned(info.path.clone(), PruneReason::KeepMarker));
+        }
+        !marked
+    });
+}
+
+/// Fills in the crate details, rustc and mount for the options that show them, and applies
+/// `--built-before-rustc`, `--stale-toolchains-only` and `--mount`.
+fn check_rustc_and_mounts(result: &mut ScanResult, config: &Config, reporter: &dyn Reporter) {
+    if config.details {
+        for info in &mut result.targets {
+            info.crate_details = Some(crate_details(info.target_dir()).unwrap_or_else(|reason| {
+                reporter.report(Event::ManifestUnreadable(info.path.clone(), reason));
+                CrateDetails::default()
+            }));
+        }
+    }
+    let check_toolchains = config.details || config.stale_toolchains_only;
+    if check_toolchains || config.built_before_rustc.is_some() {
+        for info in &mut result.targets {
+            info.rustc = read_rustc_info(info.target_dir())
+                .map_err(|reason| {
+                    reporter.report(Event::RustcInfoUnreadable(info.path.clone(), reason));
+                })
+                .ok();
+        }
+    }
+    if let Some(before) = &config.built_before_rustc {
+        result.targets.retain(|info| {
+            // Without a recorded compiler there's nothing to compare, so it's left out
+            let older = info
+                .rustc
+                .as_ref()
+                .is_some_and(|rustc| rustc.version < *before);
+            if !older {
+                reporter.report(Event::DirPruned(
+                    info.path.clone(),
+                    PruneReason::NotBuiltBeforeRustc,
+                ));
+            }
+            older
+        });
+    }
+    if check_toolchains {
+        let installed = match &config.rustup_home {
+            Some(home) => installed_toolchains(home).map_err(|e| (home.display().to_string(), e)),
+            None => Err((
+                "RUSTUP_HOME".to_string(),
+                std::io::ErrorKind::NotFound.into(),
+            )),
+        };
+        match installed {
+            Ok(installed) => {
+                for info in &mut result.targets {
+                    info.stale_toolchain =
+                        info.rustc.as_ref().is_some_and(|rustc| is_stale(rustc, &installed));
+                }
+            }
+            Err((home, e)) if config.stale_toolchains_only => result.warnings.push(format!(
+                "couldn't list rustup toolchains in {home} ({e}), so no targets are known to be stale"
+            )),
+            Err(_) => {}
+        }
+    }
+    if config.stale_toolchains_only {
+        result.targets.retain(|info| {
+            if !info.stale_toolchain {
+                reporter.report(Event::DirPruned(
+                    info.path.clone(),
+                    PruneReason::ToolchainInstalled,
+                ));
+            }
+            info.stale_toolchain
+        });
+    }
+    if config.details || config.mount.is_some() {
+        let resolver = MountResolver::system();
+        for info in &mut result.targets {
+            info.mount = resolver.resolve(&info.path);
+        }
+        if let Some(path) = &config.mount {
+            // Without knowing which filesystem was meant, nothing can be said to be on it
+            let wanted = resolver.resolve(path).map(|mount| mount.point);
+            if wanted.is_none() {
+                result.warnings.push(format!(
+                    "couldn't work out which filesystem {} is on, so no targets are shown",
+                    path.display()
+                ));
+            }
+            result.targets.retain(|info| {
+                let on =
+                    wanted.is_some() && info.mount.as_ref().map(|m| &m.point) == wanted.as_ref();
+                if !on {
+                    reporter.report(Event::DirPruned(info.path.clone(), PruneReason::OtherMount));
+                }
+                on
+            });
+        }
+    }
+}
+
+/// Upper bounds (exclusive) of the size buckets in [`SizeStats::buckets`].
+pub const SIZE_BUCKET_BOUNDS: [u64; 3] = [100_000_000, 1_000_000_000, 10_000_000_000];
+
+/// Distribution of target directory sizes, see [`size_stats`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SizeStats {
+    pub count: usize,
+    pub median: u64,
+    /// The nearest-rank 90th percentile.
+    pub p90: u64,
+    /// Population standard deviation.
+    pub std_dev: f64,
+    /// Counts of targets under 100 MB, 100 MB to 1 GB, 1 to 10 GB and over 10 GB.
+    pub buckets: [usize; 4],
+}
+
+impl SizeStats {
+    /// The stats as a JSON object for `--format json`, with the standard deviation rounded to
+    /// whole bytes as the summary shows it.
+    pub fn to_json(&self) -> String {
+        let buckets: Vec<String> = self.buckets.iter().map(usize::to_string).collect();
+        format!(
+            "{{\"count\":{},\"median\":{},\"p90\":{},\"std_dev\":{},\"buckets\":[{}]}}",
+            self.count,
+            self.median,
+            self.p90,
+            self.std_dev.round() as u64,
+            buckets.join(",")
+        )
+    }
+}
+
+/// The targets modified longest ago and most recently, leaving out ones without a known
+/// modification time. `None` if no target has one.
+pub fn age_extremes(target_info: &[TargetDirInfo]) -> Option<(&TargetDirInfo, &TargetDirInfo)> {
+    let dated = || target_info.iter().filter(|info| info.modified.is_some());
+    let oldest = dated().min_by_key(|info| info.modified)?;
+    let newest = dated().max_by_key(|info| info.modified)?;
+    Some((oldest, newest))
+}
+
+/// Calculates the distribution of sizes, all zeroes when there are no targets.
+pub fn size_stats(target_info: &[TargetDirInfo]) -> SizeStats {
+    let mut sizes: Vec<u64> = target_info.iter().map(|info| info.size).collect();
+    if sizes.is_empty() {
+        return SizeStats::default();
+    }
+    sizes.sort_unstable();
+
+    let count = sizes.len();
+    let mid = count / 2;
+    let median = if count.is_multiple_of(2) {
+        sizes[mid - 1] / 2 + sizes[mid] / 2 + (sizes[mid - 1] % 2 + sizes[mid] % 2) / 2
+    } else {
+        sizes[mid]
+    };
+    let p90 = sizes[(count * 9).div_ceil(10) - 1];
+
+    let mean = sizes.iter().map(|&size| size as f64).sum::<f64>() / count as f64;
+    let variance = sizes
+        .iter()
+        .map(|&size| (size as f64 - mean).powi(2))
+        .sum::<f64>()
+        / count as f64;
+
+    let mut buckets = [0; 4];
+    for &size in &sizes {
+        let bucket = SIZE_BUCKET_BOUNDS
+            .iter()
+            .position(|&bound| size < bound)
+            .unwrap_or(SIZE_BUCKET_BOUNDS.len());
+        buckets[bucket] += 1;
+    }
+
+    SizeStats {
+        count,
+        median,
+        p90,
+        std_dev: variance.sqrt(),
+        buckets,
+    }
+}
+
+/// Sorts the target directories into the requested order, [`SortOrder::Priority`] with the
+/// default age weight.
+pub fn sort_targets(target_info: &mut [TargetDirInfo], order: SortOrder) {
+    match order {
+        SortOrder::SizeDesc => target_info.sort_by_key(|info| Reverse(info.size)),
+        SortOrder::SizeAsc => target_info.sort_by_key(|info| info.size),
+        SortOrder::Priority => {
+            sort_by_priority(target_info, priority::DEFAULT_AGE_WEIGHT, SystemTime::now())
+        }
+        // Unknown counts go last
+        SortOrder::Files => target_info.sort_by_key(|info| Reverse(info.file_count)),
+        SortOrder::ReclaimablePerFile => {
+            target_info.sort_by_key(|info| Reverse(bytes_per_file(info)))
+        }
+    }
+}
+
+/// The average size of the files in `info`, for [`SortOrder::ReclaimablePerFile`]. Deleting
+/// takes time per file, so the more bytes each one frees the quicker the space comes back.
+///
+/// Unknown without a file count, or without any files.
+pub fn bytes_per_file(info: &TargetDirInfo) -> Option<u64> {
+    info.file_count
+        .filter(|files| *files > 0)
+        .map(|files| info.size / files)
+}
+
+/// Sorts the target directories as `config` asks, including its `--priority-age-weight`.
+pub fn sort_configured(target_info: &mut [TargetDirInfo], config: &Config) {
+    match config.sort {
+        SortOrder::Priority => {
+            sort_by_priority(target_info, config.priority_age_weight, SystemTime::now())
+        }
+        order => sort_targets(target_info, order),
+    }
+}
+
+/// A file that, in a project directory, keeps its target from being deleted.
+pub const KEEP_MARKER: &str = ".clean-big-targets-keep";
+
+/// Returns true if the project `info` belongs to has a [`KEEP_MARKER`].
+pub fn has_keep_marker(info: &TargetDirInfo) -> bool {
+    info.target_dir()
+        .parent()
+        .is_some_and(|project| project.join(KEEP_MARKER).exists())
+}
+
+/// Deleting from one disk rarely gets faster beyond a couple of jobs.
+pub const DEFAULT_DELETE_JOBS: usize = 2;
+
+/// Whether the platform's default filesystem ignores case in names (Windows and macOS).
+pub const CASE_INSENSITIVE_DEFAULT: bool = cfg!(any(windows, target_os = "macos"));
+
+/// Returns true if `name` is a build directory name, ignoring ASCII case if asked.
+///
+/// Discovery and the check before deletion both use this, so they always agree.
+pub fn is_target_name(name: &OsStr, case_insensitive: bool) -> bool {
+    if case_insensitive {
+        name.eq_ignore_ascii_case("target")
+    } else {
+        name == "target"
+    }
+}
+
+/// Finds the target directory directly inside `dir`, if there is one.
+pub(crate) fn child_target_dir(
+    dir: &Path,
+    case_insensitive: bool,
+    stats: &IoStats,
+) -> Option<PathBuf> {
+    let target_path = dir.join("target");
+    stats.count_metadata();
+    if target_path.is_dir() {
+        return Some(target_path);
+    }
+    if !case_insensitive {
+        return None;
+    }
+    stats.count_read_dir();
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_name()
+                .is_some_and(|name| is_target_name(name, true))
+                && {
+                    stats.count_metadata();
+                    path.is_dir()
+                }
+        })
 }
 
-#[derive(Debug)]
-pub struct TargetDirInfo {
-    pub path: PathBuf,
-    pub size: u64,
-}
+/// Finds the target directories under `base_dir` using `opts`, counting filesystem calls in
+/// `stats`.
+pub fn discover_targets(
+    base_dir: &Path,
+    opts: &ScanOptions,
+    reporter: &dyn Reporter,
+    stats: &IoStats,
+) -> std::io::Result<Discovery> {
+    let mut discovery = Discovery::default();
+
+    let mut walker = TargetWalker::new(base_dir, opts, reporter, stats);
+    for target_path in walker.by_ref() {
+        discovery.target_dirs.push(target_path?);
+    }
+    if opts
+        .max_results
+        .is_some_and(|max| discovery.target_dirs.len() >= max)
+    {
+        discovery.truncated = walker.has_remaining();
+    }
+    discovery.timed_out = walker.timed_out();
+
+    Ok(discovery)
+}
+
+#[deprecated(since = "0.1.2", note = "use `discover_targets` with `ScanOptions`")]
+pub fn find_target_dirs(base_dir: &Path, reporter: &dyn Reporter) -> std::io::Result<Vec<PathBuf>> {
+    Ok(discover_targets(
+        base_dir,
+        &ScanOptions::default(),
+        reporter,
+        &IoStats::default(),
+    )?
+    .target_dirs)
+}
+
+/// Like [`find_target_dirs`], but stops once `max_results` target directories have been found.
+#[deprecated(since = "0.1.2", note = "use `discover_targets` with `ScanOptions`")]
+pub fn find_target_dirs_limited(
+    base_dir: &Path,
+    reporter: &dyn Reporter,
+    max_results: Option<usize>,
+    case_insensitive: bool,
+    stats: &IoStats,
+) -> std::io::Result<Discovery> {
+    let opts = ScanOptions::new()
+        .max_results(max_results)
+        .case_insensitive(case_insensitive);
+    discover_targets(base_dir, &opts, reporter, stats)
+}
+
+/// Adds up the size of all the files under `path`.
+///
+/// Symlinks are counted as themselves and never followed, so the walk can't loop or leave
+/// the directory.
+pub fn calculate_dir_size(path: &Path) -> std::io::Result<u64> {
+    calculate_dir_size_counted(path, &IoStats::default())
+}
+
+/// Like [`calculate_dir_size`], counting filesystem calls in `stats`.
+pub fn calculate_dir_size_counted(path: &Path, stats: &IoStats) -> std::io::Result<u64> {
+    calculate_dir_size_bounded(path, stats, &FdBudget::default())
+}
+
+/// Like [`calculate_dir_size_counted`], only opening directory handles within `fds`.
+pub fn calculate_dir_size_bounded(
+    path: &Path,
+    stats: &IoStats,
+    fds: &FdBudget,
+) -> std::io::Result<u64> {
+    Sizing {
+        stats,
+        fds,
+        hardlinks: None,
+        newest_access: None,
+        newest_modified: None,
+        file_count: None,
+        vanished: None,
+        fingerprint: None,
+        progress: None,
+        cache: None,
+        deadline: Deadline::default(),
+        fast: false,
+    }
+    .size(path)
+}
+
+/// Like [`calculate_dir_size`], calling `progress` every
+/// [`PROGRESS_EVERY`](progress::PROGRESS_EVERY) entries with how far
+/// it's got, for embedders showing progress their own way. The last call is once it's done, with
+/// the same total as is returned.
+pub fn calculate_dir_size_with_progress(
+    path: &Path,
+    progress: Option<&(dyn Fn(SizeProgress) + Send + Sync)>,
+) -> std::io::Result<u64> {
+    let Some(callback) = progress else {
+        return calculate_dir_size(path);
+    };
+    let tracker = Tracker::new(callback);
+    let size = Sizing {
+        stats: &IoStats::default(),
+        fds: &FdBudget::default(),
+        hardlinks: None,
+        newest_access: None,
+        newest_modified: None,
+        file_count: None,
+        vanished: None,
+        fingerprint: None,
+        progress: Some(&tracker),
+        cache: None,
+        deadline: Deadline::default(),
+        fast: false,
+    }
+    .size(path)?;
+    tracker.send(path);
+    Ok(size)
+}
+
+/// What's shared between all the directories sized in one scan.
+#[derive(Clone, Copy)]
+struct Sizing<'a> {
+    stats: &'a IoStats,
+    fds: &'a FdBudget,
+    /// Set when the grand total should count hardlinked files once.
+    hardlinks: Option<&'a Hardlinks>,
+    /// Where to keep the newest file access time seen, for one target at a time.
+    newest_access: Option<&'a Cell<Option<SystemTime>>>,
+    /// Where to keep the newest file modification time seen, for one target at a time.
+    newest_modified: Option<&'a Cell<Option<SystemTime>>>,
+    /// Where to count the files seen, for one target at a time.
+    file_count: Option<&'a Cell<u64>>,
+    /// Where to count entries that disappeared between being listed and being looked at, for
+    /// one target at a time.
+    vanished: Option<&'a Cell<u64>>,
+    /// Where to add each file seen to, for one target at a time.
+    fingerprint: Option<&'a Fingerprint>,
+    /// Where to count progress, for one target at a time. Only the portable walk counts it.
+    progress: Option<&'a Tracker<'a>>,
+    /// Where to reuse listings of unchanged directories from, for `--size-cache`.
+    cache: Option<&'a SizeCache>,
+    /// When to give up on sizing, checked at each directory. The `--fast-scan` walk only
+    /// checks before it starts.
+    deadline: Deadline,
+    /// Use the `--fast-scan` walk where there is one.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fast: bool,
+}
+
+impl Sizing<'_> {
+    /// Sizes `path`, also keeping a [`SizeTree`] of it down to `tree_depth` levels if asked.
+    fn measure(
+        &self,
+        path: &Path,
+        tree_depth: Option<usize>,
+    ) -> std::io::Result<(u64, Option<SizeTree>)> {
+        let Some(depth) = tree_depth else {
+            return Ok((self.size(path)?, None));
+        };
+        self.stats.count_metadata();
+        if !path.is_dir() {
+            return Ok((self.size(path)?, None));
+        }
+        let name = path.file_name().unwrap_or(path.as_os_str()).to_os_string();
+        let tree = self.dir_tree(path, name, depth)?;
+        Ok((tree.total(), Some(tree)))
+    }
+
+    /// Like [`Sizing::dir_size`], but keeping each entry's size for `depth` levels down.
+    fn dir_tree(
+        &self,
+        path: &Path,
+        name: std::ffi::OsString,
+        depth: usize,
+    ) -> std::io::Result<SizeTree> {
+        self.deadline.check()?;
+        if depth == 0 {
+            return Ok(SizeTree::collapsed_dir(name, self.dir_size(path)?));
+        }
+        let mut node = SizeTree::collapsed_dir(name, 0);
+        let mut subdirs = Vec::new();
+
+        {
+            let _permit = self.fds.acquire();
+            self.stats.count_read_dir();
+            for entry in fs::read_dir(path)? {
+                let entry = entry?;
+                let Some(file_type) = self.unless_vanished(entry.file_type())? else {
+                    continue;
+                };
+                if file_type.is_dir() {
+                    subdirs.push((entry.path(), entry.file_name()));
+                } else {
+                    self.stats.count_metadata();
+                    let Some(metadata) = self.unless_vanished(entry.metadata())? else {
+                        continue;
+                    };
+                    let size = self.file_size(&metadata);
+                    if let Some(fingerprint) = self.fingerprint {
+                        fingerprint.add(&entry.path(), size);
+                    }
+                    node.children.push(SizeTree::file(entry.file_name(), size));
+                }
+            }
+        }
+
+        for (subdir, name) in subdirs {
+            if let Some(child) = self.unless_vanished(self.dir_tree(&subdir, name, depth - 1))? {
+                node.children.push(child);
+            }
+        }
+        node.children.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(node)
+    }
+
+    fn size(&self, path: &Path) -> std::io::Result<u64> {
+        self.stats.count_metadata();
+        if path.is_dir() {
+            return self.dir_size(path);
+        }
+        self.stats.count_metadata();
+        if path.is_file() {
+            self.stats.count_metadata();
+            return Ok(self.file_size(&fs::metadata(path)?));
+        }
+        Ok(0)
+    }
+
+    fn file_size(&self, metadata: &fs::Metadata) -> u64 {
+        if let Some(hardlinks) = self.hardlinks {
+            hardlinks.record(metadata);
+        }
+        self.note_file(metadata.accessed().ok(), metadata.modified().ok());
+        metadata.len()
+    }
+
+    /// Like [`Sizing::dir_size`], reusing the listing of each directory whose modification time
+    /// hasn't changed since it was put in `cache`.
+    fn cached_dir_size(&self, cache: &SizeCache, path: &Path) -> std::io::Result<u64> {
+        self.deadline.check()?;
+        self.stats.count_metadata();
+        let modified = fs::symlink_metadata(path)?.modified()?;
+        let listing = match cache.get(path, modified) {
+            Some(listing) => listing,
+            None => {
+                let listed_at = SystemTime::now();
+                let listing = self.list_dir(path)?;
+                cache.insert(path, modified, listed_at, listing.clone());
+                listing
+            }
+        };
+        if let Some(newest) = self.newest_access {
+            newest.set(newest.get().max(listing.newest_accessed));
+        }
+        if let Some(newest) = self.newest_modified {
+            newest.set(newest.get().max(listing.newest_modified));
+        }
+        if let Some(count) = self.file_count {
+            count.set(count.get() + listing.files);
+        }
+        let mut total_size = listing.bytes;
+        for subdir in &listing.subdirs {
+            total_size += self
+                .unless_vanished(self.cached_dir_size(cache, subdir))?
+                .unwrap_or(0);
+        }
+        Ok(total_size)
+    }
+
+    /// What's directly in the directory at `path`, for the [`SizeCache`].
+    fn list_dir(&self, path: &Path) -> std::io::Result<DirListing> {
+        let _permit = self.fds.acquire();
+        self.stats.count_read_dir();
+        let mut listing = DirListing::default();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let Some(file_type) = self.unless_vanished(entry.file_type())? else {
+                continue;
+            };
+            if file_type.is_dir() {
+                listing.subdirs.push(entry.path());
+                continue;
+            }
+            self.stats.count_metadata();
+            let Some(metadata) = self.unless_vanished(entry.metadata())? else {
+                continue;
+            };
+            listing.bytes += metadata.len();
+            listing.files += 1;
+            listing.newest_accessed = listing.newest_accessed.max(metadata.accessed().ok());
+            listing.newest_modified = listing.newest_modified.max(metadata.modified().ok());
+        }
+        Ok(listing)
+    }
+
+    /// `Ok(None)` in place of a `NotFound` error, which is counted. Something listed a moment
+    /// ago has been removed since, by a running build or another cleanup, and the walk carries
+    /// on without it rather than losing the whole target.
+    fn unless_vanished<T>(&self, result: std::io::Result<T>) -> std::io::Result<Option<T>> {
+        match result {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.note_vanished();
+                Ok(None)
+            }
+            result => result.map(Some),
+        }
+    }
+
+    fn note_vanished(&self) {
+        if let Some(vanished) = self.vanished {
+            vanished.set(vanished.get() + 1);
+        }
+    }
+
+    /// Keeps track of the newest times and the file count, for a file either walk found.
+    fn note_file(&self, accessed: Option<SystemTime>, modified: Option<SystemTime>) {
+        // Only files count, listing directories while sizing updates their access times
+        if let Some(newest) = self.newest_access {
+            newest.set(newest.get().max(accessed));
+        }
+        if let Some(newest) = self.newest_modified {
+            newest.set(newest.get().max(modified));
+        }
+        if let Some(count) = self.file_count {
+            count.set(count.get() + 1);
+        }
+    }
+
+    fn dir_size(&self, path: &Path) -> std::io::Result<u64> {
+        self.deadline.check()?;
+        // A cached listing can't say which files were hardlinked nor fingerprint them
+        if let Some(cache) = self.cache
+            && self.hardlinks.is_none()
+            && self.fingerprint.is_none()
+        {
+            return self.cached_dir_size(cache, path);
+        }
+        // The fast walk doesn't have the paths a fingerprint is made from
+        #[cfg(target_os = "linux")]
+        if self.fast && self.fingerprint.is_none() {
+            return fast_size::walk(
+                path,
+                self.fds,
+                self.stats,
+                &mut |file| {
+                    if let Some(hardlinks) = self.hardlinks {
+                        hardlinks.record_link(file.dev, file.ino, file.nlink, file.len);
+                    }
+                    self.note_file(file.accessed, file.modified);
+                },
+                &mut || self.note_vanished(),
+            );
+        }
+        let mut total_size = 0u64;
+        let mut subdirs = Vec::new();
+
+        // Entries keep the directory handle open, so finish with them all before recursing and
+        // only ever hold one handle per thread
+        {
+            let _permit = self.fds.acquire();
+            self.stats.count_read_dir();
+            for entry in fs::read_dir(path)? {
+                let entry = entry?;
+                let Some(file_type) = self.unless_vanished(entry.file_type())? else {
+                    continue;
+                };
+
+                if file_type.is_dir() {
+                    subdirs.push(entry.path());
+                    if let Some(progress) = self.progress {
+                        progress.visit(path, 0);
+                    }
+                } else {
+                    self.stats.count_metadata();
+                    let Some(metadata) = self.unless_vanished(entry.metadata())? else {
+                        continue;
+                    };
+                    let size = self.file_size(&metadata);
+                    if let Some(fingerprint) = self.fingerprint {
+                        fingerprint.add(&entry.path(), size);
+                    }
+                    if let Some(progress) = self.progress {
+                        progress.visit(path, size);
+                    }
+                    total_size += size;
+                }
+            }
+        }
+
+        for subdir in subdirs {
+            total_size += self.unless_vanished(self.dir_size(&subdir))?.unwrap_or(0);
+        }
+
+        Ok(total_size)
+    }
+}
+
+/// Returns true if `path` is, or contains, `dir`.
+pub(crate) fn path_contains(path: &Path, dir: &Path) -> bool {
+    match (unc::canonicalize(path), unc::canonicalize(dir)) {
+        (Ok(path), Ok(dir)) => dir.starts_with(path),
+        _ => false,
+    }
+}
+
+/// Finds other processes whose current working directory is inside `path`.
+///
+/// This only works on Linux (via `/proc`), elsewhere it returns an empty list.
+pub fn other_processes_in(path: &Path) -> Vec<u32> {
+    let mut pids = Vec::new();
+    if !cfg!(target_os = "linux") {
+        return pids;
+    }
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return pids;
+    };
+    let own_pid = std::process::id();
+    for entry in entries.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if pid == own_pid {
+            continue;
+        }
+        if let Ok(cwd) = fs::read_link(entry.path().join("cwd"))
+            && path_contains(path, &cwd)
+        {
+            pids.push(pid);
+        }
+    }
+    pids
+}
+
+/// Checks the selected directories are safe to delete.
+///
+/// Refuses outright if our own working directory is inside one of them, and asks for
+/// confirmation (or skips, if we can't ask) when another process has its working directory
+/// inside one.
+fn check_cwd_conflicts<'a>(
+    selected: Vec<&'a TargetDirInfo>,
+    output: &mut Output,
+) -> std::io::Result<Vec<&'a TargetDirInfo>> {
+    check_cwd_conflicts_in(selected, &std::env::current_dir()?, output)
+}
+
+/// Like [`check_cwd_conflicts`], with our own working directory being `cwd`.
+fn check_cwd_conflicts_in<'a>(
+    selected: Vec<&'a TargetDirInfo>,
+    cwd: &Path,
+    output: &mut Output,
+) -> std::io::Result<Vec<&'a TargetDirInfo>> {
+    if let Some(info) = selected.iter().find(|info| path_contains(&info.path, cwd)) {
+        return Err(refused(
+            &info.path,
+            "the current working directory is inside it",
+        ));
+    }
+
+    let mut result = Vec::with_capacity(selected.len());
+    for info in selected {
+        let pids = other_processes_in(&info.path);
+        if pids.is_empty() {
+            result.push(info);
+            continue;
+        }
+        writeln!(
+            output.err,
+            "Warning: processes {:?} have a working directory inside '{}'",
+            pids,
+            shown(&info.path)
+        )?;
+        if !output.interactive {
+            writeln!(output.err, "Skipping '{}'", shown(&info.path))?;
+            continue;
+        }
+        let confirmed = Confirm::new()
+            .with_prompt(format!("Delete '{}' anyway?", shown(&info.path)))
+            .default(false)
+            .interact()
+            .map_err(std::io::Error::other)?;
+        if confirmed {
+            result.push(info);
+        }
+    }
+    Ok(result)
+}
+
+/// Checks for targets git tracks files in, which were likely committed by mistake or are
+/// being used as vendored artifacts.
+///
+/// `--delete-tracked` lets them through unchecked. Otherwise `--force` skips them and an
+/// interactive run asks again.
+fn check_git_tracked<'a>(
+    selected: Vec<&'a TargetDirInfo>,
+    config: &Config,
+    output: &mut Output,
+) -> std::io::Result<Vec<&'a TargetDirInfo>> {
+    if config.delete_tracked {
+        return Ok(selected);
+    }
+    let mut result = Vec::with_capacity(selected.len());
+    for info in selected {
+        if !git::is_tracked(&info.path) {
+            result.push(info);
+            continue;
+        }
+        writeln!(
+            output.err,
+            "Warning: git tracks files in '{}'",
+            shown(&info.path)
+        )?;
+        if config.force || !output.interactive {
+            writeln!(
+                output.err,
+                "Skipping '{}', use --delete-tracked to delete it anyway",
+                shown(&info.path)
+            )?;
+            continue;
+        }
+        let confirmed = Confirm::new()
+            .with_prompt(format!("Delete '{}' anyway?", shown(&info.path)))
+            .default(false)
+            .interact()
+            .map_err(std::io::Error::other)?;
+        if confirmed {
+            result.push(info);
+        }
+    }
+    Ok(result)
+}
+
+/// Works out which entries of the deletion prompt start selected: those whose displayed path
+/// matches any of `patterns`.
+pub fn preselect_defaults(target_info: &[TargetDirInfo], patterns: &[Regex]) -> Vec<bool> {
+    target_info
+        .iter()
+        .map(|info| {
+            let path = shown(&info.path);
+            patterns.iter().any(|pattern| pattern.is_match(&path))
+        })
+        .collect()
+}
+
+/// Puts the directories to delete into `order`, leaving them as they are for `None`.
+fn order_for_deletion(selected: &mut [&TargetDirInfo], order: Option<DeleteOrder>) {
+    match order {
+        None => {}
+        Some(DeleteOrder::LargestFirst) => selected.sort_by_key(|info| Reverse(info.size)),
+        Some(DeleteOrder::SmallestFirst) => selected.sort_by_key(|info| info.size),
+        Some(DeleteOrder::Path) => selected.sort_by(|a, b| a.path.cmp(&b.path)),
+    }
+}
+
+/// Refuses to delete anything that isn't named like a target directory, or a triple inside one,
+/// or doesn't still look like wasm output.
+fn check_target_names(selected: &[&TargetDirInfo], case_insensitive: bool) -> std::io::Result<()> {
+    match selected
+        .iter()
+        .find(|info| !looks_like_target(info, case_insensitive))
+    {
+        Some(info) => Err(refused(&info.path, "it is not a target directory")),
+        None => Ok(()),
+    }
+}
+
+/// The error deleting stops with when one of the directories mustn't be, see [`refused`].
+#[derive(Debug)]
+struct Refused {
+    path: PathBuf,
+    reason: &'static str,
+}
+
+impl std::fmt::Display for Refused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "refusing to delete '{}' as {}",
+            shown(&self.path),
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for Refused {}
+
+/// An error refusing to delete `path` for `reason`, which [`refused_path`] gets the path back
+/// from.
+pub(crate) fn refused(path: &Path, reason: &'static str) -> std::io::Error {
+    std::io::Error::other(Refused {
+        path: path.to_path_buf(),
+        reason,
+    })
+}
+
+/// The directory `error` refused to delete, if it's from [`refused`].
+pub(crate) fn refused_path(error: &std::io::Error) -> Option<&Path> {
+    error
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<Refused>())
+        .map(|refused| refused.path.as_path())
+}
+
+/// Returns true if `info` is still named and laid out like the kind of directory it is.
+pub(crate) fn looks_like_target(info: &TargetDirInfo, case_insensitive: bool) -> bool {
+    let named_like_target = |path: &Path| {
+        path.file_name()
+            .is_some_and(|name| is_target_name(name, case_insensitive))
+    };
+    match info.kind {
+        TargetKind::Cargo => named_like_target(&info.path),
+        TargetKind::Triple => {
+            info.path.file_name().is_some_and(triples::is_triple)
+                && info.path.parent().is_some_and(named_like_target)
+        }
+        TargetKind::Doc => {
+            info.path.file_name() == Some(OsStr::new("doc"))
+                && info.path.parent().is_some_and(named_like_target)
+        }
+        // Checked again in case it changed since the scan
+        TargetKind::WasmPack => wasm::is_wasm_pack_pkg(&info.path),
+        TargetKind::Trunk => wasm::is_trunk_dist(&info.path),
+    }
+}
+
+/// Removes a single directory, see [`handle_deletion_with`].
+pub type Remover<'a> = dyn Fn(&Path) -> std::io::Result<()> + Sync + 'a;
+
+/// Builds a rayon thread pool, with rayon's default size if `threads` is `None`.
+pub fn build_thread_pool(threads: Option<usize>) -> std::io::Result<rayon::ThreadPool> {
+    let mut builder = rayon::ThreadPoolBuilder::new();
+    if let Some(threads) = threads {
+        builder = builder.num_threads(threads);
+    }
+    builder.build().map_err(std::io::Error::other)
+}
+
+/// Deletes target directories, in the order given.
+///
+/// Prompts for which to delete unless `config.force` is set, and deletes in parallel with
+/// `config.delete_jobs` above 1.
+pub fn handle_deletion(
+    target_info: &[TargetDirInfo],
+    config: &Config,
+    output: &mut Output,
+) -> std::io::Result<()> {
+    handle_deletion_with(
+        target_info,
+        config,
+        &|path| fs::remove_dir_all(path),
+        output,
+    )
+}
+
+/// Like [`handle_deletion`], but uses `remover` to remove each directory.
+pub fn handle_deletion_with(
+    target_info: &[TargetDirInfo],
+    config: &Config,
+    remover: &Remover,
+    output: &mut Output,
+) -> std::io::Result<()> {
+    deletion_round(target_info, config, remover, output).map(|_| ())
+}
+
+/// Sizes the `--approximate` estimates in `target_info` exactly, so what's shown and counted
+/// as freed is what was really there. One that can't be sized is left out.
+fn size_exactly(
+    target_info: &[TargetDirInfo],
+    output: &mut Output,
+) -> std::io::Result<Vec<TargetDirInfo>> {
+    writeln!(
+        output.out,
+        "Sizing the estimated directories exactly before deleting"
+    )?;
+    let mut exact = Vec::with_capacity(target_info.len());
+    for info in target_info {
+        if info.size_margin.is_none() {
+            exact.push(info.clone());
+            continue;
+        }
+        match calculate_dir_size(&info.path) {
+            Ok(size) => exact.push(TargetDirInfo {
+                size,
+                size_margin: None,
+                ..info.clone()
+            }),
+            Err(e) => writeln!(
+                output.err,
+                "Skipping {}: couldn't size it: {}",
+                shown(&info.path),
+                e
+            )?,
+        }
+    }
+    Ok(exact)
+}
+
+/// How a [`deletion_round`] ended, which decides whether `--loop` goes round again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundEnd {
+    Continue,
+    /// Quit was picked, Escape was pressed, or there's no one to ask.
+    Quit,
+}
+
+/// One go at deleting from `target_info`, like [`handle_deletion_with`]. With `loop_mode` set
+/// the prompt has a Quit item.
+pub fn deletion_round(
+    target_info: &[TargetDirInfo],
+    config: &Config,
+    remover: &Remover,
+    output: &mut Output,
+) -> std::io::Result<RoundEnd> {
+    let resized;
+    let target_info = if target_info.iter().any(|info| info.size_margin.is_some()) {
+        resized = size_exactly(target_info, output)?;
+        resized.as_slice()
+    } else {
+        target_info
+    };
+    let mut keep_list = match &config.keep_file {
+        Some(file) => Some(KeepList::load(file)?),
+        None => None,
+    };
+    let candidates: Vec<TargetDirInfo> = match &keep_list {
+        Some(list) => target_info
+            .iter()
+            .filter(|info| !list.contains(&info.path))
+            .cloned()
+            .collect(),
+        None => target_info.to_vec(),
+    };
+    if let (Some(file), kept @ 1..) = (&config.keep_file, target_info.len() - candidates.len()) {
+        writeln!(
+            output.out,
+            "Leaving {} kept directories alone (listed in {})",
+            kept,
+            file.display()
+        )?;
+    }
+    let (candidates, too_big): (Vec<TargetDirInfo>, Vec<TargetDirInfo>) =
+        candidates.into_iter().partition(|info| {
+            config
+                .exclude_larger_than
+                .is_none_or(|limit| info.size <= limit)
+        });
+    if let (Some(limit), 1..) = (config.exclude_larger_than, too_big.len()) {
+        writeln!(
+            output.out,
+            "Leaving {} directories larger than {} alone",
+            too_big.len(),
+            humanize_bytes_decimal!(limit)
+        )?;
+    }
+    let (candidates, marked): (Vec<TargetDirInfo>, Vec<TargetDirInfo>) = candidates
+        .into_iter()
+        .partition(|info| config.ignore_keep_markers || !has_keep_marker(info));
+    if !marked.is_empty() {
+        writeln!(
+            output.out,
+            "Leaving {} directories with a {} marker alone",
+            marked.len(),
+            KEEP_MARKER
+        )?;
+    }
+    // Left out before anything is offered, so they can't even be picked by mistake
+    let (candidates, protected): (Vec<TargetDirInfo>, Vec<TargetDirInfo>) = candidates
+        .into_iter()
+        .partition(|info| !config.protected.covers(info));
+    if !protected.is_empty() {
+        writeln!(
+            output.out,
+            "Leaving {} protected directories alone (listed in the config file or with a {} marker)",
+            protected.len(),
+            PROTECT_MARKER
+        )?;
+    }
+    let target_info = candidates.as_slice();
+
+    if config.force {
+        guarded_delete(
+            target_info.iter().collect(),
+            config,
+            &Identity::current(),
+            remover,
+            output,
+        )?;
+        Ok(RoundEnd::Continue)
+    } else {
+        if !output.interactive {
+            writeln!(
+                output.err,
+                "Cannot prompt for deletion: not running in interactive terminal"
+            )?;
+            return Ok(RoundEnd::Quit);
+        }
+        let prompted = auto_delete_small(target_info, config, remover, output)?;
+        let target_info = prompted.as_slice();
+        if target_info.is_empty() {
+            return Ok(RoundEnd::Continue);
+        }
+        if config.review {
+            return review_targets(
+                target_info,
+                config,
+                keep_list.as_mut(),
+                &mut prompt_decision,
+                remover,
+                output,
+            )
+            .map(|()| RoundEnd::Continue);
+        }
+        writeln!(output.out, "Prompting...")?;
+        let mut items: Vec<String> = target_info
+            .iter()
+            .map(|info| {
+                format!(
+                    "{:>10}  {}",
+                    humanize_bytes_decimal!(info.size),
+                    shown(&info.path)
+                )
+            })
+            .collect();
+        let mut defaults = preselect_defaults(target_info, &config.preselect);
+        if config.loop_mode {
+            items.push("Quit".to_string());
+            defaults.push(false);
+        }
+
+        let selections = MultiSelect::new()
+            .with_prompt("Select target directories to delete (Space to select, Enter to confirm)")
+            .items(&items)
+            .defaults(&defaults)
+            .interact_opt()
+            .map_err(std::io::Error::other)?;
+        let Some(selections) = selections else {
+            return Ok(RoundEnd::Quit);
+        };
+        if selections.contains(&target_info.len()) {
+            return Ok(RoundEnd::Quit);
+        }
+
+        if selections.is_empty() {
+            writeln!(output.out, "No directories selected for deletion")?;
+            return Ok(RoundEnd::Continue);
+        }
+
+        guarded_delete(
+            selections.iter().map(|&idx| &target_info[idx]).collect(),
+            config,
+            &Identity::current(),
+            remover,
+            output,
+        )?;
+        Ok(RoundEnd::Continue)
+    }
+}
+
+/// Deletes `selected` once it's been through the checks every deletion gets: our own and other
+/// processes' working directories, git tracked files, whether `identity` may remove each and
+/// the directory names. What's left is deleted in `--delete-order`, stopping at the first
+/// failure.
+fn guarded_delete(
+    selected: Vec<&TargetDirInfo>,
+    config: &Config,
+    identity: &Identity,
+    remover: &Remover,
+    output: &mut Output,
+) -> std::io::Result<()> {
+    let mut selected = check_cwd_conflicts(selected, output)?;
+    selected = check_git_tracked(selected, config, output)?;
+    selected = skip_without_permission(selected, identity, output.err)?;
+    check_target_names(&selected, config.case_insensitive)?;
+    order_for_deletion(&mut selected, config.delete_order);
+    if let Err(e) = delete_selected(&selected, config, remover, output.out) {
+        writeln!(output.err, "Failed to delete: '{}' - giving up now!", e)?;
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Deletes the targets with fewer than `auto_under_files` files without asking, returning the
+/// rest to prompt for.
+///
+/// Targets whose file count isn't known, such as ones resumed from a checkpoint, are asked about.
+pub fn auto_delete_small(
+    target_info: &[TargetDirInfo],
+    config: &Config,
+    remover: &Remover,
+    output: &mut Output,
+) -> std::io::Result<Vec<TargetDirInfo>> {
+    auto_delete_small_as(target_info, config, &Identity::current(), remover, output)
+}
+
+/// Like [`auto_delete_small`], leaving out the small targets `identity` couldn't remove.
+fn auto_delete_small_as(
+    target_info: &[TargetDirInfo],
+    config: &Config,
+    identity: &Identity,
+    remover: &Remover,
+    output: &mut Output,
+) -> std::io::Result<Vec<TargetDirInfo>> {
+    let Some(threshold) = config.auto_under_files else {
+        return Ok(target_info.to_vec());
+    };
+    let (small, prompted): (Vec<&TargetDirInfo>, Vec<&TargetDirInfo>) = target_info
+        .iter()
+        .partition(|info| info.file_count.is_some_and(|count| count < threshold));
+    if !small.is_empty() {
+        writeln!(
+            output.out,
+            "Deleting {} directories with fewer than {} files without asking",
+            small.len(),
+            threshold
+        )?;
+        guarded_delete(small, config, identity, remover, output)?;
+    }
+    Ok(prompted.into_iter().cloned().collect())
+}
+
+/// What to do with a directory in `--review`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Delete,
+    /// Leave it, and don't ask about it again.
+    Keep,
+    /// Leave it for now.
+    Skip,
+}
+
+fn prompt_decision(info: &TargetDirInfo) -> std::io::Result<Decision> {
+    let choice = Select::new()
+        .with_prompt(format!(
+            "{:>10}  {}",
+            humanize_bytes_decimal!(info.size),
+            shown(&info.path)
+        ))
+        .items(["Delete", "Keep (never ask again)", "Skip for now"])
+        .default(2)
+        .interact()
+        .map_err(std::io::Error::other)?;
+    Ok(match choice {
+        0 => Decision::Delete,
+        1 => Decision::Keep,
+        _ => Decision::Skip,
+    })
+}
+
+/// Asks `decide` about each target, deleting the ones it picks and adding kept ones to
+/// `keep_list`.
+///
+/// Keep decisions are saved as they're made, so they survive the run being interrupted.
+/// Without a keep list, keeping is the same as skipping.
+pub fn review_targets(
+    target_info: &[TargetDirInfo],
+    config: &Config,
+    mut keep_list: Option<&mut KeepList>,
+    decide: &mut dyn FnMut(&TargetDirInfo) -> std::io::Result<Decision>,
+    remover: &Remover,
+    output: &mut Output,
+) -> std::io::Result<()> {
+    let mut to_delete = Vec::new();
+    for info in target_info {
+        match decide(info)? {
+            Decision::Delete => to_delete.push(info),
+            Decision::Keep => match keep_list.as_deref_mut() {
+                Some(list) => {
+                    list.insert(&info.path);
+                    list.save()?;
+                }
+                None => writeln!(
+                    output.err,
+                    "No state file to remember '{}' in, skipping it for now",
+                    shown(&info.path)
+                )?,
+            },
+            Decision::Skip => {}
+        }
+    }
+
+    if to_delete.is_empty() {
+        writeln!(output.out, "No directories selected for deletion")?;
+        return Ok(());
+    }
+    guarded_delete(to_delete, config, &Identity::current(), remover, output)
+}
+
+/// Deletes the target directory of the single project at `project`, see [`Command::CleanProject`].
+///
+/// Returns the directory it found, if there was one.
+pub fn clean_project(
+    project: &Path,
+    config: &Config,
+    output: &mut Output,
+) -> std::io::Result<Option<TargetDirInfo>> {
+    clean_project_with(
+        project,
+        config,
+        |name| std::env::var(name).ok(),
+        &|path| fs::remove_dir_all(path),
+        output,
+    )
+}
+
+/// Like [`clean_project`], reading the environment through `env` and removing with `remover`.
+pub fn clean_project_with(
+    project: &Path,
+    config: &Config,
+    env: impl Fn(&str) -> Option<String>,
+    remover: &Remover,
+    output: &mut Output,
+) -> std::io::Result<Option<TargetDirInfo>> {
+    let target = project::resolve_target_dir(project, env);
+    if !target.is_dir() {
+        writeln!(output.out, "No target directory at {}", target.display())?;
+        return Ok(None);
+    }
+    let info = TargetDirInfo::new(target.clone(), calculate_dir_size(&target)?);
+    writeln!(
+        output.out,
+        "{} is using {}",
+        target.display(),
+        humanize_bytes_decimal!(info.size)
+    )?;
+    handle_deletion_with(std::slice::from_ref(&info), config, remover, output)?;
+    Ok(Some(info))
+}
+
+/// Lists the subdirectories of `path` other than the `keep` most recently modified ones.
+pub fn subdirs_beyond_newest(path: &Path, keep: usize) -> std::io::Result<Vec<PathBuf>> {
+    let mut subdirs = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            let modified = entry.metadata()?.modified().ok();
+            subdirs.push((modified, entry.path()));
+        }
+    }
+    // Newest first, anything without a modification time counts as oldest
+    subdirs.sort_by_key(|(modified, _)| Reverse(*modified));
+    Ok(subdirs
+        .into_iter()
+        .skip(keep)
+        .map(|(_, path)| path)
+        .collect())
+}
+
+/// What removing a selected target did.
+enum Removed {
+    Whole,
+    /// Old subdirectories were removed as per `--keep-newest`.
+    Subdirs(usize),
+}
+
+fn remove_target(
+    info: &TargetDirInfo,
+    keep_newest: Option<usize>,
+    remover: &Remover,
+) -> std::io::Result<Removed> {
+    match keep_newest {
+        None => remover(&info.path).map(|()| Removed::Whole),
+        Some(keep) => {
+            let old = subdirs_beyond_newest(&info.path, keep)?;
+            for path in &old {
+                remover(path)?;
+            }
+            Ok(Removed::Subdirs(old.len()))
+        }
+    }
+}
+
+/// What a call to delete removed, for the summary written after it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeletionReport {
+    /// Each target removed whole, with its size and the files in it if they were counted.
+    pub removed: Vec<(PathBuf, u64, Option<u64>)>,
+}
+
+impl DeletionReport {
+    fn add(&mut self, info: &TargetDirInfo, removed: &Removed) {
+        if let Removed::Whole = removed {
+            self.removed
+                .push((info.path.clone(), info.size, info.file_count));
+        }
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.removed.iter().map(|(_, size, _)| size).sum()
+    }
+
+    /// The files removed, of the targets whose files were counted.
+    pub fn files(&self) -> Option<u64> {
+        self.removed
+            .iter()
+            .filter_map(|(_, _, files)| *files)
+            .reduce(|a, b| a + b)
+    }
+}
+
+/// `, 1200 files` to follow a size, if they're known.
+fn files_suffix(files: Option<u64>) -> String {
+    match files {
+        Some(1) => ", 1 file".to_string(),
+        Some(files) => format!(", {files} files"),
+        None => String::new(),
+    }
+}
+
+fn write_deleted(
+    out: &mut dyn Write,
+    info: &TargetDirInfo,
+    removed: &Removed,
+    moved_to: Option<&Path>,
+) -> std::io::Result<()> {
+    match removed {
+        Removed::Whole if let Some(dir) = moved_to => writeln!(
+            out,
+            "Moved '{}' into {} successfully, ({}){}",
+            shown(&info.path),
+            dir.display(),
+            humanize_bytes_decimal!(info.size),
+            files_suffix(info.file_count)
+        ),
+        Removed::Whole => writeln!(
+            out,
+            "Deleted '{}' successfully, ({}){}",
+            shown(&info.path),
+            humanize_bytes_decimal!(info.size),
+            files_suffix(info.file_count)
+        ),
+        Removed::Subdirs(count) => writeln!(
+            out,
+            "Removed {} old subdirectories from '{}'",
+            count,
+            shown(&info.path)
+        ),
+    }
+}
+
+/// Says what removing `info` did, adding it to `report` and the log if there is one.
+fn report_removed(
+    out: &mut dyn Write,
+    log: Option<&mut DeletionLog>,
+    report: &mut DeletionReport,
+    info: &TargetDirInfo,
+    removed: &Removed,
+    config: &Config,
+) -> std::io::Result<()> {
+    write_deleted(out, info, removed, config.move_to.as_deref())?;
+    report.add(info, removed);
+    let Some(log) = log else {
+        return Ok(());
+    };
+    let kind = match removed {
+        Removed::Whole if config.move_to.is_some() => LogKind::Moved,
+        Removed::Whole => LogKind::Deleted,
+        Removed::Subdirs(_) => LogKind::Trimmed,
+    };
+    log.record(kind, info)
+}
+
+/// Writes how many files `report` removed, where they were counted. With `inodes`, the
+/// directory they were counted in and its stats from before deleting, the free inodes before
+/// and after are written too.
+fn write_deletion_summary(
+    out: &mut dyn Write,
+    report: &DeletionReport,
+    inodes: Option<(&Path, FsStats)>,
+) -> std::io::Result<()> {
+    if let Some(files) = report.files() {
+        writeln!(
+            out,
+            "Removed {} files from {} directories, ({})",
+            files,
+            report.removed.len(),
+            humanize_bytes_decimal!(report.bytes())
+        )?;
+    }
+    let Some((dir, before)) = inodes else {
+        return Ok(());
+    };
+    match (
+        before.free_inodes,
+        fs_stats(dir).map(|after| after.free_inodes),
+    ) {
+        (Some(before), Ok(Some(after))) => writeln!(
+            out,
+            "Free inodes on the filesystem of '{}': {} before, {} after",
+            shown(dir),
+            before,
+            after
+        ),
+        _ => writeln!(out, "Free inode counts aren't available here"),
+    }
+}
+
+/// Removes each of `selected`, stopping at the first failure, returning what was removed.
+///
+/// Sequential deletion reports as it goes, parallel deletion reports in order once finished.
+/// With `dry_run` nothing is removed, only reported and logged as what would be.
+fn delete_selected(
+    selected: &[&TargetDirInfo],
+    config: &Config,
+    remover: &Remover,
+    out: &mut dyn Write,
+) -> std::io::Result<DeletionReport> {
+    refuse_protected(selected, &config.protected)?;
+    let mut report = DeletionReport::default();
+    let mut log = config
+        .log_file
+        .as_deref()
+        .map(DeletionLog::open)
+        .transpose()?;
+    if config.dry_run {
+        for info in selected {
+            writeln!(
+                out,
+                "Would delete '{}', ({})",
+                shown(&info.path),
+                humanize_bytes_decimal!(info.size)
+            )?;
+            if let Some(log) = &mut log {
+                log.record(LogKind::Dry, info)?;
+            }
+        }
+        return Ok(report);
+    }
+    // Looked at from the parent, which is still there afterwards
+    let inodes = match selected.first().and_then(|info| info.path.parent()) {
+        Some(dir) if config.report_inodes => Some((dir, fs_stats(dir)?)),
+        _ => None,
+    };
+    if config.delete_jobs <= 1 {
+        for info in selected {
+            let removed = remove_target(info, config.keep_newest, remover)?;
+            report_removed(out, log.as_mut(), &mut report, info, &removed, config)?;
+        }
+        write_deletion_summary(out, &report, inodes)?;
+        return Ok(report);
+    }
+
+    // Each job takes the next target off a shared queue, so they're started in the order
+    // chosen rather than the halves a parallel iterator would split them into
+    let failed = AtomicBool::new(false);
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<Option<std::io::Result<Removed>>> = std::iter::repeat_with(|| None)
+        .take(selected.len())
+        .collect();
+    std::thread::scope(|scope| {
+        let jobs: Vec<_> = (0..config.delete_jobs.min(selected.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    while !failed.load(Ordering::Relaxed) {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(info) = selected.get(i) else {
+                            break;
+                        };
+                        let result = remove_target(info, config.keep_newest, remover);
+                        if result.is_err() {
+                            failed.store(true, Ordering::Relaxed);
+                        }
+                        done.push((i, result));
+                    }
+                    done
+                })
+            })
+            .collect();
+        for job in jobs {
+            // A job only panics if the remover did, which is passed on as it is
+            let done = job.join().unwrap_or_else(|e| std::panic::resume_unwind(e));
+            for (i, result) in done {
+                results[i] = Some(result);
+            }
+        }
+    });
+
+    let mut first_error = None;
+    for (info, result) in selected.iter().zip(results) {
+        match result {
+            Some(Ok(removed)) => {
+                report_removed(out, log.as_mut(), &mut report, info, &removed, config)?
+            }
+            Some(Err(e)) => {
+                first_error.get_or_insert(e);
+            }
+            None => {}
+        }
+    }
+    match first_error {
+        Some(e) => Err(e),
+        None => {
+            write_deletion_summary(out, &report, inodes)?;
+            Ok(report)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::NoopReporter;
+    use std::fs::File;
+    use std::io::Write;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    fn forced(delete_jobs: usize) -> Config {
+        Config {
+            force: true,
+            delete_jobs,
+            ..Config::default()
+        }
+    }
+
+    fn discover(base: &Path, reporter: &dyn Reporter) -> Vec<PathBuf> {
+        discover_targets(base, &ScanOptions::default(), reporter, &IoStats::default())
+            .unwrap()
+            .target_dirs
+    }
+
+    #[test]
+    fn test_env_vars_match_options() {
+        let vars = env_vars();
+        for var in &vars {
+            let option = var.option.trim_start_matches("--");
+            if option != "TARGET_DIR" {
+                assert_eq!(
+                    var.name,
+                    format!(
+                        "CLEAN_BIG_TARGETS_{}",
+                        option.replace('-', "_").to_uppercase()
+                    )
+                );
+            }
+        }
+        assert!(
+            vars.iter()
+                .any(|var| var.name == "CLEAN_BIG_TARGETS_THREADS")
+        );
+        assert!(
+            !vars
+                .iter()
+                .any(|var| var.option == "--delete" || var.option == "--force")
+        );
+    }
+
+    #[test]
+    fn test_conflicting_options() {
+        let conflict = |args: &[&str]| {
+            let cli = Cli::try_parse_from(
+                std::iter::once("clean-big-targets").chain(args.iter().copied()),
+            )
+            .unwrap();
+            conflicting_options(&cli)
+        };
+        assert_eq!(conflict(&[]), None);
+        assert_eq!(conflict(&["--docs-only"]), None);
+        assert_eq!(
+            conflict(&["--docs-only", "--wasm"]),
+            Some(("--docs-only", "--wasm"))
+        );
+        assert_eq!(
+            conflict(&["--triple", "wasm32-unknown-unknown", "--docs-only"]),
+            Some(("--docs-only", "--triple"))
+        );
+        assert_eq!(
+            conflict(&["--title", "Builds", "--no-header"]),
+            Some(("--no-header", "--title"))
+        );
+        assert_eq!(
+            conflict(&["--approximate", "--older-than-file", "Cargo.toml"]),
+            Some(("--older-than-file", "--approximate"))
+        );
+        assert_eq!(
+            conflict(&["--approximate", "--resume-scan"]),
+            Some(("--approximate", "--resume-scan"))
+        );
+        assert_eq!(
+            conflict(&["--size-cache", "--fast-scan"]),
+            Some(("--size-cache", "--fast-scan"))
+        );
+        assert_eq!(
+            conflict(&["--hide-kept", "--ignore-keep-markers"]),
+            Some(("--hide-kept", "--ignore-keep-markers"))
+        );
+    }
+
+    #[test]
+    fn test_calculate_dir_size_empty() {
+        let temp_dir = TempDir::new().unwrap();
+        let size = calculate_dir_size(temp_dir.path()).unwrap();
+        assert_eq!(size, 0);
+    }
+
+    #[test]
+    fn test_calculate_dir_size_single_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test.txt");
+        let mut file = File::create(&file_path).unwrap();
+        file.write_all(b"Hello, World!").unwrap();
+
+        let size = calculate_dir_size(temp_dir.path()).unwrap();
+        assert_eq!(size, 13); // "Hello, World!" is 13 bytes
+    }
+
+    #[test]
+    fn test_calculate_dir_size_multiple_files() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let mut file1 = File::create(temp_dir.path().join("file1.txt")).unwrap();
+        file1.write_all(b"12345").unwrap();
+
+        let mut file2 = File::create(temp_dir.path().join("file2.txt")).unwrap();
+        file2.write_all(b"67890").unwrap();
+
+        let size = calculate_dir_size(temp_dir.path()).unwrap();
+        assert_eq!(size, 10);
+    }
+
+    #[test]
+    fn test_sizing_skips_vanished_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        let doomed = target.join("incremental");
+        fs::create_dir_all(&doomed).unwrap();
+        fs::write(doomed.join("query-cache.bin"), [0u8; 100]).unwrap();
+        // With the directory, exactly enough entries for progress to be sent once the listing
+        // is done and before going into it
+        let files = progress::PROGRESS_EVERY - 1;
+        for file in 0..files {
+            fs::write(target.join(format!("{file}.o")), [0u8]).unwrap();
+        }
+        // Stands in for a build cleaning up while the walk is between listing and descending
+        let removed = AtomicBool::new(false);
+        let cleanup = |_: SizeProgress| {
+            if !removed.swap(true, Ordering::Relaxed) {
+                fs::remove_dir_all(&doomed).unwrap();
+            }
+        };
+        let tracker = Tracker::new(&cleanup);
+        let vanished = Cell::new(0);
+        let file_count = Cell::new(0);
+        let size = Sizing {
+            stats: &IoStats::default(),
+            fds: &FdBudget::default(),
+            hardlinks: None,
+            newest_access: None,
+            newest_modified: None,
+            file_count: Some(&file_count),
+            vanished: Some(&vanished),
+            fingerprint: None,
+            progress: Some(&tracker),
+            cache: None,
+            deadline: Deadline::default(),
+            fast: false,
+        }
+        .size(&target)
+        .unwrap();
+        assert!(removed.load(Ordering::Relaxed));
+        assert_eq!((size, file_count.get(), vanished.get()), (files, files, 1));
+
+        // A target gone entirely is nothing to size, as before
+        assert_eq!(
+            calculate_dir_size_bounded(&doomed, &IoStats::default(), &FdBudget::default()).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_calculate_dir_size_nested() {
+        let temp_dir = TempDir::new().unwrap();
+        let nested_dir = temp_dir.path().join("nested");
+        fs::create_dir(&nested_dir).unwrap();
+
+        let mut file1 = File::create(temp_dir.path().join("top.txt")).unwrap();
+        file1.write_all(b"abc").unwrap();
+
+        let mut file2 = File::create(nested_dir.join("nested.txt")).unwrap();
+        file2.write_all(b"defgh").unwrap();
+
+        let size = calculate_dir_size(temp_dir.path()).unwrap();
+        assert_eq!(size, 8);
+    }
+
+    #[test]
+    fn test_find_target_dirs_none_found() {
+        let temp_dir = TempDir::new().unwrap();
+        let result = discover(temp_dir.path(), &NoopReporter);
+        assert_eq!(result.len(), 0);
+    }
+
+    #[test]
+    fn test_find_target_dirs_single() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_dir = temp_dir.path().join("project1");
+        fs::create_dir(&project_dir).unwrap();
+        fs::create_dir(project_dir.join("target")).unwrap();
+
+        let result = discover(temp_dir.path(), &NoopReporter);
+        assert_eq!(result.len(), 1);
+        assert!(result[0].ends_with("project1/target"));
+    }
+
+    #[test]
+    fn test_find_target_dirs_multiple() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let project1 = temp_dir.path().join("project1");
+        fs::create_dir(&project1).unwrap();
+        fs::create_dir(project1.join("target")).unwrap();
+
+        let project2 = temp_dir.path().join("project2");
+        fs::create_dir(&project2).unwrap();
+        fs::create_dir(project2.join("target")).unwrap();
+
+        let project3 = temp_dir.path().join("project3");
+        fs::create_dir(&project3).unwrap();
+
+        let result = discover(temp_dir.path(), &NoopReporter);
+        assert_eq!(result.len(), 2);
+    }
+
+    #[test]
+    fn test_find_target_dirs_child_is_target() {
+        let temp_dir = TempDir::new().unwrap();
+
+        // Create a child directory named "target"
+        let target_dir = temp_dir.path().join("target");
+        fs::create_dir(&target_dir).unwrap();
+
+        // Scanning the parent should find the "target" directory and return it directly
+        let result = discover(temp_dir.path(), &NoopReporter);
+        assert_eq!(result.len(), 1);
+        assert!(result[0].ends_with("target"));
+    }
+
+    #[test]
+    fn test_calculate_dir_size_on_package() {
+        // This test runs calculate_dir_size on the package base directory
+        // to verify it works on real directory structures
+        let package_dir = std::env::var("CARGO_MANIFEST_DIR")
+            .map(PathBuf::from)
+            .unwrap();
+
+        let result = calculate_dir_size(&package_dir);
+        assert!(
+            result.is_ok(),
+            "Should successfully calculate size of package directory"
+        );
+
+        let size = result.unwrap();
+        assert!(size > 0, "Package directory should have non-zero size");
+
+        // Print size for informational purposes (visible with --nocapture)
+        eprintln!("Package directory size: {}", humanize_bytes_decimal!(size));
+        assert!(humanize_bytes_decimal!(size).ends_with(" MB"))
+    }
+
+    #[test]
+    fn test_handle_deletion_refuses_cwd_inside_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let target_dir = temp_dir.path().join("project1").join("target");
+        let debug_dir = target_dir.join("debug");
+        fs::create_dir_all(&debug_dir).unwrap();
+
+        let targets = [TargetDirInfo::new(target_dir.clone(), 0)];
+        let check = |cwd: &Path| {
+            check_cwd_conflicts_in(
+                targets.iter().collect(),
+                cwd,
+                &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+            )
+        };
+
+        let error = check(&debug_dir).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("the current working directory is inside it"),
+            "{error}"
+        );
+        assert!(target_dir.exists());
+        assert_eq!(check(temp_dir.path()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_find_target_dirs_max_results() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..20 {
+            let project = temp_dir.path().join(format!("project{i}"));
+            fs::create_dir_all(project.join("target")).unwrap();
+        }
+
+        let opts = ScanOptions::new().max_results(Some(5));
+        let result =
+            discover_targets(temp_dir.path(), &opts, &NoopReporter, &IoStats::default()).unwrap();
+        assert_eq!(result.target_dirs.len(), 5);
+        assert!(result.truncated);
+
+        let opts = ScanOptions::new().max_results(Some(50));
+        let result =
+            discover_targets(temp_dir.path(), &opts, &NoopReporter, &IoStats::default()).unwrap();
+        assert_eq!(result.target_dirs.len(), 20);
+        assert!(!result.truncated);
+    }
+
+    #[test]
+    fn test_handle_deletion_size_asc_order() {
+        let mut target_info = vec![
+            TargetDirInfo::new(PathBuf::from("/nonexistent/medium/target"), 500),
+            TargetDirInfo::new(PathBuf::from("/nonexistent/large/target"), 1000),
+            TargetDirInfo::new(PathBuf::from("/nonexistent/small/target"), 10),
+        ];
+        sort_targets(&mut target_info, SortOrder::SizeAsc);
+
+        let removed = Mutex::new(Vec::new());
+        handle_deletion_with(
+            &target_info,
+            &forced(1),
+            &|path| {
+                removed.lock().unwrap().push(path.to_path_buf());
+                Ok(())
+            },
+            &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+        )
+        .unwrap();
+
+        assert_eq!(
+            removed.into_inner().unwrap(),
+            vec![
+                PathBuf::from("/nonexistent/small/target"),
+                PathBuf::from("/nonexistent/medium/target"),
+                PathBuf::from("/nonexistent/large/target"),
+            ]
+        );
+    }
+
+    #[derive(Default)]
+    struct CollectingReporter(Mutex<Vec<Event>>);
+
+    impl Reporter for CollectingReporter {
+        fn report(&self, event: Event) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn test_find_target_dirs_reports_events() {
+        let temp_dir = TempDir::new().unwrap();
+        let base = temp_dir.path().canonicalize().unwrap();
+        fs::create_dir_all(base.join("project1").join("target")).unwrap();
+        fs::create_dir(base.join("project2")).unwrap();
+        File::create(base.join("README.md")).unwrap();
+
+        let reporter = CollectingReporter::default();
+        discover(&base, &reporter);
+
+        let mut events = reporter.0.into_inner().unwrap();
+        events.sort_by_key(|event| format!("{event:?}"));
+        assert_eq!(
+            events,
+            vec![
+                Event::DirPruned(base.join("README.md"), PruneReason::NotADirectory),
+                Event::DirPruned(base.join("project2"), PruneReason::NoTargetDir),
+                Event::TargetFound(base.join("project1").join("target")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_independent_sizing_and_deletion_pools() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..6 {
+            let target = temp_dir.path().join(format!("project{i}")).join("target");
+            fs::create_dir_all(&target).unwrap();
+            let mut file = File::create(target.join("out.bin")).unwrap();
+            file.write_all(&vec![0u8; 100 * (i + 1)]).unwrap();
+        }
+        let target_dirs = discover(temp_dir.path(), &NoopReporter);
+
+        let sizing_pool = build_thread_pool(Some(3)).unwrap();
+        assert_eq!(sizing_pool.current_num_threads(), 3);
+        let target_info: Vec<TargetDirInfo> = sizing_pool.install(|| {
+            target_dirs
+                .par_iter()
+                .map(|path| TargetDirInfo::new(path.clone(), calculate_dir_size(path).unwrap()))
+                .collect()
+        });
+        let total: u64 = target_info.iter().map(|info| info.size).sum();
+        assert_eq!(total, 2100);
+
+        handle_deletion(
+            &target_info,
+            &forced(2),
+            &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+        )
+        .unwrap();
+        assert!(target_dirs.iter().all(|path| !path.exists()));
+    }
+
+    fn fake_targets() -> Vec<TargetDirInfo> {
+        vec![
+            TargetDirInfo::new(PathBuf::from("/nonexistent/one/target"), 1000),
+            TargetDirInfo::new(PathBuf::from("/nonexistent/two/target"), 2000),
+        ]
+    }
+
+    /// Deletes [`fake_targets`] non-interactively, failing on paths containing `fail_on`.
+    fn run_deletion(fail_on: &str, force: bool) -> (std::io::Result<()>, String, String) {
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let result = handle_deletion_with(
+            &fake_targets(),
+            &Config {
+                force,
+                delete_jobs: 1,
+                ..Config::default()
+            },
+            &|path| {
+                if path.to_string_lossy().contains(fail_on) {
+                    Err(std::io::Error::other("permission denied"))
+                } else {
+                    Ok(())
+                }
+            },
+            &mut Output::new(&mut out, &mut err, false),
+        );
+        (
+            result,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_auto_under_files_skips_the_prompt_for_small_targets() {
+        let mut targets = fake_targets();
+        targets[0].file_count = Some(2);
+        targets[1].file_count = Some(500);
+        targets.push(TargetDirInfo::new(
+            PathBuf::from("/nonexistent/resumed/target"),
+            10,
+        ));
+        let config = Config {
+            delete_jobs: 1,
+            auto_under_files: Some(3),
+            ..Config::default()
+        };
+        let removed = Mutex::new(Vec::new());
+        let remover = |path: &Path| {
+            removed.lock().unwrap().push(path.to_path_buf());
+            Ok(())
+        };
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+
+        let prompted = auto_delete_small(
+            &targets,
+            &config,
+            &remover,
+            &mut Output::new(&mut out, &mut err, true),
+        )
+        .unwrap();
+        let prompted: Vec<&Path> = prompted.iter().map(|info| info.path.as_path()).collect();
+        assert_eq!(
+            prompted,
+            [
+                Path::new("/nonexistent/two/target"),
+                Path::new("/nonexistent/resumed/target")
+            ]
+        );
+        assert_eq!(
+            *removed.lock().unwrap(),
+            [PathBuf::from("/nonexistent/one/target")]
+        );
+
+        // Without the option everything is prompted for
+        let config = Config {
+            auto_under_files: None,
+            ..config
+        };
+        let prompted = auto_delete_small(
+            &targets,
+            &config,
+            &remover,
+            &mut Output::new(&mut out, &mut err, true),
+        )
+        .unwrap();
+        assert_eq!(prompted.len(), 3);
+        assert_eq!(removed.lock().unwrap().len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_auto_under_files_skips_without_permission() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let locked = temp_dir.path().join("locked");
+        let open = temp_dir.path().join("open");
+        let mut targets = Vec::new();
+        for project in [&locked, &open] {
+            fs::create_dir_all(project.join("target")).unwrap();
+            fs::set_permissions(project, fs::Permissions::from_mode(0o777)).unwrap();
+            fs::set_permissions(project.join("target"), fs::Permissions::from_mode(0o777)).unwrap();
+            targets.push(TargetDirInfo {
+                file_count: Some(1),
+                ..TargetDirInfo::new(project.join("target"), 10)
+            });
+        }
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o555)).unwrap();
+        let config = Config {
+            delete_jobs: 1,
+            auto_under_files: Some(3),
+            ..Config::default()
+        };
+        let removed = Mutex::new(Vec::new());
+        let remover = |path: &Path| {
+            removed.lock().unwrap().push(path.to_path_buf());
+            Ok(())
+        };
+        // Someone who owns neither, so the locked project can't have its target removed
+        let someone = Identity {
+            uid: 4242,
+            groups: vec![4242],
+        };
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let prompted = auto_delete_small_as(
+            &targets,
+            &config,
+            &someone,
+            &remover,
+            &mut Output::new(&mut out, &mut err, true),
+        )
+        .unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert!(prompted.is_empty());
+        assert_eq!(*removed.lock().unwrap(), [open.join("target")]);
+        assert_eq!(
+            String::from_utf8(err).unwrap(),
+            format!(
+                "Permission denied on '{}', skipping '{}'\n",
+                locked.display(),
+                locked.join("target").display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_exclude_larger_than_is_never_deleted() {
+        let config = Config {
+            delete_jobs: 1,
+            force: true,
+            exclude_larger_than: Some(1000),
+            ..Config::default()
+        };
+        let removed = Mutex::new(Vec::new());
+        let remover = |path: &Path| {
+            removed.lock().unwrap().push(path.to_path_buf());
+            Ok(())
+        };
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        handle_deletion_with(
+            &fake_targets(),
+            &config,
+            &remover,
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap();
+        // One is exactly at the limit, so it can still go
+        assert_eq!(
+            *removed.lock().unwrap(),
+            [PathBuf::from("/nonexistent/one/target")]
+        );
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains("Leaving 1 directories larger than 1 kB alone")
+        );
+    }
+
+    #[test]
+    fn test_keep_marker_is_never_deleted() {
+        let temp_dir = TempDir::new().unwrap();
+        let kept = temp_dir.path().join("kept");
+        let swept = temp_dir.path().join("swept");
+        for project in [&kept, &swept] {
+            fs::create_dir_all(project.join("target")).unwrap();
+        }
+        fs::write(kept.join(KEEP_MARKER), "").unwrap();
+        let targets = [
+            TargetDirInfo::new(kept.join("target"), 10),
+            TargetDirInfo::new(swept.join("target"), 10),
+        ];
+        let delete = |ignore_keep_markers| {
+            let config = Config {
+                delete_jobs: 1,
+                force: true,
+                delete_tracked: true,
+                ignore_keep_markers,
+                ..Config::default()
+            };
+            let removed = Mutex::new(Vec::new());
+            let remover = |path: &Path| {
+                removed.lock().unwrap().push(path.to_path_buf());
+                Ok(())
+            };
+            let mut out = Vec::new();
+            let mut err = Vec::new();
+            handle_deletion_with(
+                &targets,
+                &config,
+                &remover,
+                &mut Output::new(&mut out, &mut err, false),
+            )
+            .unwrap();
+            let mut removed = removed.into_inner().unwrap();
+            removed.sort();
+            (removed, String::from_utf8(out).unwrap())
+        };
+
+        let (removed, out) = delete(false);
+        assert_eq!(removed, [swept.join("target")]);
+        assert!(out.contains("Leaving 1 directories with a .clean-big-targets-keep marker alone"));
+        let (removed, _) = delete(true);
+        assert_eq!(removed, [kept.join("target"), swept.join("target")]);
+    }
+
+    #[test]
+    fn test_review_keep_is_persisted_and_honored() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = Config {
+            delete_jobs: 1,
+            keep_file: Some(temp_dir.path().join("keep")),
+            ..Config::default()
+        };
+        let removed = Mutex::new(Vec::new());
+        let remover = |path: &Path| {
+            removed.lock().unwrap().push(path.to_path_buf());
+            Ok(())
+        };
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+
+        let mut keep_list = KeepList::load(config.keep_file.as_ref().unwrap()).unwrap();
+        review_targets(
+            &fake_targets(),
+            &config,
+            Some(&mut keep_list),
+            &mut |info| {
+                Ok(if info.path.starts_with("/nonexistent/one") {
+                    Decision::Keep
+                } else {
+                    Decision::Skip
+                })
+            },
+            &remover,
+            &mut Output::new(&mut out, &mut err, true),
+        )
+        .unwrap();
+        assert!(removed.lock().unwrap().is_empty());
+
+        // The next run deletes everything it's allowed to, which is no longer `one`
+        let mut out = Vec::new();
+        let config = Config {
+            force: true,
+            ..config
+        };
+        handle_deletion_with(
+            &fake_targets(),
+            &config,
+            &remover,
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap();
+        assert_eq!(
+            *removed.lock().unwrap(),
+            vec![PathBuf::from("/nonexistent/two/target")]
+        );
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .starts_with("Leaving 1 kept directories alone")
+        );
+    }
+
+    #[test]
+    fn test_handle_deletion_output_success() {
+        let (result, out, err) = run_deletion("nothing", true);
+        assert!(result.is_ok());
+        assert_eq!(
+            out,
+            "Deleted '/nonexistent/one/target' successfully, (1 kB)\n\
+             Deleted '/nonexistent/two/target' successfully, (2 kB)\n"
+        );
+        assert_eq!(err, "");
+    }
+
+    #[test]
+    fn test_handle_deletion_output_failure() {
+        let (result, out, err) = run_deletion("one", true);
+        assert!(result.is_err());
+        assert_eq!(out, "");
+        assert_eq!(
+            err,
+            "Failed to delete: 'permission denied' - giving up now!\n"
+        );
+    }
+
+    #[test]
+    fn test_sort_reclaimable_per_file() {
+        let target = |name: &str, size, files| TargetDirInfo {
+            file_count: files,
+            ..TargetDirInfo::new(PathBuf::from(name), size)
+        };
+        let mut target_info = vec![
+            // The biggest, but a million tiny incremental files
+            target("incremental", 4_000_000_000, Some(1_000_000)),
+            target("binaries", 1_000_000_000, Some(10)),
+            target("resumed", 9_000_000_000, None),
+            target("nothing", 0, Some(0)),
+            target("mixed", 2_000_000_000, Some(1000)),
+        ];
+        sort_targets(&mut target_info, SortOrder::ReclaimablePerFile);
+        let order: Vec<_> = target_info
+            .iter()
+            .map(|info| (info.path.to_str().unwrap(), bytes_per_file(info)))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                ("binaries", Some(100_000_000)),
+                ("mixed", Some(2_000_000)),
+                ("incremental", Some(4000)),
+                ("resumed", None),
+                ("nothing", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deletion_report_file_counts() {
+        let temp_dir = TempDir::new().unwrap();
+        // Incremental compilation leaves lots of tiny files
+        for (project, files) in [("many", 120), ("few", 3), ("one", 1)] {
+            let dir = temp_dir
+                .path()
+                .join(project)
+                .join("target")
+                .join("incremental");
+            fs::create_dir_all(&dir).unwrap();
+            for file in 0..files {
+                fs::write(dir.join(format!("{file}.bin")), [0u8; 10]).unwrap();
+            }
+        }
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            cargo_cache_dirs: Vec::new(),
+            sort: SortOrder::Files,
+            report_inodes: true,
+            delete_tracked: true,
+            ..forced(1)
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        let found: Vec<_> = result.targets.iter().map(|info| info.file_count).collect();
+        assert_eq!(found, [Some(120), Some(3), Some(1)]);
+
+        let mut out = Vec::new();
+        let selected: Vec<&TargetDirInfo> = result.targets.iter().collect();
+        let report = delete_selected(&selected, &config, &|_| Ok(()), &mut out).unwrap();
+        let target = |project: &str| temp_dir.path().join(project).join("target");
+        assert_eq!(
+            report.removed,
+            [
+                (target("many"), 1200, Some(120)),
+                (target("few"), 30, Some(3)),
+                (target("one"), 10, Some(1)),
+            ]
+        );
+        assert_eq!(report.files(), Some(124));
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("successfully, (1.2 kB), 120 files\n"), "{out}");
+        assert!(out.contains("successfully, (10 B), 1 file\n"), "{out}");
+        assert!(
+            out.contains("Removed 124 files from 3 directories, (1.2 kB)\n"),
+            "{out}"
+        );
+        #[cfg(unix)]
+        assert!(out.contains("Free inodes on the filesystem of"), "{out}");
+    }
+
+    #[test]
+    fn test_handle_deletion_output_partial() {
+        let (result, out, err) = run_deletion("two", true);
+        assert!(result.is_err());
+        assert_eq!(
+            out,
+            "Deleted '/nonexistent/one/target' successfully, (1 kB)\n"
+        );
+        assert_eq!(
+            err,
+            "Failed to delete: 'permission denied' - giving up now!\n"
+        );
+    }
+
+    #[test]
+    fn test_handle_deletion_output_cancelled() {
+        let (result, out, err) = run_deletion("nothing", false);
+        assert!(result.is_ok());
+        assert_eq!(out, "");
+        assert_eq!(
+            err,
+            "Cannot prompt for deletion: not running in interactive terminal\n"
+        );
+    }
+
+    #[test]
+    fn test_cargo_cache_excluded_by_default() {
+        let temp_dir = TempDir::new().unwrap();
+        let cargo_home = temp_dir.path().join("cargo");
+        let registry_target = cargo_home
+            .join("registry")
+            .join("src")
+            .join("somecrate")
+            .join("target");
+        let git_target = cargo_home.join("git").join("checkouts").join("target");
+        let project_target = temp_dir.path().join("project").join("target");
+        for dir in [&registry_target, &git_target, &project_target] {
+            fs::create_dir_all(dir).unwrap();
+        }
+
+        let cache_dirs = cargo_cache_dirs(|name| match name {
+            "CARGO_HOME" => Some(cargo_home.to_string_lossy().into_owned()),
+            _ => None,
+        });
+        assert_eq!(
+            cache_dirs,
+            vec![cargo_home.join("registry"), cargo_home.join("git")]
+        );
+
+        let result = exclude_cargo_caches(
+            vec![
+                registry_target.clone(),
+                git_target.clone(),
+                project_target.clone(),
+            ],
+            &cache_dirs,
+            &NoopReporter,
+        );
+        assert_eq!(result, vec![project_target]);
+    }
+
+    #[test]
+    fn test_collapse_aliases_keeps_the_shortest_path() {
+        let ids = |path: &Path| match path.to_str()? {
+            "/home/me/src/app/target" | "/ws/app/target" => Some((1, 100)),
+            "/mnt/containers/ws/lib/target" | "/home/me/src/lib/target" => Some((1, 200)),
+            "/elsewhere/other/target" => Some((2, 100)),
+            _ => None,
+        };
+        let reporter = CollectingReporter(Mutex::new(Vec::new()));
+        let collapsed = collapse_aliases(
+            [
+                "/home/me/src/app/target",
+                "/mnt/containers/ws/lib/target",
+                "/unknown/target",
+                "/ws/app/target",
+                "/elsewhere/other/target",
+                "/home/me/src/lib/target",
+                "/unknown/target",
+            ]
+            .map(PathBuf::from)
+            .to_vec(),
+            ids,
+            &reporter,
+        );
+        // Without an id nothing can be said, so those are left alone
+        assert_eq!(
+            collapsed,
+            [
+                "/ws/app/target",
+                "/home/me/src/lib/target",
+                "/unknown/target",
+                "/elsewhere/other/target",
+                "/unknown/target",
+            ]
+            .map(PathBuf::from)
+        );
+        assert_eq!(
+            *reporter.0.lock().unwrap(),
+            [
+                Event::DirPruned(
+                    PathBuf::from("/home/me/src/app/target"),
+                    PruneReason::AliasOf(PathBuf::from("/ws/app/target"))
+                ),
+                Event::DirPruned(
+                    PathBuf::from("/mnt/containers/ws/lib/target"),
+                    PruneReason::AliasOf(PathBuf::from("/home/me/src/lib/target"))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scan_with_config() {
+        let temp_dir = TempDir::new().unwrap();
+        for (name, size) in [("small", 10), ("large", 1000)] {
+            let target = temp_dir.path().join(name).join("target");
+            fs::create_dir_all(&target).unwrap();
+            let mut file = File::create(target.join("out.bin")).unwrap();
+            file.write_all(&vec![0u8; size]).unwrap();
+        }
+
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            sort: SortOrder::SizeAsc,
+            cargo_cache_dirs: Vec::new(),
+            threads: Some(2),
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+
+        assert!(!result.truncated);
+        assert!(result.errors.is_empty());
+        let sizes: Vec<u64> = result.targets.iter().map(|info| info.size).collect();
+        assert_eq!(sizes, vec![10, 1000]);
+        assert!(result.targets[0].path.ends_with("small/target"));
+        assert!(result.targets.iter().all(|info| info.modified.is_some()));
+        assert!(result.targets.iter().all(|info| !info.empty));
+    }
+
+    #[test]
+    fn test_find_targets_counts_without_sizing() {
+        let temp_dir = TempDir::new().unwrap();
+        for name in ["one", "two", "three", "kept"] {
+            let target = temp_dir.path().join(name).join("target");
+            fs::create_dir_all(&target).unwrap();
+            fs::write(target.join("out.bin"), vec![0u8; 100]).unwrap();
+        }
+        fs::write(temp_dir.path().join("kept").join(KEEP_MARKER), "").unwrap();
+
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            cargo_cache_dirs: Vec::new(),
+            hide_kept: true,
+            // Needs sizes, so it doesn't apply
+            min_size: Some(1_000_000),
+            ..Config::default()
+        };
+        let result = find_targets(&config, &NoopReporter).unwrap();
+        assert_eq!(result.targets.len(), 3);
+        assert!(result.targets.iter().all(|info| info.size == 0));
+        assert!(scan(&config, &NoopReporter).unwrap().targets.is_empty());
+    }
+
+    #[test]
+    fn test_preselect_defaults() {
+        let target_info = vec![
+            TargetDirInfo::new(PathBuf::from("/home/me/src/experiments/one/target"), 10),
+            TargetDirInfo::new(PathBuf::from("/home/me/src/work/target"), 10),
+            TargetDirInfo::new(PathBuf::from("/home/me/scratch/target"), 10),
+        ];
+
+        assert_eq!(
+            preselect_defaults(&target_info, &[]),
+            vec![false, false, false]
+        );
+        assert_eq!(
+            preselect_defaults(
+                &target_info,
+                &[
+                    Regex::new("experiments").unwrap(),
+                    Regex::new("^/home/me/scratch/").unwrap(),
+                ]
+            ),
+            vec![true, false, true]
+        );
+    }
+
+    #[test]
+    fn test_preselect_invalid_regex_fails_parsing() {
+        assert!(Cli::try_parse_from(["clean-big-targets", "--preselect", "("]).is_err());
+        let cli =
+            Cli::try_parse_from(["clean-big-targets", "--preselect", "a", "--preselect", "b"])
+                .unwrap();
+        assert_eq!(cli.preselect.len(), 2);
+    }
+
+    #[test]
+    fn test_scan_marks_empty_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("empty").join("target")).unwrap();
+        let zero_size = temp_dir.path().join("zero").join("target");
+        fs::create_dir_all(&zero_size).unwrap();
+        File::create(zero_size.join("empty.txt")).unwrap();
+
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        assert_eq!(result.targets.len(), 2);
+        for info in &result.targets {
+            assert_eq!(info.size, 0);
+            assert_eq!(info.empty, info.path.ends_with("empty/target"));
+            assert_eq!(info.file_count, Some(if info.empty { 0 } else { 1 }));
+        }
+    }
+
+    #[test]
+    fn test_find_target_dirs_case_insensitive() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("project1").join("Target")).unwrap();
+
+        let opts = ScanOptions::new().case_insensitive(true);
+        let result =
+            discover_targets(temp_dir.path(), &opts, &NoopReporter, &IoStats::default()).unwrap();
+        assert_eq!(result.target_dirs.len(), 1);
+    }
+
+    #[test]
+    fn test_ignore_case_flag_matches_any_case() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("project1").join("Target")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("project2").join("TARGET")).unwrap();
+
+        let root = temp_dir.path().to_str().unwrap();
+        let cli = Cli::try_parse_from(["clean-big-targets", "--ignore-case", root]).unwrap();
+        assert!(cli.case_insensitive);
+        let result = scan(&Config::from(&cli), &NoopReporter).unwrap();
+        assert_eq!(result.targets.len(), 2);
+    }
+
+    #[test]
+    fn test_config_default_ignores_environment() {
+        let config = Config::default();
+        assert!(config.cargo_cache_dirs.is_empty());
+        assert_eq!(config.rustup_home, None);
+
+        // Only the command line looks them up
+        let cli = Cli::try_parse_from(["clean-big-targets"]).unwrap();
+        let config = Config::from(&cli);
+        let env = |name: &str| std::env::var(name).ok();
+        assert_eq!(config.cargo_cache_dirs, cargo_cache_dirs(env));
+        assert_eq!(config.rustup_home, rustup_home_dir(env));
+    }
+
+    #[test]
+    fn test_discover_targets_options_are_independent() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..4 {
+            fs::create_dir_all(temp_dir.path().join(format!("lower{i}")).join("target")).unwrap();
+            fs::create_dir_all(temp_dir.path().join(format!("upper{i}")).join("TARGET")).unwrap();
+        }
+        let run = |opts: &ScanOptions| {
+            discover_targets(temp_dir.path(), opts, &NoopReporter, &IoStats::default()).unwrap()
+        };
+
+        // A limit alone still matches case-sensitively
+        let result = run(&ScanOptions::new()
+            .case_insensitive(false)
+            .max_results(Some(2)));
+        assert_eq!(result.target_dirs.len(), 2);
+        assert!(result.truncated);
+        assert!(result.target_dirs.iter().all(|p| p.ends_with("target")));
+
+        // Ignoring case alone doesn't limit anything
+        let result = run(&ScanOptions::new().case_insensitive(true).max_results(None));
+        assert_eq!(result.target_dirs.len(), 8);
+        assert!(!result.truncated);
+
+        let result = run(&ScanOptions::new()
+            .case_insensitive(true)
+            .max_results(Some(6)));
+        assert_eq!(result.target_dirs.len(), 6);
+        assert!(result.truncated);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_deprecated_find_target_dirs_wrappers() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..3 {
+            fs::create_dir_all(temp_dir.path().join(format!("project{i}")).join("target")).unwrap();
+        }
+        assert_eq!(
+            find_target_dirs(temp_dir.path(), &NoopReporter)
+                .unwrap()
+                .len(),
+            3
+        );
+        let result = find_target_dirs_limited(
+            temp_dir.path(),
+            &NoopReporter,
+            Some(2),
+            false,
+            &IoStats::default(),
+        )
+        .unwrap();
+        assert_eq!(result.target_dirs.len(), 2);
+        assert!(result.truncated);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_find_target_dirs_case_sensitive_on_linux() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("project1").join("Target")).unwrap();
+
+        let result = discover(temp_dir.path(), &NoopReporter);
+        assert!(result.is_empty());
+    }
+
+    #[cfg(any(windows, target_os = "macos"))]
+    #[test]
+    fn test_find_target_dirs_case_insensitive_by_default() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("project1").join("Target")).unwrap();
+
+        let result = discover(temp_dir.path(), &NoopReporter);
+        assert_eq!(result.len(), 1);
+    }
+
+    #[test]
+    fn test_clean_project_removes_only_its_target() {
+        let temp_dir = TempDir::new().unwrap();
+        for project in ["chosen", "sibling"] {
+            let target = temp_dir.path().join(project).join("target");
+            fs::create_dir_all(target.join("debug")).unwrap();
+            fs::write(target.join("debug").join("artifact"), vec![0u8; 100]).unwrap();
+        }
+
+        let mut out = Vec::new();
+        let info = clean_project_with(
+            &temp_dir.path().join("chosen"),
+            &forced(1),
+            |_| None,
+            &|path| fs::remove_dir_all(path),
+            &mut Output::new(&mut out, &mut std::io::sink(), false),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(info.size, 100);
+        assert!(!temp_dir.path().join("chosen").join("target").exists());
+        assert!(temp_dir.path().join("sibling").join("target").exists());
+        assert!(String::from_utf8(out).unwrap().contains("is using 100 B"));
+
+        let info = clean_project_with(
+            &temp_dir.path().join("chosen"),
+            &forced(1),
+            |_| None,
+            &|_| panic!("nothing to remove"),
+            &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+        )
+        .unwrap();
+        assert!(info.is_none());
+    }
+
+    #[test]
+    fn test_handle_deletion_skips_git_tracked_targets() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path().join("repo");
+        let plain = temp_dir.path().join("plain");
+        for project in [&repo, &plain] {
+            fs::create_dir_all(project.join("target")).unwrap();
+            fs::write(project.join("target").join("vendored.a"), "x").unwrap();
+        }
+        let git = |args: &[&str]| {
+            std::process::Command::new("git")
+                .arg("-C")
+                .arg(&repo)
+                .args(args)
+                .output()
+                .is_ok_and(|output| output.status.success())
+        };
+        if !(git(&["init", "-q"]) && git(&["add", "target"])) {
+            // Without git there's nothing tracked, as the other deletion tests show
+            return;
+        }
+        let target_info = [
+            TargetDirInfo::new(repo.join("target"), 1),
+            TargetDirInfo::new(plain.join("target"), 1),
+        ];
+        let removed = Mutex::new(Vec::new());
+        let remover = |path: &Path| {
+            removed.lock().unwrap().push(path.to_path_buf());
+            Ok(())
+        };
+
+        let mut err = Vec::new();
+        handle_deletion_with(
+            &target_info,
+            &forced(1),
+            &remover,
+            &mut Output::new(&mut std::io::sink(), &mut err, false),
+        )
+        .unwrap();
+        assert_eq!(*removed.lock().unwrap(), [plain.join("target")]);
+        assert!(String::from_utf8(err).unwrap().contains("--delete-tracked"));
+
+        removed.lock().unwrap().clear();
+        let config = Config {
+            delete_tracked: true,
+            ..forced(1)
+        };
+        handle_deletion_with(
+            &target_info,
+            &config,
+            &remover,
+            &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+        )
+        .unwrap();
+        assert_eq!(removed.into_inner().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_handle_deletion_checks_target_name() {
+        let target_info = [TargetDirInfo::new(
+            PathBuf::from("/nonexistent/project/Target"),
+            10,
+        )];
+        let remover = |_: &Path| Ok(());
+
+        let mut config = forced(1);
+        config.case_insensitive = false;
+        let result = handle_deletion_with(
+            &target_info,
+            &config,
+            &remover,
+            &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+        );
+        assert!(result.is_err());
+
+        config.case_insensitive = true;
+        let result = handle_deletion_with(
+            &target_info,
+            &config,
+            &remover,
+            &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+        );
+        assert!(result.is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_target_dirs_ignores_symlink_outside_root() {
+        let root = TempDir::new().unwrap();
+        let outside = TempDir::new().unwrap();
+        fs::create_dir_all(outside.path().join("project").join("target")).unwrap();
+        fs::create_dir_all(root.path().join("inside").join("target")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("project"), root.path().join("escape"))
+            .unwrap();
+        std::os::unix::fs::symlink(root.path().join("inside"), root.path().join("again")).unwrap();
+
+        let reporter = CollectingReporter::default();
+        let result = discover(root.path(), &reporter);
+        assert_eq!(result.len(), 1);
+        assert!(reporter.0.into_inner().unwrap().contains(&Event::DirPruned(
+            root.path().canonicalize().unwrap().join("escape"),
+            PruneReason::OutsideRoot
+        )));
+    }
+
+    #[test]
+    fn test_scan_with_max_open_fds() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..8 {
+            // Deeper than the limit, so holding a handle per level would never finish
+            let mut dir = temp_dir.path().join(format!("project{i}")).join("target");
+            for depth in 0..6 {
+                dir = dir.join(format!("level{depth}"));
+            }
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("file"), b"12345").unwrap();
+        }
+
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            threads: Some(8),
+            max_open_fds: Some(2),
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        assert!(result.errors.is_empty());
+        assert_eq!(result.targets.len(), 8);
+        assert!(result.targets.iter().all(|info| info.size == 5));
+
+        let fds = FdBudget::new(Some(2));
+        let targets: Vec<PathBuf> = result.targets.into_iter().map(|info| info.path).collect();
+        build_thread_pool(Some(8)).unwrap().install(|| {
+            targets.par_iter().for_each(|path| {
+                calculate_dir_size_bounded(path, &IoStats::default(), &fds).unwrap();
+            })
+        });
+        assert!(fds.peak() <= 2);
+    }
+
+    #[test]
+    fn test_scan_keeps_size_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("project").join("target");
+        fs::create_dir_all(target.join("debug").join("deps").join("nested")).unwrap();
+        fs::write(target.join("CACHEDIR.TAG"), b"tag").unwrap();
+        fs::write(target.join("debug").join("app"), vec![0u8; 100]).unwrap();
+        fs::write(target.join("debug").join("deps").join("a"), vec![0u8; 20]).unwrap();
+        fs::write(
+            target.join("debug").join("deps").join("nested").join("b"),
+            vec![0u8; 5],
+        )
+        .unwrap();
+
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            tree_depth: Some(2),
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        let info = &result.targets[0];
+        assert_eq!(info.size, 128);
+        assert_eq!(info.size, calculate_dir_size(&info.path).unwrap());
+
+        let tree = info.tree.as_ref().unwrap();
+        assert_eq!(tree.total(), 128);
+        let names: Vec<&OsStr> = tree.children.iter().map(|c| c.name.as_os_str()).collect();
+        assert_eq!(names, ["CACHEDIR.TAG", "debug"]);
+        let debug = &tree.children[1];
+        assert_eq!(debug.children[0], SizeTree::file("app".into(), 100));
+        assert_eq!(
+            debug.children[1],
+            SizeTree::collapsed_dir("deps".into(), 25)
+        );
+
+        let config = Config {
+            tree_depth: None,
+            ..config
+        };
+        assert!(
+            scan(&config, &NoopReporter).unwrap().targets[0]
+                .tree
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_scan_unused_for_filters_on_access_time() {
+        let temp_dir = TempDir::new().unwrap();
+        let now = SystemTime::now();
+        for (project, days) in [("stale", 100), ("recent", 1)] {
+            let target = temp_dir.path().join(project).join("target");
+            fs::create_dir_all(&target).unwrap();
+            let file = File::create(target.join("artifact")).unwrap();
+            let accessed = now - Duration::from_secs(days * 86400);
+            file.set_times(fs::FileTimes::new().set_accessed(accessed))
+                .unwrap();
+        }
+
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        assert_eq!(result.targets.len(), 2);
+        assert!(result.targets.iter().all(|info| info.accessed.is_some()));
+
+        let config = Config {
+            unused_for: Some(Duration::from_secs(30 * 86400)),
+            ..config
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        let paths: Vec<&Path> = result
+            .targets
+            .iter()
+            .map(|info| info.path.as_path())
+            .collect();
+        assert_eq!(paths.len(), 1);
+        assert!(paths[0].ends_with("stale/target"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_watch_for_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        fs::create_dir(&target).unwrap();
+        // Back an hour, so a change now is seen however coarse the clock
+        let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        File::open(&target).unwrap().set_modified(hour_ago).unwrap();
+
+        let (size, snapshot_at, changed) =
+            watch_for_changes(&target, || calculate_dir_size(&target).unwrap());
+        assert_eq!(size, 0);
+        assert!(!changed);
+        assert!(snapshot_at > hour_ago);
+
+        // A build writing a new artifact partway through the walk
+        let (_, _, changed) = watch_for_changes(&target, || {
+            fs::write(target.join("incremental"), "x").unwrap();
+        });
+        assert!(changed);
+        let (_, _, changed) = watch_for_changes(&temp_dir.path().join("missing"), || ());
+        assert!(!changed);
+    }
+
+    #[test]
+    fn test_scan_git_only() {
+        let temp_dir = TempDir::new().unwrap();
+        for project in ["repo", "scratch"] {
+            fs::create_dir_all(temp_dir.path().join(project).join("target")).unwrap();
+        }
+        fs::create_dir(temp_dir.path().join("repo").join(".git")).unwrap();
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            cargo_cache_dirs: Vec::new(),
+            git_only: true,
+            ..Config::default()
+        };
+        let reporter = CollectingReporter::default();
+        let result = scan(&config, &reporter).unwrap();
+        let paths: Vec<&Path> = result
+            .targets
+            .iter()
+            .map(|info| info.path.as_path())
+            .collect();
+        assert_eq!(paths, [temp_dir.path().join("repo").join("target")]);
+        assert!(reporter.0.lock().unwrap().contains(&Event::DirPruned(
+            temp_dir.path().join("scratch").join("target"),
+            PruneReason::NotInGitRepo
+        )));
+    }
+
+    /// A walk made slow by pausing on each event its function picks out.
+    struct SlowReporter(fn(&Event) -> bool);
+
+    impl Reporter for SlowReporter {
+        fn report(&self, event: Event) {
+            if (self.0)(&event) {
+                std::thread::sleep(Duration::from_millis(60));
+            }
+        }
+    }
+
+    #[test]
+    fn test_scan_timeout() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..5 {
+            let target = temp_dir.path().join(format!("project{i}")).join("target");
+            fs::create_dir_all(&target).unwrap();
+            fs::write(target.join("artifact"), vec![0u8; 1000]).unwrap();
+        }
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            cargo_cache_dirs: Vec::new(),
+            threads: Some(1),
+            timeout: Some(Duration::from_millis(100)),
+            ..Config::default()
+        };
+
+        // Passing while sizing, those not yet sized are left out rather than shown short
+        let slow_sizing = SlowReporter(|event| matches!(event, Event::SizeComputed(..)));
+        let result = scan(&config, &slow_sizing).unwrap();
+        assert!(result.timed_out);
+        assert!(!result.targets.is_empty());
+        assert!(result.not_sized > 0);
+        assert_eq!(result.targets.len() + result.not_sized, 5);
+        assert!(result.targets.iter().all(|info| info.size == 1000));
+        assert!(result.errors.is_empty());
+        assert_eq!(
+            result.warnings,
+            [deadline::timed_out_warning(
+                Duration::from_millis(100),
+                result.not_sized
+            )]
+        );
+
+        // Passing while walking, the walk stops and there's no time left to size what it found
+        let slow_walk = SlowReporter(|event| matches!(event, Event::TargetFound(_)));
+        let result = scan(&config, &slow_walk).unwrap();
+        assert!(result.timed_out);
+        assert!(result.targets.is_empty());
+        assert!((1..5).contains(&result.not_sized));
+
+        let config = Config {
+            timeout: None,
+            ..config
+        };
+        let result = scan(&config, &slow_sizing).unwrap();
+        assert!(!result.timed_out);
+        assert_eq!(result.targets.len(), 5);
+    }
+
+    #[test]
+    fn test_scan_older_than_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let released = SystemTime::now() - Duration::from_secs(10 * 86400);
+        let reference = temp_dir.path().join("v1.0.0");
+        File::create(&reference)
+            .unwrap()
+            .set_modified(released)
+            .unwrap();
+        for (project, days) in [("abandoned", 30), ("active", 1)] {
+            let target = temp_dir.path().join(project).join("target");
+            fs::create_dir_all(&target).unwrap();
+            let built = SystemTime::now() - Duration::from_secs(days * 86400);
+            File::create(target.join("artifact"))
+                .unwrap()
+                .set_modified(built)
+                .unwrap();
+        }
+        // An older file doesn't make up for a newer one
+        File::create(temp_dir.path().join("active").join("target").join("old"))
+            .unwrap()
+            .set_modified(released - Duration::from_secs(86400))
+            .unwrap();
+
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            older_than_file: Some(reference),
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        let paths: Vec<&Path> = result
+            .targets
+            .iter()
+            .map(|info| info.path.as_path())
+            .collect();
+        assert_eq!(paths, [temp_dir.path().join("abandoned").join("target")]);
+
+        let config = Config {
+            older_than_file: Some(temp_dir.path().join("missing")),
+            ..config
+        };
+        let error = scan(&config, &NoopReporter).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("Couldn't read the modification time of "),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn test_scan_size_band_is_inclusive() {
+        let temp_dir = TempDir::new().unwrap();
+        for (project, size) in [
+            ("tiny", 10),
+            ("low", 100),
+            ("mid", 500),
+            ("high", 1000),
+            ("big", 5000),
+        ] {
+            let target = temp_dir.path().join(project).join("target");
+            fs::create_dir_all(&target).unwrap();
+            fs::write(target.join("artifact"), vec![0u8; size]).unwrap();
+        }
+        let sizes = |config: &Config| {
+            let result = scan(config, &NoopReporter).unwrap();
+            let sizes: Vec<u64> = result.targets.iter().map(|info| info.size).collect();
+            (sizes, result.left_out)
+        };
+        let left_out = |option, count, size| LeftOut {
+            option,
+            count,
+            size,
+        };
+
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            max_size: Some(1000),
+            ..Config::default()
+        };
+        assert_eq!(
+            sizes(&config),
+            (
+                vec![1000, 500, 100, 10],
+                vec![left_out("--max-size", 1, 5000)]
+            )
+        );
+
+        let config = Config {
+            min_size: Some(100),
+            ..config
+        };
+        assert_eq!(
+            sizes(&config),
+            (
+                vec![1000, 500, 100],
+                vec![
+                    left_out("--max-size", 1, 5000),
+                    left_out("--min-size", 1, 10)
+                ]
+            )
+        );
+
+        let config = Config {
+            max_size: None,
+            ..config
+        };
+        assert_eq!(
+            sizes(&config),
+            (
+                vec![5000, 1000, 500, 100],
+                vec![left_out("--min-size", 1, 10)]
+            )
+        );
+
+        // A band of one size
+        let config = Config {
+            min_size: Some(500),
+            max_size: Some(500),
+            ..config
+        };
+        assert_eq!(
+            sizes(&config),
+            (
+                vec![500],
+                vec![
+                    left_out("--max-size", 2, 6000),
+                    left_out("--min-size", 2, 110)
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_scan_file_count_bounds() {
+        let temp_dir = TempDir::new().unwrap();
+        for (project, files) in [("stub", 1), ("few", 3), ("many", 20)] {
+            let target = temp_dir.path().join(project).join("target");
+            fs::create_dir_all(target.join("debug")).unwrap();
+            for file in 0..files {
+                fs::write(target.join("debug").join(file.to_string()), [0u8; 10]).unwrap();
+            }
+        }
+        let counts = |config: &Config| {
+            let result = scan(config, &NoopReporter).unwrap();
+            let counts: Vec<Option<u64>> =
+                result.targets.iter().map(|info| info.file_count).collect();
+            (counts, result.left_out)
+        };
+
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            min_files: Some(3),
+            ..Config::default()
+        };
+        assert_eq!(
+            counts(&config),
+            (
+                vec![Some(20), Some(3)],
+                vec![LeftOut {
+                    option: "--min-files",
+                    count: 1,
+                    size: 10
+                }]
+            )
+        );
+
+        let config = Config {
+            min_files: None,
+            max_files: Some(3),
+            ..config
+        };
+        assert_eq!(
+            counts(&config),
+            (
+                vec![Some(3), Some(1)],
+                vec![LeftOut {
+                    option: "--max-files",
+                    count: 1,
+                    size: 200
+                }]
+            )
+        );
+    }
 
-pub fn find_target_dirs(base_dir: &Path, debug: bool) -> std::io::Result<Vec<PathBuf>> {
-    let mut target_dirs = Vec::new();
+    #[test]
+    fn test_scan_mount_filters_by_filesystem() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("a").join("target")).unwrap();
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            mount: Some(temp_dir.path().to_path_buf()),
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        assert_eq!(result.targets.len(), 1);
+        if cfg!(target_os = "linux") {
+            assert!(result.targets[0].mount.is_some());
+        }
 
-    for entry in fs::read_dir(base_dir.canonicalize()?)? {
-        let entry = entry?;
-        let path = entry.path();
+        let config = Config {
+            mount: Some(temp_dir.path().join("missing")),
+            ..config
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        assert!(result.targets.is_empty());
+        assert_eq!(result.warnings.len(), 1);
+    }
 
-        if !path.is_dir() {
-            continue;
+    #[cfg(unix)]
+    #[test]
+    fn test_age_extremes_from_mtimes() {
+        let temp_dir = TempDir::new().unwrap();
+        let now = SystemTime::now();
+        for (project, days) in [("middle", 30), ("oldest", 120), ("newest", 0)] {
+            let target = temp_dir.path().join(project).join("target");
+            fs::create_dir_all(&target).unwrap();
+            File::open(&target)
+                .unwrap()
+                .set_modified(now - Duration::from_secs(days * 86400))
+                .unwrap();
         }
-        if let Some(filename) = path.file_name()
-            && filename == "target"
-        {
-            return Ok(vec![path]);
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        let (oldest, newest) = age_extremes(&result.targets).unwrap();
+        assert!(oldest.path.ends_with("oldest/target"));
+        assert!(newest.path.ends_with("newest/target"));
+
+        assert!(age_extremes(&[TargetDirInfo::new(PathBuf::from("/a/target"), 0)]).is_none());
+        assert!(age_extremes(&[]).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_age_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        let now = SystemTime::now();
+        let days_ago = |days: u64| now - Duration::from_secs(days * 86400);
+        let set_age = |path: &Path, days| {
+            File::open(path)
+                .unwrap()
+                .set_modified(days_ago(days))
+                .unwrap();
+        };
+        let project = temp_dir.path().join("project");
+        let target = project.join("target");
+        fs::create_dir_all(target.join("debug").join("deps")).unwrap();
+        for (file, days) in [
+            (project.join("Cargo.lock"), 100),
+            (target.join("debug").join("old"), 200),
+            (target.join("debug").join("deps").join("libnew.rlib"), 10),
+        ] {
+            fs::write(&file, "").unwrap();
+            set_age(&file, days);
         }
+        set_age(&target, 50);
+        let info = TargetDirInfo::new(target.clone(), 0);
 
-        let target_path = path.join("target");
-        if target_path.exists() && target_path.is_dir() {
-            if debug {
-                eprintln!("Found target directory: {:?}", target_path);
-            }
-            target_dirs.push(target_path);
+        assert_eq!(target_age(&info, AgeSource::Dir, None), Some(days_ago(50)));
+        assert_eq!(
+            target_age(&info, AgeSource::NewestArtifact, None),
+            Some(days_ago(10))
+        );
+        assert_eq!(
+            target_age(&info, AgeSource::CargoLock, None),
+            Some(days_ago(100))
+        );
+        // The walk stops before reaching `deps`
+        assert_eq!(newest_artifact_modified(&target, 1), Some(days_ago(200)));
+        assert_eq!(newest_artifact_modified(&project.join("missing"), 3), None);
+        assert_eq!(cargo_lock_modified(temp_dir.path()), None);
+
+        // Sizing finds the newest artifact however deep it is
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        assert_eq!(result.targets[0].modified, Some(days_ago(10)));
+    }
+
+    #[test]
+    fn test_age_from_accessed() {
+        let temp_dir = TempDir::new().unwrap();
+        let now = SystemTime::now();
+        let days_ago = |days: u64| now - Duration::from_secs(days * 86400);
+        for (project, accessed) in [("used", 2), ("idle", 90)] {
+            let target = temp_dir.path().join(project).join("target");
+            fs::create_dir_all(target.join("debug")).unwrap();
+            let artifact = target.join("debug").join(project);
+            fs::write(&artifact, "").unwrap();
+            // Built long ago either way, only one has been read since
+            File::open(&artifact)
+                .unwrap()
+                .set_times(
+                    fs::FileTimes::new()
+                        .set_modified(days_ago(365))
+                        .set_accessed(days_ago(accessed)),
+                )
+                .unwrap();
         }
+
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            age_from: AgeSource::Accessed,
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        let (oldest, newest) = age_extremes(&result.targets).unwrap();
+        assert!(newest.path.ends_with("used/target"));
+        assert_eq!(newest.modified, Some(days_ago(2)));
+        assert!(oldest.path.ends_with("idle/target"));
+        assert_eq!(oldest.modified, Some(days_ago(90)));
+
+        let built = scan(
+            &Config {
+                age_from: AgeSource::NewestArtifact,
+                ..config
+            },
+            &NoopReporter,
+        )
+        .unwrap();
+        assert!(
+            built
+                .targets
+                .iter()
+                .all(|info| info.modified == Some(days_ago(365)))
+        );
     }
 
-    Ok(target_dirs)
-}
+    #[test]
+    fn test_scan_triples_sizes_each_triple_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let write = |project: &str, dir: &str, size: usize| {
+            let dir = temp_dir.path().join(project).join("target").join(dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("artifact"), vec![0u8; size]).unwrap();
+        };
+        write("both", "wasm32-unknown-unknown", 100);
+        write("both", "aarch64-unknown-linux-musl", 20);
+        write("both", "debug", 1000);
+        write("wasm-only", "wasm32-unknown-unknown", 50);
+        write("native", "debug", 1000);
 
-pub fn calculate_dir_size(path: &PathBuf) -> std::io::Result<u64> {
-    let mut total_size = 0u64;
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            triples: vec![
+                "wasm32-unknown-unknown".to_string(),
+                "aarch64-unknown-linux-musl".to_string(),
+            ],
+            ..forced(1)
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        let found: Vec<(&Path, u64)> = result
+            .targets
+            .iter()
+            .map(|info| {
+                let path = info.path.strip_prefix(temp_dir.path()).unwrap();
+                (path, info.size)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (Path::new("both/target/wasm32-unknown-unknown"), 100),
+                (Path::new("wasm-only/target/wasm32-unknown-unknown"), 50),
+                (Path::new("both/target/aarch64-unknown-linux-musl"), 20),
+            ]
+        );
+        assert!(
+            result
+                .targets
+                .iter()
+                .all(|info| info.kind == TargetKind::Triple)
+        );
 
-    if path.is_dir() {
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let entry_path = entry.path();
+        handle_deletion_with(
+            &result.targets,
+            &config,
+            &|path| fs::remove_dir_all(path),
+            &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+        )
+        .unwrap();
+        assert!(temp_dir.path().join("both/target/debug").exists());
+        assert!(
+            !temp_dir
+                .path()
+                .join("both/target/wasm32-unknown-unknown")
+                .exists()
+        );
+        assert!(temp_dir.path().join("native/target/debug").exists());
+    }
 
-            if entry_path.is_file() {
-                total_size += entry.metadata()?.len();
-            } else if entry_path.is_dir() {
-                total_size += calculate_dir_size(&entry_path)?;
-            }
-        }
-    } else if path.is_file() {
-        total_size = fs::metadata(path)?.len();
+    #[test]
+    fn test_docs_only_removes_just_the_docs() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("app").join("target");
+        fs::create_dir_all(target.join("doc").join("app")).unwrap();
+        fs::create_dir_all(target.join("debug")).unwrap();
+        fs::write(
+            target.join("doc").join("app").join("index.html"),
+            vec![0u8; 300],
+        )
+        .unwrap();
+        fs::write(target.join("debug").join("app"), vec![0u8; 1000]).unwrap();
+        fs::create_dir_all(temp_dir.path().join("undocumented").join("target")).unwrap();
+
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            docs_only: true,
+            ..forced(1)
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        assert_eq!(result.targets.len(), 1);
+        assert_eq!(
+            result.targets[0].path,
+            target.join("doc").canonicalize().unwrap()
+        );
+        assert_eq!(result.targets[0].size, 300);
+        assert_eq!(result.targets[0].kind, TargetKind::Doc);
+        assert!(result.targets[0].target_dir().ends_with("app/target"));
+
+        let mut out = Vec::new();
+        handle_deletion_with(
+            &result.targets,
+            &config,
+            &|path| fs::remove_dir_all(path),
+            &mut Output::new(&mut out, &mut std::io::sink(), false),
+        )
+        .unwrap();
+        assert!(!target.join("doc").exists());
+        assert!(target.join("debug").join("app").exists());
+        assert!(String::from_utf8(out).unwrap().contains("(300 B)"));
     }
 
-    Ok(total_size)
-}
+    #[test]
+    fn test_scan_wasm_finds_pkg_next_to_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join("app");
+        fs::create_dir_all(project.join("target")).unwrap();
+        fs::create_dir_all(project.join("pkg")).unwrap();
+        fs::write(
+            project.join("pkg").join("package.json"),
+            "{\"files\": [\"app_bg.wasm\"]}",
+        )
+        .unwrap();
+        fs::write(project.join("pkg").join("app_bg.wasm"), vec![0u8; 100]).unwrap();
 
-pub fn handle_deletion(target_info: &[TargetDirInfo], force: bool) -> std::io::Result<()> {
-    // Check if we can interact with the user
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            ..forced(1)
+        };
+        assert_eq!(scan(&config, &NoopReporter).unwrap().targets.len(), 1);
 
-    if force {
-        for info in target_info {
-            match fs::remove_dir_all(&info.path) {
-                Ok(_) => println!(
-                    "Deleted '{}' successfully, ({})",
-                    info.path.display(),
-                    humanize_bytes_decimal!(info.size)
-                ),
-                Err(e) => {
-                    eprintln!("Failed to delete: '{}' - giving up now!", e);
-                    return Err(e);
-                }
-            }
+        let config = Config {
+            wasm: true,
+            ..config
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        let pkg = result
+            .targets
+            .iter()
+            .find(|info| info.kind == TargetKind::WasmPack)
+            .unwrap();
+        assert!(pkg.path.ends_with("app/pkg"));
+        assert!(pkg.size > 100);
+
+        handle_deletion_with(
+            &result.targets,
+            &config,
+            &|path| fs::remove_dir_all(path),
+            &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+        )
+        .unwrap();
+        assert!(!project.join("pkg").exists());
+    }
+
+    #[test]
+    fn test_scan_resumes_from_checkpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        let projects = temp_dir.path().join("projects");
+        for project in ["done", "cut-off", "pending"] {
+            let target = projects.join(project).join("target");
+            fs::create_dir_all(&target).unwrap();
+            fs::write(target.join("artifact"), vec![0u8; 10]).unwrap();
         }
-    } else {
-        if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
-            eprintln!("Cannot prompt for deletion: not running in interactive terminal");
-            return Ok(());
+        // An interrupted scan got as far as sizing one target and was partway through saving
+        // the next. The saved size differs from the real one, to show it isn't worked out again.
+        let done = projects.join("done").join("target").canonicalize().unwrap();
+        let cut_off = projects
+            .join("cut-off")
+            .join("target")
+            .canonicalize()
+            .unwrap();
+        let file = temp_dir.path().join("scan-checkpoint");
+        fs::write(
+            &file,
+            format!("12345\t-\t{}\n10\t-\t{}", done.display(), cut_off.display()),
+        )
+        .unwrap();
+
+        let reporter = CollectingReporter::default();
+        let config = Config {
+            roots: vec![projects.clone()],
+            checkpoint: Some(file.clone()),
+            ..Config::default()
+        };
+        let result = scan(&config, &reporter).unwrap();
+
+        let sizes: Vec<(&Path, u64)> = result
+            .targets
+            .iter()
+            .map(|info| (info.path.as_path(), info.size))
+            .collect();
+        assert_eq!(sizes.len(), 3);
+        assert_eq!(sizes[0], (done.as_path(), 12345));
+        assert!(sizes[1..].iter().all(|(_, size)| *size == 10));
+        let events = reporter.0.into_inner().unwrap();
+        let computed = events
+            .iter()
+            .filter(|event| matches!(event, Event::SizeComputed(..)))
+            .count();
+        assert_eq!(computed, 2);
+        assert!(
+            events
+                .iter()
+                .any(|event| matches!(event, Event::SizeResumed(path, 12345) if *path == done))
+        );
+        // Finished, so there's nothing left to resume
+        assert!(!file.exists());
+    }
+
+    /// Writes a `.rustc_info.json` like cargo's, with just the `rustc -vV` output.
+    fn write_rustc_info(target: &Path, release: &str, commit_hash: &str) {
+        let info = format!(
+            "{{\"rustc_fingerprint\":1,\"outputs\":{{\"2\":{{\"success\":true,\"stdout\":\
+             \"rustc {release}\\ncommit-hash: {commit_hash}\\nrelease: {release}\\n\"}}}}}}"
+        );
+        fs::write(target.join(".rustc_info.json"), info).unwrap();
+    }
+
+    #[test]
+    fn test_scan_stale_toolchains_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let rustlib = temp_dir
+            .path()
+            .join("rustup")
+            .join("toolchains")
+            .join("stable-x86_64-unknown-linux-gnu")
+            .join("lib")
+            .join("rustlib");
+        fs::create_dir_all(&rustlib).unwrap();
+        fs::write(
+            rustlib.join("multirust-channel-manifest.toml"),
+            "[pkg.rustc]\nversion = \"1.95.0 (59807616e 2026-04-14)\"\n",
+        )
+        .unwrap();
+        let projects = temp_dir.path().join("projects");
+        for (project, release, commit_hash) in [
+            (
+                "current",
+                "1.95.0",
+                "59807616e1fa2540724bfbac14d7976d7e4a3860",
+            ),
+            (
+                "removed",
+                "1.90.0",
+                "1159e78c4747b02ef996e55082b704c09b970588",
+            ),
+        ] {
+            let target = projects.join(project).join("target");
+            fs::create_dir_all(&target).unwrap();
+            write_rustc_info(&target, release, commit_hash);
         }
-        println!("Prompting...");
-        let items: Vec<String> = target_info
+        fs::create_dir_all(projects.join("unknown").join("target")).unwrap();
+
+        let config = Config {
+            roots: vec![projects.clone()],
+            details: true,
+            rustup_home: Some(temp_dir.path().join("rustup")),
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        let stale: Vec<bool> = ["current", "removed", "unknown"]
             .iter()
-            .map(|info| {
-                format!(
-                    "{:>10}  {}",
-                    humanize_bytes_decimal!(info.size),
-                    info.path.display()
-                )
+            .map(|project| {
+                let info = result
+                    .targets
+                    .iter()
+                    .find(|info| info.path.starts_with(projects.join(project)));
+                info.unwrap().stale_toolchain
             })
             .collect();
+        assert_eq!(stale, [false, true, false]);
 
-        let selections = MultiSelect::new()
-            .with_prompt("Select target directories to delete (Space to select, Enter to confirm)")
-            .items(&items)
-            .interact()
-            .map_err(std::io::Error::other)?;
+        let config = Config {
+            details: false,
+            stale_toolchains_only: true,
+            ..config
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        assert_eq!(result.targets.len(), 1);
+        assert!(result.targets[0].path.ends_with("removed/target"));
+        assert!(result.warnings.is_empty());
 
-        if selections.is_empty() {
-            println!("No directories selected for deletion");
-            return Ok(());
-        }
+        // Without rustup there's only a warning, and nothing is picked out as stale
+        let config = Config {
+            rustup_home: Some(temp_dir.path().join("no-rustup")),
+            ..config
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        assert!(result.targets.is_empty());
+        assert_eq!(result.warnings.len(), 1);
+    }
 
-        for &idx in &selections {
-            let info = &target_info[idx];
-            match fs::remove_dir_all(&info.path) {
-                Ok(_) => println!(
-                    "Deleted '{}' successfully, ({})",
-                    info.path.display(),
-                    humanize_bytes_decimal!(info.size)
-                ),
-                Err(e) => {
-                    eprintln!("Failed to delete: {}", e);
-                    return Err(e);
-                }
+    #[test]
+    fn test_scan_built_before_rustc() {
+        let temp_dir = TempDir::new().unwrap();
+        for (project, release) in [
+            ("old", Some("1.70.0")),
+            ("new", Some("1.80.0")),
+            ("none", None),
+        ] {
+            let target = temp_dir.path().join(project).join("target");
+            fs::create_dir_all(&target).unwrap();
+            if let Some(release) = release {
+                write_rustc_info(&target, release, "unknown");
             }
         }
-    }
 
-    Ok(())
-}
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            built_before_rustc: Some(RustcVersion::parse("1.75").unwrap()),
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        assert_eq!(result.targets.len(), 1);
+        assert!(result.targets[0].path.ends_with("old/target"));
+        assert_eq!(
+            result.targets[0]
+                .rustc
+                .as_ref()
+                .unwrap()
+                .version
+                .to_string(),
+            "1.70.0"
+        );
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs::File;
-    use std::io::Write;
-    use tempfile::TempDir;
+        let config = Config {
+            built_before_rustc: None,
+            details: true,
+            ..config
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        assert_eq!(result.targets.len(), 3);
+        let none = result
+            .targets
+            .iter()
+            .find(|info| info.path.ends_with("none/target"))
+            .unwrap();
+        assert!(none.rustc.is_none());
+    }
 
+    #[cfg(unix)]
     #[test]
-    fn test_calculate_dir_size_empty() {
+    fn test_scan_dedupes_hardlinks_across_dirs() {
         let temp_dir = TempDir::new().unwrap();
-        let size = calculate_dir_size(&temp_dir.path().to_path_buf()).unwrap();
-        assert_eq!(size, 0);
+        let first = temp_dir.path().join("first").join("target");
+        let second = temp_dir.path().join("second").join("target");
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+        fs::write(first.join("shared"), vec![0u8; 1000]).unwrap();
+        fs::hard_link(first.join("shared"), second.join("shared")).unwrap();
+        fs::write(second.join("own"), vec![0u8; 10]).unwrap();
+
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            dedupe_hardlinks: true,
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        let sizes: Vec<u64> = result.targets.iter().map(|info| info.size).collect();
+        assert_eq!(sizes, vec![1010, 1000]);
+        assert_eq!(result.deduplicated_total, Some(1010));
+
+        let config = Config {
+            dedupe_hardlinks: false,
+            ..config
+        };
+        assert_eq!(
+            scan(&config, &NoopReporter).unwrap().deduplicated_total,
+            None
+        );
     }
 
+    #[cfg(unix)]
     #[test]
-    fn test_calculate_dir_size_single_file() {
+    fn test_calculate_dir_size_does_not_follow_symlinks() {
         let temp_dir = TempDir::new().unwrap();
-        let file_path = temp_dir.path().join("test.txt");
-        let mut file = File::create(&file_path).unwrap();
-        file.write_all(b"Hello, World!").unwrap();
+        let mut file = File::create(temp_dir.path().join("file.txt")).unwrap();
+        file.write_all(b"12345").unwrap();
+        // A loop back to the parent would recurse forever if followed
+        std::os::unix::fs::symlink(temp_dir.path(), temp_dir.path().join("loop")).unwrap();
 
-        let size = calculate_dir_size(&temp_dir.path().to_path_buf()).unwrap();
-        assert_eq!(size, 13); // "Hello, World!" is 13 bytes
+        let size = calculate_dir_size(temp_dir.path()).unwrap();
+        let link_size = fs::symlink_metadata(temp_dir.path().join("loop"))
+            .unwrap()
+            .len();
+        assert_eq!(size, 5 + link_size);
     }
 
     #[test]
-    fn test_calculate_dir_size_multiple_files() {
+    fn test_resolve_roots_precedence() {
         let temp_dir = TempDir::new().unwrap();
+        let a = temp_dir.path().join("a");
+        let b = temp_dir.path().join("b");
+        let c = temp_dir.path().join("c");
+        for dir in [&a, &b, &c] {
+            fs::create_dir(dir).unwrap();
+        }
+        let dirs = std::env::join_paths([&b, &a]).unwrap();
+        let dir = c.clone().into_os_string();
 
-        let mut file1 = File::create(temp_dir.path().join("file1.txt")).unwrap();
-        file1.write_all(b"12345").unwrap();
+        assert_eq!(resolve_roots(&[], None, None), vec![PathBuf::from(".")]);
+        assert_eq!(resolve_roots(&[], None, Some(&dir)), vec![c.clone()]);
+        assert_eq!(
+            resolve_roots(&[], Some(&dirs), Some(&dir)),
+            vec![b.clone(), a.clone()]
+        );
+        assert_eq!(
+            resolve_roots(std::slice::from_ref(&a), Some(&dirs), Some(&dir)),
+            vec![a.clone(), b.clone()]
+        );
+        assert_eq!(
+            resolve_roots(&[c.clone(), c.join("..").join("c")], None, Some(&dir)),
+            vec![c.clone()]
+        );
+    }
 
-        let mut file2 = File::create(temp_dir.path().join("file2.txt")).unwrap();
-        file2.write_all(b"67890").unwrap();
+    #[test]
+    fn test_cli_multiple_roots() {
+        let cli = Cli::try_parse_from(["clean-big-targets", "one", "two"]).unwrap();
+        assert_eq!(
+            resolve_roots(&cli.target_dirs, None, None),
+            vec![PathBuf::from("one"), PathBuf::from("two")]
+        );
+    }
 
-        let size = calculate_dir_size(&temp_dir.path().to_path_buf()).unwrap();
-        assert_eq!(size, 10);
+    #[derive(Default)]
+    struct ThreadCountingReporter(Mutex<HashSet<std::thread::ThreadId>>);
+
+    impl Reporter for ThreadCountingReporter {
+        fn report(&self, event: Event) {
+            if let Event::SizeComputed(..) = event {
+                self.0.lock().unwrap().insert(std::thread::current().id());
+            }
+        }
     }
 
     #[test]
-    fn test_calculate_dir_size_nested() {
+    fn test_pool_sizes_respected() {
         let temp_dir = TempDir::new().unwrap();
-        let nested_dir = temp_dir.path().join("nested");
-        fs::create_dir(&nested_dir).unwrap();
-
-        let mut file1 = File::create(temp_dir.path().join("top.txt")).unwrap();
-        file1.write_all(b"abc").unwrap();
+        for i in 0..32 {
+            let target = temp_dir.path().join(format!("project{i}")).join("target");
+            fs::create_dir_all(target.join("debug")).unwrap();
+            File::create(target.join("debug").join("out.bin")).unwrap();
+        }
 
-        let mut file2 = File::create(nested_dir.join("nested.txt")).unwrap();
-        file2.write_all(b"defgh").unwrap();
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            threads: Some(2),
+            delete_jobs: 3,
+            force: true,
+            ..Config::default()
+        };
+        let reporter = ThreadCountingReporter::default();
+        let result = scan(&config, &reporter).unwrap();
+        assert_eq!(result.targets.len(), 32);
+        let sizing_threads = reporter.0.into_inner().unwrap();
+        assert!(!sizing_threads.is_empty() && sizing_threads.len() <= 2);
+        assert!(!sizing_threads.contains(&std::thread::current().id()));
 
-        let size = calculate_dir_size(&temp_dir.path().to_path_buf()).unwrap();
-        assert_eq!(size, 8);
+        let deletion_threads = Mutex::new(HashSet::new());
+        handle_deletion_with(
+            &result.targets,
+            &config,
+            &|path| {
+                deletion_threads
+                    .lock()
+                    .unwrap()
+                    .insert(std::thread::current().id());
+                fs::remove_dir_all(path)
+            },
+            &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+        )
+        .unwrap();
+        let deletion_threads = deletion_threads.into_inner().unwrap();
+        assert!(!deletion_threads.is_empty() && deletion_threads.len() <= 3);
+        assert!(deletion_threads.is_disjoint(&sizing_threads));
+        assert!(result.targets.iter().all(|info| !info.path.exists()));
     }
 
     #[test]
-    fn test_find_target_dirs_none_found() {
+    fn test_io_stats_match_fixture() {
         let temp_dir = TempDir::new().unwrap();
-        let result = find_target_dirs(temp_dir.path(), false).unwrap();
-        assert_eq!(result.len(), 0);
+        let target = temp_dir.path().join("project1").join("target");
+        let nested = target.join("debug");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir(temp_dir.path().join("project2")).unwrap();
+        File::create(temp_dir.path().join("README.md")).unwrap();
+        for name in ["a", "b", "c"] {
+            File::create(target.join(name)).unwrap();
+        }
+        for name in ["d", "e"] {
+            File::create(nested.join(name)).unwrap();
+        }
+
+        let stats = IoStats::default();
+        let opts = ScanOptions::new().case_insensitive(false);
+        discover_targets(temp_dir.path(), &opts, &NoopReporter, &stats).unwrap();
+        // Checking the root isn't a project, the root listing, then a stat for each of its 3
+        // entries and a `target` check in each of the 2 directories
+        assert_eq!(stats.read_dir_calls(), 1);
+        assert_eq!(stats.metadata_calls(), 6);
+
+        let stats = IoStats::default();
+        calculate_dir_size_counted(&target, &stats).unwrap();
+        // Listings of target and target/debug, the initial is_dir and one per file
+        assert_eq!(stats.read_dir_calls(), 2);
+        assert_eq!(stats.metadata_calls(), 6);
     }
 
     #[test]
-    fn test_find_target_dirs_single() {
-        let temp_dir = TempDir::new().unwrap();
-        let project_dir = temp_dir.path().join("project1");
-        fs::create_dir(&project_dir).unwrap();
-        fs::create_dir(project_dir.join("target")).unwrap();
+    fn test_size_stats_empty() {
+        let stats = size_stats(&[]);
+        assert_eq!(stats, SizeStats::default());
+        assert!(!stats.std_dev.is_nan());
+    }
 
-        let result = find_target_dirs(temp_dir.path(), false).unwrap();
-        assert_eq!(result.len(), 1);
-        assert!(result[0].ends_with("project1/target"));
+    #[test]
+    fn test_size_stats_single() {
+        let stats = size_stats(&[TargetDirInfo::new(PathBuf::from("/a/target"), 500)]);
+        assert_eq!(stats.count, 1);
+        assert_eq!(stats.median, 500);
+        assert_eq!(stats.p90, 500);
+        assert_eq!(stats.std_dev, 0.0);
+        assert_eq!(stats.buckets, [1, 0, 0, 0]);
     }
 
     #[test]
-    fn test_find_target_dirs_multiple() {
+    fn test_size_stats_distribution() {
+        let sizes = [
+            10,
+            20,
+            30,
+            40,
+            50_000_000,
+            200_000_000,
+            900_000_000,
+            2_000_000_000,
+            5_000_000_000,
+            20_000_000_000,
+        ];
+        let target_info: Vec<TargetDirInfo> = sizes
+            .iter()
+            .map(|&size| TargetDirInfo::new(PathBuf::from("/a/target"), size))
+            .collect();
+        let stats = size_stats(&target_info);
+        assert_eq!(stats.count, 10);
+        assert_eq!(stats.median, 125_000_000);
+        assert_eq!(stats.p90, 5_000_000_000);
+        assert_eq!(stats.buckets, [5, 2, 2, 1]);
+        assert!((stats.std_dev - 5_921_235_086.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_keep_newest_subdirs() {
         let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("project").join("target");
+        let now = SystemTime::now();
+        for (name, age) in [("debug", 10), ("release", 30), ("doc", 20), ("tmp", 40)] {
+            let dir = target.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            File::open(&dir)
+                .unwrap()
+                .set_modified(now - std::time::Duration::from_secs(age * 86400))
+                .unwrap();
+        }
+        File::create(target.join("CACHEDIR.TAG")).unwrap();
 
-        let project1 = temp_dir.path().join("project1");
-        fs::create_dir(&project1).unwrap();
-        fs::create_dir(project1.join("target")).unwrap();
+        let mut out = Vec::new();
+        handle_deletion(
+            &[TargetDirInfo::new(target.clone(), 0)],
+            &Config {
+                keep_newest: Some(2),
+                ..forced(1)
+            },
+            &mut Output::new(&mut out, &mut std::io::sink(), false),
+        )
+        .unwrap();
 
-        let project2 = temp_dir.path().join("project2");
-        fs::create_dir(&project2).unwrap();
-        fs::create_dir(project2.join("target")).unwrap();
+        let mut remaining: Vec<String> = fs::read_dir(&target)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        remaining.sort();
+        assert_eq!(remaining, vec!["CACHEDIR.TAG", "debug", "doc"]);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("Removed 2 old subdirectories from '{}'\n", target.display())
+        );
+    }
 
-        let project3 = temp_dir.path().join("project3");
-        fs::create_dir(&project3).unwrap();
+    #[test]
+    fn test_cli_generate_unit_subcommand() {
+        let cli = Cli::try_parse_from([
+            "clean-big-targets",
+            "generate-unit",
+            "--schedule",
+            "daily",
+            "--args",
+            "--delete --force /srv/builds",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::GenerateUnit { ref schedule, ref args, install_user: false })
+                if schedule == "daily" && args == "--delete --force /srv/builds"
+        ));
+    }
 
-        let result = find_target_dirs(temp_dir.path(), false).unwrap();
-        assert_eq!(result.len(), 2);
+    #[test]
+    fn test_cli_tiers_flag() {
+        let cli = Cli::try_parse_from(["clean-big-targets", "--tiers"]).unwrap();
+        assert_eq!(cli.output_format(), OutputFormat::Tiers);
+        let cli = Cli::try_parse_from(["clean-big-targets", "--format", "json"]).unwrap();
+        assert_eq!(cli.output_format(), OutputFormat::Json);
+        assert!(Cli::try_parse_from(["clean-big-targets", "--tiers", "--format", "json"]).is_err());
+        assert!(Cli::try_parse_from(["clean-big-targets", "--tiers", "--porcelain"]).is_err());
     }
 
     #[test]
-    fn test_find_target_dirs_child_is_target() {
-        let temp_dir = TempDir::new().unwrap();
+    fn test_cli_clean_project_subcommand() {
+        let cli =
+            Cli::try_parse_from(["clean-big-targets", "clean-project", "--force", "app"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::CleanProject { ref path, force: true, review: false, dry_run: false })
+                if path == Path::new("app")
+        ));
+        let cli = Cli::try_parse_from(["clean-big-targets", "clean-project", "app", "--dry-run"])
+            .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::CleanProject {
+                force: false,
+                dry_run: true,
+                ..
+            })
+        ));
+        assert!(
+            Cli::try_parse_from([
+                "clean-big-targets",
+                "clean-project",
+                "--force",
+                "--review",
+                "app"
+            ])
+            .is_err()
+        );
+    }
 
-        // Create a child directory named "target"
-        let target_dir = temp_dir.path().join("target");
-        fs::create_dir(&target_dir).unwrap();
+    #[test]
+    fn test_free_space_below() {
+        let roots = [PathBuf::from("/roomy"), PathBuf::from("/full")];
+        let free_space = |path: &Path| match path.to_str() {
+            Some("/roomy") => Ok(50_000_000_000),
+            Some("/full") => Ok(1_000_000_000),
+            _ => Err(std::io::ErrorKind::NotFound.into()),
+        };
 
-        // Scanning the parent should find the "target" directory and return it directly
-        let result = find_target_dirs(temp_dir.path(), false).unwrap();
-        assert_eq!(result.len(), 1);
-        assert!(result[0].ends_with("target"));
+        // Healthy disks mean an early exit
+        assert_eq!(
+            free_space_below(&roots[..1], 20_000_000_000, &free_space).unwrap(),
+            None
+        );
+        assert_eq!(
+            free_space_below(&roots, 20_000_000_000, &free_space).unwrap(),
+            Some((Path::new("/full"), 1_000_000_000))
+        );
+        assert_eq!(
+            free_space_below(&roots, 100_000_000_000, &free_space).unwrap(),
+            Some((Path::new("/roomy"), 50_000_000_000))
+        );
+        assert!(free_space_below(&[PathBuf::from("/gone")], 1, &free_space).is_err());
     }
 
     #[test]
-    fn test_calculate_dir_size_on_package() {
-        // This test runs calculate_dir_size on the package base directory
-        // to verify it works on real directory structures
-        let package_dir = std::env::var("CARGO_MANIFEST_DIR")
-            .map(PathBuf::from)
-            .unwrap();
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("100MB"), Ok(100_000_000));
+        assert_eq!(parse_size("1.5 GB"), Ok(1_500_000_000));
+        assert_eq!(parse_size("2GiB"), Ok(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("10kb"), Ok(10_000));
+        assert!(parse_size("").is_err());
+        assert!(parse_size("GB").is_err());
+        assert!(parse_size("10 parsecs").is_err());
+    }
 
-        let result = calculate_dir_size(&package_dir);
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30d"), Ok(Duration::from_secs(30 * 86400)));
+        assert_eq!(parse_duration("2W"), Ok(Duration::from_secs(14 * 86400)));
+        assert_eq!(parse_duration("12 h"), Ok(Duration::from_secs(12 * 3600)));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("1.5d").is_err());
+        assert!(parse_duration("3 fortnights").is_err());
+    }
+
+    #[test]
+    fn test_pattern_set_case_and_braces() {
+        let globs = ["{vendor,third_party}/**"];
+        let exact = PatternSet::new(&globs, false).unwrap();
+        let folded = PatternSet::new(&globs, true).unwrap();
+        for path in [
+            "/code/vendor/x/target",
+            "/code/Vendor/target",
+            "/code/VENDOR/target",
+        ] {
+            assert!(folded.is_match(Path::new(path)), "{path}");
+        }
+        assert!(exact.is_match(Path::new("/code/vendor/x/target")));
+        assert!(exact.is_match(Path::new("/code/third_party/target")));
+        assert!(!exact.is_match(Path::new("/code/Vendor/target")));
+        assert!(!exact.is_match(Path::new("/code/vendored/target")));
+        assert!(!folded.is_match(Path::new("/code/other/target")));
+
+        assert_eq!(
+            PatternSet::new(&["{a,{b,c}}"], false).unwrap_err(),
+            "invalid pattern `{a,{b,c}}`: braces can't be nested"
+        );
+        assert_eq!(
+            PatternSet::new(&["{a,b"], false).unwrap_err(),
+            "invalid pattern `{a,b`: `{` isn't closed"
+        );
+        // Outside braces they're only characters
         assert!(
-            result.is_ok(),
-            "Should successfully calculate size of package directory"
+            PatternSet::new(&["a,b}"], false)
+                .unwrap()
+                .is_match(Path::new("/x/a,b}"))
         );
+    }
 
-        let size = result.unwrap();
-        assert!(size > 0, "Package directory should have non-zero size");
+    #[test]
+    fn test_pattern_set_separators() {
+        let set = |glob: &str| PatternSet::new(&[glob], false).unwrap();
+        let matches = |glob: &str, path: &str| set(glob).is_match(Path::new(path));
 
-        // Print size for informational purposes (visible with --nocapture)
-        eprintln!("Package directory size: {}", humanize_bytes_decimal!(size));
-        assert!(humanize_bytes_decimal!(size).ends_with(" MB"))
+        // `*` and `?` stay within a component
+        assert!(matches("ml-*", "/home/ml-vision"));
+        assert!(!matches("/home/*", "/home/me/ml"));
+        assert!(!matches("a?b", "/a/b"));
+        // `**` as a component spans any number of them, none included
+        assert!(matches("a/**/b", "/x/a/b"));
+        assert!(matches("a/**/b", "/x/a/one/two/b"));
+        assert!(!matches("a/**/b", "/x/a/b/c"));
+        assert!(matches("a/**", "/x/a/b/c"));
+        assert!(!matches("a/**", "/x/a"));
+        assert!(matches("**/target", "/target"));
+        // Anywhere else it's just `*`
+        assert!(matches("ml**", "/ml-vision"));
+        assert!(!matches("ml**", "/ml/vision"));
+        // Anchored at the root or not
+        assert!(matches("/work/monorepo", "/work/monorepo"));
+        assert!(!matches("/work/monorepo", "/old/work/monorepo"));
+        assert!(!matches("monorepo", "/work/big-monorepo"));
+        assert!(matches("work\\monorepo", "C:\\work\\monorepo"));
+
+        assert!(set("monorepo").matches_within(Path::new("/work/monorepo/target")));
+        assert!(!PatternSet::default().matches_within(Path::new("/work")));
+    }
+
+    #[test]
+    fn test_delete_order_independent_of_sort() {
+        let mut target_info = vec![
+            TargetDirInfo::new(PathBuf::from("/nonexistent/b/target"), 500),
+            TargetDirInfo::new(PathBuf::from("/nonexistent/c/target"), 10),
+            TargetDirInfo::new(PathBuf::from("/nonexistent/a/target"), 1000),
+        ];
+        sort_targets(&mut target_info, SortOrder::SizeAsc);
+
+        let removal_order = |delete_order| {
+            let removed = Mutex::new(Vec::new());
+            handle_deletion_with(
+                &target_info,
+                &Config {
+                    delete_order,
+                    ..forced(1)
+                },
+                &|path| {
+                    removed.lock().unwrap().push(path.to_path_buf());
+                    Ok(())
+                },
+                &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+            )
+            .unwrap();
+            removed
+                .into_inner()
+                .unwrap()
+                .iter()
+                .map(|path| path.parent().unwrap().file_name().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(removal_order(None), ["c", "b", "a"]);
+        assert_eq!(
+            removal_order(Some(DeleteOrder::LargestFirst)),
+            ["a", "b", "c"]
+        );
+        assert_eq!(
+            removal_order(Some(DeleteOrder::SmallestFirst)),
+            ["c", "b", "a"]
+        );
+        assert_eq!(removal_order(Some(DeleteOrder::Path)), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_delete_order_kept_with_parallel_jobs() {
+        let target_info: Vec<_> = (0..8u64)
+            .map(|i| TargetDirInfo::new(PathBuf::from(format!("/nonexistent/{i}/target")), i))
+            .collect();
+        let removed = Mutex::new(Vec::new());
+        handle_deletion_with(
+            &target_info,
+            &Config {
+                delete_order: Some(DeleteOrder::LargestFirst),
+                ..forced(DEFAULT_DELETE_JOBS)
+            },
+            &|path| {
+                removed.lock().unwrap().push(path.to_path_buf());
+                std::thread::sleep(Duration::from_millis(20));
+                Ok(())
+            },
+            &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+        )
+        .unwrap();
+
+        // Jobs running side by side can finish out of turn, but never start far ahead of it
+        let removed = removed.into_inner().unwrap();
+        assert_in_turn(&removed);
+    }
+
+    #[test]
+    fn test_default_delete_jobs_follow_sort() {
+        let cli = Cli::try_parse_from([
+            "clean-big-targets",
+            "--delete",
+            "--force",
+            "--sort",
+            "size-asc",
+        ])
+        .unwrap();
+        let config = Config::from(&cli);
+        assert_eq!(config.delete_jobs, DEFAULT_DELETE_JOBS);
+        let mut target_info: Vec<_> = (0..8u64)
+            .map(|i| TargetDirInfo::new(PathBuf::from(format!("/nonexistent/{i}/target")), 7 - i))
+            .collect();
+        sort_configured(&mut target_info, &config);
+        let removed = Mutex::new(Vec::new());
+        handle_deletion_with(
+            &target_info,
+            &config,
+            &|path| {
+                removed.lock().unwrap().push(path.to_path_buf());
+                std::thread::sleep(Duration::from_millis(20));
+                Ok(())
+            },
+            &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+        )
+        .unwrap();
+        assert_in_turn(&removed.into_inner().unwrap());
+    }
+
+    /// Checks each of `removed`, the `/nonexistent/<i>/target` paths in the order they were
+    /// removed, started within a job's reach of its turn, `7 - i`.
+    fn assert_in_turn(removed: &[PathBuf]) {
+        assert_eq!(removed.len(), 8);
+        for (position, path) in removed.iter().enumerate() {
+            let i: usize = path
+                .parent()
+                .unwrap()
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .parse()
+                .unwrap();
+            let expected = 7 - i;
+            assert!(
+                position.abs_diff(expected) < DEFAULT_DELETE_JOBS,
+                "{removed:?}"
+            );
+        }
     }
 }
@@ -10,87 +10,776 @@
 #![deny(clippy::needless_pass_by_value)]
 #![deny(clippy::trivially_copy_pass_by_ref)]
 
-use std::{cmp::Reverse, process::ExitCode};
+use std::{
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
-use clap::Parser;
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
 use clean_big_targets::{
-    Cli, TargetDirInfo, calculate_dir_size, find_target_dirs, handle_deletion,
+    Cli, Command, ScanResult,
+    biggest::{biggest_files, render_biggest_files},
+    calculate_dir_size,
+    cargo_home::{CargoHomeSummary, render_cargo_home_summary},
+    cargo_home_dir, clean_project_with,
+    config::Config,
+    conflicting_options,
+    deadline::TIMED_OUT_EXIT,
+    doctor::{self, Status},
+    drives::{fixed_drives, subtotals_by_root},
+    env_vars,
+    explain::{Recorder, explain, explain_all, render_explain_all, render_explanation},
+    find_targets, free_space_below,
+    fsstat::available_space,
+    handle_deletion_with,
+    meta::scan_meta,
+    output::{
+        Output, OutputFormat, TableOptions,
+        du::{du_blocks, du_human, render_du},
+        env::render_env,
+        fixed::render_fixed_table,
+        html::render_html,
+        json::{read_json, render_error, render_json, render_warning},
+        ncdu::render_ncdu,
+        plist::render_plist,
+        prometheus::render_prometheus,
+        render_env_list, render_porcelain, render_size, render_table, render_triples,
+        tiers::render_tiers,
+        use_color,
+    },
+    paths::Dirs,
+    permissions::remove_dir_all_clearing_readonly,
+    plan::{execute_plan, read_plan, render_plan, revalidate},
+    protect::{Protected, config_file},
+    relocate::move_into,
+    report::{NoopReporter, Reporter, StderrReporter},
+    restore::{MoveJournal, default_journal_file, restore_last},
+    scan,
+    session::run_loop,
+    size_stats, sort_configured,
+    status::RunTally,
+    systemd,
+    triples::summarize_triples,
 };
 use humanize_bytes::humanize_bytes_decimal;
-use rayon::prelude::*;
-use std::cmp::Reverse;
+
+/// Reports a failure on stderr as [`render_error`] does, for the JSON formats.
+fn json_error(error: &str, path: Option<&Path>, message: &str) {
+    // There's nowhere left to report stderr failing
+    let _ = render_error(&mut std::io::stderr(), error, path, message);
+}
+
+/// The configuration for `cli`, with the protected directories from the config file. A config
+/// file that can't be read stops the run, rather than deleting what it meant to protect.
+fn load_config(cli: &Cli) -> Option<Config> {
+    let mut config = Config::from(cli);
+    let Some(file) = config_file(|name| std::env::var(name).ok()) else {
+        return Some(config);
+    };
+    match Protected::load(&file, config.glob_ignore_case) {
+        Ok(protected) => {
+            config.protected = protected;
+            Some(config)
+        }
+        Err(e) => {
+            eprintln!("Error reading the config file {}: {}", file.display(), e);
+            None
+        }
+    }
+}
+
+/// Loads and checks the targets from an earlier `--format json` in place of a scan.
+fn from_json(file: &Path, config: &Config, max_growth: u64) -> std::io::Result<ScanResult> {
+    let contents = std::fs::read_to_string(file)?;
+    let entries = read_json(&contents).map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{}: {}", file.display(), e),
+        )
+    })?;
+    let mut stdout = std::io::stdout();
+    let mut stderr = std::io::stderr();
+    let mut targets = revalidate(
+        entries,
+        config,
+        max_growth,
+        &mut Output::detect(&mut stdout, &mut stderr),
+    )?;
+    sort_configured(&mut targets, config);
+    Ok(ScanResult {
+        targets,
+        ..ScanResult::default()
+    })
+}
+
+/// Runs the `doctor` checks against `path`, failing if any of them did.
+fn run_doctor(path: &Path, json: bool) -> ExitCode {
+    let checks = doctor::diagnose(
+        path,
+        std::io::stdout().is_terminal(),
+        std::io::stderr().is_terminal(),
+    );
+    let rendered = if json {
+        doctor::render_checks_json(&mut std::io::stdout(), &checks)
+    } else {
+        doctor::render_checks(&mut std::io::stdout(), &checks)
+    };
+    if let Err(e) = rendered {
+        eprintln!("Error writing output: {}", e);
+        return ExitCode::FAILURE;
+    }
+    if checks.iter().any(|check| check.status == Status::Fail) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Moves back what the last `--move-to` run moved, failing if any of it couldn't be.
+fn run_restore_last(data_dir: Option<&Path>) -> ExitCode {
+    let dirs = Dirs::resolve(None, data_dir, |name| std::env::var(name).ok());
+    let Some(journal) = default_journal_file(&dirs) else {
+        eprintln!("Couldn't work out the state directory, give --data-dir");
+        return ExitCode::FAILURE;
+    };
+    let restored = match restore_last(&journal) {
+        Ok(restored) => restored,
+        Err(e) => {
+            eprintln!("Error reading moves from {}: {}", journal.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if restored.is_empty() {
+        println!("Nothing to restore, no moves are recorded");
+        return ExitCode::SUCCESS;
+    }
+    let mut code = ExitCode::SUCCESS;
+    for (record, result) in restored {
+        match result {
+            Ok(()) => println!(
+                "Restored {} from {}",
+                record.from.display(),
+                record.to.display()
+            ),
+            Err(e) => {
+                eprintln!(
+                    "Couldn't restore {} from {}: {}",
+                    record.from.display(),
+                    record.to.display(),
+                    e
+                );
+                code = ExitCode::FAILURE;
+            }
+        }
+    }
+    code
+}
+
+fn generate_unit(schedule: &str, args: &str, install_user: bool) -> ExitCode {
+    let args = match shell_words::split(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("Couldn't parse --args: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let binary = match std::env::current_exe() {
+        Ok(binary) => binary,
+        Err(e) => {
+            eprintln!("Couldn't find the path to this binary: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let units = match systemd::generate_units(&binary, &args, schedule) {
+        Ok(units) => units,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if !install_user {
+        println!("# {}.service\n{}", systemd::UNIT_NAME, units.service);
+        println!("# {}.timer\n{}", systemd::UNIT_NAME, units.timer);
+        return ExitCode::SUCCESS;
+    }
+    let Some(dir) = systemd::user_unit_dir(|name| std::env::var(name).ok()) else {
+        eprintln!("Couldn't work out the systemd user unit directory");
+        return ExitCode::FAILURE;
+    };
+    match systemd::install_units(&units, &dir) {
+        Ok(paths) => {
+            for path in paths {
+                println!("Wrote {}", path.display());
+            }
+            println!(
+                "Enable with: systemctl --user enable --now {}.timer",
+                systemd::UNIT_NAME
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error writing units to {}: {}", dir.display(), e);
+            ExitCode::FAILURE
+        }
+    }
+}
 
 fn main() -> ExitCode {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = match Cli::from_arg_matches(&matches) {
+        Ok(cli) => cli,
+        Err(e) => e.exit(),
+    };
+
+    if let Some(Command::GenerateUnit {
+        schedule,
+        args,
+        install_user,
+    }) = &cli.command
+    {
+        return generate_unit(schedule, args, *install_user);
+    }
+    if let Some(Command::Doctor { path, json }) = &cli.command {
+        return run_doctor(path, *json);
+    }
+    if let Some(Command::RestoreLast { data_dir }) = &cli.command {
+        return run_restore_last(data_dir.as_deref());
+    }
+    if let Some((first, second)) = conflicting_options(&cli) {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("the argument '{first}' cannot be used with '{second}'"),
+            )
+            .exit();
+    }
+
+    if cli.list_env {
+        if let Err(e) = render_env_list(&mut std::io::stdout(), &env_vars()) {
+            eprintln!("Error writing output: {}", e);
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    if let Some(path) = &cli.size_of {
+        if !path.is_dir() {
+            eprintln!("Not a directory: {:?}", path);
+            return ExitCode::FAILURE;
+        }
+        let rendered =
+            calculate_dir_size(path).and_then(|size| render_size(&mut std::io::stdout(), size));
+        if let Err(e) = rendered {
+            eprintln!("Error calculating size for {:?}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    let started = Instant::now();
+    let tally = RunTally::new();
+    let code = run(&cli, &matches, &tally);
+    let status_line = tally.status_line(started.elapsed());
+    if cli.status_line.enabled(std::io::stderr().is_terminal()) {
+        eprintln!("{}", status_line);
+    }
+    if cli.summary_json {
+        eprintln!("{}", status_line.to_json());
+    }
+    code
+}
+
+/// Everything but the subcommands and flags that don't scan or delete, counting what's found
+/// and deleted in `tally` for the status line.
+fn run(cli: &Cli, matches: &ArgMatches, tally: &RunTally) -> ExitCode {
+    // Recording each move, so `restore-last` can put them back
+    let journal = cli
+        .move_to
+        .as_ref()
+        .and_then(|_| {
+            default_journal_file(&Dirs::resolve(
+                cli.cache_dir.as_deref(),
+                cli.data_dir.as_deref(),
+                |name| std::env::var(name).ok(),
+            ))
+        })
+        .map(|file| MoveJournal::new(&file));
+    if cli.move_to.is_some() && journal.is_none() {
+        eprintln!(
+            "Warning: couldn't work out the state directory, so restore-last won't be able to \
+             move these back, give --data-dir"
+        );
+    }
+    let remove_dir_all = |path: &Path| match &cli.move_to {
+        Some(dir) => {
+            let dest = move_into(path, dir)?;
+            match &journal {
+                Some(journal) => journal.record(path, &dest),
+                None => Ok(()),
+            }
+        }
+        None if cli.no_clear_readonly => std::fs::remove_dir_all(path),
+        None => remove_dir_all_clearing_readonly(path),
+    };
+    let remover = tally.counting(&remove_dir_all);
+
+    if let Some(Command::CleanProject {
+        path,
+        force,
+        review,
+        dry_run,
+    }) = &cli.command
+    {
+        let mut stdout = std::io::stdout();
+        let mut stderr = std::io::stderr();
+        let Some(config) = load_config(cli) else {
+            return ExitCode::FAILURE;
+        };
+        // The top level deletion flags can't be given with a subcommand, so it has its own
+        let config = Config {
+            force: *force,
+            review: *review,
+            dry_run: *dry_run,
+            ..config
+        };
+        return match clean_project_with(
+            path,
+            &config,
+            |name| std::env::var(name).ok(),
+            &remover,
+            &mut Output::detect(&mut stdout, &mut stderr),
+        ) {
+            Ok(found) => {
+                tally.found(found.as_slice());
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("Error cleaning {}: {}", path.display(), e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    if let Some(file) = &cli.execute_plan {
+        let plan = match std::fs::read_to_string(file) {
+            Ok(contents) => read_plan(&contents),
+            Err(e) => Err(e.to_string()),
+        };
+        let plan = match plan {
+            Ok(plan) => plan,
+            Err(e) => {
+                eprintln!("Error reading the plan in {}: {}", file.display(), e);
+                return ExitCode::FAILURE;
+            }
+        };
+        tally.found(&plan);
+        let mut stdout = std::io::stdout();
+        let mut stderr = std::io::stderr();
+        let Some(config) = load_config(cli) else {
+            return ExitCode::FAILURE;
+        };
+        return match execute_plan(
+            &plan,
+            &config,
+            &remover,
+            &mut Output::detect(&mut stdout, &mut stderr),
+        ) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Error during deletion: {}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
 
-    if cli.debug {
+    if cli.debug > 0 {
         eprintln!("Debug mode is on");
     }
 
-    if !cli.target_dir.exists() {
-        eprintln!("Target directory does not exist: {:?}", cli.target_dir);
+    if cli.all_drives && fixed_drives().is_none() {
+        eprintln!("Warning: --all-drives only works on Windows, scanning as usual");
+    }
+    let Some(config) = load_config(cli) else {
         return ExitCode::FAILURE;
+    };
+    if let (Some(min), Some(max)) = (config.min_size, config.max_size)
+        && min > max
+    {
+        eprintln!(
+            "--min-size {} is bigger than --max-size {}",
+            humanize_bytes_decimal!(min),
+            humanize_bytes_decimal!(max)
+        );
+        return ExitCode::FAILURE;
+    }
+    if let (Some(min), Some(max)) = (config.min_files, config.max_files)
+        && min > max
+    {
+        eprintln!("--min-files {} is more than --max-files {}", min, max);
+        return ExitCode::FAILURE;
+    }
+    for root in &config.roots {
+        if !root.exists() {
+            if cli.output_format().is_json() {
+                json_error("root", Some(root), "does not exist");
+            } else {
+                eprintln!("Target directory does not exist: {:?}", root);
+            }
+            return ExitCode::FAILURE;
+        }
+
+        if cli.debug > 0 {
+            eprintln!("Target directory: {:?}", root);
+        }
+    }
+
+    if let Some(threshold) = config.when_free_below {
+        let free_space = |path: &std::path::Path| available_space(path);
+        match free_space_below(&config.roots, threshold, &free_space) {
+            Ok(Some((root, available))) => {
+                if cli.debug > 0 {
+                    eprintln!("{} bytes free for {:?}, cleaning up", available, root);
+                }
+            }
+            Ok(None) => {
+                eprintln!(
+                    "At least {} is free, nothing to do",
+                    humanize_bytes_decimal!(threshold)
+                );
+                return ExitCode::SUCCESS;
+            }
+            Err(e) => {
+                eprintln!("Error checking free space: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
     }
 
-    if cli.debug {
-        eprintln!("Target directory: {:?}", cli.target_dir);
+    let reporter: &dyn Reporter = if cli.debug > 0 {
+        &StderrReporter
+    } else {
+        &NoopReporter
+    };
+
+    if cli.count_only {
+        return match find_targets(&config, reporter) {
+            Ok(result) => {
+                tally.found(&result.targets);
+                for warning in &result.warnings {
+                    eprintln!("Warning: {}", warning);
+                }
+                println!("{}", result.targets.len());
+                scan_finished(&result)
+            }
+            Err(e) => {
+                eprintln!("Error scanning directories: {}", e);
+                ExitCode::FAILURE
+            }
+        };
     }
 
-    // Find all target directories
-    let target_dirs = match find_target_dirs(&cli.target_dir, cli.debug) {
-        Ok(dirs) => dirs,
+    if let Some(Command::BiggestFiles { top, .. }) = &cli.command {
+        let found = find_targets(&config, reporter).and_then(|result| {
+            tally.found(&result.targets);
+            let targets: Vec<PathBuf> = result.targets.into_iter().map(|info| info.path).collect();
+            biggest_files(&targets, *top)
+        });
+        let rendered = match found {
+            Ok(files) => render_biggest_files(&mut std::io::stdout(), &files),
+            Err(e) => {
+                eprintln!("Error scanning directories: {}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+        return match rendered {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Error writing output: {}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let recorder = Recorder::new(reporter);
+    let explaining = cli.explain.is_some() || cli.explain_all || cli.debug > 1;
+    let scanned = match &cli.from_json {
+        Some(file) => from_json(file, &config, cli.max_growth),
+        None if explaining => scan(&config, &recorder),
+        None => scan(&config, reporter),
+    };
+    let result = match scanned {
+        Ok(result) => result,
+        Err(e) if cli.output_format().is_json() => {
+            json_error("scan", None, &e.to_string());
+            return ExitCode::FAILURE;
+        }
         Err(e) => {
             eprintln!("Error scanning directories: {}", e);
             return ExitCode::FAILURE;
         }
     };
+    for (path, e) in &result.errors {
+        if cli.output_format().is_json() {
+            json_error("size", Some(path), &e.to_string());
+        } else {
+            eprintln!("Error calculating size for {:?}: {}", path, e);
+        }
+    }
+    for warning in &result.warnings {
+        if cli.output_format().is_json() {
+            // Nowhere left to report stderr failing, as with errors
+            let _ = render_warning(&mut std::io::stderr(), warning);
+        } else {
+            eprintln!("Warning: {}", warning);
+        }
+    }
 
-    if target_dirs.is_empty() {
+    if let Some(path) = &cli.explain {
+        let steps = explain(path, &config, &result, &recorder.events());
+        return match render_explanation(&mut std::io::stdout(), path, &steps) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("Error writing output: {}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    if cli.explain_all || cli.debug > 1 {
+        let decided = explain_all(&config, &result, &recorder.events());
+        if let Err(e) = render_explain_all(&mut std::io::stderr(), &decided) {
+            eprintln!("Error writing output: {}", e);
+        }
+    }
+
+    let target_info = &result.targets;
+    tally.found(target_info);
+    // An exporter needs to say there's nothing, rather than leave the last metrics in place, and
+    // a plist pipeline or a sourcing script needs something to parse
+    if target_info.is_empty()
+        && !matches!(
+            cli.output_format(),
+            OutputFormat::Prometheus | OutputFormat::Plist | OutputFormat::Env
+        )
+    {
         eprintln!("No target directories found");
-        return ExitCode::SUCCESS;
+        for left in &result.left_out {
+            eprintln!(
+                "{} in {} left out by {}",
+                humanize_bytes_decimal!(left.size),
+                left.count,
+                left.option
+            );
+        }
+        return scan_finished(&result);
     }
 
-    if cli.debug {
-        eprintln!("Found {} target directories", target_dirs.len());
+    if cli.debug > 0 {
+        eprintln!("Found {} target directories", target_info.len());
+        eprintln!(
+            "Scan I/O: {} read_dir calls, {} metadata calls",
+            result.io_stats.read_dir_calls(),
+            result.io_stats.metadata_calls()
+        );
     }
 
-    // Calculate sizes in parallel using rayon
-    let mut target_info: Vec<TargetDirInfo> = target_dirs
-        .par_iter()
-        .filter_map(|path| match calculate_dir_size(path) {
-            Ok(size) => Some(TargetDirInfo {
-                path: path.clone(),
-                size,
-            }),
-            Err(e) => {
+    // Display results
+    let mut stdout = std::io::stdout();
+    let mut stderr = std::io::stderr();
+    if cli.list_triples {
+        let rendered = summarize_triples(target_info)
+            .and_then(|triples| render_triples(&mut stdout, &triples));
+        if let Err(e) = rendered {
+            eprintln!("Error listing triples: {}", e);
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
+    // A scan cut short hasn't seen everything, so deletion isn't started at all
+    if cli.delete && result.timed_out {
+        eprintln!("Not deleting anything, the scan didn't finish before --timeout");
+        return scan_finished(&result);
+    }
+    if cli.delete && config.loop_mode {
+        let mut rescan = || {
+            let result = scan(&config, reporter)?;
+            for (path, e) in &result.errors {
                 eprintln!("Error calculating size for {:?}: {}", path, e);
-                None
             }
-        })
-        .collect();
+            tally.seen(&result.targets);
+            Ok(result.targets)
+        };
+        let free_space = |path: &Path| available_space(path);
+        if let Err(e) = run_loop(
+            target_info.clone(),
+            &config,
+            &mut rescan,
+            &free_space,
+            &remover,
+            &mut Output::detect(&mut stdout, &mut stderr),
+        ) {
+            eprintln!("Error during deletion: {}", e);
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
+    if cli.delete {
+        if let Err(e) = handle_deletion_with(
+            target_info,
+            &config,
+            &remover,
+            &mut Output::detect(&mut stdout, &mut stderr),
+        ) {
+            eprintln!("Error during deletion: {}", e);
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
 
-    // Sort by size (largest first)
-    target_info.sort_by_key(|info| Reverse(info.size));
+    let human_readable = matches!(
+        cli.output_format(),
+        OutputFormat::Table | OutputFormat::Tiers
+    );
+    let cargo_home_summary = if cli.with_cargo_home_summary
+        && !cli.porcelain
+        && (human_readable || cli.output_format() == OutputFormat::Json)
+    {
+        let Some(cargo_home) = cargo_home_dir(|name| std::env::var(name).ok()) else {
+            eprintln!("Couldn't work out CARGO_HOME for the summary");
+            return ExitCode::FAILURE;
+        };
+        match CargoHomeSummary::measure(&cargo_home) {
+            Ok(summary) => Some(summary),
+            Err(e) if cli.output_format().is_json() => {
+                json_error("cargo_home", Some(&cargo_home), &e.to_string());
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Error summarising {}: {}", cargo_home.display(), e);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        None
+    };
 
-    // Display results
-    if !cli.delete {
-        println!("\nTarget directories (sorted by size):");
-        println!("{:>10}  PATH", "SIZE");
-        println!("{}", "-".repeat(80));
-        for info in &target_info {
-            println!(
-                "{:>10}  {}",
-                humanize_bytes_decimal!(info.size),
-                info.path.display()
-            );
+    let rendered = if cli.porcelain {
+        render_porcelain(&mut stdout, target_info)
+    } else {
+        match cli.output_format() {
+            OutputFormat::Table => {
+                let color = use_color(
+                    cli.color,
+                    |name| std::env::var(name).ok(),
+                    stdout.is_terminal(),
+                );
+                let options = TableOptions {
+                    order: cli.sort,
+                    color,
+                    truncated: result.truncated,
+                    report_empty: cli.report_empty,
+                    stats: cli.stats,
+                    show_extremes: cli.show_extremes,
+                    age_format: cli.age_format,
+                    dedup_report: cli.dedup_report,
+                    deduplicated_total: result.deduplicated_total,
+                    show_canonical: cli.show_canonical,
+                    details: cli.details,
+                    show_accessed: cli.show_accessed,
+                    subtotals: if cli.all_drives && fixed_drives().is_some() {
+                        subtotals_by_root(target_info, &config.roots)
+                    } else {
+                        Vec::new()
+                    },
+                    left_out: result.left_out.clone(),
+                    strip_prefixes: cli.strip_prefix.clone(),
+                    priority_age_weight: Some(cli.priority_age_weight),
+                    title: cli.title.clone(),
+                    no_header: cli.no_header,
+                };
+                render_table(&mut stdout, target_info, &options)
+            }
+            OutputFormat::TableNoColorWidth => {
+                render_fixed_table(&mut stdout, target_info, cli.path_width, &cli.strip_prefix)
+            }
+            OutputFormat::Html => render_html(&mut stdout, target_info, &cli.strip_prefix),
+            OutputFormat::Tiers => render_tiers(
+                &mut stdout,
+                target_info,
+                &cli.tier_bounds,
+                &cli.strip_prefix,
+            ),
+            OutputFormat::Du => render_du(
+                &mut stdout,
+                target_info,
+                du_human,
+                cli.du_total,
+                &cli.strip_prefix,
+            ),
+            OutputFormat::DuBytes => render_du(
+                &mut stdout,
+                target_info,
+                du_blocks,
+                cli.du_total,
+                &cli.strip_prefix,
+            ),
+            OutputFormat::Ncdu => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |since| since.as_secs());
+                render_ncdu(&mut stdout, target_info, now)
+            }
+            OutputFormat::Prometheus => render_prometheus(&mut stdout, target_info),
+            OutputFormat::Env => render_env(&mut stdout, target_info),
+            OutputFormat::Plist => render_plist(&mut stdout, target_info),
+            OutputFormat::Json => {
+                let meta = (!cli.no_meta).then(|| {
+                    scan_meta(
+                        &config.roots,
+                        matches,
+                        |name| std::env::var(name).ok(),
+                        SystemTime::now(),
+                    )
+                });
+                let stats = cli.stats.then(|| size_stats(target_info));
+                render_json(
+                    &mut stdout,
+                    target_info,
+                    meta.as_ref(),
+                    stats.as_ref(),
+                    cargo_home_summary.as_ref(),
+                )
+            }
+            OutputFormat::JsonlPlan => render_plan(&mut stdout, target_info),
+        }
+    };
+    if let Err(e) = rendered {
+        if cli.output_format().is_json() {
+            json_error("output", None, &e.to_string());
+        } else {
+            eprintln!("Error writing output: {}", e);
         }
-        let total_size: u64 = target_info.iter().map(|i| i.size).sum();
-        println!("{}", "-".repeat(80));
-        println!("{:>10}  Total", humanize_bytes_decimal!(total_size));
-    } else if let Err(e) = handle_deletion(&target_info, cli.force) {
-        eprintln!("Error during deletion: {}", e);
         return ExitCode::FAILURE;
     }
 
-    ExitCode::SUCCESS
+    if let Some(summary) = cargo_home_summary.filter(|_| human_readable) {
+        let targets_total = target_info.iter().map(|info| info.size).sum();
+        if let Err(e) = render_cargo_home_summary(&mut stdout, &summary, targets_total) {
+            eprintln!("Error writing output: {}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    scan_finished(&result)
+}
+
+/// Success, or [`TIMED_OUT_EXIT`] if `--timeout` cut the scan short, so scripts can tell.
+fn scan_finished(result: &ScanResult) -> ExitCode {
+    if result.timed_out {
+        ExitCode::from(TIMED_OUT_EXIT)
+    } else {
+        ExitCode::SUCCESS
+    }
 }
@@ -0,0 +1,232 @@
+use std::{fs, path::Path};
+
+/// The crate a target directory belongs to, from the `Cargo.toml` next to it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CrateDetails {
+    pub name: String,
+    /// The version, `workspace` if it's inherited or `-` for a workspace without a package.
+    pub version: String,
+}
+
+/// What was found in a manifest.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    pub package_name: Option<String>,
+    pub version: Option<ManifestVersion>,
+    /// There's a `[workspace]` table.
+    pub workspace: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ManifestVersion {
+    Literal(String),
+    /// `version.workspace = true` or `version = { workspace = true }`.
+    Workspace,
+}
+
+/// Reads a quoted TOML basic or literal string from the start of `value`, returning it and
+/// whatever follows.
+pub(crate) fn parse_string(value: &str) -> Result<(String, &str), String> {
+    let quote = value
+        .chars()
+        .next()
+        .filter(|&c| c == '"' || c == '\'')
+        .ok_or_else(|| format!("expected a string, found `{value}`"))?;
+    let rest = &value[1..];
+    let end = rest
+        .find(quote)
+        .ok_or_else(|| format!("unterminated string `{value}`"))?;
+    Ok((rest[..end].to_string(), &rest[end + 1..]))
+}
+
+/// Drops a trailing comment, checking nothing else follows the value.
+fn expect_end(rest: &str) -> Result<(), String> {
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(())
+    } else {
+        Err(format!("unexpected `{rest}` after value"))
+    }
+}
+
+fn is_workspace_true(value: &str) -> bool {
+    let inner = value.trim_start_matches('{').trim_end_matches('}');
+    inner.split(',').any(|pair| {
+        pair.split_once('=')
+            .is_some_and(|(k, v)| k.trim() == "workspace" && v.trim() == "true")
+    })
+}
+
+/// A shallow parser that only understands the `[package]` name and version and whether
+/// there's a `[workspace]`, so it doesn't need a full TOML parser.
+pub fn parse_manifest(contents: &str) -> Result<Manifest, String> {
+    let mut manifest = Manifest::default();
+    let mut table = String::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let context = |e: String| format!("line {}: {e}", number + 1);
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header.strip_prefix('[').unwrap_or(header);
+            let end = header
+                .find(']')
+                .ok_or_else(|| context("unterminated table header".to_string()))?;
+            table = header[..end].trim().to_string();
+            if table == "workspace" || table.starts_with("workspace.") {
+                manifest.workspace = true;
+            }
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            // Continuation of a multi-line value, which the fields we read never are
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        match (table.as_str(), key) {
+            ("package", "name") => {
+                let (name, rest) = parse_string(value).map_err(context)?;
+                expect_end(rest).map_err(context)?;
+                manifest.package_name = Some(name);
+            }
+            ("package", "version") if value.starts_with('{') => {
+                manifest.version = is_workspace_true(value).then_some(ManifestVersion::Workspace);
+            }
+            ("package", "version") => {
+                let (version, rest) = parse_string(value).map_err(context)?;
+                expect_end(rest).map_err(context)?;
+                manifest.version = Some(ManifestVersion::Literal(version));
+            }
+            ("package", "version.workspace") | ("package.version", "workspace") => {
+                let inherited = value.split('#').next().is_some_and(|v| v.trim() == "true");
+                manifest.version = inherited.then_some(ManifestVersion::Workspace);
+            }
+            _ => {}
+        }
+    }
+    Ok(manifest)
+}
+
+/// Works out the crate for the target directory at `target`, from its parent's `Cargo.toml`.
+///
+/// No manifest gives blank details. An unreadable or malformed one is an error, which
+/// callers are expected to note and then treat as blank.
+pub fn crate_details(target: &Path) -> Result<CrateDetails, String> {
+    let Some(project) = target.parent() else {
+        return Ok(CrateDetails::default());
+    };
+    let contents = match fs::read_to_string(project.join("Cargo.toml")) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(CrateDetails::default()),
+        Err(e) => return Err(e.to_string()),
+    };
+    let manifest = parse_manifest(&contents)?;
+    Ok(match manifest.package_name {
+        Some(name) => CrateDetails {
+            name,
+            version: match manifest.version {
+                Some(ManifestVersion::Literal(version)) => version,
+                Some(ManifestVersion::Workspace) => "workspace".to_string(),
+                None => "-".to_string(),
+            },
+        },
+        None if manifest.workspace => CrateDetails {
+            name: project
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            version: "-".to_string(),
+        },
+        None => CrateDetails::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_manifest_package() {
+        let manifest = parse_manifest(
+            "# A comment\n[package]\nname = \"example\" # trailing\nversion = '1.2.3'\n\
+             edition = \"2024\"\n\n[dependencies]\nname = \"not-this\"\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.package_name.as_deref(), Some("example"));
+        assert_eq!(
+            manifest.version,
+            Some(ManifestVersion::Literal("1.2.3".to_string()))
+        );
+        assert!(!manifest.workspace);
+    }
+
+    #[test]
+    fn test_parse_manifest_workspace_version() {
+        for version in ["version.workspace = true", "version = { workspace = true }"] {
+            let manifest =
+                parse_manifest(&format!("[package]\nname = \"member\"\n{version}\n")).unwrap();
+            assert_eq!(
+                manifest.version,
+                Some(ManifestVersion::Workspace),
+                "{version}"
+            );
+        }
+        let manifest =
+            parse_manifest("[package]\nname = \"member\"\n[package.version]\nworkspace = true\n")
+                .unwrap();
+        assert_eq!(manifest.version, Some(ManifestVersion::Workspace));
+    }
+
+    #[test]
+    fn test_parse_manifest_virtual_workspace() {
+        let manifest = parse_manifest("[workspace]\nmembers = [\n  \"a\",\n  \"b\",\n]\n").unwrap();
+        assert_eq!(manifest.package_name, None);
+        assert!(manifest.workspace);
+    }
+
+    #[test]
+    fn test_parse_manifest_malformed() {
+        assert!(parse_manifest("[package\nname = \"x\"\n").is_err());
+        assert!(parse_manifest("[package]\nname = \"unterminated\n").is_err());
+        assert!(parse_manifest("[package]\nname = example\n").is_err());
+    }
+
+    #[test]
+    fn test_crate_details() {
+        let temp_dir = TempDir::new().unwrap();
+        let write = |dir: &str, manifest: &str| {
+            let project = temp_dir.path().join(dir);
+            fs::create_dir_all(&project).unwrap();
+            fs::write(project.join("Cargo.toml"), manifest).unwrap();
+            project.join("target")
+        };
+
+        let target = write(
+            "odd-dir",
+            "[package]\nname = \"real-name\"\nversion = \"0.3.0\"\n",
+        );
+        assert_eq!(
+            crate_details(&target).unwrap(),
+            CrateDetails {
+                name: "real-name".to_string(),
+                version: "0.3.0".to_string()
+            }
+        );
+        let target = write("workspace-root", "[workspace]\nmembers = [\"a\"]\n");
+        assert_eq!(
+            crate_details(&target).unwrap(),
+            CrateDetails {
+                name: "workspace-root".to_string(),
+                version: "-".to_string()
+            }
+        );
+        let target = write("broken", "[package\n");
+        assert!(crate_details(&target).is_err());
+        assert_eq!(
+            crate_details(&temp_dir.path().join("missing").join("target")).unwrap(),
+            CrateDetails::default()
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::{ArgMatches, CommandFactory};
+
+use crate::output::ncdu::escape_json;
+
+/// Where and how a scan was made, for telling apart results gathered from several machines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScanMeta {
+    pub hostname: Option<String>,
+    pub username: Option<String>,
+    pub roots: Vec<PathBuf>,
+    pub version: &'static str,
+    /// When the scan was made, in UTC like `2024-05-01T12:00:00Z`.
+    pub timestamp: String,
+    /// Every option with a value, whether from the command line, the environment or its
+    /// default, as `(long name, values)`.
+    pub options: Vec<(String, Vec<String>)>,
+}
+
+impl ScanMeta {
+    /// The metadata as a JSON object.
+    pub fn to_json(&self) -> String {
+        let string = |value: &str| format!("\"{}\"", escape_json(value));
+        let optional = |value: &Option<String>| value.as_deref().map_or("null".to_string(), string);
+        let list = |values: Vec<String>| format!("[{}]", values.join(","));
+        let roots = self
+            .roots
+            .iter()
+            .map(|root| string(&root.to_string_lossy()))
+            .collect();
+        let options: Vec<String> = self
+            .options
+            .iter()
+            .map(|(name, values)| {
+                let values = values.iter().map(|value| string(value)).collect();
+                format!("{}:{}", string(name), list(values))
+            })
+            .collect();
+        format!(
+            "{{\"hostname\":{},\"username\":{},\"roots\":{},\"version\":{},\"timestamp\":{},\"options\":{{{}}}}}",
+            optional(&self.hostname),
+            optional(&self.username),
+            list(roots),
+            string(self.version),
+            string(&self.timestamp),
+            options.join(",")
+        )
+    }
+}
+
+/// Formats `time` as a UTC timestamp like `2024-05-01T12:00:00Z`, to the second.
+pub fn format_utc(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Howard Hinnant's days to civil date, for the proleptic Gregorian calendar
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// The value of every option clap has one for, after merging the command line, the
+/// environment and defaults. Only the options themselves are looked at, never the rest of the
+/// environment.
+pub fn effective_options(matches: &ArgMatches) -> Vec<(String, Vec<String>)> {
+    crate::Cli::command()
+        .get_arguments()
+        .filter(|arg| !matches!(arg.get_id().as_str(), "help" | "version"))
+        .filter_map(|arg| {
+            let id = arg.get_id().as_str();
+            let values = matches
+                .get_raw(id)?
+                .map(|value| value.to_string_lossy().into_owned())
+                .collect();
+            let name = arg.get_long().unwrap_or(id).to_string();
+            Some((name, values))
+        })
+        .collect()
+}
+
+/// This machine's name, from `HOSTNAME` or `COMPUTERNAME`, or `/etc/hostname`.
+fn hostname(env: &impl Fn(&str) -> Option<String>) -> Option<String> {
+    env("HOSTNAME")
+        .or_else(|| env("COMPUTERNAME"))
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Gathers the [`ScanMeta`] for a scan of `roots` made at `now`.
+pub fn scan_meta(
+    roots: &[PathBuf],
+    matches: &ArgMatches,
+    env: impl Fn(&str) -> Option<String>,
+    now: SystemTime,
+) -> ScanMeta {
+    ScanMeta {
+        hostname: hostname(&env),
+        username: env("USER")
+            .or_else(|| env("USERNAME"))
+            .filter(|name| !name.is_empty()),
+        roots: roots.to_vec(),
+        version: env!("CARGO_PKG_VERSION"),
+        timestamp: format_utc(now),
+        options: effective_options(matches),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_utc() {
+        assert_eq!(format_utc(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            format_utc(UNIX_EPOCH + Duration::from_secs(1_709_210_096)),
+            "2024-02-29T12:34:56Z"
+        );
+        assert_eq!(
+            format_utc(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn test_scan_meta() {
+        let matches = crate::Cli::command()
+            .try_get_matches_from(["clean-big-targets", "--max-size", "1GB", "/code"])
+            .unwrap();
+        let env = |name: &str| match name {
+            "HOSTNAME" => Some("builder-3".to_string()),
+            "USER" => Some("ci".to_string()),
+            "AWS_SECRET_ACCESS_KEY" => Some("hunter2".to_string()),
+            _ => None,
+        };
+        let now = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        let meta = scan_meta(&[PathBuf::from("/code")], &matches, env, now);
+
+        assert_eq!(meta.hostname.as_deref(), Some("builder-3"));
+        assert_eq!(meta.username.as_deref(), Some("ci"));
+        assert_eq!(meta.timestamp, "2024-02-29T12:34:56Z");
+        let option = |name: &str| {
+            meta.options
+                .iter()
+                .find(|(option, _)| option == name)
+                .map(|(_, values)| values.clone())
+        };
+        assert_eq!(option("max-size"), Some(vec!["1GB".to_string()]));
+        // Defaults are part of the effective options too
+        assert_eq!(option("sort"), Some(vec!["size-desc".to_string()]));
+        assert_eq!(option("min-size"), None);
+
+        let json = meta.to_json();
+        for field in [
+            "\"hostname\":\"builder-3\"",
+            "\"username\":\"ci\"",
+            "\"roots\":[\"/code\"]",
+            "\"version\":",
+            "\"timestamp\":\"2024-02-29T12:34:56Z\"",
+            "\"max-size\":[\"1GB\"]",
+        ] {
+            assert!(json.contains(field), "{field} in {json}");
+        }
+        // Nothing else from the environment ends up in it
+        assert!(!json.contains("hunter2"));
+        assert!(!json.contains("AWS_SECRET_ACCESS_KEY"));
+    }
+}
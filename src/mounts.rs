@@ -0,0 +1,431 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// The filesystem something is on: where it's mounted and what's mounted there.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mount {
+    pub point: PathBuf,
+    /// The device or source, such as `/dev/nvme0n1p2`, or the volume name on Windows.
+    pub device: String,
+}
+
+impl fmt::Display for Mount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.point.display(), self.device)
+    }
+}
+
+/// Undoes the octal escapes mountinfo uses for spaces, tabs, newlines and backslashes.
+fn unescape_mountinfo(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .filter(|_| bytes[i] == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match octal {
+            Some(byte) => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            None => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+/// Parses the contents of `/proc/self/mountinfo`, skipping lines that don't parse.
+///
+/// Each line is `<id> <parent> <major:minor> <root> <mount point> <options> [optional
+/// fields...] - <fs type> <source> <super options>`.
+pub fn parse_mountinfo(contents: &str) -> Vec<Mount> {
+    parse_mountinfo_lines(contents)
+        .into_iter()
+        .map(|line| line.mount)
+        .collect()
+}
+
+/// What one line of mountinfo says about a mount.
+struct MountLine {
+    mount: Mount,
+    noatime: bool,
+    fs_type: String,
+}
+
+fn parse_mountinfo_line(line: &str) -> Option<MountLine> {
+    let fields: Vec<&str> = line.split(' ').collect();
+    let point = fields.get(4)?;
+    let noatime = fields.get(5)?.split(',').any(|option| option == "noatime");
+    let separator = fields.iter().position(|&field| field == "-")?;
+    let fs_type = fields.get(separator + 1)?;
+    let source = fields.get(separator + 2)?;
+    let mount = Mount {
+        point: PathBuf::from(unescape_mountinfo(point)),
+        device: unescape_mountinfo(source),
+    };
+    Some(MountLine {
+        mount,
+        noatime,
+        fs_type: unescape_mountinfo(fs_type),
+    })
+}
+
+fn parse_mountinfo_lines(contents: &str) -> Vec<MountLine> {
+    contents.lines().filter_map(parse_mountinfo_line).collect()
+}
+
+/// The line in `table` for the mount `path` is on, chosen as [`longest_prefix`] does.
+fn line_for<'a>(path: &Path, table: &'a [MountLine]) -> Option<&'a MountLine> {
+    table
+        .iter()
+        .filter(|line| path.starts_with(&line.mount.point))
+        .max_by_key(|line| line.mount.point.components().count())
+}
+
+/// The mount `path` is on and its filesystem type, such as `ext4` or `nfs4`, going by the
+/// mountinfo `contents`.
+pub fn fs_type(path: &Path, contents: &str) -> Option<(Mount, String)> {
+    line_for(path, &parse_mountinfo_lines(contents))
+        .map(|line| (line.mount.clone(), line.fs_type.clone()))
+}
+
+/// Returns true for filesystem types that are reached over the network, where every stat is a
+/// round trip.
+pub fn is_network_fs(fs_type: &str) -> bool {
+    let fs_type = fs_type.strip_prefix("fuse.").unwrap_or(fs_type);
+    matches!(
+        fs_type,
+        "nfs"
+            | "nfs4"
+            | "cifs"
+            | "smb3"
+            | "smbfs"
+            | "afs"
+            | "9p"
+            | "ceph"
+            | "glusterfs"
+            | "lustre"
+            | "sshfs"
+            | "rclone"
+            | "davfs"
+    )
+}
+
+/// The first mount any of `paths` is on that's mounted `noatime`, going by the mountinfo
+/// `contents`. Access times there are never updated, so say nothing about use.
+pub fn noatime_mount<'a>(
+    paths: impl IntoIterator<Item = &'a Path>,
+    contents: &str,
+) -> Option<Mount> {
+    let table = parse_mountinfo_lines(contents);
+    paths.into_iter().find_map(|path| {
+        let line = line_for(path, &table)?;
+        line.noatime.then(|| line.mount.clone())
+    })
+}
+
+/// The mount in `mounts` that `path` is on, the one with the longest mount point that's a
+/// prefix of it. Later mounts win ties, as they're mounted over the earlier ones.
+pub fn longest_prefix<'a>(path: &Path, mounts: &'a [Mount]) -> Option<&'a Mount> {
+    mounts
+        .iter()
+        .filter(|mount| path.starts_with(&mount.point))
+        // The last of equally long ones
+        .max_by_key(|mount| mount.point.components().count())
+}
+
+/// Works out which mount each path is on, remembering the answer for each device.
+///
+/// On Linux that's a longest prefix match against the mount table, elsewhere the operating
+/// system is asked about each path. Everything on one device gets the same answer, so a
+/// filesystem bind mounted in two places shows up under whichever was resolved first.
+pub struct MountResolver {
+    table: Vec<Mount>,
+    cache: Mutex<HashMap<u64, Option<Mount>>>,
+}
+
+impl MountResolver {
+    /// A resolver matching paths against `table`, such as one from [`parse_mountinfo`].
+    pub fn new(table: Vec<Mount>) -> Self {
+        Self {
+            table,
+            cache: Mutex::default(),
+        }
+    }
+
+    /// A resolver for this system's mounts.
+    pub fn system() -> Self {
+        let table = if cfg!(target_os = "linux") {
+            std::fs::read_to_string("/proc/self/mountinfo")
+                .map(|contents| parse_mountinfo(&contents))
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        Self::new(table)
+    }
+
+    /// The mount `path` is on, if it can be worked out.
+    pub fn resolve(&self, path: &Path) -> Option<Mount> {
+        let path = crate::unc::canonicalize(path).ok()?;
+        let Some(device) = device_id(&path) else {
+            return self.lookup(&path);
+        };
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .entry(device)
+            .or_insert_with(|| self.lookup(&path))
+            .clone()
+    }
+
+    fn lookup(&self, path: &Path) -> Option<Mount> {
+        if self.table.is_empty() {
+            return query_mount(path);
+        }
+        longest_prefix(path, &self.table).cloned()
+    }
+}
+
+#[cfg(unix)]
+fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn device_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Asks the operating system which mount `path` is on, where there's no mount table to read.
+fn query_mount(path: &Path) -> Option<Mount> {
+    #[cfg(target_os = "macos")]
+    {
+        macos::query_mount(path)
+    }
+    #[cfg(windows)]
+    {
+        windows::query_mount(path)
+    }
+    #[cfg(not(any(target_os = "macos", windows)))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use std::{
+        ffi::{CStr, CString},
+        os::unix::ffi::OsStrExt,
+        path::{Path, PathBuf},
+    };
+
+    use super::Mount;
+
+    pub(super) fn query_mount(path: &Path) -> Option<Mount> {
+        let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        // SAFETY: statfs is plain old data, which it's fine to start out zeroed
+        let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+        // SAFETY: `path` is NUL terminated and `stats` is valid for writes
+        if unsafe { libc::statfs(path.as_ptr(), &mut stats) } != 0 {
+            return None;
+        }
+        // SAFETY: the kernel fills both names in as NUL terminated strings
+        let (point, device) = unsafe {
+            (
+                CStr::from_ptr(stats.f_mntonname.as_ptr()),
+                CStr::from_ptr(stats.f_mntfromname.as_ptr()),
+            )
+        };
+        Some(Mount {
+            point: PathBuf::from(point.to_string_lossy().into_owned()),
+            device: device.to_string_lossy().into_owned(),
+        })
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{
+        ffi::OsString,
+        os::windows::ffi::{OsStrExt, OsStringExt},
+        path::{Path, PathBuf},
+    };
+
+    use windows_sys::Win32::Storage::FileSystem::{
+        GetVolumeNameForVolumeMountPointW, GetVolumePathNameW,
+    };
+
+    use super::Mount;
+
+    const BUFFER_LEN: usize = 1024;
+
+    fn from_wide(buffer: &[u16]) -> OsString {
+        let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+        OsString::from_wide(&buffer[..len])
+    }
+
+    pub(super) fn query_mount(path: &Path) -> Option<Mount> {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut point = [0u16; BUFFER_LEN];
+        // SAFETY: `wide` is NUL terminated and `point` holds the BUFFER_LEN given
+        if unsafe { GetVolumePathNameW(wide.as_ptr(), point.as_mut_ptr(), BUFFER_LEN as u32) } == 0
+        {
+            return None;
+        }
+        let mut volume = [0u16; BUFFER_LEN];
+        // SAFETY: `point` was NUL terminated by the last call and `volume` holds BUFFER_LEN
+        let named = unsafe {
+            GetVolumeNameForVolumeMountPointW(
+                point.as_ptr(),
+                volume.as_mut_ptr(),
+                BUFFER_LEN as u32,
+            )
+        };
+        let point = from_wide(&point);
+        let device = if named == 0 {
+            point.to_string_lossy().into_owned()
+        } else {
+            from_wide(&volume).to_string_lossy().into_owned()
+        };
+        Some(Mount {
+            point: PathBuf::from(point),
+            device,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+22 1 259:2 / / rw,relatime shared:1 - ext4 /dev/nvme0n1p2 rw
+25 22 0:21 / /proc rw,nosuid shared:12 - proc proc rw
+30 22 259:3 / /home rw,relatime shared:2 - ext4 /dev/nvme0n1p3 rw
+31 30 8:17 / /home/me/big\\040disk rw,relatime shared:3 - xfs /dev/sdb1 rw
+40 30 259:3 /me/src /home/me/projects rw,relatime shared:2 - ext4 /dev/nvme0n1p3 rw
+not a mountinfo line
+";
+
+    fn mount(point: &str, device: &str) -> Mount {
+        Mount {
+            point: PathBuf::from(point),
+            device: device.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_mountinfo() {
+        let mounts = parse_mountinfo(MOUNTINFO);
+        assert_eq!(
+            mounts,
+            [
+                mount("/", "/dev/nvme0n1p2"),
+                mount("/proc", "proc"),
+                mount("/home", "/dev/nvme0n1p3"),
+                mount("/home/me/big disk", "/dev/sdb1"),
+                mount("/home/me/projects", "/dev/nvme0n1p3"),
+            ]
+        );
+        assert_eq!(mount("/home", "/dev/sda1").to_string(), "/home (/dev/sda1)");
+    }
+
+    #[test]
+    fn test_fs_type() {
+        let contents =
+            format!("{MOUNTINFO}50 30 0:50 / /home/me/nfs rw - nfs4 server:/export rw\n");
+        assert_eq!(
+            fs_type(Path::new("/home/me/big disk/app"), &contents),
+            Some((mount("/home/me/big disk", "/dev/sdb1"), "xfs".to_string()))
+        );
+        let (_, nfs) = fs_type(Path::new("/home/me/nfs/code"), &contents).unwrap();
+        assert!(is_network_fs(&nfs));
+        assert!(is_network_fs("fuse.sshfs"));
+        assert!(!is_network_fs("ext4"));
+        assert!(fs_type(Path::new("relative"), &contents).is_none());
+    }
+
+    #[test]
+    fn test_noatime_mount() {
+        let contents = MOUNTINFO.replace(
+            "/home/me/big\\040disk rw,relatime",
+            "/home/me/big\\040disk rw,noatime",
+        );
+        let paths = |paths: &'static [&'static str]| paths.iter().map(Path::new);
+        assert_eq!(
+            noatime_mount(
+                paths(&["/home/me/code/target", "/home/me/big disk/app/target"]),
+                &contents
+            ),
+            Some(mount("/home/me/big disk", "/dev/sdb1"))
+        );
+        assert_eq!(
+            noatime_mount(paths(&["/home/me/code/target"]), &contents),
+            None
+        );
+        assert_eq!(
+            noatime_mount(paths(&["/home/me/big disk/target"]), MOUNTINFO),
+            None
+        );
+    }
+
+    #[test]
+    fn test_longest_prefix_with_nested_mounts() {
+        let mounts = parse_mountinfo(MOUNTINFO);
+        let on = |path: &str| longest_prefix(Path::new(path), &mounts).map(|m| m.point.clone());
+        assert_eq!(on("/usr/src/app/target"), Some(PathBuf::from("/")));
+        assert_eq!(on("/home/me/code/target"), Some(PathBuf::from("/home")));
+        assert_eq!(
+            on("/home/me/big disk/app/target"),
+            Some(PathBuf::from("/home/me/big disk"))
+        );
+        // Whole components only, `/home/me/big` isn't on `/home/me/big disk`
+        assert_eq!(on("/home/me/big/target"), Some(PathBuf::from("/home")));
+        assert_eq!(
+            on("/home/me/projects/app/target"),
+            Some(PathBuf::from("/home/me/projects"))
+        );
+        assert_eq!(longest_prefix(Path::new("/anything"), &[]), None);
+
+        // A later mount over the same point hides the earlier one
+        let over = [
+            mount("/", "/dev/a"),
+            mount("/data", "/dev/b"),
+            mount("/data", "/dev/c"),
+        ];
+        assert_eq!(longest_prefix(Path::new("/data/x"), &over), Some(&over[2]));
+    }
+
+    #[test]
+    fn test_resolver_caches_per_device() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        for dir in ["first", "second"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let scratch = Mount {
+            point: root.clone(),
+            device: "scratch".to_string(),
+        };
+        let resolver = MountResolver::new(vec![mount("/", "rootfs"), scratch.clone()]);
+        assert_eq!(resolver.resolve(&root.join("first")), Some(scratch.clone()));
+        assert_eq!(resolver.resolve(&root.join("second")), Some(scratch));
+        if cfg!(unix) {
+            assert_eq!(resolver.cache.lock().unwrap().len(), 1);
+        }
+        assert_eq!(resolver.resolve(&root.join("missing")), None);
+    }
+}
@@ -0,0 +1,1004 @@
+use std::{
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+use humanize_bytes::humanize_bytes_decimal;
+
+use crate::{
+    EnvVar, LeftOut, SortOrder, TargetDirInfo, TargetKind, age_extremes, bytes_per_file,
+    dedup::duplicate_groups,
+    estimate::Estimate,
+    meta::format_utc,
+    priority::{DEFAULT_AGE_WEIGHT, score},
+    size_stats,
+    triples::TripleSummary,
+    unc,
+};
+
+pub mod du;
+pub mod env;
+pub mod fixed;
+pub mod html;
+pub mod json;
+pub mod ncdu;
+pub mod plist;
+pub mod prometheus;
+pub mod tiers;
+
+const RESET: &str = "\x1b[0m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const GREEN: &str = "\x1b[32m";
+
+/// Where user-facing messages go.
+pub struct Output<'a> {
+    pub out: &'a mut dyn Write,
+    pub err: &'a mut dyn Write,
+    /// Whether we can prompt the user for input.
+    pub interactive: bool,
+}
+
+impl<'a> Output<'a> {
+    pub fn new(out: &'a mut dyn Write, err: &'a mut dyn Write, interactive: bool) -> Self {
+        Self {
+            out,
+            err,
+            interactive,
+        }
+    }
+
+    /// Writes to the given writers, prompting only if stdin and stderr are terminals.
+    pub fn detect(out: &'a mut dyn Write, err: &'a mut dyn Write) -> Self {
+        let interactive = std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+        Self::new(out, err, interactive)
+    }
+}
+
+/// How to present the results when not deleting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// A human readable table
+    #[default]
+    Table,
+    /// The table with fixed column widths and no color, so reports can be compared with
+    /// `diff`, see `--path-width`
+    TableNoColorWidth,
+    /// A standalone HTML document with a sortable table
+    Html,
+    /// Grouped into size tiers with subtotals, see `--tier-bounds`
+    Tiers,
+    /// `<size>\t<path>` lines like `du -sh`, see `--du-total`
+    Du,
+    /// `<1K-blocks>\t<path>` lines like `du -sk`, see `--du-total`
+    DuBytes,
+    /// An ncdu JSON export, to browse with `ncdu -f`
+    Ncdu,
+    /// Prometheus gauges, for node_exporter's textfile collector
+    Prometheus,
+    /// `CBT_DIR_COUNT=12` style shell assignments of the count, the total and the largest
+    /// directory, to `eval` or source
+    Env,
+    /// An XML property list of each directory's `Path` and `Size` and the `Total`, for macOS
+    /// tooling such as Automator
+    Plist,
+    /// A JSON object with each directory and a `meta` object saying where the scan was made,
+    /// see `--no-meta`
+    Json,
+    /// A JSON object per line with each directory's path, size and file count, for another
+    /// program to delete them or pass to `--execute-plan`
+    JsonlPlan,
+}
+
+impl OutputFormat {
+    /// Returns true for the JSON formats, whose errors are written to stderr as JSON too.
+    pub fn is_json(self) -> bool {
+        matches!(self, Self::Json | Self::JsonlPlan)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Colorize when writing to a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Works out whether output should be colorized.
+///
+/// Precedence is: an explicit `--color always|never`, then `CLICOLOR_FORCE` (set and not `0`
+/// forces color on), then `NO_COLOR` (set and non-empty turns color off), then whether the
+/// output is a terminal.
+pub fn use_color(
+    choice: ColorChoice,
+    env: impl Fn(&str) -> Option<String>,
+    is_terminal: bool,
+) -> bool {
+    match choice {
+        ColorChoice::Always => return true,
+        ColorChoice::Never => return false,
+        ColorChoice::Auto => {}
+    }
+    if env("CLICOLOR_FORCE").is_some_and(|value| !value.is_empty() && value != "0") {
+        return true;
+    }
+    if env("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        return false;
+    }
+    is_terminal
+}
+
+/// How to lay out the results table.
+#[derive(Clone, Debug, Default)]
+pub struct TableOptions {
+    pub order: SortOrder,
+    pub color: bool,
+    /// Add a notice that the scan stopped early.
+    pub truncated: bool,
+    /// Show "(empty)" instead of a size for directories with no entries at all.
+    pub report_empty: bool,
+    /// Add size distribution statistics after the total.
+    pub stats: bool,
+    /// Add the least and most recently built targets after the total.
+    pub show_extremes: bool,
+    /// Group the targets that look like copies of each other, see
+    /// [`duplicate_groups`](crate::dedup::duplicate_groups).
+    pub dedup_report: bool,
+    /// Add a total that counts hardlinked files once.
+    pub deduplicated_total: Option<u64>,
+    /// Follow each path with where it really is, when symlinks make that differ.
+    pub show_canonical: bool,
+    /// Add NAME and VERSION columns from each target's
+    /// [`CrateDetails`](crate::manifest::CrateDetails).
+    pub details: bool,
+    /// Add a LAST ACCESSED column from each target's newest file access time.
+    pub show_accessed: bool,
+    /// How the LAST ACCESSED column and `show_extremes` show times.
+    pub age_format: AgeFormat,
+    /// Subtotals to add after the total, such as one per drive for `--all-drives`.
+    pub subtotals: Vec<(PathBuf, u64)>,
+    /// What the size and file count bounds left out, to note after the total.
+    pub left_out: Vec<LeftOut>,
+    /// Prefixes to rewrite in the paths shown, see [`shown_path`].
+    pub strip_prefixes: Vec<StripPrefix>,
+    /// The `--priority-age-weight` for the PRIORITY column `details` adds, the default if not
+    /// given.
+    pub priority_age_weight: Option<f64>,
+    /// Used in place of the usual title line.
+    pub title: Option<String>,
+    /// Leave out the title, column names and the rule under them.
+    pub no_header: bool,
+}
+
+/// A `--strip-prefix` value, rewriting `prefix` to `replacement` in the paths shown.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StripPrefix {
+    pub prefix: PathBuf,
+    pub replacement: String,
+}
+
+impl StripPrefix {
+    /// Parses `PREFIX[=REPLACEMENT]`, the replacement being empty if it's not given.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (prefix, replacement) = value.split_once('=').unwrap_or((value, ""));
+        if prefix.is_empty() {
+            return Err(format!("'{value}' has an empty prefix"));
+        }
+        Ok(Self {
+            prefix: PathBuf::from(prefix),
+            replacement: replacement.to_string(),
+        })
+    }
+}
+
+/// How to show `path` with the longest matching prefix in `strip` rewritten.
+///
+/// Prefixes match whole components, so `/home/al` doesn't match `/home/alice`. Only the text
+/// shown changes, the path itself is what gets deleted. Extended-length Windows paths are
+/// shown, and matched against `strip`, as they'd be typed, see [`unc`].
+pub fn shown_path(path: &Path, strip: &[StripPrefix]) -> String {
+    let path = unc::display_form(path);
+    let longest = strip
+        .iter()
+        .filter_map(|strip| Some((strip, path.strip_prefix(&strip.prefix).ok()?)))
+        .max_by_key(|(strip, _)| strip.prefix.components().count());
+    let Some((strip, rest)) = longest else {
+        return path.display().to_string();
+    };
+    match (strip.replacement.as_str(), rest.as_os_str().is_empty()) {
+        ("", _) => rest.display().to_string(),
+        (replacement, true) => replacement.to_string(),
+        (replacement, false) => Path::new(replacement).join(rest).display().to_string(),
+    }
+}
+
+/// How times are shown in the table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum AgeFormat {
+    /// How long ago, such as `3 days ago`
+    #[default]
+    Relative,
+    /// The UTC time, such as `2024-05-01T12:00:00Z`
+    Iso,
+}
+
+/// Formats `time` as `format` asks, or `-` if it isn't known.
+pub fn format_time(time: Option<SystemTime>, now: SystemTime, format: AgeFormat) -> String {
+    match (format, time) {
+        (AgeFormat::Relative, _) => format_age(time, now),
+        (AgeFormat::Iso, Some(time)) => format_utc(time),
+        (AgeFormat::Iso, None) => "-".to_string(),
+    }
+}
+
+/// Formats how long ago `time` was, such as `3 days ago`, or `-` if it isn't known.
+pub fn format_age(time: Option<SystemTime>, now: SystemTime) -> String {
+    let Some(since) = time.and_then(|time| now.duration_since(time).ok()) else {
+        return "-".to_string();
+    };
+    let hours = since.as_secs() / 3600;
+    match (hours / 24, hours) {
+        (1, _) => "1 day ago".to_string(),
+        (days @ 2.., _) => format!("{days} days ago"),
+        (0, 1) => "1 hour ago".to_string(),
+        (0, hours @ 2..) => format!("{hours} hours ago"),
+        _ => "within the hour".to_string(),
+    }
+}
+
+/// The path to show for `info`, with ` -> <canonical path>` added if asked and it differs.
+fn display_path(info: &TargetDirInfo, options: &TableOptions) -> String {
+    let path = shown_path(&info.path, &options.strip_prefixes);
+    if !options.show_canonical {
+        return path;
+    }
+    match unc::canonicalize(&info.path) {
+        Ok(canonical) if canonical != info.path => format!(
+            "{path} -> {}",
+            shown_path(&canonical, &options.strip_prefixes)
+        ),
+        _ => path,
+    }
+}
+
+/// Formats a single row of the results table, coloring the size by magnitude if asked.
+pub fn format_row(info: &TargetDirInfo, options: &TableOptions) -> String {
+    format_row_columns(info, options, "")
+}
+
+/// Like [`format_row`], with `columns` between the size and the path.
+fn format_row_columns(info: &TargetDirInfo, options: &TableOptions, columns: &str) -> String {
+    let size = if options.report_empty && info.empty {
+        format!("{:>10}", "(empty)")
+    } else if info.size_margin.is_some() || info.vanished > 0 {
+        format!("{:>10}", format!("~{}", humanize_bytes_decimal!(info.size)))
+    } else {
+        format!("{:>10}", humanize_bytes_decimal!(info.size))
+    };
+    let mut path = format!("{columns}{}", display_path(info, options));
+    if let TargetKind::WasmPack | TargetKind::Trunk = info.kind {
+        path.push_str(&format!("  ({})", info.kind.as_str()));
+    }
+    if info.stale_toolchain {
+        path.push_str("  STALE-TOOLCHAIN");
+    }
+    if info.protected {
+        path.push_str("  PROTECTED");
+    }
+    if !options.color {
+        return format!("{}  {}", size, path);
+    }
+    let code = match info.size {
+        s if s >= 1_000_000_000 => RED,
+        s if s >= 100_000_000 => YELLOW,
+        _ => GREEN,
+    };
+    format!("{code}{size}{RESET}  {path}")
+}
+
+/// Writes the results table, including the total and a notice if the scan was truncated.
+pub fn render_table(
+    out: &mut dyn Write,
+    target_info: &[TargetDirInfo],
+    options: &TableOptions,
+) -> std::io::Result<()> {
+    let order = match options.order {
+        SortOrder::SizeDesc => "size, largest first",
+        SortOrder::SizeAsc => "size, smallest first",
+        SortOrder::Priority => "reclaim priority, highest first",
+        SortOrder::Files => "file count, most first",
+        SortOrder::ReclaimablePerFile => "bytes per file, most first",
+    };
+    // Optional columns between SIZE and PATH, as (header, one value per row)
+    let mut extra: Vec<(&str, Vec<String>)> = Vec::new();
+    if options.details {
+        let details: Vec<_> = target_info
+            .iter()
+            .map(|info| info.crate_details.clone().unwrap_or_default())
+            .collect();
+        extra.push(("NAME", details.iter().map(|d| d.name.clone()).collect()));
+        extra.push(("VERSION", details.into_iter().map(|d| d.version).collect()));
+        let rustc = target_info.iter().map(|info| {
+            info.rustc
+                .as_ref()
+                .map(|rustc| rustc.version.to_string())
+                .unwrap_or_default()
+        });
+        extra.push(("RUSTC", rustc.collect()));
+        let mount = target_info.iter().map(|info| {
+            info.mount
+                .as_ref()
+                .map(|mount| mount.to_string())
+                .unwrap_or_default()
+        });
+        extra.push(("MOUNT", mount.collect()));
+        // In billions, which keeps the scores of big old targets readable
+        let now = SystemTime::now();
+        let age_weight = options.priority_age_weight.unwrap_or(DEFAULT_AGE_WEIGHT);
+        let priority = target_info
+            .iter()
+            .map(|info| format!("{:.1}", score(info, age_weight, now) / 1e9));
+        extra.push(("PRIORITY", priority.collect()));
+    }
+    if options.order == SortOrder::ReclaimablePerFile {
+        let per_file = target_info.iter().map(|info| {
+            bytes_per_file(info)
+                .map(|bytes| humanize_bytes_decimal!(bytes).to_string())
+                .unwrap_or_default()
+        });
+        extra.push(("PER FILE", per_file.collect()));
+    }
+    if options.show_accessed {
+        let now = SystemTime::now();
+        let ages = target_info
+            .iter()
+            .map(|info| format_time(info.accessed, now, options.age_format));
+        extra.push(("LAST ACCESSED", ages.collect()));
+    }
+    let widths: Vec<usize> = extra
+        .iter()
+        .map(|(header, values)| {
+            values
+                .iter()
+                .map(String::len)
+                .fold(header.len(), usize::max)
+        })
+        .collect();
+    let columns = |row: Option<usize>| {
+        let mut columns = String::new();
+        for ((header, values), width) in extra.iter().zip(&widths) {
+            let value = row.map_or(*header, |row| values[row].as_str());
+            columns.push_str(&format!("{value:<width$}  "));
+        }
+        columns
+    };
+
+    if !options.no_header {
+        match &options.title {
+            Some(title) => writeln!(out, "\n{title}")?,
+            None => writeln!(out, "\nTarget directories (sorted by {order}):")?,
+        }
+        writeln!(out, "{:>10}  {}PATH", "SIZE", columns(None))?;
+        writeln!(out, "{}", "-".repeat(80))?;
+    }
+    for (row, info) in target_info.iter().enumerate() {
+        writeln!(
+            out,
+            "{}",
+            format_row_columns(info, options, &columns(Some(row)))
+        )?;
+    }
+    let total_size: u64 = target_info.iter().map(|i| i.size).sum();
+    writeln!(out, "{}", "-".repeat(80))?;
+    writeln!(out, "{:>10}  Total", humanize_bytes_decimal!(total_size))?;
+    if target_info.iter().any(|info| info.size_margin.is_some()) {
+        let total = target_info
+            .iter()
+            .map(|info| Estimate {
+                bytes: info.size,
+                margin: info.size_margin.unwrap_or(0),
+            })
+            .fold(Estimate::default(), Estimate::combine);
+        writeln!(
+            out,
+            "{:>10}  Either way on the total, as sizes marked ~ are estimates from sampling \
+             ({:.1}%)",
+            format!("±{}", humanize_bytes_decimal!(total.margin)),
+            total.margin_percent()
+        )?;
+    }
+    let protected: Vec<&TargetDirInfo> = target_info.iter().filter(|info| info.protected).collect();
+    if !protected.is_empty() {
+        writeln!(
+            out,
+            "{:>10}  Protected, in {} director{}, which won't be deleted",
+            humanize_bytes_decimal!(protected.iter().map(|info| info.size).sum::<u64>()),
+            protected.len(),
+            if protected.len() == 1 { "y" } else { "ies" }
+        )?;
+    }
+    for (root, size) in &options.subtotals {
+        writeln!(
+            out,
+            "{:>10}  Subtotal for {}",
+            humanize_bytes_decimal!(*size),
+            root.display()
+        )?;
+    }
+    for left in &options.left_out {
+        writeln!(
+            out,
+            "{:>10}  Left out by {}, in {} director{}",
+            humanize_bytes_decimal!(left.size),
+            left.option,
+            left.count,
+            if left.count == 1 { "y" } else { "ies" }
+        )?;
+    }
+    if let Some(deduplicated) = options.deduplicated_total {
+        writeln!(
+            out,
+            "{:>10}  Total, counting hardlinked files once",
+            humanize_bytes_decimal!(deduplicated)
+        )?;
+    }
+    if options.stats {
+        let stats = size_stats(target_info);
+        writeln!(out, "{:>10}  Median", humanize_bytes_decimal!(stats.median))?;
+        writeln!(
+            out,
+            "{:>10}  90th percentile",
+            humanize_bytes_decimal!(stats.p90)
+        )?;
+        writeln!(
+            out,
+            "{:>10}  Standard deviation",
+            humanize_bytes_decimal!(stats.std_dev.round() as u64)
+        )?;
+        let labels = ["< 100 MB", "100 MB - 1 GB", "1 GB - 10 GB", "> 10 GB"];
+        for (label, count) in labels.iter().zip(stats.buckets) {
+            writeln!(out, "{:>10}  {}", count, label)?;
+        }
+    }
+    if let Some((oldest, newest)) = age_extremes(target_info).filter(|_| options.show_extremes) {
+        let now = SystemTime::now();
+        writeln!(
+            out,
+            "Oldest: {} (built {}), Newest: {} (built {})",
+            shown_path(&oldest.path, &options.strip_prefixes),
+            format_time(oldest.modified, now, options.age_format),
+            shown_path(&newest.path, &options.strip_prefixes),
+            format_time(newest.modified, now, options.age_format)
+        )?;
+    }
+    if options.dedup_report {
+        let groups = duplicate_groups(target_info);
+        if groups.is_empty() {
+            writeln!(out, "No target directories with identical contents")?;
+        }
+        for group in groups {
+            writeln!(
+                out,
+                "Identical contents, {} each:",
+                humanize_bytes_decimal!(group[0].size)
+            )?;
+            for info in group {
+                writeln!(out, "  {}", shown_path(&info.path, &options.strip_prefixes))?;
+            }
+        }
+    }
+    if options.truncated {
+        writeln!(
+            out,
+            "Scan truncated after {} target directories (--max-results), results are incomplete",
+            target_info.len()
+        )?;
+    }
+    Ok(())
+}
+
+/// Escapes a path for porcelain output, see [`render_porcelain`].
+pub fn escape_porcelain_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for chunk in path.as_os_str().as_encoded_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '\t' => escaped.push_str("\\t"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                c => escaped.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            escaped.push_str(&format!("\\x{byte:02x}"));
+        }
+    }
+    escaped
+}
+
+/// Writes `size` on its own, for `--size-of`.
+pub fn render_size(out: &mut dyn Write, size: u64) -> std::io::Result<()> {
+    writeln!(out, "{}", humanize_bytes_decimal!(size))
+}
+
+/// Writes `vars` as an aligned table of variable, option and description, for `--list-env`.
+pub fn render_env_list(out: &mut dyn Write, vars: &[EnvVar]) -> std::io::Result<()> {
+    let name_width = vars.iter().map(|var| var.name.len()).max().unwrap_or(0);
+    let option_width = vars.iter().map(|var| var.option.len()).max().unwrap_or(0);
+    for var in vars {
+        writeln!(
+            out,
+            "{:<name_width$}  {:<option_width$}  {}",
+            var.name, var.option, var.help
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the triples found by `--list-triples`, with how many targets have each.
+pub fn render_triples(out: &mut dyn Write, triples: &[TripleSummary]) -> std::io::Result<()> {
+    writeln!(out, "{:>10}  {:>7}  TRIPLE", "SIZE", "TARGETS")?;
+    for summary in triples {
+        writeln!(
+            out,
+            "{:>10}  {:>7}  {}",
+            humanize_bytes_decimal!(summary.size),
+            summary.targets,
+            summary.triple
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes results in the stable `--porcelain` format.
+///
+/// One line per directory: `<size_bytes>\t<epoch_mtime>\t<kind>\t<path>`. `epoch_mtime` is whole
+/// seconds since the Unix epoch, or `-` if unknown. The path is escaped by
+/// [`escape_porcelain_path`]. This format is frozen, any new information gets a new format.
+pub fn render_porcelain(out: &mut dyn Write, target_info: &[TargetDirInfo]) -> std::io::Result<()> {
+    for info in target_info {
+        let mtime = info
+            .modified
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_secs().to_string())
+            .unwrap_or_else(|| "-".to_string());
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}",
+            info.size,
+            mtime,
+            info.kind.as_str(),
+            escape_porcelain_path(&info.path)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::CrateDetails;
+    use std::path::PathBuf;
+
+    fn info() -> TargetDirInfo {
+        TargetDirInfo::new(PathBuf::from("/projects/example/target"), 2_000_000_000)
+    }
+
+    #[test]
+    fn test_no_color_env_disables_color_on_terminal() {
+        let color = use_color(
+            ColorChoice::Auto,
+            |name| (name == "NO_COLOR").then(|| "1".to_string()),
+            true,
+        );
+        assert!(!color);
+        let options = TableOptions {
+            color,
+            ..TableOptions::default()
+        };
+        assert!(!format_row(&info(), &options).contains('\x1b'));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_show_canonical_for_symlinked_project() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let real = temp_dir.path().join("hidden").join("real");
+        std::fs::create_dir_all(real.join("target")).unwrap();
+        std::os::unix::fs::symlink(&real, temp_dir.path().join("link")).unwrap();
+
+        let root = temp_dir.path().canonicalize().unwrap();
+        let found: Vec<TargetDirInfo> =
+            crate::walk::walk_targets(&root, &Default::default(), &Default::default())
+                .map(|path| TargetDirInfo::new(path.unwrap(), 0))
+                .collect();
+        assert_eq!(found.len(), 1);
+
+        let options = TableOptions {
+            show_canonical: true,
+            ..TableOptions::default()
+        };
+        assert_eq!(
+            format_row(&found[0], &options),
+            format!(
+                "{:>10}  {} -> {}",
+                "0 B",
+                root.join("link").join("target").display(),
+                root.join("hidden").join("real").join("target").display()
+            )
+        );
+        // Nothing is added when the path is already canonical, or without the option
+        let direct = TargetDirInfo::new(root.join("hidden").join("real").join("target"), 0);
+        assert!(!format_row(&direct, &options).contains(" -> "));
+        assert!(!format_row(&found[0], &TableOptions::default()).contains(" -> "));
+    }
+
+    #[test]
+    fn test_render_table_notes_what_bounds_left_out() {
+        let target_info = [TargetDirInfo::new(PathBuf::from("/code/a/target"), 1000)];
+        let render = |left_out| {
+            let mut out = Vec::new();
+            let options = TableOptions {
+                left_out,
+                ..TableOptions::default()
+            };
+            render_table(&mut out, &target_info, &options).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let rendered = render(vec![
+            LeftOut {
+                option: "--max-size",
+                count: 2,
+                size: 3_000_000_000,
+            },
+            LeftOut {
+                option: "--min-files",
+                count: 1,
+                size: 2_000,
+            },
+        ]);
+        assert!(rendered.contains("      3 GB  Left out by --max-size, in 2 directories\n"));
+        assert!(rendered.contains("      2 kB  Left out by --min-files, in 1 directory\n"));
+        assert!(!render(Vec::new()).contains("Left out"));
+    }
+
+    #[test]
+    fn test_strip_prefix_changes_only_the_shown_path() {
+        let strip = vec![
+            StripPrefix::parse("/home/alice=~").unwrap(),
+            StripPrefix::parse("/home/alice/work/").unwrap(),
+        ];
+        assert_eq!(
+            strip[0],
+            StripPrefix {
+                prefix: PathBuf::from("/home/alice"),
+                replacement: "~".to_string()
+            }
+        );
+        assert!(StripPrefix::parse("=~").is_err());
+
+        let path = PathBuf::from("/home/alice/code/app/target");
+        let info = TargetDirInfo::new(path.clone(), 1000);
+        let options = TableOptions {
+            strip_prefixes: strip.clone(),
+            ..TableOptions::default()
+        };
+        assert_eq!(
+            format_row(&info, &options),
+            format!(
+                "{:>10}  {}",
+                "1 kB",
+                Path::new("~/code/app/target").display()
+            )
+        );
+        assert_eq!(info.path, path);
+
+        // The longest prefix wins, and prefixes only match whole components
+        assert_eq!(
+            shown_path(Path::new("/home/alice/work/app/target"), &strip),
+            Path::new("app/target").display().to_string()
+        );
+        assert_eq!(
+            shown_path(Path::new("/home/alicia/target"), &strip),
+            "/home/alicia/target"
+        );
+        assert_eq!(shown_path(Path::new("/home/alice"), &strip), "~");
+    }
+
+    #[test]
+    fn test_render_table_details_columns() {
+        let mut named = TargetDirInfo::new(PathBuf::from("/code/odd-dir/target"), 1000);
+        named.crate_details = Some(CrateDetails {
+            name: "real-name".to_string(),
+            version: "0.3.0".to_string(),
+        });
+        named.rustc = Some(crate::rustc::RustcInfo {
+            version: crate::rustc::RustcVersion::parse("1.75.0").unwrap(),
+            commit_hash: None,
+        });
+        named.mount = Some(crate::mounts::Mount {
+            point: PathBuf::from("/code"),
+            device: "/dev/sdb1".to_string(),
+        });
+        let mut blank = TargetDirInfo::new(PathBuf::from("/code/broken/target"), 500);
+        blank.crate_details = Some(CrateDetails::default());
+
+        let options = TableOptions {
+            details: true,
+            ..TableOptions::default()
+        };
+        let mut out = Vec::new();
+        render_table(&mut out, &[named, blank], &options).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[2],
+            "      SIZE  NAME       VERSION  RUSTC   MOUNT              PRIORITY  PATH"
+        );
+        assert_eq!(
+            lines[4],
+            "      1 kB  real-name  0.3.0    1.75.0  /code (/dev/sdb1)  0.0       /code/odd-dir/target"
+        );
+        assert_eq!(
+            lines[5],
+            "     500 B                                                 0.0       /code/broken/target"
+        );
+
+        // 5 GB that was last built 30 days ago scores 150 billion
+        let mut stale = TargetDirInfo::new(PathBuf::from("/code/stale/target"), 5_000_000_000);
+        stale.modified = Some(SystemTime::now() - std::time::Duration::from_secs(30 * 86400));
+        let mut out = Vec::new();
+        render_table(&mut out, &[stale], &options).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("  150.0     /code/stale/target"), "{out}");
+    }
+
+    #[test]
+    fn test_format_age() {
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        let ago = |secs| Some(now - std::time::Duration::from_secs(secs));
+        assert_eq!(format_age(None, now), "-");
+        assert_eq!(format_age(ago(60), now), "within the hour");
+        assert_eq!(format_age(ago(3600), now), "1 hour ago");
+        assert_eq!(format_age(ago(5 * 3600), now), "5 hours ago");
+        assert_eq!(format_age(ago(86400 + 5), now), "1 day ago");
+        assert_eq!(format_age(ago(40 * 86400), now), "40 days ago");
+        // Access times in the future, from clock skew, aren't known ages
+        assert_eq!(
+            format_age(Some(now + std::time::Duration::from_secs(10)), now),
+            "-"
+        );
+    }
+
+    #[test]
+    fn test_format_time() {
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(1_709_210_096);
+        let built = Some(now - std::time::Duration::from_secs(3 * 86400));
+        assert_eq!(format_time(built, now, AgeFormat::Relative), "3 days ago");
+        assert_eq!(
+            format_time(built, now, AgeFormat::Iso),
+            "2024-02-26T12:34:56Z"
+        );
+        assert_eq!(format_time(None, now, AgeFormat::Iso), "-");
+    }
+
+    #[test]
+    fn test_render_table_last_accessed_column() {
+        let mut accessed = info();
+        accessed.accessed = Some(SystemTime::now() - std::time::Duration::from_secs(3 * 86400));
+        let unknown = TargetDirInfo::new(PathBuf::from("/projects/noatime/target"), 10);
+        let options = TableOptions {
+            show_accessed: true,
+            ..TableOptions::default()
+        };
+        let mut out = Vec::new();
+        render_table(&mut out, &[accessed, unknown], &options).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[2], "      SIZE  LAST ACCESSED  PATH");
+        assert_eq!(
+            lines[4],
+            "      2 GB  3 days ago     /projects/example/target"
+        );
+        assert_eq!(
+            lines[5],
+            "      10 B  -              /projects/noatime/target"
+        );
+    }
+
+    #[test]
+    fn test_color_precedence() {
+        let force_and_no_color = |name: &str| match name {
+            "CLICOLOR_FORCE" | "NO_COLOR" => Some("1".to_string()),
+            _ => None,
+        };
+        assert!(use_color(ColorChoice::Auto, force_and_no_color, false));
+        assert!(!use_color(ColorChoice::Never, force_and_no_color, true));
+        assert!(use_color(ColorChoice::Always, |_| None, false));
+        assert!(use_color(ColorChoice::Auto, |_| None, true));
+        assert!(!use_color(ColorChoice::Auto, |_| None, false));
+        let options = TableOptions {
+            color: true,
+            ..TableOptions::default()
+        };
+        assert!(format_row(&info(), &options).contains('\x1b'));
+    }
+
+    #[test]
+    fn test_render_table() {
+        let mut out = Vec::new();
+        let options = TableOptions {
+            truncated: true,
+            ..TableOptions::default()
+        };
+        render_table(&mut out, &[info()], &options).unwrap();
+        let dashes = "-".repeat(80);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "\nTarget directories (sorted by size, largest first):\n      SIZE  PATH\n{dashes}\n\
+                 \x20     2 GB  /projects/example/target\n{dashes}\n      2 GB  Total\n\
+                 Scan truncated after 1 target directories (--max-results), results are incomplete\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_render_porcelain_golden() {
+        let mut with_mtime = TargetDirInfo::new(PathBuf::from("/projects/example/target"), 1234);
+        with_mtime.modified = Some(UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000));
+        let target_info = [
+            with_mtime,
+            TargetDirInfo::new(PathBuf::from("/projects/tab\there/target"), 0),
+            TargetDirInfo::new(PathBuf::from("/projects/back\\slash\nnewline/target"), 42),
+        ];
+
+        let mut out = Vec::new();
+        render_porcelain(&mut out, &target_info).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            include_str!("../testdata/porcelain.txt")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_escape_porcelain_path_invalid_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"/projects/bad\xff/target"));
+        assert_eq!(escape_porcelain_path(path), "/projects/bad\\xff/target");
+    }
+
+    #[test]
+    fn test_render_table_subtotals() {
+        let options = TableOptions {
+            subtotals: vec![(PathBuf::from("C:\\"), 2000), (PathBuf::from("D:\\"), 500)],
+            ..TableOptions::default()
+        };
+        let target_info = [
+            TargetDirInfo::new(PathBuf::from("C:\\code\\a\\target"), 2000),
+            TargetDirInfo::new(PathBuf::from("D:\\code\\b\\target"), 500),
+        ];
+        let mut out = Vec::new();
+        render_table(&mut out, &target_info, &options).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains(
+                "    2.5 kB  Total\n      2 kB  Subtotal for C:\\\n     500 B  Subtotal for D:\\\n"
+            ),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn test_title_and_no_header() {
+        let targets = [TargetDirInfo::new(
+            PathBuf::from("/projects/app/target"),
+            1000,
+        )];
+        let render = |options: &TableOptions| {
+            let mut out = Vec::new();
+            render_table(&mut out, &targets, options).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let titled = render(&TableOptions {
+            title: Some("Build caches on ci-runner-3".to_string()),
+            ..TableOptions::default()
+        });
+        assert!(titled.starts_with("\nBuild caches on ci-runner-3\n      SIZE  PATH\n"));
+        assert!(!titled.contains("Target directories"));
+
+        let bare = render(&TableOptions {
+            no_header: true,
+            ..TableOptions::default()
+        });
+        assert!(
+            bare.starts_with("      1 kB  /projects/app/target\n"),
+            "{bare}"
+        );
+        assert!(!bare.contains("SIZE"));
+        assert!(bare.contains("      1 kB  Total"));
+    }
+
+    #[test]
+    fn test_wasm_kind_label() {
+        let mut pkg = TargetDirInfo::new(PathBuf::from("/projects/app/pkg"), 1000);
+        pkg.kind = TargetKind::WasmPack;
+        assert_eq!(
+            format_row(&pkg, &TableOptions::default()),
+            "      1 kB  /projects/app/pkg  (wasm-pack)"
+        );
+    }
+
+    #[test]
+    fn test_stale_toolchain_label() {
+        let mut stale = TargetDirInfo::new(PathBuf::from("/projects/old/target"), 1000);
+        stale.stale_toolchain = true;
+        assert_eq!(
+            format_row(&stale, &TableOptions::default()),
+            "      1 kB  /projects/old/target  STALE-TOOLCHAIN"
+        );
+        stale.protected = true;
+        assert_eq!(
+            format_row(&stale, &TableOptions::default()),
+            "      1 kB  /projects/old/target  STALE-TOOLCHAIN  PROTECTED"
+        );
+    }
+
+    #[test]
+    fn test_report_empty_label() {
+        let options = TableOptions {
+            report_empty: true,
+            ..TableOptions::default()
+        };
+        let mut empty = TargetDirInfo::new(PathBuf::from("/projects/empty/target"), 0);
+        empty.empty = true;
+        let unsized_dir = TargetDirInfo::new(PathBuf::from("/projects/unsized/target"), 0);
+
+        assert_eq!(
+            format_row(&empty, &options),
+            "   (empty)  /projects/empty/target"
+        );
+        assert_eq!(
+            format_row(&unsized_dir, &options),
+            "       0 B  /projects/unsized/target"
+        );
+        assert!(!format_row(&empty, &TableOptions::default()).contains("(empty)"));
+    }
+
+    #[test]
+    fn test_render_table_stats() {
+        let options = TableOptions {
+            stats: true,
+            ..TableOptions::default()
+        };
+        let mut out = Vec::new();
+        render_table(&mut out, &[info()], &options).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("      2 GB  Median\n"));
+        assert!(out.contains("         1  1 GB - 10 GB\n"));
+    }
+
+    #[test]
+    fn test_render_size_of_fixture() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let fixture = temp_dir.path().join("target");
+        std::fs::create_dir_all(fixture.join("debug")).unwrap();
+        std::fs::write(fixture.join("CACHEDIR.TAG"), vec![0u8; 500]).unwrap();
+        std::fs::write(fixture.join("debug").join("app"), vec![0u8; 2000]).unwrap();
+
+        let size = crate::calculate_dir_size(&fixture).unwrap();
+        assert_eq!(size, 2500);
+        let mut out = Vec::new();
+        render_size(&mut out, size).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "2.5 kB\n");
+    }
+}
@@ -0,0 +1,115 @@
+use std::io::Write;
+
+use crate::{
+    TargetDirInfo,
+    output::{StripPrefix, shown_path},
+};
+
+const DU_UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+
+/// Formats `bytes` the way `du -h --apparent-size` does.
+///
+/// Sizes are in powers of 1024 and rounded up, with one decimal place below 10, so `1536`
+/// is `1.5K` and `10240` is `10K`. Anything under 1 KiB is a plain byte count.
+pub fn du_human(bytes: u64) -> String {
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+    let bytes = u128::from(bytes);
+    let mut divisor = 1024u128;
+    let mut unit = 0;
+    loop {
+        let tenths = (bytes * 10).div_ceil(divisor);
+        if tenths < 100 {
+            return format!("{}.{}{}", tenths / 10, tenths % 10, DU_UNITS[unit]);
+        }
+        let whole = bytes.div_ceil(divisor);
+        if whole < 1024 || unit == DU_UNITS.len() - 1 {
+            return format!("{whole}{}", DU_UNITS[unit]);
+        }
+        divisor *= 1024;
+        unit += 1;
+    }
+}
+
+/// Formats `bytes` as 1K blocks, rounded up, like `du -k`.
+pub fn du_blocks(bytes: u64) -> String {
+    bytes.div_ceil(1024).to_string()
+}
+
+/// Writes `<size>\t<path>` lines like `du -s`, with a `<size>\ttotal` line last if asked.
+///
+/// `format_size` is [`du_human`] or [`du_blocks`]. There's no header, so the output can be
+/// piped straight into `sort -h` and friends.
+pub fn render_du(
+    out: &mut dyn Write,
+    target_info: &[TargetDirInfo],
+    format_size: fn(u64) -> String,
+    total: bool,
+    strip: &[StripPrefix],
+) -> std::io::Result<()> {
+    for info in target_info {
+        writeln!(
+            out,
+            "{}\t{}",
+            format_size(info.size),
+            shown_path(&info.path, strip)
+        )?;
+    }
+    if total {
+        let total: u64 = target_info.iter().map(|info| info.size).sum();
+        writeln!(out, "{}\ttotal", format_size(total))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn targets() -> Vec<TargetDirInfo> {
+        [
+            ("/projects/big/target", 5_368_709_120),
+            ("/projects/medium/target", 157_286_400),
+            ("/projects/small/target", 1536),
+            ("/projects/tiny/target", 13),
+            ("/projects/empty/target", 0),
+        ]
+        .into_iter()
+        .map(|(path, size)| TargetDirInfo::new(PathBuf::from(path), size))
+        .collect()
+    }
+
+    #[test]
+    fn test_du_human() {
+        assert_eq!(du_human(0), "0");
+        assert_eq!(du_human(1023), "1023");
+        assert_eq!(du_human(1024), "1.0K");
+        assert_eq!(du_human(1025), "1.1K");
+        assert_eq!(du_human(10 * 1024 - 1), "10K");
+        assert_eq!(du_human(1024 * 1024 - 1), "1.0M");
+        assert_eq!(du_human(1024 * 1024 * 1024 * 15), "15G");
+        assert_eq!(du_human(u64::MAX), "16E");
+    }
+
+    #[test]
+    fn test_render_du_golden() {
+        let mut out = Vec::new();
+        render_du(&mut out, &targets(), du_human, true, &[]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            include_str!("../../testdata/du.txt")
+        );
+    }
+
+    #[test]
+    fn test_render_du_bytes_golden() {
+        let mut out = Vec::new();
+        render_du(&mut out, &targets(), du_blocks, false, &[]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            include_str!("../../testdata/du-bytes.txt")
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use std::io::Write;
+
+use crate::TargetDirInfo;
+
+/// Writes a summary of the targets as shell variable assignments, to `eval` or source.
+///
+/// `CBT_DIR_COUNT` and `CBT_TOTAL_BYTES` are always set, `CBT_LARGEST_BYTES` and
+/// `CBT_LARGEST_PATH` are 0 and empty when nothing was found. The path is quoted so the shell
+/// reads it back as it was.
+pub fn render_env(out: &mut dyn Write, target_info: &[TargetDirInfo]) -> std::io::Result<()> {
+    let total: u64 = target_info.iter().map(|info| info.size).sum();
+    let largest = target_info.iter().max_by_key(|info| info.size);
+    writeln!(out, "CBT_DIR_COUNT={}", target_info.len())?;
+    writeln!(out, "CBT_TOTAL_BYTES={total}")?;
+    writeln!(
+        out,
+        "CBT_LARGEST_BYTES={}",
+        largest.map_or(0, |info| info.size)
+    )?;
+    let path = largest.map_or_else(String::new, |info| info.path.to_string_lossy().into_owned());
+    writeln!(out, "CBT_LARGEST_PATH={}", shell_words::quote(&path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+    use std::path::PathBuf;
+
+    fn rendered(target_info: &[TargetDirInfo]) -> String {
+        let mut out = Vec::new();
+        render_env(&mut out, target_info).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_render_env() {
+        let target_info = [
+            TargetDirInfo::new(PathBuf::from("/projects/small/target"), 1536),
+            TargetDirInfo::new(PathBuf::from("/projects/it's $big/target"), 36_700_160_160),
+        ];
+        let out = rendered(&target_info);
+        assert_eq!(
+            out,
+            "CBT_DIR_COUNT=2\n\
+             CBT_TOTAL_BYTES=36700161696\n\
+             CBT_LARGEST_BYTES=36700160160\n\
+             CBT_LARGEST_PATH='/projects/it'\\''s $big/target'\n"
+        );
+        // A name, `=` and a word the shell takes as it is, without anything it would expand
+        let assignment =
+            Regex::new(r"^[A-Z_][A-Z0-9_]*=([A-Za-z0-9_./:,+@%-]*|'([^']|'\\'')*')$").unwrap();
+        for line in out.lines() {
+            assert!(assignment.is_match(line), "{line}");
+        }
+
+        assert_eq!(
+            rendered(&[]),
+            "CBT_DIR_COUNT=0\nCBT_TOTAL_BYTES=0\nCBT_LARGEST_BYTES=0\nCBT_LARGEST_PATH=''\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_render_env_evaluates() {
+        let target_info = [TargetDirInfo::new(
+            PathBuf::from("/projects/it's $big `x`/target"),
+            4096,
+        )];
+        let script = format!(
+            "{}printf '%s|%s|%s' \"$CBT_DIR_COUNT\" \"$CBT_TOTAL_BYTES\" \"$CBT_LARGEST_PATH\"",
+            rendered(&target_info)
+        );
+        let output = std::process::Command::new("sh")
+            .args(["-c", &script])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "1|4096|/projects/it's $big `x`/target"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::io::Write;
+
+use humanize_bytes::humanize_bytes_decimal;
+
+use crate::{
+    TargetDirInfo,
+    output::{StripPrefix, shown_path},
+};
+
+/// The `--path-width` used when it isn't given.
+pub const DEFAULT_PATH_WIDTH: usize = 60;
+
+/// Shortest `--path-width` allowed, so a truncated path keeps more than the `...`.
+pub const MIN_PATH_WIDTH: usize = 10;
+
+/// Parses a `--path-width`, which has to be at least [`MIN_PATH_WIDTH`].
+pub fn parse_path_width(value: &str) -> Result<usize, String> {
+    let width: usize = value
+        .parse()
+        .map_err(|_| format!("'{value}' isn't a number of characters"))?;
+    if width < MIN_PATH_WIDTH {
+        return Err(format!("the path width must be at least {MIN_PATH_WIDTH}"));
+    }
+    Ok(width)
+}
+
+/// Fits `path` into exactly `width` characters, padding it or replacing the start with `...`.
+///
+/// The end is kept as it's what tells `target` directories apart.
+fn fit_path(path: &str, width: usize) -> String {
+    let chars: Vec<char> = path.chars().collect();
+    if chars.len() <= width {
+        return format!("{path:<width$}");
+    }
+    let tail: String = chars[chars.len() - (width - 3)..].iter().collect();
+    format!("...{tail}")
+}
+
+/// Writes the table with a fixed `width` path column and a 10 character size column, with no
+/// color and nothing that depends on the terminal or the other rows.
+///
+/// The same targets always give the same bytes, so two reports can be compared with `diff`.
+pub fn render_fixed_table(
+    out: &mut dyn Write,
+    target_info: &[TargetDirInfo],
+    width: usize,
+    strip: &[StripPrefix],
+) -> std::io::Result<()> {
+    writeln!(out, "{:<width$}  {:>10}", "PATH", "SIZE")?;
+    writeln!(out, "{}", "-".repeat(width + 12))?;
+    for info in target_info {
+        writeln!(
+            out,
+            "{}  {:>10}",
+            fit_path(&shown_path(&info.path, strip), width),
+            humanize_bytes_decimal!(info.size)
+        )?;
+    }
+    let total: u64 = target_info.iter().map(|info| info.size).sum();
+    writeln!(out, "{}", "-".repeat(width + 12))?;
+    writeln!(
+        out,
+        "{:<width$}  {:>10}",
+        "Total",
+        humanize_bytes_decimal!(total)
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_fit_path() {
+        assert_eq!(fit_path("/a/target", 12), "/a/target   ");
+        assert_eq!(fit_path("/projects/big/target", 12), "...ig/target");
+        assert_eq!(fit_path("/projects/big/target", 12).chars().count(), 12);
+        assert_eq!(fit_path("/héllo/wörld/target", 12).chars().count(), 12);
+    }
+
+    #[test]
+    fn test_render_fixed_table_is_stable() {
+        let target_info = [
+            TargetDirInfo::new(PathBuf::from("/projects/big/target"), 5_000_000_000),
+            TargetDirInfo::new(
+                PathBuf::from("/projects/a/very/deeply/nested/workspace/member/target"),
+                1536,
+            ),
+        ];
+        let render = || {
+            let mut out = Vec::new();
+            render_fixed_table(&mut out, &target_info, 30, &[]).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        // Nothing depends on the terminal, so every run lines up with the last
+        let rendered = render();
+        assert_eq!(rendered, render());
+        assert_eq!(
+            rendered,
+            "PATH                                  SIZE\n\
+             ------------------------------------------\n\
+             /projects/big/target                  5 GB\n\
+             ...ted/workspace/member/target      1.5 kB\n\
+             ------------------------------------------\n\
+             Total                                 5 GB\n"
+        );
+        assert!(rendered.lines().all(|line| line.chars().count() == 42));
+    }
+
+    #[test]
+    fn test_parse_path_width() {
+        assert_eq!(parse_path_width("80"), Ok(80));
+        assert!(parse_path_width("5").is_err());
+        assert!(parse_path_width("wide").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use std::io::Write;
+
+use humanize_bytes::humanize_bytes_decimal;
+
+use crate::{
+    TargetDirInfo,
+    output::{StripPrefix, shown_path},
+};
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.8em; text-align: left; }
+th { background: #eee; cursor: pointer; }
+td.size { text-align: right; }
+tfoot td { font-weight: bold; }";
+
+// Sorts the table body by the clicked column, using data-sort values where present
+const SCRIPT: &str = "document.querySelectorAll('th').forEach(function (th, col) {
+  th.addEventListener('click', function () {
+    var body = th.closest('table').tBodies[0];
+    var asc = th.dataset.asc !== 'true';
+    th.dataset.asc = asc;
+    var key = function (row) {
+      var cell = row.cells[col];
+      return cell.dataset.sort !== undefined ? Number(cell.dataset.sort) : cell.textContent;
+    };
+    Array.from(body.rows).sort(function (a, b) {
+      var x = key(a), y = key(b);
+      return (x < y ? -1 : x > y ? 1 : 0) * (asc ? 1 : -1);
+    }).forEach(function (row) { body.appendChild(row); });
+  });
+});";
+
+/// Escapes text for use in HTML content and attribute values.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes a standalone HTML document with a sortable table of the results and a total.
+pub fn render_html(
+    out: &mut dyn Write,
+    target_info: &[TargetDirInfo],
+    strip: &[StripPrefix],
+) -> std::io::Result<()> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html lang=\"en\">")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>Target directories</title>")?;
+    writeln!(out, "<style>\n{STYLE}\n</style>")?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    writeln!(out, "<h1>Target directories</h1>")?;
+    writeln!(out, "<table>")?;
+    writeln!(out, "<thead><tr><th>Path</th><th>Size</th></tr></thead>")?;
+    writeln!(out, "<tbody>")?;
+    for info in target_info {
+        writeln!(
+            out,
+            "<tr><td>{}</td><td class=\"size\" data-sort=\"{}\">{}</td></tr>",
+            escape_html(&shown_path(&info.path, strip)),
+            info.size,
+            humanize_bytes_decimal!(info.size)
+        )?;
+    }
+    writeln!(out, "</tbody>")?;
+    let total_size: u64 = target_info.iter().map(|i| i.size).sum();
+    writeln!(
+        out,
+        "<tfoot><tr><td>Total</td><td class=\"size\">{}</td></tr></tfoot>",
+        humanize_bytes_decimal!(total_size)
+    )?;
+    writeln!(out, "</table>")?;
+    writeln!(out, "<script>\n{SCRIPT}\n</script>")?;
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_render_html() {
+        let target_info = [
+            TargetDirInfo::new(PathBuf::from("/projects/one/target"), 2_000_000),
+            TargetDirInfo::new(PathBuf::from("/projects/<two>&/target"), 1000),
+        ];
+        let mut out = Vec::new();
+        render_html(&mut out, &target_info, &[]).unwrap();
+        let html = String::from_utf8(out).unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>\n<html lang=\"en\">"));
+        assert!(html.trim_end().ends_with("</html>"));
+        assert_eq!(html.matches("<table>").count(), 1);
+        assert_eq!(html.matches("</table>").count(), 1);
+        assert_eq!(html.matches("<tr>").count(), html.matches("</tr>").count());
+        // Header, one per directory, and the total
+        assert_eq!(html.matches("<tr>").count(), 4);
+        assert!(html.contains("<td>/projects/one/target</td>"));
+        assert!(html.contains("<td>/projects/&lt;two&gt;&amp;/target</td>"));
+        assert!(html.contains("data-sort=\"2000000\""));
+        assert!(
+            html.contains("<tfoot><tr><td>Total</td><td class=\"size\">2 MB</td></tr></tfoot>")
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use std::{io::Write, path::Path};
+
+use crate::{
+    SizeStats, TargetDirInfo,
+    cargo_home::CargoHomeSummary,
+    meta::ScanMeta,
+    output::ncdu::escape_json,
+    plan::{Value, parse_json, target_from_fields},
+};
+
+/// The `format_version` written by [`render_json`], and the only one [`read_json`] accepts.
+/// It goes up whenever a change would stop an older release reading the output right.
+pub const JSON_FORMAT_VERSION: u64 = 1;
+
+/// Writes the results as one JSON object: the `format_version`, `meta` if given, each target's
+/// path, size, file count and kind under `targets`, and the `total` size, followed by the
+/// `--stats` as `stats` and the `--with-cargo-home-summary` as `cargo_home` when given.
+pub fn render_json(
+    out: &mut dyn Write,
+    target_info: &[TargetDirInfo],
+    meta: Option<&ScanMeta>,
+    stats: Option<&SizeStats>,
+    cargo_home: Option<&CargoHomeSummary>,
+) -> std::io::Result<()> {
+    write!(out, "{{\"format_version\":{JSON_FORMAT_VERSION},")?;
+    if let Some(meta) = meta {
+        write!(out, "\"meta\":{},", meta.to_json())?;
+    }
+    let targets: Vec<String> = target_info
+        .iter()
+        .map(|info| {
+            let file_count = info
+                .file_count
+                .map_or_else(|| "null".to_string(), |count| count.to_string());
+            format!(
+                "{{\"path\":\"{}\",\"size\":{},\"file_count\":{},\"kind\":\"{}\"}}",
+                escape_json(&info.path.to_string_lossy()),
+                info.size,
+                file_count,
+                info.kind.as_str()
+            )
+        })
+        .collect();
+    let total: u64 = target_info.iter().map(|info| info.size).sum();
+    write!(
+        out,
+        "\"targets\":[{}],\"total\":{}",
+        targets.join(","),
+        total
+    )?;
+    if let Some(stats) = stats {
+        write!(out, ",\"stats\":{}", stats.to_json())?;
+    }
+    if let Some(cargo_home) = cargo_home {
+        write!(out, ",\"cargo_home\":{}", cargo_home.to_json(total))?;
+    }
+    writeln!(out, "}}")
+}
+
+/// Writes a failure as a line holding one JSON object,
+/// `{"error":"size","path":"/code/app/target","message":"Permission denied"}`, with `path`
+/// null when there isn't one. Under the JSON formats these go to stderr in place of the plain
+/// messages, so a program reading them can tell a failure from anything else written there.
+pub fn render_error(
+    out: &mut dyn Write,
+    error: &str,
+    path: Option<&Path>,
+    message: &str,
+) -> std::io::Result<()> {
+    let path = path.map_or_else(
+        || "null".to_string(),
+        |path| format!("\"{}\"", escape_json(&path.to_string_lossy())),
+    );
+    writeln!(
+        out,
+        "{{\"error\":\"{}\",\"path\":{},\"message\":\"{}\"}}",
+        escape_json(error),
+        path,
+        escape_json(message)
+    )
+}
+
+/// Writes a warning the way [`render_error`] writes failures, `{"warning":"..."}`.
+pub fn render_warning(out: &mut dyn Write, message: &str) -> std::io::Result<()> {
+    writeln!(out, "{{\"warning\":\"{}\"}}", escape_json(message))
+}
+
+/// Reads the targets back out of what [`render_json`] wrote, for `--from-json`.
+pub fn read_json(contents: &str) -> Result<Vec<TargetDirInfo>, String> {
+    let Value::Object(fields) = parse_json(contents)? else {
+        return Err("expected an object".to_string());
+    };
+    let field = |name: &str| fields.iter().find(|(key, _)| key == name).map(|(_, v)| v);
+    match field("format_version") {
+        Some(Value::Number(JSON_FORMAT_VERSION)) => {}
+        Some(Value::Number(version)) => {
+            return Err(format!(
+                "format_version {version} isn't supported, only {JSON_FORMAT_VERSION} is"
+            ));
+        }
+        _ => return Err("missing 'format_version'".to_string()),
+    }
+    let targets = fields
+        .into_iter()
+        .find_map(|(key, value)| (key == "targets").then_some(value));
+    let Some(Value::Array(targets)) = targets else {
+        return Err("missing 'targets'".to_string());
+    };
+    targets
+        .into_iter()
+        .enumerate()
+        .map(|(i, target)| {
+            match target {
+                Value::Object(fields) => target_from_fields(fields),
+                _ => Err("expected an object".to_string()),
+            }
+            .map_err(|e| format!("target {}: {e}", i + 1))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_render_json() {
+        let mut info = TargetDirInfo::new(PathBuf::from("/code/app/target"), 1000);
+        info.file_count = Some(3);
+        let targets = [info, TargetDirInfo::new(PathBuf::from("/code/b/target"), 5)];
+
+        let mut out = Vec::new();
+        render_json(&mut out, &targets, None, None, None).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"format_version\":1,\"targets\":[\
+             {\"path\":\"/code/app/target\",\"size\":1000,\"file_count\":3,\"kind\":\"cargo\"},\
+             {\"path\":\"/code/b/target\",\"size\":5,\"file_count\":null,\"kind\":\"cargo\"}\
+             ],\"total\":1005}\n"
+        );
+
+        let meta = ScanMeta {
+            hostname: Some("builder-3".to_string()),
+            username: None,
+            roots: vec![PathBuf::from("/code")],
+            version: "1.2.3",
+            timestamp: "2024-02-29T12:34:56Z".to_string(),
+            options: vec![("sort".to_string(), vec!["size-desc".to_string()])],
+        };
+        let mut out = Vec::new();
+        render_json(&mut out, &[], Some(&meta), None, None).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"format_version\":1,\"meta\":{\"hostname\":\"builder-3\",\"username\":null,\"roots\":[\"/code\"],\
+             \"version\":\"1.2.3\",\"timestamp\":\"2024-02-29T12:34:56Z\",\
+             \"options\":{\"sort\":[\"size-desc\"]}},\"targets\":[],\"total\":0}\n"
+        );
+    }
+
+    #[test]
+    fn test_read_json_round_trips() {
+        let mut info = TargetDirInfo::new(PathBuf::from("/code/app/target"), 1000);
+        info.file_count = Some(3);
+        let meta = crate::meta::scan_meta(
+            &[PathBuf::from("/code")],
+            &crate::Cli::command().get_matches_from(["clean-big-targets", "/code"]),
+            |_| None,
+            std::time::UNIX_EPOCH,
+        );
+        let mut out = Vec::new();
+        render_json(&mut out, &[info], Some(&meta), None, None).unwrap();
+
+        let read = read_json(&String::from_utf8(out).unwrap()).unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].path, PathBuf::from("/code/app/target"));
+        assert_eq!((read[0].size, read[0].file_count), (1000, Some(3)));
+    }
+
+    #[test]
+    fn test_render_json_stats_and_cargo_home_round_trip() {
+        use crate::cargo_home::CargoHomeComponent;
+
+        let targets = [
+            TargetDirInfo::new(PathBuf::from("/code/app/target"), 300),
+            TargetDirInfo::new(PathBuf::from("/code/b/target"), 100),
+        ];
+        let stats = crate::size_stats(&targets);
+        let cargo_home = CargoHomeSummary {
+            cargo_home: PathBuf::from("/home/me/.cargo"),
+            components: vec![
+                CargoHomeComponent {
+                    label: "Registry cache",
+                    path: PathBuf::from("/home/me/.cargo/registry/cache"),
+                    size: 50,
+                },
+                CargoHomeComponent {
+                    label: "Installed binaries",
+                    path: PathBuf::from("/home/me/.cargo/bin"),
+                    size: 7,
+                },
+            ],
+        };
+        let mut out = Vec::new();
+        render_json(&mut out, &targets, None, Some(&stats), Some(&cargo_home)).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.ends_with(
+            "\"total\":400,\
+             \"stats\":{\"count\":2,\"median\":200,\"p90\":300,\"std_dev\":100,\"buckets\":[2,0,0,0]},\
+             \"cargo_home\":{\"path\":\"/home/me/.cargo\",\"components\":[\
+             {\"label\":\"Registry cache\",\"path\":\"/home/me/.cargo/registry/cache\",\"size\":50},\
+             {\"label\":\"Installed binaries\",\"path\":\"/home/me/.cargo/bin\",\"size\":7}],\
+             \"total\":57,\"targets_total\":400,\"combined_total\":457}}\n"
+        ));
+
+        // Still read back by `--from-json`, and the new objects parse as written
+        assert_eq!(read_json(&out).unwrap().len(), 2);
+        let Value::Object(fields) = parse_json(&out).unwrap() else {
+            panic!("expected an object");
+        };
+        let field = |object: &[(String, Value)], name: &str| {
+            object
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| match value {
+                    Value::Number(number) => *number,
+                    _ => panic!("{name} isn't a number"),
+                })
+        };
+        let object = |name: &str| match fields.iter().find(|(key, _)| key == name) {
+            Some((_, Value::Object(object))) => object,
+            _ => panic!("missing {name}"),
+        };
+        assert_eq!(field(object("stats"), "median"), Some(stats.median));
+        assert_eq!(field(object("stats"), "p90"), Some(stats.p90));
+        assert_eq!(
+            field(object("cargo_home"), "total"),
+            Some(cargo_home.total())
+        );
+        assert_eq!(field(object("cargo_home"), "combined_total"), Some(457));
+
+        // Neither is there unless asked for
+        let mut out = Vec::new();
+        render_json(&mut out, &targets, None, None, None).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains("\"stats\"") && !out.contains("\"cargo_home\""));
+    }
+
+    #[test]
+    fn test_size_error_is_structured() {
+        // As scanning gives for a target it wasn't allowed to read
+        let path = PathBuf::from("/code/\"quoted\"/target");
+        let error = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+
+        let mut out = Vec::new();
+        render_error(&mut out, "size", Some(&path), &error.to_string()).unwrap();
+        render_error(&mut out, "scan", None, "no roots").unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+
+        let Value::Object(fields) = parse_json(lines[0]).unwrap() else {
+            panic!("not an object: {}", lines[0]);
+        };
+        assert_eq!(
+            fields,
+            [
+                ("error".to_string(), Value::String("size".to_string())),
+                (
+                    "path".to_string(),
+                    Value::String(path.to_string_lossy().into_owned())
+                ),
+                ("message".to_string(), Value::String(error.to_string())),
+            ]
+        );
+        assert_eq!(
+            lines[1],
+            "{\"error\":\"scan\",\"path\":null,\"message\":\"no roots\"}"
+        );
+
+        let mut out = Vec::new();
+        render_warning(&mut out, "a \"warning\"").unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"warning\":\"a \\\"warning\\\"\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_read_json_checks_the_version() {
+        assert_eq!(
+            read_json("{\"format_version\":2,\"targets\":[]}").unwrap_err(),
+            "format_version 2 isn't supported, only 1 is"
+        );
+        assert_eq!(
+            read_json("{\"targets\":[]}").unwrap_err(),
+            "missing 'format_version'"
+        );
+        assert_eq!(
+            read_json("{\"format_version\":1,\"targets\":[{\"size\":1}]}").unwrap_err(),
+            "target 1: missing 'path'"
+        );
+        assert!(
+            read_json("{\"format_version\":1,\"targets\":[]}")
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
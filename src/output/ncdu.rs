@@ -0,0 +1,201 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{TargetDirInfo, tree::SizeTree};
+
+/// How many levels under each target `--format ncdu` lists, deeper directories are collapsed
+/// into one entry so the export stays a manageable size.
+pub const NCDU_TREE_DEPTH: usize = 2;
+
+/// Escapes text for use inside a JSON string.
+pub fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The directories between the common root and the targets.
+#[derive(Default)]
+struct Dir<'a> {
+    subdirs: BTreeMap<&'a OsStr, Dir<'a>>,
+    targets: Vec<&'a TargetDirInfo>,
+}
+
+/// The deepest directory containing every target.
+fn common_root(target_info: &[TargetDirInfo]) -> PathBuf {
+    let mut parents = target_info
+        .iter()
+        .map(|info| info.path.parent().unwrap_or(&info.path));
+    let Some(first) = parents.next() else {
+        return PathBuf::from(".");
+    };
+    let mut root = first;
+    for parent in parents {
+        while !parent.starts_with(root) {
+            match root.parent() {
+                Some(up) => root = up,
+                None => break,
+            }
+        }
+    }
+    root.to_path_buf()
+}
+
+fn write_info(out: &mut dyn Write, name: &OsStr, size: Option<u64>) -> std::io::Result<()> {
+    write!(
+        out,
+        "{{\"name\":\"{}\"",
+        escape_json(&name.to_string_lossy())
+    )?;
+    if let Some(size) = size {
+        // Sizes are apparent sizes, disk usage isn't measured so it's reported the same
+        write!(out, ",\"asize\":{size},\"dsize\":{size}")?;
+    }
+    write!(out, "}}")
+}
+
+fn write_tree(out: &mut dyn Write, tree: &SizeTree) -> std::io::Result<()> {
+    if !tree.is_dir {
+        return write_info(out, &tree.name, Some(tree.size));
+    }
+    write!(out, "[")?;
+    write_info(out, &tree.name, (tree.size > 0).then_some(tree.size))?;
+    for child in &tree.children {
+        writeln!(out, ",")?;
+        write_tree(out, child)?;
+    }
+    write!(out, "]")
+}
+
+fn write_dir(out: &mut dyn Write, name: &OsStr, dir: &Dir) -> std::io::Result<()> {
+    write!(out, "[")?;
+    write_info(out, name, None)?;
+    for (name, subdir) in &dir.subdirs {
+        writeln!(out, ",")?;
+        write_dir(out, name, subdir)?;
+    }
+    for info in &dir.targets {
+        writeln!(out, ",")?;
+        match &info.tree {
+            Some(tree) => write_tree(out, tree)?,
+            None => {
+                let name = info.path.file_name().unwrap_or(info.path.as_os_str());
+                write_tree(
+                    out,
+                    &SizeTree::collapsed_dir(name.to_os_string(), info.size),
+                )?;
+            }
+        }
+    }
+    write!(out, "]")
+}
+
+/// Writes the targets as an ncdu 1.x JSON export, for browsing with `ncdu -f`.
+///
+/// The export is rooted at the deepest directory containing all the targets, with the
+/// directories leading down to each target and whatever of its [`SizeTree`] was kept.
+/// `timestamp` is in seconds since the Unix epoch.
+pub fn render_ncdu(
+    out: &mut dyn Write,
+    target_info: &[TargetDirInfo],
+    timestamp: u64,
+) -> std::io::Result<()> {
+    let root = common_root(target_info);
+    let mut tree = Dir::default();
+    for info in target_info {
+        let parent = info.path.parent().unwrap_or(&info.path);
+        let relative = parent.strip_prefix(&root).unwrap_or(Path::new(""));
+        let mut dir = &mut tree;
+        for component in relative.iter() {
+            dir = dir.subdirs.entry(component).or_default();
+        }
+        dir.targets.push(info);
+    }
+
+    writeln!(
+        out,
+        "[1,2,{{\"progname\":\"{}\",\"progver\":\"{}\",\"timestamp\":{timestamp}}},",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    )?;
+    write_dir(out, root.as_os_str(), &tree)?;
+    writeln!(out, "]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, size: u64) -> SizeTree {
+        SizeTree::file(name.into(), size)
+    }
+
+    #[test]
+    fn test_render_ncdu_golden() {
+        let mut with_tree = TargetDirInfo::new(PathBuf::from("/code/app/target"), 1510);
+        with_tree.tree = Some(SizeTree {
+            children: vec![
+                file("CACHEDIR.TAG", 10),
+                SizeTree {
+                    children: vec![
+                        file("app", 500),
+                        SizeTree::collapsed_dir("deps".into(), 1000),
+                    ],
+                    ..SizeTree::collapsed_dir("debug".into(), 0)
+                },
+            ],
+            ..SizeTree::collapsed_dir("target".into(), 0)
+        });
+        let target_info = [
+            with_tree,
+            TargetDirInfo::new(PathBuf::from("/code/libs/\"quoted\"/target"), 42),
+        ];
+
+        let mut out = Vec::new();
+        render_ncdu(&mut out, &target_info, 1_700_000_000).unwrap();
+        let expected = include_str!("../../testdata/ncdu.json")
+            .replace("@VERSION@", env!("CARGO_PKG_VERSION"));
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_common_root() {
+        let targets = |paths: &[&str]| -> Vec<TargetDirInfo> {
+            paths
+                .iter()
+                .map(|path| TargetDirInfo::new(PathBuf::from(path), 0))
+                .collect()
+        };
+        assert_eq!(
+            common_root(&targets(&["/code/a/target", "/code/b/c/target"])),
+            PathBuf::from("/code")
+        );
+        assert_eq!(
+            common_root(&targets(&["/code/a/target"])),
+            PathBuf::from("/code/a")
+        );
+        assert_eq!(
+            common_root(&targets(&["/code/a/target", "/other/target"])),
+            PathBuf::from("/")
+        );
+    }
+
+    #[test]
+    fn test_escape_json() {
+        assert_eq!(escape_json("a\"b\\c\n\u{1}"), "a\\\"b\\\\c\\n\\u0001");
+    }
+}
@@ -0,0 +1,113 @@
+use std::io::Write;
+
+use crate::TargetDirInfo;
+
+/// Escapes text for a plist `<string>`. Characters XML 1.0 doesn't allow at all, such as
+/// most control characters, become U+FFFD as there's no escape for them.
+pub fn escape_plist_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => escaped.push(char::REPLACEMENT_CHARACTER),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes the targets as an XML property list, for Automator and AppleScript.
+///
+/// The top level dict has `Targets`, an array with a dict of `Path` and `Size` (in bytes) for
+/// each target, and `Total`, the size of them all.
+pub fn render_plist(out: &mut dyn Write, target_info: &[TargetDirInfo]) -> std::io::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">"#
+    )?;
+    writeln!(out, r#"<plist version="1.0">"#)?;
+    writeln!(out, "<dict>")?;
+    writeln!(out, "\t<key>Targets</key>")?;
+    if target_info.is_empty() {
+        writeln!(out, "\t<array/>")?;
+    } else {
+        writeln!(out, "\t<array>")?;
+        for info in target_info {
+            writeln!(out, "\t\t<dict>")?;
+            writeln!(out, "\t\t\t<key>Path</key>")?;
+            writeln!(
+                out,
+                "\t\t\t<string>{}</string>",
+                escape_plist_string(&info.path.to_string_lossy())
+            )?;
+            writeln!(out, "\t\t\t<key>Size</key>")?;
+            writeln!(out, "\t\t\t<integer>{}</integer>", info.size)?;
+            writeln!(out, "\t\t</dict>")?;
+        }
+        writeln!(out, "\t</array>")?;
+    }
+    let total: u64 = target_info.iter().map(|info| info.size).sum();
+    writeln!(out, "\t<key>Total</key>")?;
+    writeln!(out, "\t<integer>{total}</integer>")?;
+    writeln!(out, "</dict>")?;
+    writeln!(out, "</plist>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture() -> Vec<u8> {
+        let target_info = [
+            TargetDirInfo::new(PathBuf::from("/projects/big/target"), 5_368_709_120),
+            TargetDirInfo::new(PathBuf::from("/code/R&D <new>/\u{1}target"), 1536),
+        ];
+        let mut out = Vec::new();
+        render_plist(&mut out, &target_info).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_render_plist_golden() {
+        let out = String::from_utf8(fixture()).unwrap();
+        assert_eq!(out, include_str!("../../testdata/targets.plist"));
+
+        let mut out = Vec::new();
+        render_plist(&mut out, &[]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(
+            "<key>Targets</key>\n\t<array/>\n\t<key>Total</key>\n\t<integer>0</integer>"
+        ));
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_render_plist_parses_with_plutil() {
+        use std::process::{Command, Stdio};
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("targets.plist");
+        std::fs::write(&file, fixture()).unwrap();
+        let lint = Command::new("plutil")
+            .arg("-lint")
+            .arg(&file)
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(lint.success());
+        let json = Command::new("plutil")
+            .args(["-convert", "json", "-o", "-"])
+            .arg(&file)
+            .output()
+            .unwrap();
+        let json = String::from_utf8(json.stdout).unwrap();
+        assert!(json.contains(r#""Total":5368710656"#), "{json}");
+        assert!(json.contains(r#""Path":"#), "{json}");
+        assert!(json.contains(r#""Size":1536"#), "{json}");
+    }
+}
@@ -0,0 +1,94 @@
+use std::io::Write;
+
+use crate::TargetDirInfo;
+
+/// Escapes a label value for the Prometheus text exposition format, which only allows `\\`,
+/// `\"` and `\n` as escapes.
+pub fn escape_label_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes the targets as Prometheus gauges, for node_exporter's textfile collector.
+///
+/// Each target gets a `clean_big_targets_reclaimable_bytes{path="..."}` sample, followed by the
+/// overall total and how many target directories there are.
+pub fn render_prometheus(
+    out: &mut dyn Write,
+    target_info: &[TargetDirInfo],
+) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "# HELP clean_big_targets_reclaimable_bytes Size of a build output directory that could be deleted."
+    )?;
+    writeln!(out, "# TYPE clean_big_targets_reclaimable_bytes gauge")?;
+    for info in target_info {
+        writeln!(
+            out,
+            "clean_big_targets_reclaimable_bytes{{path=\"{}\"}} {}",
+            escape_label_value(&info.path.to_string_lossy()),
+            info.size
+        )?;
+    }
+    let total: u64 = target_info.iter().map(|info| info.size).sum();
+    writeln!(
+        out,
+        "# HELP clean_big_targets_reclaimable_total_bytes Size of every build output directory found."
+    )?;
+    writeln!(
+        out,
+        "# TYPE clean_big_targets_reclaimable_total_bytes gauge"
+    )?;
+    writeln!(out, "clean_big_targets_reclaimable_total_bytes {total}")?;
+    writeln!(
+        out,
+        "# HELP clean_big_targets_directories Number of build output directories found."
+    )?;
+    writeln!(out, "# TYPE clean_big_targets_directories gauge")?;
+    writeln!(out, "clean_big_targets_directories {}", target_info.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_render_prometheus_golden() {
+        let target_info = [
+            TargetDirInfo::new(PathBuf::from("/projects/big/target"), 5_368_709_120),
+            TargetDirInfo::new(PathBuf::from("C:\\code\\\"odd\"\nname\\target"), 1536),
+        ];
+        let mut out = Vec::new();
+        render_prometheus(&mut out, &target_info).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out, include_str!("../../testdata/prometheus.prom"));
+
+        // Every line is a comment or `name{labels} value`, as the exposition format requires
+        let sample = Regex::new(
+            r#"^[a-zA-Z_:][a-zA-Z0-9_:]*(\{[a-zA-Z_][a-zA-Z0-9_]*="([^"\\\n]|\\[\\"n])*"\})? [0-9]+$"#,
+        )
+        .unwrap();
+        for line in out.lines() {
+            assert!(
+                line.starts_with("# HELP ") || line.starts_with("# TYPE ") || sample.is_match(line),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_escape_label_value() {
+        assert_eq!(escape_label_value("a\\b\"c\nd"), "a\\\\b\\\"c\\nd");
+        assert_eq!(escape_label_value("/plain/path"), "/plain/path");
+    }
+}
@@ -0,0 +1,170 @@
+use std::io::Write;
+
+use humanize_bytes::humanize_bytes_decimal;
+
+use crate::{
+    TargetDirInfo,
+    output::{StripPrefix, shown_path},
+    parse_size,
+};
+
+pub const TIER_NAMES: [&str; 4] = ["Huge", "Large", "Medium", "Small"];
+
+/// Lower bounds of the Huge, Large and Medium tiers, anything smaller is Small.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TierBounds(pub [u64; 3]);
+
+impl Default for TierBounds {
+    fn default() -> Self {
+        Self([10_000_000_000, 1_000_000_000, 100_000_000])
+    }
+}
+
+/// Parses three comma separated sizes, largest first, such as `10GB,1GB,100MB`.
+pub fn parse_tier_bounds(value: &str) -> Result<TierBounds, String> {
+    let sizes = value
+        .split(',')
+        .map(|size| parse_size(size.trim()))
+        .collect::<Result<Vec<u64>, String>>()?;
+    let bounds: [u64; 3] = sizes
+        .try_into()
+        .map_err(|_| "expected three sizes, such as 10GB,1GB,100MB".to_string())?;
+    if bounds[0] <= bounds[1] || bounds[1] <= bounds[2] {
+        return Err("tier bounds must be given largest first".to_string());
+    }
+    Ok(TierBounds(bounds))
+}
+
+/// Splits the targets into Huge, Large, Medium and Small tiers, keeping their order.
+pub fn assign_tiers<'a>(
+    target_info: &'a [TargetDirInfo],
+    bounds: &TierBounds,
+) -> [Vec<&'a TargetDirInfo>; 4] {
+    let mut tiers: [Vec<&TargetDirInfo>; 4] = Default::default();
+    for info in target_info {
+        let tier = bounds
+            .0
+            .iter()
+            .position(|&bound| info.size >= bound)
+            .unwrap_or(3);
+        tiers[tier].push(info);
+    }
+    tiers
+}
+
+/// Writes the targets grouped under a header per tier, each with a subtotal.
+pub fn render_tiers(
+    out: &mut dyn Write,
+    target_info: &[TargetDirInfo],
+    bounds: &TierBounds,
+    strip: &[StripPrefix],
+) -> std::io::Result<()> {
+    let tiers = assign_tiers(target_info, bounds);
+    for (tier, (name, members)) in TIER_NAMES.iter().zip(&tiers).enumerate() {
+        if members.is_empty() {
+            continue;
+        }
+        let range = match tier {
+            0 => format!(">= {}", humanize_bytes_decimal!(bounds.0[0])),
+            3 => format!("< {}", humanize_bytes_decimal!(bounds.0[2])),
+            _ => format!(
+                "{} - {}",
+                humanize_bytes_decimal!(bounds.0[tier]),
+                humanize_bytes_decimal!(bounds.0[tier - 1])
+            ),
+        };
+        writeln!(out, "\n{name} ({range}), {} directories:", members.len())?;
+        for info in members {
+            writeln!(
+                out,
+                "  {:>10}  {}",
+                humanize_bytes_decimal!(info.size),
+                shown_path(&info.path, strip)
+            )?;
+        }
+        let subtotal: u64 = members.iter().map(|info| info.size).sum();
+        writeln!(out, "  {:>10}  Subtotal", humanize_bytes_decimal!(subtotal))?;
+    }
+    let total: u64 = target_info.iter().map(|info| info.size).sum();
+    writeln!(out, "\n{:>12}  Total", humanize_bytes_decimal!(total))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn targets(sizes: &[u64]) -> Vec<TargetDirInfo> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, &size)| TargetDirInfo::new(PathBuf::from(format!("/p{i}/target")), size))
+            .collect()
+    }
+
+    #[test]
+    fn test_assign_tiers() {
+        let target_info = targets(&[
+            20_000_000_000,
+            10_000_000_000,
+            5_000_000_000,
+            1_000_000_000,
+            500_000_000,
+            99_999_999,
+            0,
+        ]);
+        let tiers = assign_tiers(&target_info, &TierBounds::default());
+        let sizes: Vec<Vec<u64>> = tiers
+            .iter()
+            .map(|tier| tier.iter().map(|info| info.size).collect())
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![
+                vec![20_000_000_000, 10_000_000_000],
+                vec![5_000_000_000, 1_000_000_000],
+                vec![500_000_000],
+                vec![99_999_999, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_tiers_subtotals() {
+        let target_info = targets(&[3_000, 2_000, 500, 100]);
+        let bounds = parse_tier_bounds("2kB,1kB,200B").unwrap();
+        let mut out = Vec::new();
+        render_tiers(&mut out, &target_info, &bounds, &[]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "
+Huge (>= 2 kB), 2 directories:
+        3 kB  /p0/target
+        2 kB  /p1/target
+        5 kB  Subtotal
+
+Medium (200 B - 1 kB), 1 directories:
+       500 B  /p2/target
+       500 B  Subtotal
+
+Small (< 200 B), 1 directories:
+       100 B  /p3/target
+       100 B  Subtotal
+
+      5.6 kB  Total
+"
+        );
+    }
+
+    #[test]
+    fn test_parse_tier_bounds() {
+        assert_eq!(
+            parse_tier_bounds("10GB,1GB,100MB").unwrap(),
+            TierBounds::default()
+        );
+        assert!(parse_tier_bounds("1GB,10GB,100MB").is_err());
+        assert!(parse_tier_bounds("1GB,100MB").is_err());
+        assert!(parse_tier_bounds("1GB,lots,100MB").is_err());
+    }
+}
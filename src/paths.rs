@@ -0,0 +1,196 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The name of this tool's own directory inside each base directory.
+const APP_DIR: &str = "clean-big-targets";
+
+/// Works out a base directory: `xdg_var` if it's set, then the platform's usual place, then
+/// `unix` under `HOME`.
+fn base_dir(
+    env: &impl Fn(&str) -> Option<String>,
+    xdg_var: &str,
+    windows_var: &str,
+    macos: &str,
+    unix: &str,
+) -> Option<PathBuf> {
+    let set = |name: &str| env(name).filter(|dir| !dir.is_empty()).map(PathBuf::from);
+    if let Some(dir) = set(xdg_var) {
+        return Some(dir);
+    }
+    if cfg!(windows)
+        && let Some(dir) = set(windows_var)
+    {
+        return Some(dir);
+    }
+    let home = set("HOME")?;
+    if cfg!(target_os = "macos") && !macos.is_empty() {
+        return Some(home.join(macos));
+    }
+    Some(home.join(unix))
+}
+
+/// Where things that can be worked out again go, `$XDG_CACHE_HOME/clean-big-targets`, falling
+/// back to `~/Library/Caches` on macOS, `%LOCALAPPDATA%` on Windows and `~/.cache`.
+pub fn cache_dir(env: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let base = base_dir(
+        &env,
+        "XDG_CACHE_HOME",
+        "LOCALAPPDATA",
+        "Library/Caches",
+        ".cache",
+    )?;
+    Some(base.join(APP_DIR))
+}
+
+/// Where records worth keeping go, `$XDG_DATA_HOME/clean-big-targets`, falling back to
+/// `~/Library/Application Support` on macOS, `%APPDATA%` on Windows and `~/.local/share`.
+pub fn data_dir(env: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let base = base_dir(
+        &env,
+        "XDG_DATA_HOME",
+        "APPDATA",
+        "Library/Application Support",
+        ".local/share",
+    )?;
+    Some(base.join(APP_DIR))
+}
+
+/// Where state kept between runs goes, `$XDG_STATE_HOME/clean-big-targets`, falling back to
+/// `%LOCALAPPDATA%` on Windows and `~/.local/state`.
+pub fn state_dir(env: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let base = base_dir(&env, "XDG_STATE_HOME", "LOCALAPPDATA", "", ".local/state")?;
+    Some(base.join(APP_DIR))
+}
+
+/// The base directory for configuration, `$XDG_CONFIG_HOME` or `~/.config`, which other tools'
+/// configuration such as systemd's is found under too.
+pub fn config_home(env: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    base_dir(&env, "XDG_CONFIG_HOME", "APPDATA", "", ".config")
+}
+
+/// Where everything this tool keeps between runs goes. Anything that persists finds its file
+/// through here, so pointing these at a temporary directory keeps a test self-contained.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Dirs {
+    /// Things that can be worked out again, such as `--resume-scan`'s checkpoint.
+    pub cache: Option<PathBuf>,
+    /// Records worth keeping.
+    pub data: Option<PathBuf>,
+    /// State kept between runs, such as the directories `--review` was told to keep.
+    pub state: Option<PathBuf>,
+}
+
+impl Dirs {
+    /// The directories to use, with `--cache-dir` and `--data-dir` taking precedence over the
+    /// environment. `--data-dir` holds the state too.
+    pub fn resolve(
+        cache_override: Option<&Path>,
+        data_override: Option<&Path>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Self {
+        Self {
+            cache: cache_override
+                .map(Path::to_path_buf)
+                .or_else(|| cache_dir(&env)),
+            data: data_override
+                .map(Path::to_path_buf)
+                .or_else(|| data_dir(&env)),
+            state: data_override
+                .map(Path::to_path_buf)
+                .or_else(|| state_dir(&env)),
+        }
+    }
+}
+
+/// Creates the directory `file` goes in if it isn't there yet, saying which when it can't be.
+pub fn create_parent(file: &Path) -> std::io::Result<()> {
+    let Some(parent) = file
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    else {
+        return Ok(());
+    };
+    fs::create_dir_all(parent).map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!(
+                "Couldn't create the directory {} for {}: {}",
+                parent.display(),
+                file.display(),
+                e
+            ),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_override_precedence() {
+        let env = |name: &str| match name {
+            "XDG_CACHE_HOME" => Some("/cache".to_string()),
+            "XDG_DATA_HOME" => Some("/data".to_string()),
+            "XDG_STATE_HOME" => Some("/state".to_string()),
+            "HOME" => Some("/home/user".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            Dirs::resolve(None, None, env),
+            Dirs {
+                cache: Some(PathBuf::from("/cache/clean-big-targets")),
+                data: Some(PathBuf::from("/data/clean-big-targets")),
+                state: Some(PathBuf::from("/state/clean-big-targets")),
+            }
+        );
+        assert_eq!(
+            Dirs::resolve(Some(Path::new("/tmp/c")), Some(Path::new("/tmp/d")), env),
+            Dirs {
+                cache: Some(PathBuf::from("/tmp/c")),
+                data: Some(PathBuf::from("/tmp/d")),
+                state: Some(PathBuf::from("/tmp/d")),
+            }
+        );
+        assert_eq!(Dirs::resolve(None, None, |_| None), Dirs::default());
+
+        // An empty XDG variable counts as unset
+        let env = |name: &str| match name {
+            "XDG_CACHE_HOME" => Some(String::new()),
+            "HOME" => Some("/home/user".to_string()),
+            _ => None,
+        };
+        if cfg!(all(unix, not(target_os = "macos"))) {
+            assert_eq!(
+                cache_dir(env),
+                Some(PathBuf::from("/home/user/.cache/clean-big-targets"))
+            );
+            assert_eq!(config_home(env), Some(PathBuf::from("/home/user/.config")));
+        }
+    }
+
+    #[test]
+    fn test_create_parent_failure() {
+        let temp_dir = TempDir::new().unwrap();
+        let blocker = temp_dir.path().join("blocker");
+        fs::write(&blocker, "a file, not a directory").unwrap();
+        let file = blocker.join("state").join("keep");
+
+        let error = create_parent(&file).unwrap_err();
+        assert!(
+            error.to_string().starts_with(&format!(
+                "Couldn't create the directory {} for {}: ",
+                blocker.join("state").display(),
+                file.display()
+            )),
+            "{error}"
+        );
+
+        let file = temp_dir.path().join("new").join("keep");
+        create_parent(&file).unwrap();
+        assert!(temp_dir.path().join("new").is_dir());
+    }
+}
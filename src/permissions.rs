@@ -0,0 +1,232 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::TargetDirInfo;
+
+/// Who deletions run as, for working out up front whether they'd be allowed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identity {
+    /// The effective user id, which is what the kernel checks.
+    pub uid: u32,
+    /// The effective group id and every supplementary group.
+    pub groups: Vec<u32>,
+}
+
+impl Identity {
+    /// Whoever this process is running as.
+    #[cfg(unix)]
+    pub fn current() -> Self {
+        // SAFETY: neither can fail, and they only read the process's credentials
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        let mut groups = vec![gid];
+        // SAFETY: a size of 0 only asks how many groups there are
+        let count = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+        if let Ok(len) = usize::try_from(count) {
+            let mut supplementary = vec![0; len];
+            // SAFETY: `supplementary` has room for the `count` groups asked for
+            let filled = unsafe { libc::getgroups(count, supplementary.as_mut_ptr()) };
+            supplementary.truncate(usize::try_from(filled).unwrap_or(0));
+            groups.extend(supplementary);
+        }
+        Self { uid, groups }
+    }
+
+    #[cfg(not(unix))]
+    pub fn current() -> Self {
+        Self {
+            uid: 0,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Returns true if this identity could add and remove entries in the directory at `path`.
+    ///
+    /// When `path` can't be looked at there's no telling, so this says yes and leaves it to
+    /// the deletion to fail.
+    #[cfg(unix)]
+    pub fn can_modify(&self, path: &Path) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        let Ok(metadata) = std::fs::metadata(path) else {
+            return true;
+        };
+        // Root can change any directory
+        if self.uid == 0 {
+            return true;
+        }
+        let mode = metadata.mode();
+        let bits = if metadata.uid() == self.uid {
+            mode >> 6
+        } else if self.groups.contains(&metadata.gid()) {
+            mode >> 3
+        } else {
+            mode
+        };
+        // Writing to a directory needs the execute bit too
+        bits & 0o3 == 0o3
+    }
+
+    /// Best effort where there are no mode bits, only the read-only attribute.
+    #[cfg(not(unix))]
+    pub fn can_modify(&self, path: &Path) -> bool {
+        !std::fs::metadata(path).is_ok_and(|metadata| metadata.permissions().readonly())
+    }
+
+    /// The first of `path` and its parent this identity can't modify, if any. Removing `path`
+    /// needs both: its own entries are removed from it, and it's removed from its parent.
+    pub fn missing_permission(&self, path: &Path) -> Option<PathBuf> {
+        [Some(path), path.parent()]
+            .into_iter()
+            .flatten()
+            .find(|dir| !self.can_modify(dir))
+            .map(Path::to_path_buf)
+    }
+}
+
+/// Leaves out the directories `identity` couldn't remove, saying so on `err`.
+///
+/// Without permission `remove_dir_all` gets partway through before failing, which leaves a
+/// half deleted target that's neither usable nor gone.
+pub fn skip_without_permission<'a>(
+    selected: Vec<&'a TargetDirInfo>,
+    identity: &Identity,
+    err: &mut dyn Write,
+) -> std::io::Result<Vec<&'a TargetDirInfo>> {
+    let mut allowed = Vec::with_capacity(selected.len());
+    for info in selected {
+        match identity.missing_permission(&info.path) {
+            Some(dir) => writeln!(
+                err,
+                "Permission denied on '{}', skipping '{}'",
+                dir.display(),
+                info.path.display()
+            )?,
+            None => allowed.push(info),
+        }
+    }
+    Ok(allowed)
+}
+
+/// Removes the directory at `path` like `remove_dir_all`, clearing the read-only attribute on
+/// everything inside and trying again if that's what stopped it.
+///
+/// Windows won't delete a read-only file, which cargo and some build scripts leave behind.
+#[cfg(windows)]
+pub fn remove_dir_all_clearing_readonly(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_dir_all(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            clear_readonly(path)?;
+            std::fs::remove_dir_all(path)
+        }
+        removed => removed,
+    }
+}
+
+/// Elsewhere a file's own permissions don't stop it being deleted, only its directory's.
+#[cfg(not(windows))]
+pub fn remove_dir_all_clearing_readonly(path: &Path) -> std::io::Result<()> {
+    std::fs::remove_dir_all(path)
+}
+
+/// Clears the read-only attribute on `path` and everything inside, not following links.
+#[cfg(windows)]
+fn clear_readonly(path: &Path) -> std::io::Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        // Removed since its directory was listed, which is as good
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        metadata => metadata?,
+    };
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            clear_readonly(&entry?.path())?;
+        }
+    }
+    let mut permissions = metadata.permissions();
+    if permissions.readonly() {
+        // Only the attribute on Windows, not write access for everyone as it would be on unix
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        std::fs::set_permissions(path, permissions)?;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, windows))]
+mod windows_tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_remove_read_only_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("app").join("target");
+        fs::create_dir_all(target.join("debug")).unwrap();
+        let file = target.join("debug").join("app.pdb");
+        fs::write(&file, "debug info").unwrap();
+        let mut permissions = fs::metadata(&file).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&file, permissions).unwrap();
+
+        remove_dir_all_clearing_readonly(&target).unwrap();
+        assert!(!target.exists());
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::{fs, os::unix::fs::PermissionsExt};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_missing_permission() {
+        let temp_dir = TempDir::new().unwrap();
+        let locked = temp_dir.path().join("locked");
+        let open = temp_dir.path().join("open");
+        for project in [&locked, &open] {
+            fs::create_dir_all(project.join("target")).unwrap();
+            fs::set_permissions(project, fs::Permissions::from_mode(0o777)).unwrap();
+            fs::set_permissions(project.join("target"), fs::Permissions::from_mode(0o777)).unwrap();
+        }
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o555)).unwrap();
+
+        // Someone who doesn't own either, so only the bits for everyone else apply
+        let someone = Identity {
+            uid: 4242,
+            groups: vec![4242],
+        };
+        assert_eq!(
+            someone.missing_permission(&locked.join("target")),
+            Some(locked.clone())
+        );
+        assert_eq!(someone.missing_permission(&open.join("target")), None);
+        assert_eq!(someone.missing_permission(&open.join("missing")), None);
+        let root = Identity {
+            uid: 0,
+            groups: vec![0],
+        };
+        assert_eq!(root.missing_permission(&locked.join("target")), None);
+
+        let targets = [
+            TargetDirInfo::new(locked.join("target"), 10),
+            TargetDirInfo::new(open.join("target"), 10),
+        ];
+        let mut err = Vec::new();
+        let allowed =
+            skip_without_permission(targets.iter().collect(), &someone, &mut err).unwrap();
+        assert_eq!(allowed.len(), 1);
+        assert_eq!(allowed[0].path, open.join("target"));
+        assert_eq!(
+            String::from_utf8(err).unwrap(),
+            format!(
+                "Permission denied on '{}', skipping '{}'\n",
+                locked.display(),
+                locked.join("target").display()
+            )
+        );
+
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
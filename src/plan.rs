@@ -0,0 +1,562 @@
+use std::{io::Write, path::PathBuf};
+
+use humanize_bytes::humanize_bytes_decimal;
+
+use crate::{
+    Remover, TargetDirInfo, TargetKind, calculate_dir_size,
+    config::Config,
+    handle_deletion_with, looks_like_target,
+    output::{Output, ncdu::escape_json},
+    protect::refuse_protected,
+    unc,
+};
+
+/// Writes a deletion plan for `--format jsonl-plan`, one JSON object per line such as
+/// `{"path":"/code/app/target","size":1000,"file_count":12,"kind":"cargo"}`.
+///
+/// `file_count` is `null` when it isn't known. Paths that aren't valid UTF-8 can't be written
+/// exactly, so won't match anything when the plan is executed.
+pub fn render_plan(out: &mut dyn Write, target_info: &[TargetDirInfo]) -> std::io::Result<()> {
+    for info in target_info {
+        let file_count = info
+            .file_count
+            .map_or_else(|| "null".to_string(), |count| count.to_string());
+        writeln!(
+            out,
+            "{{\"path\":\"{}\",\"size\":{},\"file_count\":{},\"kind\":\"{}\"}}",
+            escape_json(&info.path.to_string_lossy()),
+            info.size,
+            file_count,
+            info.kind.as_str()
+        )?;
+    }
+    Ok(())
+}
+
+/// A parsed JSON value. Numbers are only ever whole and not negative in what this tool writes.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Value {
+    String(String),
+    Number(u64),
+    Bool(bool),
+    Null,
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+/// Reads a JSON string starting just after its opening quote, returning it and what's left.
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let mut parsed = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((parsed, &text[i + 1..])),
+            '\\' => match chars.next().map(|(_, c)| c) {
+                Some('"') => parsed.push('"'),
+                Some('\\') => parsed.push('\\'),
+                Some('/') => parsed.push('/'),
+                Some('n') => parsed.push('\n'),
+                Some('r') => parsed.push('\r'),
+                Some('t') => parsed.push('\t'),
+                Some('b') => parsed.push('\u{8}'),
+                Some('f') => parsed.push('\u{c}'),
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                    let code = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| format!("bad \\u escape '{hex}'"))?;
+                    parsed.push(code);
+                }
+                other => return Err(format!("bad escape {other:?}")),
+            },
+            c => parsed.push(c),
+        }
+    }
+    Err("unterminated string".to_string())
+}
+
+/// Reads the items of an array or object starting just after the opening bracket, with
+/// `item` reading each one, returning them and what's left.
+fn parse_items<'a, T>(
+    mut rest: &'a str,
+    close: char,
+    mut item: impl FnMut(&'a str) -> Result<(T, &'a str), String>,
+) -> Result<(Vec<T>, &'a str), String> {
+    let mut items = Vec::new();
+    rest = rest.trim_start();
+    if let Some(after) = rest.strip_prefix(close) {
+        return Ok((items, after));
+    }
+    loop {
+        let (parsed, after) = item(rest)?;
+        items.push(parsed);
+        rest = after.trim_start();
+        if let Some(after) = rest.strip_prefix(',') {
+            rest = after.trim_start();
+        } else if let Some(after) = rest.strip_prefix(close) {
+            return Ok((items, after));
+        } else {
+            return Err(format!("expected ',' or '{close}'"));
+        }
+    }
+}
+
+/// Reads one JSON value from the start of `text`, returning it and what's left.
+fn parse_value(text: &str) -> Result<(Value, &str), String> {
+    let text = text.trim_start();
+    if let Some(after) = text.strip_prefix('"') {
+        let (value, after) = parse_string(after)?;
+        Ok((Value::String(value), after))
+    } else if let Some(after) = text.strip_prefix('{') {
+        let (fields, after) = parse_items(after, '}', |rest| {
+            let (key, after) = parse_string(rest.strip_prefix('"').ok_or("expected a key")?)?;
+            let after = after.trim_start().strip_prefix(':').ok_or("expected ':'")?;
+            let (value, after) =
+                parse_value(after).map_err(|e| format!("expected a value for '{key}': {e}"))?;
+            Ok(((key, value), after))
+        })?;
+        Ok((Value::Object(fields), after))
+    } else if let Some(after) = text.strip_prefix('[') {
+        let (values, after) = parse_items(after, ']', parse_value)?;
+        Ok((Value::Array(values), after))
+    } else if let Some(after) = text.strip_prefix("null") {
+        Ok((Value::Null, after))
+    } else if let Some(after) = text.strip_prefix("true") {
+        Ok((Value::Bool(true), after))
+    } else if let Some(after) = text.strip_prefix("false") {
+        Ok((Value::Bool(false), after))
+    } else {
+        let end = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        let number = text[..end]
+            .parse()
+            .map_err(|_| "expected a string, whole number, object, array, true, false or null")?;
+        Ok((Value::Number(number), &text[end..]))
+    }
+}
+
+/// Parses a whole JSON document, which mustn't have anything after the value.
+pub(crate) fn parse_json(text: &str) -> Result<Value, String> {
+    let (value, rest) = parse_value(text)?;
+    if !rest.trim().is_empty() {
+        return Err("trailing text".to_string());
+    }
+    Ok(value)
+}
+
+/// Parses one line written by [`render_plan`]. Keys it doesn't know are ignored, and only
+/// `path` is required.
+pub fn parse_plan_line(line: &str) -> Result<TargetDirInfo, String> {
+    match parse_json(line)? {
+        Value::Object(fields) => target_from_fields(fields),
+        _ => Err("expected an object".to_string()),
+    }
+}
+
+/// Builds a target from the fields [`render_plan`] writes for each one, ignoring any others.
+pub(crate) fn target_from_fields(fields: Vec<(String, Value)>) -> Result<TargetDirInfo, String> {
+    let mut path = None;
+    let mut info = TargetDirInfo::new(PathBuf::new(), 0);
+    for (key, value) in fields {
+        match (key.as_str(), value) {
+            ("path", Value::String(value)) => path = Some(PathBuf::from(value)),
+            ("size", Value::Number(size)) => info.size = size,
+            ("file_count", Value::Number(count)) => info.file_count = Some(count),
+            ("file_count", Value::Null) => {}
+            ("kind", Value::String(kind)) => {
+                info.kind =
+                    TargetKind::from_name(&kind).ok_or_else(|| format!("unknown kind '{kind}'"))?;
+            }
+            ("path" | "size" | "file_count" | "kind", value) => {
+                return Err(format!("unexpected value for '{key}': {value:?}"));
+            }
+            _ => {}
+        }
+    }
+    info.path = path.ok_or("missing 'path'")?;
+    Ok(info)
+}
+
+/// Reads a plan, skipping blank lines. The first line that doesn't parse is an error, as a
+/// mangled plan shouldn't be half carried out.
+pub fn read_plan(contents: &str) -> Result<Vec<TargetDirInfo>, String> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            parse_plan_line(line).map_err(|e| format!("line {}: {e}", number + 1))
+        })
+        .collect()
+}
+
+/// Deletes the directories in a plan for `--execute-plan`, without prompting as whoever wrote
+/// the plan already chose them.
+///
+/// Directories that have gone since the plan was made are skipped. Everything else is resolved
+/// and sized again, as the plan's paths may be relative or through symlinks and its sizes stale
+/// or made up, and gets the same checks as any other deletion: the keep list,
+/// `--exclude-larger-than`, the working directory, git tracked files and the directory name. A
+/// plan naming a protected directory is refused outright, as it was written by hand or against
+/// some other config.
+pub fn execute_plan(
+    plan: &[TargetDirInfo],
+    config: &Config,
+    remover: &Remover,
+    output: &mut Output,
+) -> std::io::Result<()> {
+    let mut present = Vec::with_capacity(plan.len());
+    for info in plan {
+        match resolved(info) {
+            Some(info) => present.push(info),
+            None => writeln!(
+                output.err,
+                "Skipping {}: it's no longer there",
+                info.path.display()
+            )?,
+        }
+    }
+    refuse_protected(&present.iter().collect::<Vec<_>>(), &config.protected)?;
+    let mut sized = Vec::with_capacity(present.len());
+    for info in present {
+        match calculate_dir_size(&info.path) {
+            Ok(size) => sized.push(TargetDirInfo { size, ..info }),
+            Err(e) => writeln!(
+                output.err,
+                "Skipping {}: couldn't size it again: {}",
+                info.path.display(),
+                e
+            )?,
+        }
+    }
+    let config = Config {
+        force: true,
+        ..config.clone()
+    };
+    handle_deletion_with(&sized, &config, remover, output)
+}
+
+/// `info` at its canonical path, as a scan would have found it, so the protected entries match
+/// it however it was written. `None` if there's no longer a directory there.
+fn resolved(info: &TargetDirInfo) -> Option<TargetDirInfo> {
+    let path = unc::canonicalize(&info.path)
+        .ok()
+        .filter(|path| path.is_dir())?;
+    Some(TargetDirInfo {
+        path,
+        ..info.clone()
+    })
+}
+
+/// Checks the targets from an earlier scan, for `--from-json`, before they're offered for
+/// deletion as if just found.
+///
+/// Directories that have gone or no longer look like build directories are reported and
+/// dropped, not errors, as things change between the scan and the clean up. The rest are
+/// resolved to where they really are and sized again, with a warning for any that grew by more
+/// than `max_growth` percent so a user isn't surprised by deleting more than was agreed to.
+pub fn revalidate(
+    entries: Vec<TargetDirInfo>,
+    config: &Config,
+    max_growth: u64,
+    output: &mut Output,
+) -> std::io::Result<Vec<TargetDirInfo>> {
+    let mut checked = Vec::with_capacity(entries.len());
+    for info in entries {
+        let Some(mut info) = resolved(&info) else {
+            writeln!(
+                output.err,
+                "Skipping {}: it's no longer there",
+                info.path.display()
+            )?;
+            continue;
+        };
+        if !looks_like_target(&info, config.case_insensitive) {
+            writeln!(
+                output.err,
+                "Skipping {}: it no longer looks like a build directory",
+                info.path.display()
+            )?;
+            continue;
+        }
+        let size = match calculate_dir_size(&info.path) {
+            Ok(size) => size,
+            Err(e) => {
+                writeln!(
+                    output.err,
+                    "Skipping {}: couldn't size it again: {}",
+                    info.path.display(),
+                    e
+                )?;
+                continue;
+            }
+        };
+        let grown = size.saturating_sub(info.size);
+        if grown > 0 && grown.saturating_mul(100) > info.size.saturating_mul(max_growth) {
+            writeln!(
+                output.err,
+                "Warning: {} has grown from {} to {} since the scan",
+                info.path.display(),
+                humanize_bytes_decimal!(info.size),
+                humanize_bytes_decimal!(size)
+            )?;
+        }
+        info.size = size;
+        info.protected = config.protected.covers(&info);
+        checked.push(info);
+    }
+    Ok(checked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::Path, sync::Mutex};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_render_plan_round_trips() {
+        let mut counted = TargetDirInfo::new(PathBuf::from("/code/\"quoted\"\\app/target"), 1000);
+        counted.file_count = Some(12);
+        let mut wasm = TargetDirInfo::new(PathBuf::from("/code/app/pkg"), 5);
+        wasm.kind = TargetKind::WasmPack;
+        let mut out = Vec::new();
+        render_plan(&mut out, &[counted, wasm]).unwrap();
+        let plan = String::from_utf8(out).unwrap();
+        assert_eq!(
+            plan,
+            "{\"path\":\"/code/\\\"quoted\\\"\\\\app/target\",\"size\":1000,\"file_count\":12,\"kind\":\"cargo\"}\n\
+             {\"path\":\"/code/app/pkg\",\"size\":5,\"file_count\":null,\"kind\":\"wasm-pack\"}\n"
+        );
+
+        let read = read_plan(&plan).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].path, Path::new("/code/\"quoted\"\\app/target"));
+        assert_eq!((read[0].size, read[0].file_count), (1000, Some(12)));
+        assert_eq!(read[1].kind, TargetKind::WasmPack);
+        assert_eq!(read[1].file_count, None);
+    }
+
+    #[test]
+    fn test_read_plan_errors() {
+        assert_eq!(
+            read_plan("\n{ \"path\" : \"/a/target\", \"extra\": \"x\" }\n").unwrap()[0].path,
+            Path::new("/a/target")
+        );
+        assert_eq!(
+            read_plan("{\"path\":\"/a/target\"}\n{\"size\":1}").unwrap_err(),
+            "line 2: missing 'path'"
+        );
+        assert!(read_plan("{\"path\":\"/a/target\"").is_err());
+        assert!(read_plan("{\"path\":1}").is_err());
+        assert!(read_plan("{\"path\":\"/a/target\",\"kind\":\"nope\"}").is_err());
+        assert!(read_plan("[]").is_err());
+    }
+
+    #[test]
+    fn test_execute_plan() {
+        let temp_dir = TempDir::new().unwrap();
+        // Where the paths resolve to
+        let root = unc::canonicalize(temp_dir.path()).unwrap();
+        let target = root.join("app").join("target");
+        let not_target = root.join("app").join("src");
+        fs::create_dir_all(&target).unwrap();
+        fs::create_dir_all(&not_target).unwrap();
+        let plan = read_plan(&format!(
+            "{{\"path\":\"{}\",\"size\":10}}\n{{\"path\":\"{}\",\"size\":10}}\n",
+            escape_json(&target.to_string_lossy()),
+            escape_json(&root.join("gone").join("target").to_string_lossy())
+        ))
+        .unwrap();
+
+        let removed = Mutex::new(Vec::new());
+        let remover = |path: &Path| {
+            removed.lock().unwrap().push(path.to_path_buf());
+            Ok(())
+        };
+        let config = Config {
+            delete_jobs: 1,
+            delete_tracked: true,
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        execute_plan(
+            &plan,
+            &config,
+            &remover,
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap();
+        assert_eq!(*removed.lock().unwrap(), [target]);
+        assert!(String::from_utf8(err).unwrap().contains("no longer there"));
+
+        // A plan can't be used to delete something that isn't a target directory
+        let plan = [TargetDirInfo::new(not_target, 10)];
+        let mut err = Vec::new();
+        assert!(
+            execute_plan(
+                &plan,
+                &config,
+                &remover,
+                &mut Output::new(&mut out, &mut err, false),
+            )
+            .is_err()
+        );
+        assert_eq!(removed.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_execute_plan_resizes() {
+        let temp_dir = TempDir::new().unwrap();
+        // Where the paths resolve to
+        let root = unc::canonicalize(temp_dir.path()).unwrap();
+        let target = root.join("app").join("target");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("artifact"), vec![0u8; 2000]).unwrap();
+        // Far smaller than it is, which --exclude-larger-than mustn't go by
+        let plan = [TargetDirInfo::new(target.clone(), 10)];
+
+        let removed = Mutex::new(Vec::new());
+        let remover = |path: &Path| {
+            removed.lock().unwrap().push(path.to_path_buf());
+            Ok(())
+        };
+        let config = Config {
+            delete_jobs: 1,
+            delete_tracked: true,
+            exclude_larger_than: Some(1000),
+            ..Config::default()
+        };
+        let mut out = Vec::new();
+        execute_plan(
+            &plan,
+            &config,
+            &remover,
+            &mut Output::new(&mut out, &mut std::io::sink(), false),
+        )
+        .unwrap();
+        assert!(removed.lock().unwrap().is_empty());
+
+        let config = Config {
+            exclude_larger_than: Some(3000),
+            ..config
+        };
+        execute_plan(
+            &plan,
+            &config,
+            &remover,
+            &mut Output::new(&mut out, &mut std::io::sink(), false),
+        )
+        .unwrap();
+        assert_eq!(*removed.lock().unwrap(), [target]);
+        assert!(String::from_utf8(out).unwrap().contains("2 kB"));
+    }
+
+    #[test]
+    fn test_revalidate() {
+        let temp_dir = TempDir::new().unwrap();
+        // Where the paths resolve to
+        let root = unc::canonicalize(temp_dir.path()).unwrap();
+        let grown = root.join("grown").join("target");
+        let same = root.join("same").join("target");
+        let renamed = root.join("renamed").join("out");
+        for dir in [&grown, &same, &renamed] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(grown.join("big"), vec![0u8; 1000]).unwrap();
+        fs::write(same.join("small"), vec![0u8; 100]).unwrap();
+        let gone = root.join("gone").join("target");
+        let entries = vec![
+            TargetDirInfo::new(grown.clone(), 100),
+            TargetDirInfo::new(same.clone(), 100),
+            TargetDirInfo::new(renamed, 0),
+            TargetDirInfo::new(gone, 0),
+        ];
+
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let checked = revalidate(
+            entries,
+            &Config::default(),
+            10,
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap();
+        let checked: Vec<(PathBuf, u64)> = checked
+            .into_iter()
+            .map(|info| (info.path, info.size))
+            .collect();
+        assert_eq!(checked, [(grown.clone(), 1000), (same, 100)]);
+
+        let err = String::from_utf8(err).unwrap();
+        assert!(err.contains("no longer there"), "{err}");
+        assert!(
+            err.contains("no longer looks like a build directory"),
+            "{err}"
+        );
+        assert_eq!(err.matches("has grown").count(), 1, "{err}");
+        assert!(err.contains(&format!("{} has grown from 100 B to 1 kB", grown.display())));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_plan_paths_resolved_before_protection() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = unc::canonicalize(temp_dir.path()).unwrap();
+        let target = root.join("monorepo").join("target");
+        fs::create_dir_all(&target).unwrap();
+        std::os::unix::fs::symlink(root.join("monorepo"), root.join("link")).unwrap();
+        // The same directory through a symlink, and relative to where the tests run
+        let linked = root.join("link").join("target");
+        let cwd = std::env::current_dir().unwrap();
+        let relative: PathBuf = cwd
+            .components()
+            .skip(1)
+            .map(|_| Path::new(".."))
+            .chain([target.strip_prefix("/").unwrap()])
+            .collect();
+        assert!(relative.is_relative());
+
+        let config = Config {
+            delete_jobs: 1,
+            delete_tracked: true,
+            protected: crate::protect::Protected::parse(
+                &format!("protected = [\"{}\"]", root.join("monorepo").display()),
+                false,
+            )
+            .unwrap(),
+            ..Config::default()
+        };
+        let remover = |_: &Path| -> std::io::Result<()> { panic!("it's protected") };
+        for path in [&linked, &relative] {
+            let plan = [TargetDirInfo::new(path.clone(), 10)];
+            let error = execute_plan(
+                &plan,
+                &config,
+                &remover,
+                &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+            )
+            .unwrap_err();
+            assert!(error.to_string().contains("protected"), "{path:?}: {error}");
+        }
+
+        let checked = revalidate(
+            vec![
+                TargetDirInfo::new(linked, 0),
+                TargetDirInfo::new(relative, 0),
+            ],
+            &config,
+            10,
+            &mut Output::new(&mut std::io::sink(), &mut std::io::sink(), false),
+        )
+        .unwrap();
+        assert_eq!(checked.len(), 2);
+        for info in checked {
+            assert_eq!(info.path, target);
+            assert!(info.protected);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! `--sort priority`, ordering targets by how much reclaiming each is worth.
+
+use std::{cmp::Ordering, time::SystemTime};
+
+use crate::TargetDirInfo;
+
+/// The `--priority-age-weight` used when none is given, which makes a target twice as old
+/// count the same as one twice as big.
+pub const DEFAULT_AGE_WEIGHT: f64 = 1.0;
+
+/// Parses `--priority-age-weight`, which can't be negative as newer targets would then score
+/// higher.
+pub fn parse_age_weight(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(weight) if weight.is_finite() && weight >= 0.0 => Ok(weight),
+        _ => Err(format!("'{value}' isn't a number of at least 0")),
+    }
+}
+
+/// How many days ago `modified` was, as a fraction. Unknown or future times count as 0.
+pub fn age_days(modified: Option<SystemTime>, now: SystemTime) -> f64 {
+    modified
+        .and_then(|modified| now.duration_since(modified).ok())
+        .map_or(0.0, |age| age.as_secs_f64() / 86400.0)
+}
+
+/// The reclaim priority of a target, `size_bytes × age_days ^ age_weight`.
+///
+/// With a weight of 0 it's the size alone, and the bigger the weight the more staleness counts.
+pub fn priority_score(size_bytes: u64, age_days: f64, age_weight: f64) -> f64 {
+    size_bytes as f64 * age_days.max(0.0).powf(age_weight)
+}
+
+/// The [`priority_score`] of `info`, using its age as the scan worked it out.
+pub fn score(info: &TargetDirInfo, age_weight: f64, now: SystemTime) -> f64 {
+    priority_score(info.size, age_days(info.modified, now), age_weight)
+}
+
+/// Sorts `targets` highest priority first, the biggest first among equal scores.
+pub fn sort_by_priority(targets: &mut [TargetDirInfo], age_weight: f64, now: SystemTime) {
+    targets.sort_by(|a, b| {
+        let (a_score, b_score) = (score(a, age_weight, now), score(b, age_weight, now));
+        b_score
+            .partial_cmp(&a_score)
+            .unwrap_or(Ordering::Equal)
+            .then(b.size.cmp(&a.size))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{path::PathBuf, time::Duration};
+
+    #[test]
+    fn test_priority_ordering() {
+        const GB: u64 = 1_000_000_000;
+        // (name, size, age in days)
+        let pairs = [
+            ("active monorepo", 40 * GB, 0.5),
+            ("ancient stub", 2_000_000, 900.0),
+            ("stale service", 8 * GB, 120.0),
+            ("last month", 5 * GB, 30.0),
+        ];
+        let ranked = |age_weight: f64| {
+            let mut ranked: Vec<_> = pairs
+                .iter()
+                .map(|(name, size, age)| (*name, priority_score(*size, *age, age_weight)))
+                .collect();
+            ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+            ranked.into_iter().map(|(name, _)| name).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ranked(DEFAULT_AGE_WEIGHT),
+            [
+                "stale service",
+                "last month",
+                "active monorepo",
+                "ancient stub"
+            ]
+        );
+        // Size alone
+        assert_eq!(
+            ranked(0.0),
+            [
+                "active monorepo",
+                "stale service",
+                "last month",
+                "ancient stub"
+            ]
+        );
+        // Age counting for a lot more lifts the ancient stub above the active monorepo
+        assert_eq!(
+            ranked(3.0),
+            [
+                "stale service",
+                "ancient stub",
+                "last month",
+                "active monorepo"
+            ]
+        );
+
+        assert_eq!(priority_score(1000, 2.0, 1.0), 2000.0);
+        assert_eq!(priority_score(1000, 0.0, 1.0), 0.0);
+        assert_eq!(parse_age_weight("1.5"), Ok(1.5));
+        assert!(parse_age_weight("-1").is_err());
+        assert!(parse_age_weight("NaN").is_err());
+    }
+
+    #[test]
+    fn test_sort_by_priority() {
+        let now = SystemTime::now();
+        let target = |name: &str, size, days: u64| {
+            let mut info = TargetDirInfo::new(PathBuf::from(name), size);
+            info.modified = Some(now - Duration::from_secs(days * 86400));
+            info
+        };
+        let mut targets = vec![
+            target("/big-new/target", 1000, 1),
+            target("/small-old/target", 100, 100),
+            target("/unknown/target", 5000, 0),
+            target("/now/target", 9000, 0),
+        ];
+        targets[2].modified = None;
+        sort_by_priority(&mut targets, DEFAULT_AGE_WEIGHT, now);
+        let order: Vec<_> = targets.iter().map(|info| info.path.clone()).collect();
+        assert_eq!(
+            order,
+            [
+                PathBuf::from("/small-old/target"),
+                PathBuf::from("/big-new/target"),
+                PathBuf::from("/now/target"),
+                PathBuf::from("/unknown/target"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// How many entries are visited between calls to a sizing progress callback.
+pub const PROGRESS_EVERY: u64 = 1000;
+
+/// How far sizing one directory has got, passed to the callback given to
+/// [`calculate_dir_size_with_progress`](crate::calculate_dir_size_with_progress).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SizeProgress {
+    /// The directory being listed when this was sent, or the one being sized once it's done.
+    pub path: PathBuf,
+    /// Files and directories visited so far.
+    pub entries: u64,
+    /// The size of the files visited so far.
+    pub bytes: u64,
+}
+
+/// A sizing progress callback, as an embedder would keep one.
+pub type ProgressFn = Box<dyn Fn(SizeProgress) + Send + Sync>;
+
+/// Keeps count while one directory is sized, calling `callback` every [`PROGRESS_EVERY`]
+/// entries.
+pub(crate) struct Tracker<'a> {
+    callback: &'a (dyn Fn(SizeProgress) + Send + Sync),
+    entries: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl<'a> Tracker<'a> {
+    pub(crate) fn new(callback: &'a (dyn Fn(SizeProgress) + Send + Sync)) -> Self {
+        Self {
+            callback,
+            entries: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts an entry of `bytes` found while listing `dir`.
+    pub(crate) fn visit(&self, dir: &Path, bytes: u64) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        if (self.entries.fetch_add(1, Ordering::Relaxed) + 1).is_multiple_of(PROGRESS_EVERY) {
+            self.send(dir);
+        }
+    }
+
+    /// Sends the counts as they are, such as once `path` has been sized.
+    pub(crate) fn send(&self, path: &Path) {
+        (self.callback)(SizeProgress {
+            path: path.to_path_buf(),
+            entries: self.entries.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calculate_dir_size_with_progress;
+    use std::{
+        fs,
+        sync::{Arc, Mutex},
+    };
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_progress_callback() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        for dir in 0..5 {
+            let dir = target.join("debug").join(format!("build-{dir}"));
+            fs::create_dir_all(&dir).unwrap();
+            for file in 0..500 {
+                fs::write(dir.join(format!("{file}.o")), vec![0u8; file % 7]).unwrap();
+            }
+        }
+
+        // Boxed as an embedder would keep it, which needs the calls shared rather than borrowed
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let progress: ProgressFn = {
+            let calls = Arc::clone(&calls);
+            Box::new(move |progress| calls.lock().unwrap().push(progress))
+        };
+        let total = calculate_dir_size_with_progress(&target, Some(progress.as_ref())).unwrap();
+        let calls = calls.lock().unwrap();
+
+        assert_eq!(total, crate::calculate_dir_size(&target).unwrap());
+        // 2500 files and 6 directories
+        assert_eq!(calls.len(), 3);
+        assert!(
+            calls
+                .windows(2)
+                .all(|pair| pair[0].bytes <= pair[1].bytes && pair[0].entries < pair[1].entries)
+        );
+        let last = calls.last().unwrap();
+        assert_eq!(
+            (last.path.as_path(), last.entries, last.bytes),
+            (target.as_path(), 2506, total)
+        );
+    }
+}
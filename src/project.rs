@@ -0,0 +1,107 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Reads `build.target-dir` from the contents of a `.cargo/config.toml`.
+///
+/// Like the manifest reader this is shallow, it understands `[build]` with `target-dir = "..."`
+/// and the dotted `build.target-dir = "..."` at the top level.
+pub fn config_target_dir(contents: &str) -> Option<String> {
+    let mut table = String::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[') {
+            table = header.trim_end_matches(']').trim().to_string();
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim();
+        let is_target_dir = (table == "build" && key == "target-dir")
+            || (table.is_empty() && key == "build.target-dir");
+        if is_target_dir {
+            let value = value.trim();
+            let quote = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+            let rest = &value[1..];
+            return rest.find(quote).map(|end| rest[..end].to_string());
+        }
+    }
+    None
+}
+
+/// Works out where cargo puts the target directory for the project at `project`.
+///
+/// `CARGO_TARGET_DIR` wins, then `build.target-dir` from the nearest `.cargo/config.toml` (or
+/// `.cargo/config`) in the project or its parents, which is relative to the directory holding
+/// `.cargo`. Otherwise it's `target` in the project.
+pub fn resolve_target_dir(project: &Path, env: impl Fn(&str) -> Option<String>) -> PathBuf {
+    if let Some(dir) = env("CARGO_TARGET_DIR").filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    for dir in project.ancestors() {
+        for name in ["config.toml", "config"] {
+            let Ok(contents) = fs::read_to_string(dir.join(".cargo").join(name)) else {
+                continue;
+            };
+            if let Some(target_dir) = config_target_dir(&contents) {
+                return dir.join(target_dir);
+            }
+        }
+    }
+    project.join("target")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_config_target_dir() {
+        assert_eq!(
+            config_target_dir("[build]\njobs = 4\ntarget-dir = \"out/target\" # shared\n"),
+            Some("out/target".to_string())
+        );
+        assert_eq!(
+            config_target_dir("build.target-dir = '/tmp/target'\n"),
+            Some("/tmp/target".to_string())
+        );
+        assert_eq!(config_target_dir("[env]\ntarget-dir = \"nope\"\n"), None);
+        assert_eq!(config_target_dir(""), None);
+    }
+
+    #[test]
+    fn test_resolve_target_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join("workspace").join("member");
+        fs::create_dir_all(&project).unwrap();
+        assert_eq!(
+            resolve_target_dir(&project, |_| None),
+            project.join("target")
+        );
+
+        let cargo_dir = temp_dir.path().join("workspace").join(".cargo");
+        fs::create_dir_all(&cargo_dir).unwrap();
+        fs::write(
+            cargo_dir.join("config.toml"),
+            "[build]\ntarget-dir = \"shared/target\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            resolve_target_dir(&project, |_| None),
+            temp_dir
+                .path()
+                .join("workspace")
+                .join("shared")
+                .join("target")
+        );
+
+        let env = |name: &str| (name == "CARGO_TARGET_DIR").then(|| "/elsewhere".to_string());
+        assert_eq!(
+            resolve_target_dir(&project, env),
+            PathBuf::from("/elsewhere")
+        );
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{PatternSet, TargetDirInfo, manifest::parse_string, paths::config_home, refused};
+
+/// A file that, in a project directory, protects its target just as the config file can.
+/// Unlike a keep marker no flag gets past it.
+pub const PROTECT_MARKER: &str = ".keep-target";
+
+/// A file that, inside a target directory, protects it like a [`PROTECT_MARKER`].
+pub const TARGET_KEEP_FILE: &str = ".keep";
+
+/// Returns true if `info` or its project has been marked protected from within the repository.
+pub fn has_protect_marker(info: &TargetDirInfo) -> bool {
+    let target = info.target_dir();
+    target.join(TARGET_KEEP_FILE).exists()
+        || target
+            .parent()
+            .is_some_and(|project| project.join(PROTECT_MARKER).exists())
+}
+
+/// Where the config file is, `config.toml` in `$XDG_CONFIG_HOME/clean-big-targets`.
+pub fn config_file(env: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    Some(
+        config_home(env)?
+            .join("clean-big-targets")
+            .join("config.toml"),
+    )
+}
+
+/// Skips whitespace and `#` comments, which can sit between the items of a multi-line array.
+fn skip_blank(mut rest: &str) -> &str {
+    loop {
+        rest = rest.trim_start();
+        match rest.strip_prefix('#') {
+            Some(comment) => rest = comment.split_once('\n').map_or("", |(_, after)| after),
+            None => return rest,
+        }
+    }
+}
+
+/// Reads the array of strings at the start of `value`.
+fn parse_array(value: &str) -> Result<Vec<String>, String> {
+    let mut rest = value
+        .strip_prefix('[')
+        .ok_or_else(|| "expected an array of strings".to_string())?;
+    let mut items = Vec::new();
+    loop {
+        rest = skip_blank(rest);
+        if rest.starts_with(']') {
+            return Ok(items);
+        }
+        let (item, after) = parse_string(rest)?;
+        items.push(item);
+        rest = skip_blank(after);
+        if let Some(after) = rest.strip_prefix(',') {
+            rest = after;
+        } else if !rest.starts_with(']') {
+            return Err("expected `,` or `]` in the array".to_string());
+        }
+    }
+}
+
+/// Target directories that are never deleted, whatever flags are given, from the `protected`
+/// list in the config file. There's deliberately no flag to lift it, only editing the file.
+#[derive(Clone, Debug, Default)]
+pub struct Protected {
+    patterns: PatternSet,
+}
+
+impl Protected {
+    /// Reads the top level `protected = ["/work/monorepo", "ml/**"]` from the contents of a
+    /// config file. Each entry is a path or [`PatternSet`] glob, matched against targets as
+    /// found by the scan, so with symlinks resolved.
+    ///
+    /// This is only as much TOML as the list needs, other keys and tables are skipped.
+    pub fn parse(contents: &str, ignore_case: bool) -> Result<Self, String> {
+        let mut table = false;
+        let mut offset = 0;
+        for line in contents.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') {
+                table = true;
+                continue;
+            }
+            let Some((key, _)) = trimmed.split_once('=') else {
+                continue;
+            };
+            if table || key.trim() != "protected" {
+                continue;
+            }
+            // The value can carry on over the lines after
+            let value = &contents[start..];
+            let value = &value[value.find('=').map_or(0, |equals| equals + 1)..];
+            let patterns = parse_array(value.trim_start())
+                .and_then(|globs| PatternSet::new(&globs, ignore_case))
+                .map_err(|e| format!("protected: {e}"))?;
+            return Ok(Self { patterns });
+        }
+        Ok(Self::default())
+    }
+
+    /// Reads the list from the config file at `file`, which not existing means nothing is
+    /// protected.
+    pub fn load(file: &Path, ignore_case: bool) -> std::io::Result<Self> {
+        let contents = match fs::read_to_string(file) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+        Self::parse(&contents, ignore_case)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Returns true if `info` is protected, by the scan having marked it so, an entry matching
+    /// or a marker file.
+    pub fn covers(&self, info: &TargetDirInfo) -> bool {
+        info.protected || self.protects(&info.path) || has_protect_marker(info)
+    }
+
+    /// Returns true if `path`, or a directory it's in, matches an entry.
+    pub fn protects(&self, path: &Path) -> bool {
+        self.patterns.matches_within(path)
+    }
+
+    /// The entry protecting `path`, if one does.
+    pub fn protected_by(&self, path: &Path) -> Option<&str> {
+        self.patterns.match_within(path)
+    }
+}
+
+/// Marks which of `targets` are protected, so listings can say. This is where markers are
+/// looked for, once per target.
+pub fn mark_protected(targets: &mut [TargetDirInfo], protected: &Protected) {
+    for info in targets {
+        info.protected = protected.covers(info);
+    }
+}
+
+/// Returns an error naming the first of `selected` that's protected, the last check before
+/// anything is removed.
+pub fn refuse_protected(selected: &[&TargetDirInfo], protected: &Protected) -> std::io::Result<()> {
+    match selected.iter().find(|info| protected.covers(info)) {
+        Some(info) => Err(refused(&info.path, "it is protected")),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_protected() {
+        let contents = r#"
+# Never these
+protected = [
+    "/work/monorepo", # two hour build
+    'ml-*/**',
+]
+other = "ignored"
+
+[elsewhere]
+protected = ["/not/read"]
+"#;
+        let protected = Protected::parse(contents, false).unwrap();
+        assert!(protected.protects(Path::new("/work/monorepo/target")));
+        assert!(protected.protects(Path::new("/work/monorepo")));
+        assert!(!protected.protects(Path::new("/work/monorepo-old/target")));
+        assert!(protected.protects(Path::new("/home/me/ml-vision/target")));
+        assert!(!protected.protects(Path::new("/home/me/ml-vision")));
+        assert!(!protected.protects(Path::new("/not/read/target")));
+
+        assert!(
+            !Protected::parse("", false)
+                .unwrap()
+                .protects(Path::new("/a/target"))
+        );
+        assert_eq!(
+            Protected::parse("protected = \"/one\"", false).unwrap_err(),
+            "protected: expected an array of strings"
+        );
+        assert_eq!(
+            Protected::parse("protected = [\"/one\" \"/two\"]", false).unwrap_err(),
+            "protected: expected `,` or `]` in the array"
+        );
+    }
+
+    #[test]
+    fn test_every_deletion_path_refuses_protected() {
+        use crate::{
+            Config, clean_project_with, delete_selected, deletion_round, output::Output,
+            plan::execute_plan,
+        };
+        use std::{fs, sync::Mutex};
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("monorepo").join("target");
+        fs::create_dir_all(&target).unwrap();
+        let mut info = TargetDirInfo::new(target.clone(), 10);
+        info.file_count = Some(1);
+        let protected = Protected::parse(
+            &format!(
+                "protected = ['{}']",
+                temp_dir.path().join("monorepo").display()
+            ),
+            false,
+        )
+        .unwrap();
+        let removed = Mutex::new(Vec::new());
+        let remover = |path: &Path| {
+            removed.lock().unwrap().push(path.to_path_buf());
+            Ok(())
+        };
+        let config = Config {
+            delete_jobs: 1,
+            delete_tracked: true,
+            protected,
+            ..Config::default()
+        };
+        let forced = Config {
+            force: true,
+            ..config.clone()
+        };
+
+        // Forced, where it's left out with a message
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        deletion_round(
+            std::slice::from_ref(&info),
+            &forced,
+            &remover,
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains("Leaving 1 protected directories alone")
+        );
+
+        // Interactively, where it's never offered nor deleted as small
+        for config in [
+            Config {
+                review: true,
+                ..config.clone()
+            },
+            Config {
+                auto_under_files: Some(10),
+                ..config.clone()
+            },
+        ] {
+            let (mut out, mut err) = (Vec::new(), Vec::new());
+            deletion_round(
+                std::slice::from_ref(&info),
+                &config,
+                &remover,
+                &mut Output::new(&mut out, &mut err, true),
+            )
+            .unwrap();
+        }
+
+        // A plan naming it is refused as a whole
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let error = execute_plan(
+            std::slice::from_ref(&info),
+            &config,
+            &remover,
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("is protected"), "{error}");
+
+        // Cleaning the one project
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        clean_project_with(
+            &temp_dir.path().join("monorepo"),
+            &forced,
+            |_| None,
+            &remover,
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap();
+
+        // And the last check, should anything get past the others
+        let mut out = Vec::new();
+        assert!(delete_selected(&[&info], &forced, &remover, &mut out).is_err());
+
+        assert!(removed.lock().unwrap().is_empty());
+        assert!(target.is_dir());
+    }
+
+    #[test]
+    fn test_protect_markers() {
+        use crate::{
+            Config, handle_deletion_with,
+            output::{Output, TableOptions, render_table},
+            report::NoopReporter,
+            scan,
+        };
+        use std::{fs, sync::Mutex};
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        for name in ["marked", "kept", "swept"] {
+            let target = temp_dir.path().join(name).join("target");
+            fs::create_dir_all(&target).unwrap();
+            fs::write(target.join("out.bin"), vec![0u8; 1000]).unwrap();
+        }
+        fs::write(temp_dir.path().join("marked").join(PROTECT_MARKER), "").unwrap();
+        fs::write(
+            temp_dir
+                .path()
+                .join("kept")
+                .join("target")
+                .join(TARGET_KEEP_FILE),
+            "",
+        )
+        .unwrap();
+
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            cargo_cache_dirs: Vec::new(),
+            force: true,
+            delete_jobs: 1,
+            delete_tracked: true,
+            // Gets past keep markers, not these
+            ignore_keep_markers: true,
+            ..Config::default()
+        };
+        let result = scan(&config, &NoopReporter).unwrap();
+        let mut protected: Vec<_> = result
+            .targets
+            .iter()
+            .filter(|info| info.protected)
+            .map(|info| info.path.clone())
+            .collect();
+        protected.sort();
+        assert_eq!(
+            protected,
+            [
+                temp_dir.path().join("kept").join("target"),
+                temp_dir.path().join("marked").join("target"),
+            ]
+        );
+
+        let mut out = Vec::new();
+        render_table(&mut out, &result.targets, &TableOptions::default()).unwrap();
+        let table = String::from_utf8(out).unwrap();
+        assert!(
+            table.contains(&format!(
+                "{}  PROTECTED",
+                temp_dir.path().join("marked").join("target").display()
+            )),
+            "{table}"
+        );
+        assert!(
+            table.contains("      2 kB  Protected, in 2 directories, which won't be deleted"),
+            "{table}"
+        );
+
+        let removed = Mutex::new(Vec::new());
+        let remover = |path: &Path| {
+            removed.lock().unwrap().push(path.to_path_buf());
+            Ok(())
+        };
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        handle_deletion_with(
+            &result.targets,
+            &config,
+            &remover,
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap();
+        assert_eq!(
+            removed.into_inner().unwrap(),
+            [temp_dir.path().join("swept").join("target")]
+        );
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains("Leaving 2 protected directories alone")
+        );
+
+        // Found again without the scan's marking, the markers are still seen
+        let unmarked = TargetDirInfo::new(temp_dir.path().join("kept").join("target"), 10);
+        assert!(Protected::default().covers(&unmarked));
+    }
+
+    #[test]
+    fn test_config_file() {
+        let env = |name: &str| (name == "XDG_CONFIG_HOME").then(|| "/config".to_string());
+        assert_eq!(
+            config_file(env),
+            Some(PathBuf::from("/config/clean-big-targets/config.toml"))
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! `--move-to`, which moves selected targets somewhere else rather than deleting them.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Held while a name in the destination is picked and taken, so parallel moves don't pick the
+/// same one.
+static PICKING: Mutex<()> = Mutex::new(());
+
+/// The name a target is moved to, its project's name and its own such as `app-target`, so
+/// targets from different projects don't collide.
+fn moved_name(path: &Path) -> String {
+    let name = path.file_name().map(|name| name.to_string_lossy());
+    let project = path
+        .parent()
+        .and_then(Path::file_name)
+        .map(|name| name.to_string_lossy());
+    match (project, name) {
+        (Some(project), Some(name)) => format!("{project}-{name}"),
+        (None, Some(name)) => name.into_owned(),
+        _ => "target".to_string(),
+    }
+}
+
+/// The first of `name`, `name-2`, `name-3` and so on that isn't in `dir` yet.
+fn unique_destination(dir: &Path, name: &str) -> PathBuf {
+    let mut candidate = dir.join(name);
+    let mut n = 2;
+    while candidate.symlink_metadata().is_ok() {
+        candidate = dir.join(format!("{name}-{n}"));
+        n += 1;
+    }
+    candidate
+}
+
+/// Copies everything in `from` into the existing directory `to`, recreating symlinks rather
+/// than following them.
+fn copy_contents(from: &Path, to: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let source = entry.path();
+        let dest = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            fs::create_dir(&dest)?;
+            copy_contents(&source, &dest)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(&source, &dest)?;
+        } else {
+            fs::copy(&source, &dest)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(source: &Path, dest: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, dest)
+}
+
+#[cfg(windows)]
+fn copy_symlink(source: &Path, dest: &Path) -> std::io::Result<()> {
+    let link = fs::read_link(source)?;
+    if fs::metadata(source).is_ok_and(|metadata| metadata.is_dir()) {
+        std::os::windows::fs::symlink_dir(link, dest)
+    } else {
+        std::os::windows::fs::symlink_file(link, dest)
+    }
+}
+
+/// Moves the directory at `from` to `to`, which mustn't exist yet, by copying and then
+/// removing the original. What `rename` can't do across filesystems.
+///
+/// A copy that fails partway is removed again, leaving the original as it was.
+pub fn copy_then_remove(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir(to)?;
+    copy_into_then_remove(from, to)
+}
+
+/// Like [`copy_then_remove`], into `to` which has already been created empty.
+fn copy_into_then_remove(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Err(e) = copy_contents(from, to) {
+        // The original's still whole, so the half copy is only in the way
+        let _ = fs::remove_dir_all(to);
+        return Err(e);
+    }
+    fs::remove_dir_all(from)
+}
+
+/// Moves the target at `path` into the directory `dir`, creating it if need be, and returns
+/// where it ended up.
+///
+/// Renaming is tried first, falling back to [`copy_then_remove`] when `dir` is on another
+/// filesystem.
+pub fn move_into(path: &Path, dir: &Path) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let name = moved_name(path);
+    let picking = PICKING.lock().unwrap_or_else(|e| e.into_inner());
+    let dest = unique_destination(dir, &name);
+    match fs::rename(path, &dest) {
+        Ok(()) => Ok(dest),
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            // Taking the name before letting go, as the copy could take a while
+            fs::create_dir(&dest)?;
+            drop(picking);
+            copy_into_then_remove(path, &dest)?;
+            Ok(dest)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, TargetDirInfo, handle_deletion_with, output::Output};
+    use tempfile::TempDir;
+
+    fn fixture(project: &Path) -> PathBuf {
+        let target = project.join("target");
+        fs::create_dir_all(target.join("debug")).unwrap();
+        fs::write(target.join("debug").join("app"), vec![1u8; 1000]).unwrap();
+        target
+    }
+
+    #[test]
+    fn test_move_to_destination() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = fixture(&temp_dir.path().join("projects").join("app"));
+        let dest = temp_dir.path().join("scratch");
+        let config = Config {
+            force: true,
+            delete_jobs: 1,
+            delete_tracked: true,
+            move_to: Some(dest.clone()),
+            ..Config::default()
+        };
+        let remover = |path: &Path| move_into(path, &dest).map(|_| ());
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        handle_deletion_with(
+            &[TargetDirInfo::new(target.clone(), 1000)],
+            &config,
+            &remover,
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap();
+
+        assert!(!target.exists());
+        assert_eq!(
+            fs::read(dest.join("app-target").join("debug").join("app")).unwrap(),
+            vec![1u8; 1000]
+        );
+        assert!(String::from_utf8(out).unwrap().contains(&format!(
+            "Moved '{}' into {} successfully, (1 kB)",
+            target.display(),
+            dest.display()
+        )));
+
+        // Another project's target of the same name doesn't overwrite it
+        let other = fixture(&temp_dir.path().join("elsewhere").join("app"));
+        assert_eq!(move_into(&other, &dest).unwrap(), dest.join("app-target-2"));
+        assert!(dest.join("app-target").join("debug").is_dir());
+    }
+
+    #[test]
+    fn test_copy_then_remove() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = fixture(&temp_dir.path().join("app"));
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("debug/app", target.join("latest")).unwrap();
+        let dest = temp_dir.path().join("moved");
+
+        copy_then_remove(&target, &dest).unwrap();
+        assert!(!target.exists());
+        assert_eq!(
+            fs::read(dest.join("debug").join("app")).unwrap().len(),
+            1000
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            fs::read_link(dest.join("latest")).unwrap(),
+            PathBuf::from("debug/app")
+        );
+
+        // Copying onto something already there leaves the original alone
+        let target = fixture(&temp_dir.path().join("again"));
+        assert!(copy_then_remove(&target, &dest).is_err());
+        assert!(target.join("debug").join("app").is_file());
+        assert!(dest.join("debug").join("app").is_file());
+    }
+}
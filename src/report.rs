@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+/// Why a directory was skipped during discovery.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PruneReason {
+    NotADirectory,
+    NoTargetDir,
+    /// Inside the cargo registry, git checkouts or similar cache.
+    CargoCache,
+    /// A symlink leading outside the scan root.
+    OutsideRoot,
+    /// Already scanned through another path, such as a symlink.
+    AlreadyVisited,
+    /// The same directory as this one, reached another way such as through a bind mount.
+    AliasOf(PathBuf),
+    /// Files inside were read more recently than `--unused-for` allows.
+    RecentlyAccessed,
+    /// A file inside was modified after `--older-than-file`'s reference file.
+    NewerThanReference,
+    /// Smaller than `--min-size`.
+    BelowMinSize,
+    /// Bigger than `--max-size`.
+    AboveMaxSize,
+    /// Fewer files than `--min-files`.
+    BelowMinFiles,
+    /// More files than `--max-files`.
+    AboveMaxFiles,
+    /// Not known to be built by a rustc older than `--built-before-rustc`.
+    NotBuiltBeforeRustc,
+    /// `--stale-toolchains-only` was given and the toolchain that built it is still installed
+    /// (or isn't known).
+    ToolchainInstalled,
+    /// On a different filesystem from the one `--mount` asked for.
+    OtherMount,
+    /// `--hide-kept` was given and the project has a `.clean-big-targets-keep` file.
+    KeepMarker,
+    /// Its name starts with a dot, and `--hidden` wasn't given.
+    Hidden,
+    /// `--git-only` was given and the project isn't in a git work tree.
+    NotInGitRepo,
+}
+
+/// Things that happen during a scan which a caller may want to know about.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    TargetFound(PathBuf),
+    DirPruned(PathBuf, PruneReason),
+    SizeComputed(PathBuf, u64),
+    /// The size was taken from `--resume-scan`'s checkpoint instead of being worked out again.
+    SizeResumed(PathBuf, u64),
+    /// A size couldn't be saved to the checkpoint, so it'll be worked out again if resumed.
+    CheckpointFailed(PathBuf, String),
+    /// The `Cargo.toml` next to a target couldn't be read or parsed for `--details`.
+    ManifestUnreadable(PathBuf, String),
+    /// A target's `.rustc_info.json` couldn't be read or parsed.
+    RustcInfoUnreadable(PathBuf, String),
+    /// The directory's modification time moved while it was being sized, so the size may
+    /// be unreliable.
+    ChangedWhileSizing(PathBuf),
+}
+
+/// Receives [`Event`]s from the scanning functions.
+pub trait Reporter: Sync {
+    fn report(&self, event: Event);
+}
+
+/// Ignores all events.
+pub struct NoopReporter;
+
+impl Reporter for NoopReporter {
+    fn report(&self, _event: Event) {}
+}
+
+/// Prints events to stderr, used by the CLI in `--debug` mode.
+pub struct StderrReporter;
+
+impl Reporter for StderrReporter {
+    fn report(&self, event: Event) {
+        match event {
+            Event::TargetFound(path) => eprintln!("Found target directory: {:?}", path),
+            Event::DirPruned(path, reason) => eprintln!("Skipping {:?}: {:?}", path, reason),
+            Event::SizeComputed(path, size) => eprintln!("Size of {:?}: {} bytes", path, size),
+            Event::SizeResumed(path, size) => {
+                eprintln!("Size of {:?} from the checkpoint: {} bytes", path, size)
+            }
+            Event::CheckpointFailed(path, reason) => {
+                eprintln!("Couldn't checkpoint the size of {:?}: {}", path, reason)
+            }
+            Event::ManifestUnreadable(path, reason) => {
+                eprintln!("Couldn't read the manifest for {:?}: {}", path, reason)
+            }
+            Event::RustcInfoUnreadable(path, reason) => {
+                eprintln!("Couldn't tell which rustc built {:?}: {}", path, reason)
+            }
+            Event::ChangedWhileSizing(path) => eprintln!(
+                "Warning: {:?} changed while it was being sized, it may be mid-build so its size is unreliable",
+                path
+            ),
+        }
+    }
+}
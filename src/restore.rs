@@ -0,0 +1,337 @@
+//! The `restore-last` subcommand, which undoes the last run that moved targets away with
+//! `--move-to`.
+//!
+//! Each move is appended to the [`MoveJournal`] as it happens, tagged with the run it was part
+//! of, so restoring puts back everything the most recent run moved even if it was cut short.
+
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use crate::{
+    meta::format_utc,
+    output::escape_porcelain_path,
+    paths::{Dirs, create_parent},
+    relocate::copy_then_remove,
+};
+
+/// Where moves are recorded by default, `moves` in the state directory.
+pub fn default_journal_file(dirs: &Dirs) -> Option<PathBuf> {
+    Some(dirs.state.as_ref()?.join("moves"))
+}
+
+/// A target moved by `--move-to`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MoveRecord {
+    /// Which run moved it, the same for every target that run moved.
+    pub run: String,
+    /// Where it was.
+    pub from: PathBuf,
+    /// Where it was moved to.
+    pub to: PathBuf,
+}
+
+impl MoveRecord {
+    /// The line it's kept as in the journal, tab separated like the `--log-file`. The paths are
+    /// escaped as `--porcelain` does, so a tab or newline in one can't split the line up.
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}",
+            self.run,
+            escape_porcelain_path(&self.from),
+            escape_porcelain_path(&self.to)
+        )
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(3, '\t');
+        Some(Self {
+            run: fields.next()?.to_string(),
+            from: unescape_path(fields.next()?),
+            to: unescape_path(fields.next()?),
+        })
+    }
+}
+
+/// Undoes [`escape_porcelain_path`]. Away from Unix a path that wasn't valid Unicode can't be
+/// put back exactly, and gets replacement characters.
+fn unescape_path(field: &str) -> PathBuf {
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = || {
+            let digits = std::str::from_utf8(bytes.get(i + 2..i + 4)?).ok()?;
+            u8::from_str_radix(digits, 16).ok()
+        };
+        let (byte, len) = match (bytes[i], bytes.get(i + 1)) {
+            (b'\\', Some(b'\\')) => (b'\\', 2),
+            (b'\\', Some(b't')) => (b'\t', 2),
+            (b'\\', Some(b'n')) => (b'\n', 2),
+            (b'\\', Some(b'r')) => (b'\r', 2),
+            (b'\\', Some(b'x')) => hex().map_or((b'\\', 1), |byte| (byte, 4)),
+            (byte, _) => (byte, 1),
+        };
+        unescaped.push(byte);
+        i += len;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        PathBuf::from(std::ffi::OsString::from_vec(unescaped))
+    }
+    #[cfg(not(unix))]
+    PathBuf::from(String::from_utf8_lossy(&unescaped).into_owned())
+}
+
+/// The journal each move is appended to as it happens, opened when the first one is.
+pub struct MoveJournal {
+    path: PathBuf,
+    run: String,
+    file: Mutex<Option<File>>,
+}
+
+impl MoveJournal {
+    /// A journal at `path` for a run starting now, told apart from others by the time and the
+    /// process.
+    pub fn new(path: &Path) -> Self {
+        Self::for_run(
+            path,
+            format!("{}-{}", format_utc(SystemTime::now()), std::process::id()),
+        )
+    }
+
+    fn for_run(path: &Path, run: String) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            run,
+            file: Mutex::default(),
+        }
+    }
+
+    /// Records that the target at `from` was moved to `to`.
+    pub fn record(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if file.is_none() {
+            create_parent(&self.path)?;
+            *file = Some(
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            );
+        }
+        let record = MoveRecord {
+            run: self.run.clone(),
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        };
+        match file.as_mut() {
+            Some(file) => writeln!(file, "{}", record.to_line()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Moves `record`'s target back to where it was, as long as nothing has taken its place.
+fn move_back(record: &MoveRecord) -> std::io::Result<()> {
+    if record.from.symlink_metadata().is_ok() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            "something is there again, perhaps a new build",
+        ));
+    }
+    create_parent(&record.from)?;
+    match fs::rename(&record.to, &record.from) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            copy_then_remove(&record.to, &record.from)
+        }
+        moved => moved,
+    }
+}
+
+/// Moves back every target the most recent run in the journal at `path` moved, returning how
+/// each went. Those restored are dropped from the journal, so the next call undoes the run
+/// before; any that couldn't be are kept to try again.
+pub fn restore_last(path: &Path) -> std::io::Result<Vec<(MoveRecord, std::io::Result<()>)>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let records: Vec<MoveRecord> = contents.lines().filter_map(MoveRecord::parse).collect();
+    let Some(last) = records.last().map(|record| record.run.clone()) else {
+        return Ok(Vec::new());
+    };
+    let (restoring, earlier): (Vec<MoveRecord>, Vec<MoveRecord>) =
+        records.into_iter().partition(|record| record.run == last);
+    // Most recent first, in case one target was moved into another's place
+    let restored: Vec<_> = restoring
+        .into_iter()
+        .rev()
+        .map(|record| {
+            let restored = move_back(&record);
+            (record, restored)
+        })
+        .collect();
+
+    let mut remaining = String::new();
+    let failed = restored
+        .iter()
+        .rev()
+        .filter(|(_, restored)| restored.is_err())
+        .map(|(record, _)| record);
+    for record in earlier.iter().chain(failed) {
+        remaining.push_str(&record.to_line());
+        remaining.push('\n');
+    }
+    // Write then rename, so an interrupted save can't lose the earlier runs
+    let partial = path.with_extension("partial");
+    fs::write(&partial, remaining)?;
+    fs::rename(&partial, path)?;
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, TargetDirInfo, handle_deletion_with, output::Output, relocate::move_into};
+    use tempfile::TempDir;
+
+    /// Moves `targets` into `trash` as a run of its own, recording them in `journal`.
+    fn move_away(targets: &[PathBuf], trash: &Path, journal: &MoveJournal) {
+        let config = Config {
+            force: true,
+            delete_jobs: 1,
+            delete_tracked: true,
+            move_to: Some(trash.to_path_buf()),
+            ..Config::default()
+        };
+        let remover = |path: &Path| {
+            let dest = move_into(path, trash)?;
+            journal.record(path, &dest)
+        };
+        let targets: Vec<_> = targets
+            .iter()
+            .map(|target| TargetDirInfo::new(target.clone(), 4))
+            .collect();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        handle_deletion_with(
+            &targets,
+            &config,
+            &remover,
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_record_line_escapes_paths() {
+        let record = MoveRecord {
+            run: "run".to_string(),
+            from: PathBuf::from("/code/tab\there/new\nline\\target"),
+            to: PathBuf::from("/trash/tab\there-target"),
+        };
+        assert_eq!(
+            record.to_line(),
+            "run\t/code/tab\\there/new\\nline\\\\target\t/trash/tab\\there-target"
+        );
+        assert_eq!(MoveRecord::parse(&record.to_line()), Some(record));
+        // A backslash that doesn't start an escape is kept
+        assert_eq!(unescape_path("/odd\\x/\\q\\"), Path::new("/odd\\x/\\q\\"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unescape_path_invalid_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+        let path = Path::new(std::ffi::OsStr::from_bytes(b"/projects/bad\xff/target"));
+        assert_eq!(unescape_path(&escape_porcelain_path(path)), path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_last_unusual_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let trash = temp_dir.path().join("trash");
+        let journal_file = temp_dir.path().join("moves");
+        let targets: Vec<PathBuf> = ["tab\there", "new\nline", "back\\slash"]
+            .iter()
+            .map(|project| temp_dir.path().join(project).join("target"))
+            .collect();
+        for target in &targets {
+            fs::create_dir_all(target).unwrap();
+        }
+        move_away(&targets, &trash, &MoveJournal::new(&journal_file));
+        assert!(targets.iter().all(|target| !target.exists()));
+        assert_eq!(
+            fs::read_to_string(&journal_file).unwrap().lines().count(),
+            3
+        );
+
+        let restored = restore_last(&journal_file).unwrap();
+        assert_eq!(restored.len(), 3);
+        assert!(restored.iter().all(|(_, restored)| restored.is_ok()));
+        assert!(targets.iter().all(|target| target.is_dir()));
+    }
+
+    #[test]
+    fn test_restore_last_run() {
+        let temp_dir = TempDir::new().unwrap();
+        let trash = temp_dir.path().join("trash");
+        let journal_file = temp_dir.path().join("state").join("moves");
+        let target = |project: &str| temp_dir.path().join(project).join("target");
+        for project in ["old", "app", "lib"] {
+            fs::create_dir_all(target(project).join("debug")).unwrap();
+            fs::write(target(project).join("debug").join(project), project).unwrap();
+        }
+        move_away(
+            &[target("old")],
+            &trash,
+            &MoveJournal::for_run(&journal_file, "first".to_string()),
+        );
+        move_away(
+            &[target("app"), target("lib")],
+            &trash,
+            &MoveJournal::for_run(&journal_file, "second".to_string()),
+        );
+        assert!(!target("app").exists());
+        assert!(trash.join("app-target").join("debug").join("app").is_file());
+
+        let restored = restore_last(&journal_file).unwrap();
+        let from: Vec<_> = restored.iter().map(|(record, _)| &record.from).collect();
+        assert_eq!(from, [&target("lib"), &target("app")]);
+        assert!(restored.iter().all(|(_, restored)| restored.is_ok()));
+        for project in ["app", "lib"] {
+            assert_eq!(
+                fs::read_to_string(target(project).join("debug").join(project)).unwrap(),
+                project
+            );
+        }
+        assert!(!trash.join("app-target").exists());
+        assert!(!target("old").exists());
+
+        // Then the run before, unless something has taken its place
+        fs::create_dir_all(target("old")).unwrap();
+        let restored = restore_last(&journal_file).unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(
+            restored[0].1.as_ref().unwrap_err().kind(),
+            std::io::ErrorKind::AlreadyExists
+        );
+        fs::remove_dir(target("old")).unwrap();
+        assert!(restore_last(&journal_file).unwrap()[0].1.is_ok());
+        assert!(target("old").join("debug").join("old").is_file());
+
+        assert!(restore_last(&journal_file).unwrap().is_empty());
+        assert!(
+            restore_last(&temp_dir.path().join("missing"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use std::{
+    cmp::Ordering,
+    fmt, fs,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+/// A rustc release such as `1.75.0` or `1.77.0-nightly`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RustcVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// The channel or pre-release part after a `-`, such as `nightly` or `beta.3`.
+    pub pre: Option<String>,
+}
+
+impl RustcVersion {
+    /// Parses `1.75.0`, `1.75` or `1.77.0-nightly`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        let (numbers, pre) = match value.split_once('-') {
+            Some((numbers, pre)) => (numbers, Some(pre.to_string())),
+            None => (value, None),
+        };
+        let mut parts = numbers.split('.').map(|part| {
+            part.parse::<u64>()
+                .map_err(|_| format!("invalid rustc version '{value}'"))
+        });
+        let major = parts
+            .next()
+            .ok_or_else(|| format!("invalid rustc version '{value}'"))??;
+        let minor = parts.next().transpose()?.unwrap_or(0);
+        let patch = parts.next().transpose()?.unwrap_or(0);
+        if parts.next().is_some() {
+            return Err(format!("invalid rustc version '{value}'"));
+        }
+        Ok(Self {
+            major,
+            minor,
+            patch,
+            pre,
+        })
+    }
+}
+
+impl Ord for RustcVersion {
+    /// Pre-releases come before the release they lead up to, so `1.77.0-nightly` < `1.77.0`.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => a.cmp(b),
+            })
+    }
+}
+
+impl PartialOrd for RustcVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for RustcVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre) = &self.pre {
+            write!(f, "-{pre}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The compiler that built a target directory, from its `.rustc_info.json`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RustcInfo {
+    pub version: RustcVersion,
+    pub commit_hash: Option<String>,
+}
+
+/// Finds `key: value` in the `rustc -vV` output cargo keeps in `.rustc_info.json`.
+///
+/// That output is stored as a JSON string, so lines end in a literal `\n`.
+fn verbose_field<'a>(contents: &'a str, key: &str) -> Option<&'a str> {
+    let start = contents.find(&format!("\\n{key}: "))? + key.len() + 4;
+    let rest = &contents[start..];
+    let end = rest.find(['\\', '"']).unwrap_or(rest.len());
+    Some(rest[..end].trim())
+}
+
+/// Pulls the rustc release and commit out of the contents of a `.rustc_info.json`.
+///
+/// Errors if it doesn't look like the file cargo writes or holds no `rustc -vV` output.
+pub fn parse_rustc_info(contents: &str) -> Result<RustcInfo, String> {
+    if !contents.trim_start().starts_with('{') || !contents.contains("\"outputs\"") {
+        return Err("not a .rustc_info.json".to_string());
+    }
+    let release =
+        verbose_field(contents, "release").ok_or_else(|| "no rustc release found".to_string())?;
+    Ok(RustcInfo {
+        version: RustcVersion::parse(release)?,
+        commit_hash: verbose_field(contents, "commit-hash")
+            .filter(|hash| *hash != "unknown")
+            .map(str::to_string),
+    })
+}
+
+/// Reads the compiler that built the target directory at `target`.
+pub fn read_rustc_info(target: &Path) -> Result<RustcInfo, String> {
+    let contents =
+        fs::read_to_string(target.join(".rustc_info.json")).map_err(|e| e.to_string())?;
+    parse_rustc_info(&contents)
+}
+
+/// A toolchain installed by rustup under `$RUSTUP_HOME/toolchains`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstalledToolchain {
+    /// The directory name, such as `stable-x86_64-unknown-linux-gnu`.
+    pub name: String,
+    pub version: RustcVersion,
+    /// The abbreviated commit hash rustup records, such as `59807616e`.
+    pub commit_hash: Option<String>,
+}
+
+impl InstalledToolchain {
+    /// Whether this is the compiler `info` was recorded from, by commit when both are known
+    /// and by version otherwise.
+    pub fn built(&self, info: &RustcInfo) -> bool {
+        match (&self.commit_hash, &info.commit_hash) {
+            (Some(short), Some(full)) => full.starts_with(short.as_str()),
+            _ => self.version == info.version,
+        }
+    }
+}
+
+/// Whether none of the `installed` toolchains built the target `info` was recorded from, in
+/// which case it'll be rebuilt from scratch whatever toolchain is used next.
+pub fn is_stale(info: &RustcInfo, installed: &[InstalledToolchain]) -> bool {
+    !installed.iter().any(|toolchain| toolchain.built(info))
+}
+
+/// Parses the rustc version rustup records for a toolchain, `1.95.0 (59807616e 2026-04-14)`.
+pub fn parse_channel_version(value: &str) -> Result<(RustcVersion, Option<String>), String> {
+    let (version, rest) = value.split_once(' ').unwrap_or((value, ""));
+    let commit_hash = rest
+        .trim_start_matches('(')
+        .split_whitespace()
+        .next()
+        .map(str::to_string);
+    Ok((RustcVersion::parse(version)?, commit_hash))
+}
+
+/// Reads the `[pkg.rustc]` version out of a toolchain's `multirust-channel-manifest.toml`.
+fn manifest_rustc_version(manifest: &Path) -> std::io::Result<Option<String>> {
+    let mut in_rustc = false;
+    for line in BufReader::new(fs::File::open(manifest)?).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_rustc = line == "[pkg.rustc]";
+        } else if in_rustc && let Some(value) = line.strip_prefix("version = ") {
+            return Ok(Some(value.trim_matches('"').to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// Lists the toolchains in `rustup_home`, erroring if it has no `toolchains` directory.
+///
+/// Toolchains rustup didn't install itself, such as ones added with `rustup toolchain link`,
+/// have no record of their version and are left out.
+pub fn installed_toolchains(rustup_home: &Path) -> std::io::Result<Vec<InstalledToolchain>> {
+    let mut toolchains = Vec::new();
+    for entry in fs::read_dir(rustup_home.join("toolchains"))? {
+        let entry = entry?;
+        let manifest = entry
+            .path()
+            .join("lib")
+            .join("rustlib")
+            .join("multirust-channel-manifest.toml");
+        let Ok(Some(value)) = manifest_rustc_version(&manifest) else {
+            continue;
+        };
+        if let Ok((version, commit_hash)) = parse_channel_version(&value) {
+            toolchains.push(InstalledToolchain {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                version,
+                commit_hash,
+            });
+        }
+    }
+    toolchains.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(toolchains)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STABLE: &str = r#"{"rustc_fingerprint":8668999387863862814,"outputs":{"7971740275564407648":{"success":true,"status":"","code":0,"stdout":"___\nlib___.rlib\nlib___.so\n/root/.rustup/toolchains/stable-x86_64-unknown-linux-gnu\noff\npacked\nunpacked\n___\ndebug_assertions\npanic=\"unwind\"\nunix\n","stderr":""},"17747080675513052775":{"success":true,"status":"","code":0,"stdout":"rustc 1.95.0 (59807616e 2026-04-14)\nbinary: rustc\ncommit-hash: 59807616e1fa2540724bfbac14d7976d7e4a3860\ncommit-date: 2026-04-14\nhost: x86_64-unknown-linux-gnu\nrelease: 1.95.0\nLLVM version: 22.1.2\n","stderr":""}},"successes":{}}"#;
+
+    const NIGHTLY: &str = r#"{"rustc_fingerprint":1,"outputs":{"2":{"success":true,"status":"","code":0,"stdout":"rustc 1.77.0-nightly (5518eaa94 2024-01-29)\nbinary: rustc\ncommit-hash: 5518eaa946291f00471af8b254b2a1715f234882\ncommit-date: 2024-01-29\nhost: aarch64-apple-darwin\nrelease: 1.77.0-nightly\nLLVM version: 17.0.6\n","stderr":""}},"successes":{}}"#;
+
+    #[test]
+    fn test_parse_rustc_info() {
+        let info = parse_rustc_info(STABLE).unwrap();
+        assert_eq!(info.version, RustcVersion::parse("1.95.0").unwrap());
+        assert_eq!(
+            info.commit_hash.as_deref(),
+            Some("59807616e1fa2540724bfbac14d7976d7e4a3860")
+        );
+
+        let info = parse_rustc_info(NIGHTLY).unwrap();
+        assert_eq!(info.version.to_string(), "1.77.0-nightly");
+
+        assert!(parse_rustc_info("").is_err());
+        assert!(parse_rustc_info("{\"rustc_fingerprint\":1,\"outputs\":{}}").is_err());
+        assert!(parse_rustc_info("not json at all").is_err());
+    }
+
+    #[test]
+    fn test_rustc_version_ordering() {
+        let version = |value| RustcVersion::parse(value).unwrap();
+        assert!(version("1.75.0") < version("1.76.0"));
+        assert!(version("1.9.0") < version("1.10.0"));
+        assert!(version("1.77.0-nightly") < version("1.77.0"));
+        assert!(version("1.76.0") < version("1.77.0-nightly"));
+        assert!(version("1.77.0-beta.2") < version("1.77.0-beta.3"));
+        assert_eq!(version("1.75"), version("1.75.0"));
+        assert!(RustcVersion::parse("one.two").is_err());
+        assert!(RustcVersion::parse("1.2.3.4").is_err());
+    }
+
+    fn toolchain(name: &str, channel_version: &str) -> InstalledToolchain {
+        let (version, commit_hash) = parse_channel_version(channel_version).unwrap();
+        InstalledToolchain {
+            name: name.to_string(),
+            version,
+            commit_hash,
+        }
+    }
+
+    #[test]
+    fn test_parse_channel_version() {
+        let (version, commit) = parse_channel_version("1.95.0 (59807616e 2026-04-14)").unwrap();
+        assert_eq!(version.to_string(), "1.95.0");
+        assert_eq!(commit.as_deref(), Some("59807616e"));
+        let (version, commit) = parse_channel_version("1.97.0-nightly").unwrap();
+        assert_eq!(version.to_string(), "1.97.0-nightly");
+        assert_eq!(commit, None);
+        assert!(parse_channel_version("nonsense (abc)").is_err());
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let stable = parse_rustc_info(STABLE).unwrap();
+        let nightly = parse_rustc_info(NIGHTLY).unwrap();
+        let installed = [
+            toolchain(
+                "stable-x86_64-unknown-linux-gnu",
+                "1.95.0 (59807616e 2026-04-14)",
+            ),
+            toolchain(
+                "nightly-x86_64-unknown-linux-gnu",
+                "1.97.0-nightly (e50aa6fba 2026-05-19)",
+            ),
+        ];
+        assert!(!is_stale(&stable, &installed));
+        // Same nightly version number, but a newer nightly has replaced it
+        assert!(is_stale(&nightly, &installed));
+        assert!(is_stale(&stable, &[]));
+
+        // Without a recorded commit, the version has to match
+        let no_commit = RustcInfo {
+            commit_hash: None,
+            ..stable.clone()
+        };
+        assert!(!is_stale(&no_commit, &installed));
+        let older = RustcInfo {
+            version: RustcVersion::parse("1.80.0").unwrap(),
+            commit_hash: None,
+        };
+        assert!(is_stale(&older, &installed));
+    }
+
+    #[test]
+    fn test_installed_toolchains() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        assert!(installed_toolchains(temp_dir.path()).is_err());
+
+        let rustlib = temp_dir
+            .path()
+            .join("toolchains")
+            .join("stable-x86_64-unknown-linux-gnu")
+            .join("lib")
+            .join("rustlib");
+        fs::create_dir_all(&rustlib).unwrap();
+        fs::write(
+            rustlib.join("multirust-channel-manifest.toml"),
+            "date = \"2026-04-14\"\n[pkg.cargo]\nversion = \"1.95.0 (f2d3ce0bd 2026-03-21)\"\n\
+             [pkg.rustc]\nversion = \"1.95.0 (59807616e 2026-04-14)\"\n",
+        )
+        .unwrap();
+        fs::create_dir_all(temp_dir.path().join("toolchains").join("linked")).unwrap();
+
+        assert_eq!(
+            installed_toolchains(temp_dir.path()).unwrap(),
+            [toolchain(
+                "stable-x86_64-unknown-linux-gnu",
+                "1.95.0 (59807616e 2026-04-14)"
+            )]
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use humanize_bytes::humanize_bytes_decimal;
+
+use crate::{
+    Remover, RoundEnd, TargetDirInfo,
+    config::Config,
+    deletion_round,
+    output::{Output, TableOptions, render_table},
+    refused_path,
+};
+
+/// Runs `--loop`: deletes from `targets`, then calls `rescan` for the next round's, until the
+/// user quits or there's nothing left. Returns the bytes freed over the whole session.
+///
+/// A directory that fails to delete, or that a round refused to, is left out of the rounds
+/// after, so it isn't offered again.
+/// `free_space` is asked about each root for the summary before each prompt.
+pub fn run_loop(
+    mut targets: Vec<TargetDirInfo>,
+    config: &Config,
+    rescan: &mut dyn FnMut() -> std::io::Result<Vec<TargetDirInfo>>,
+    free_space: &dyn Fn(&Path) -> std::io::Result<u64>,
+    remover: &Remover,
+    output: &mut Output,
+) -> std::io::Result<u64> {
+    let mut failed: HashSet<PathBuf> = HashSet::new();
+    let mut session_freed = 0;
+    loop {
+        targets.retain(|info| !failed.contains(&info.path));
+        if targets.is_empty() {
+            writeln!(output.out, "Nothing left to delete")?;
+            break;
+        }
+        let options = TableOptions {
+            order: config.sort,
+            ..TableOptions::default()
+        };
+        render_table(output.out, &targets, &options)?;
+        for root in &config.roots {
+            if let Ok(available) = free_space(root) {
+                writeln!(
+                    output.out,
+                    "{} free on {}",
+                    humanize_bytes_decimal!(available),
+                    root.display()
+                )?;
+            }
+        }
+
+        let sizes: HashMap<&Path, u64> = targets
+            .iter()
+            .map(|info| (info.path.as_path(), info.size))
+            .collect();
+        let freed = AtomicU64::new(0);
+        let round_failed = Mutex::new(Vec::new());
+        let counting = |path: &Path| {
+            let removed = remover(path);
+            match &removed {
+                Ok(()) => {
+                    // Subdirectories removed for `--keep-newest` aren't known to be any size
+                    let size = sizes.get(path).copied().unwrap_or(0);
+                    freed.fetch_add(size, Ordering::Relaxed);
+                }
+                Err(_) => round_failed
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(path.to_path_buf()),
+            }
+            removed
+        };
+        let round = deletion_round(&targets, config, &counting, output);
+        let round_failed = round_failed.into_inner().unwrap_or_else(|e| e.into_inner());
+        // The session carries on without the directory the round stopped at
+        let end = match round {
+            Ok(end) => end,
+            // A failed removal has been reported already
+            Err(_) if !round_failed.is_empty() => RoundEnd::Continue,
+            Err(e) => {
+                writeln!(output.err, "Error during deletion: {}", e)?;
+                failed.extend(refused_path(&e).map(Path::to_path_buf));
+                RoundEnd::Continue
+            }
+        };
+        failed.extend(round_failed);
+        let freed = freed.into_inner();
+        session_freed += freed;
+        writeln!(
+            output.out,
+            "Freed {} this round, {} so far",
+            humanize_bytes_decimal!(freed),
+            humanize_bytes_decimal!(session_freed)
+        )?;
+        if end == RoundEnd::Quit {
+            break;
+        }
+        targets = rescan()?;
+    }
+    writeln!(
+        output.out,
+        "Freed {} in total this session",
+        humanize_bytes_decimal!(session_freed)
+    )?;
+    Ok(session_freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TargetDirInfo;
+
+    #[test]
+    fn test_run_loop_totals_rounds_and_skips_failures() {
+        let config = Config {
+            delete_jobs: 1,
+            force: true,
+            delete_tracked: true,
+            roots: vec![PathBuf::from("/nonexistent")],
+            ..Config::default()
+        };
+        let first = vec![
+            TargetDirInfo::new(PathBuf::from("/nonexistent/one/target"), 1000),
+            TargetDirInfo::new(PathBuf::from("/nonexistent/two/target"), 2000),
+        ];
+        let mut rounds = vec![
+            // Deleting stops at the first failure, which isn't offered again after
+            vec![
+                TargetDirInfo::new(PathBuf::from("/nonexistent/stuck/target"), 50),
+                TargetDirInfo::new(PathBuf::from("/nonexistent/three/target"), 500),
+            ],
+            vec![TargetDirInfo::new(
+                PathBuf::from("/nonexistent/stuck/target"),
+                50,
+            )],
+        ]
+        .into_iter();
+        let mut rescan = || Ok(rounds.next().unwrap_or_default());
+        let removed = Mutex::new(Vec::new());
+        let remover = |path: &Path| {
+            if path.ends_with("stuck/target") {
+                return Err(std::io::Error::other("busy"));
+            }
+            removed.lock().unwrap().push(path.to_path_buf());
+            Ok(())
+        };
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let freed = run_loop(
+            first,
+            &config,
+            &mut rescan,
+            &|_| Ok(10_000),
+            &remover,
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap();
+
+        assert_eq!(freed, 3000);
+        assert_eq!(removed.lock().unwrap().len(), 2);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("10 kB free on /nonexistent"), "{out}");
+        assert!(out.contains("Freed 3 kB this round, 3 kB so far"), "{out}");
+        assert!(out.contains("Freed 0 B this round, 3 kB so far"), "{out}");
+        assert!(out.contains("Nothing left to delete"), "{out}");
+        assert!(out.ends_with("Freed 3 kB in total this session\n"), "{out}");
+    }
+
+    #[test]
+    fn test_run_loop_reports_and_skips_refusals() {
+        let config = Config {
+            delete_jobs: 1,
+            force: true,
+            delete_tracked: true,
+            ..Config::default()
+        };
+        let targets = vec![
+            TargetDirInfo::new(PathBuf::from("/nonexistent/app/target"), 1000),
+            TargetDirInfo::new(PathBuf::from("/nonexistent/app/src"), 10),
+        ];
+        // The same again, then nothing left
+        let mut rounds = vec![targets.clone()].into_iter();
+        let mut rescan = || Ok(rounds.next().unwrap_or_default());
+        let removed = Mutex::new(Vec::new());
+        let remover = |path: &Path| {
+            removed.lock().unwrap().push(path.to_path_buf());
+            Ok(())
+        };
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        let freed = run_loop(
+            targets,
+            &config,
+            &mut rescan,
+            &|_| Ok(10_000),
+            &remover,
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap();
+
+        assert_eq!(freed, 1000);
+        assert_eq!(
+            *removed.lock().unwrap(),
+            [PathBuf::from("/nonexistent/app/target")]
+        );
+        let err = String::from_utf8(err).unwrap();
+        assert_eq!(
+            err.matches("refusing to delete '/nonexistent/app/src'")
+                .count(),
+            1,
+            "{err}"
+        );
+    }
+}
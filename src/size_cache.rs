@@ -0,0 +1,173 @@
+//! `--size-cache`, remembering what each directory held between scans in one process, so
+//! `--loop` rescans and long-running embedders only list the directories that changed.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+/// How long after a directory's modification time a listing has to be made to be trusted.
+/// A change within the same tick of a coarse clock wouldn't move the time on.
+const SETTLE: Duration = Duration::from_secs(2);
+
+/// What was directly in a directory when it was listed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DirListing {
+    /// The size of the files, subdirectories not included.
+    pub bytes: u64,
+    pub files: u64,
+    pub newest_accessed: Option<SystemTime>,
+    pub newest_modified: Option<SystemTime>,
+    pub subdirs: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+struct Cached {
+    modified: SystemTime,
+    listed_at: SystemTime,
+    listing: DirListing,
+}
+
+/// Directory listings keyed by path, reused while the directory's modification time stays
+/// the same.
+///
+/// Adding, removing or renaming an entry changes that, which is how builds change their
+/// output. A file rewritten in place doesn't, so its old size would be kept until something
+/// else changes in its directory.
+#[derive(Debug, Default)]
+pub struct SizeCache {
+    dirs: Mutex<HashMap<PathBuf, Cached>>,
+    reused: AtomicU64,
+    listed: AtomicU64,
+}
+
+impl SizeCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The listing of `path` if it was made since the directory last changed at `modified`.
+    pub fn get(&self, path: &Path, modified: SystemTime) -> Option<DirListing> {
+        let dirs = self.dirs.lock().unwrap_or_else(|e| e.into_inner());
+        let cached = dirs
+            .get(path)
+            .filter(|cached| cached.modified == modified)
+            .filter(|cached| {
+                cached
+                    .listed_at
+                    .duration_since(modified)
+                    .is_ok_and(|since| since >= SETTLE)
+            })?;
+        self.reused.fetch_add(1, Ordering::Relaxed);
+        Some(cached.listing.clone())
+    }
+
+    /// Keeps the `listing` of `path`, made at `listed_at` when it was last modified at
+    /// `modified`.
+    pub fn insert(
+        &self,
+        path: &Path,
+        modified: SystemTime,
+        listed_at: SystemTime,
+        listing: DirListing,
+    ) {
+        self.listed.fetch_add(1, Ordering::Relaxed);
+        let mut dirs = self.dirs.lock().unwrap_or_else(|e| e.into_inner());
+        dirs.insert(
+            path.to_path_buf(),
+            Cached {
+                modified,
+                listed_at,
+                listing,
+            },
+        );
+    }
+
+    /// How many directories were reused rather than listed, and how many were listed.
+    pub fn counts(&self) -> (u64, u64) {
+        (
+            self.reused.load(Ordering::Relaxed),
+            self.listed.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{Config, report::NoopReporter, scan};
+    use std::{fs, sync::Arc};
+    use tempfile::TempDir;
+
+    /// Sets the modification time of every directory under `path` back an hour, so listings
+    /// made now are settled.
+    fn settle(path: &Path) {
+        let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::open(path)
+            .unwrap()
+            .set_modified(hour_ago)
+            .unwrap();
+        for entry in fs::read_dir(path).unwrap() {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_dir() {
+                settle(&entry.path());
+            }
+        }
+    }
+
+    #[test]
+    fn test_second_scan_reuses_unchanged() {
+        let temp_dir = TempDir::new().unwrap();
+        for project in ["same", "changed"] {
+            let debug = temp_dir.path().join(project).join("target").join("debug");
+            fs::create_dir_all(debug.join("deps")).unwrap();
+            fs::write(debug.join("app"), vec![0u8; 1000]).unwrap();
+            fs::write(debug.join("deps").join("lib.rlib"), vec![0u8; 500]).unwrap();
+        }
+        settle(temp_dir.path());
+        let cache = Arc::new(SizeCache::new());
+        let config = Config {
+            roots: vec![temp_dir.path().to_path_buf()],
+            cargo_cache_dirs: Vec::new(),
+            size_cache: Some(Arc::clone(&cache)),
+            ..Config::default()
+        };
+        let sizes = || {
+            let result = scan(&config, &NoopReporter).unwrap();
+            let mut sizes: Vec<_> = result
+                .targets
+                .iter()
+                .map(|info| (info.path.clone(), info.size, info.file_count))
+                .collect();
+            sizes.sort();
+            sizes
+        };
+        let target = |project: &str| temp_dir.path().join(project).join("target");
+
+        assert_eq!(
+            sizes(),
+            [
+                (target("changed"), 1500, Some(2)),
+                (target("same"), 1500, Some(2)),
+            ]
+        );
+        // target, debug and deps in each
+        assert_eq!(cache.counts(), (0, 6));
+
+        fs::write(target("changed").join("debug").join("new"), vec![0u8; 250]).unwrap();
+        assert_eq!(
+            sizes(),
+            [
+                (target("changed"), 1750, Some(3)),
+                (target("same"), 1500, Some(2)),
+            ]
+        );
+        // Only the changed `debug` is listed again
+        assert_eq!(cache.counts(), (5, 7));
+    }
+}
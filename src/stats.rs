@@ -0,0 +1,35 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counts the filesystem calls made during a scan, safe to share between threads.
+#[derive(Debug, Default)]
+pub struct IoStats {
+    read_dir: AtomicU64,
+    metadata: AtomicU64,
+}
+
+impl IoStats {
+    pub const fn new() -> Self {
+        Self {
+            read_dir: AtomicU64::new(0),
+            metadata: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of directories listed.
+    pub fn read_dir_calls(&self) -> u64 {
+        self.read_dir.load(Ordering::Relaxed)
+    }
+
+    /// Number of stat-like calls (`metadata`, `is_dir`, `is_file`).
+    pub fn metadata_calls(&self) -> u64 {
+        self.metadata.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn count_read_dir(&self) {
+        self.read_dir.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_metadata(&self) {
+        self.metadata.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,282 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use clap::ValueEnum;
+
+use crate::{Remover, TargetDirInfo};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum StatusLineMode {
+    /// Only when stderr isn't a terminal, such as when a script is capturing it
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl StatusLineMode {
+    /// Returns true if the status line should be written.
+    pub fn enabled(self, stderr_is_terminal: bool) -> bool {
+        match self {
+            Self::Auto => !stderr_is_terminal,
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+/// The one line summary written to stderr at the end of a run for `--status-line`.
+///
+/// Scripts grep for it, so the fields and their order don't change:
+/// `clean-big-targets: found=12 total_bytes=212400000000 deleted=3 freed_bytes=41000000000 failed=0 duration_ms=8421`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatusLine {
+    pub found: u64,
+    pub total_bytes: u64,
+    pub deleted: u64,
+    pub freed_bytes: u64,
+    pub failed: u64,
+    pub duration_ms: u64,
+}
+
+impl fmt::Display for StatusLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "clean-big-targets: found={} total_bytes={} deleted={} freed_bytes={} failed={} duration_ms={}",
+            self.found,
+            self.total_bytes,
+            self.deleted,
+            self.freed_bytes,
+            self.failed,
+            self.duration_ms
+        )
+    }
+}
+
+impl StatusLine {
+    /// The outcome as one JSON object for `--summary-json`, such as
+    /// `{"found":12,"total_bytes":212400000000,"deleted_bytes":41000000000,"failed":0}`.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"found\":{},\"total_bytes\":{},\"deleted_bytes\":{},\"failed\":{}}}",
+            self.found, self.total_bytes, self.freed_bytes, self.failed
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    found: u64,
+    total_bytes: u64,
+    sizes: HashMap<PathBuf, u64>,
+    removed: Vec<PathBuf>,
+    failed: u64,
+}
+
+impl Counts {
+    fn learn_sizes(&mut self, targets: &[TargetDirInfo]) {
+        self.sizes
+            .extend(targets.iter().map(|info| (info.path.clone(), info.size)));
+    }
+}
+
+/// Gathers what a run found and deleted, for its [`StatusLine`].
+///
+/// Shared between the scan, the remover and any `--loop` rescans, so it's safe to use from
+/// several threads.
+#[derive(Debug, Default)]
+pub struct RunTally {
+    counts: Mutex<Counts>,
+}
+
+impl RunTally {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn counts(&self) -> std::sync::MutexGuard<'_, Counts> {
+        self.counts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Counts `targets` as found by the run.
+    pub fn found(&self, targets: &[TargetDirInfo]) {
+        let mut counts = self.counts();
+        counts.found += targets.len() as u64;
+        counts.total_bytes += targets.iter().map(|info| info.size).sum::<u64>();
+        counts.learn_sizes(targets);
+    }
+
+    /// Learns the sizes of `targets` without counting them as found, such as for a rescan
+    /// between `--loop` rounds.
+    pub fn seen(&self, targets: &[TargetDirInfo]) {
+        self.counts().learn_sizes(targets);
+    }
+
+    /// Wraps `remover` so every directory it removes or fails to is counted.
+    pub fn counting<'a>(
+        &'a self,
+        remover: &'a Remover<'a>,
+    ) -> impl Fn(&Path) -> std::io::Result<()> + Sync + 'a {
+        move |path: &Path| {
+            let removed = remover(path);
+            let mut counts = self.counts();
+            match &removed {
+                Ok(()) => counts.removed.push(path.to_path_buf()),
+                Err(_) => counts.failed += 1,
+            }
+            removed
+        }
+    }
+
+    /// The status line for the run so far, which took `duration`.
+    ///
+    /// Only whole targets whose size is known count as deleted, so the subdirectories
+    /// `--keep-newest` removes are left out.
+    pub fn status_line(&self, duration: Duration) -> StatusLine {
+        let counts = self.counts();
+        let mut deleted = 0;
+        let mut freed_bytes = 0;
+        for size in counts
+            .removed
+            .iter()
+            .filter_map(|path| counts.sizes.get(path))
+        {
+            deleted += 1;
+            freed_bytes += size;
+        }
+        StatusLine {
+            found: counts.found,
+            total_bytes: counts.total_bytes,
+            deleted,
+            freed_bytes,
+            failed: counts.failed,
+            duration_ms: u64::try_from(duration.as_millis()).unwrap_or(u64::MAX),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config, handle_deletion_with, output::Output, plan::execute_plan, session::run_loop,
+    };
+
+    fn targets() -> Vec<TargetDirInfo> {
+        vec![
+            TargetDirInfo::new(PathBuf::from("/nonexistent/one/target"), 1000),
+            TargetDirInfo::new(PathBuf::from("/nonexistent/two/target"), 2000),
+        ]
+    }
+
+    fn forced() -> Config {
+        Config {
+            force: true,
+            delete_jobs: 1,
+            delete_tracked: true,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_status_line_modes() {
+        assert!(StatusLineMode::Auto.enabled(false));
+        assert!(!StatusLineMode::Auto.enabled(true));
+        assert!(StatusLineMode::Always.enabled(true));
+        assert!(!StatusLineMode::Never.enabled(false));
+    }
+
+    #[test]
+    fn test_status_line_for_each_run_mode() {
+        let duration = Duration::from_millis(8421);
+        let remove_ok = |_: &Path| Ok(());
+        let remove_one = |path: &Path| {
+            if path.starts_with("/nonexistent/two") {
+                return Err(std::io::Error::other("busy"));
+            }
+            Ok(())
+        };
+
+        // Listing only
+        let tally = RunTally::new();
+        tally.found(&targets());
+        assert_eq!(
+            tally.status_line(duration).to_string(),
+            "clean-big-targets: found=2 total_bytes=3000 deleted=0 freed_bytes=0 failed=0 duration_ms=8421"
+        );
+
+        // Deleting, stopping at the first failure
+        let tally = RunTally::new();
+        tally.found(&targets());
+        let remover = tally.counting(&remove_one);
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        assert!(
+            handle_deletion_with(
+                &targets(),
+                &forced(),
+                &remover,
+                &mut Output::new(&mut out, &mut err, false),
+            )
+            .is_err()
+        );
+        assert_eq!(
+            tally.status_line(duration).to_string(),
+            "clean-big-targets: found=2 total_bytes=3000 deleted=1 freed_bytes=1000 failed=1 duration_ms=8421"
+        );
+        assert_eq!(
+            tally.status_line(duration).to_json(),
+            r#"{"found":2,"total_bytes":3000,"deleted_bytes":1000,"failed":1}"#
+        );
+
+        // Carrying out a plan, where missing directories are skipped rather than failing
+        let tally = RunTally::new();
+        tally.found(&targets());
+        let remover = tally.counting(&remove_ok);
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        execute_plan(
+            &targets(),
+            &forced(),
+            &remover,
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap();
+        assert_eq!(
+            tally.status_line(duration).to_string(),
+            "clean-big-targets: found=2 total_bytes=3000 deleted=0 freed_bytes=0 failed=0 duration_ms=8421"
+        );
+
+        // Looping, where a rescan finds a target the first scan didn't
+        let tally = RunTally::new();
+        tally.found(&targets()[..1]);
+        let remover = tally.counting(&remove_ok);
+        let mut rounds = vec![targets()[1..].to_vec()].into_iter();
+        let mut rescan = || {
+            let found = rounds.next().unwrap_or_default();
+            tally.seen(&found);
+            Ok(found)
+        };
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        run_loop(
+            targets()[..1].to_vec(),
+            &Config {
+                loop_mode: true,
+                ..forced()
+            },
+            &mut rescan,
+            &|_| Ok(0),
+            &remover,
+            &mut Output::new(&mut out, &mut err, false),
+        )
+        .unwrap();
+        assert_eq!(
+            tally.status_line(duration).to_string(),
+            "clean-big-targets: found=1 total_bytes=1000 deleted=2 freed_bytes=3000 failed=0 duration_ms=8421"
+        );
+    }
+}
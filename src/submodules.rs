@@ -0,0 +1,190 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{child_target_dir, stats::IoStats, walk::is_hidden};
+
+/// Reads a `.gitmodules` value, which may be quoted, and may be followed by a comment.
+fn parse_value(raw: &str) -> String {
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut chars = raw.trim().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' => match chars.next() {
+                Some('t') => value.push('\t'),
+                Some('n') => value.push('\n'),
+                Some(c) => value.push(c),
+                None => {}
+            },
+            '#' | ';' if !quoted => break,
+            c => value.push(c),
+        }
+    }
+    value.trim_end().to_string()
+}
+
+/// The `path` of every submodule in the contents of a `.gitmodules` file, in the order given.
+///
+/// This is only as much of git's config format as `.gitmodules` uses: `[submodule "name"]`
+/// sections holding `key = value` lines. Lines it doesn't understand are skipped.
+pub fn parse_gitmodules(contents: &str) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let mut in_submodule = false;
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[') {
+            in_submodule = section
+                .split(|c: char| c.is_whitespace() || c == ']')
+                .next()
+                .is_some_and(|name| name.eq_ignore_ascii_case("submodule"));
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if in_submodule && key.trim().eq_ignore_ascii_case("path") {
+            let path = parse_value(value);
+            if !path.is_empty() {
+                paths.push(PathBuf::from(path));
+            }
+        }
+    }
+    paths
+}
+
+/// Returns true if `path` stays inside the project it's relative to.
+fn is_inside(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// The target directories of the submodules `project`'s `.gitmodules` lists, and of their own
+/// submodules one level further down.
+///
+/// Submodules that haven't been checked out, or don't have a target, are skipped.
+pub fn submodule_targets(project: &Path, case_insensitive: bool, stats: &IoStats) -> Vec<PathBuf> {
+    let mut targets = Vec::new();
+    let mut projects = vec![(project.to_path_buf(), 0)];
+    while let Some((project, depth)) = projects.pop() {
+        stats.count_metadata();
+        let Ok(contents) = fs::read_to_string(project.join(".gitmodules")) else {
+            continue;
+        };
+        for path in parse_gitmodules(&contents) {
+            if !is_inside(&path) {
+                continue;
+            }
+            let submodule = project.join(path);
+            if let Some(target) = child_target_dir(&submodule, case_insensitive, stats) {
+                targets.push(target);
+            }
+            if depth == 0 {
+                projects.push((submodule, 1));
+            }
+        }
+    }
+    targets
+}
+
+/// The submodule targets of `root` and of each directory directly inside it, the projects a
+/// scan of `root` looks at. Hidden directories are only looked at with `hidden` set.
+pub fn find_submodule_targets(
+    root: &Path,
+    case_insensitive: bool,
+    hidden: bool,
+    stats: &IoStats,
+) -> Vec<PathBuf> {
+    let mut projects = vec![root.to_path_buf()];
+    stats.count_read_dir();
+    if let Ok(entries) = fs::read_dir(root) {
+        projects.extend(
+            entries
+                .flatten()
+                .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+                .map(|entry| entry.path())
+                .filter(|path| hidden || !is_hidden(path)),
+        );
+    }
+    projects
+        .iter()
+        .flat_map(|project| submodule_targets(project, case_insensitive, stats))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_gitmodules() {
+        let contents = r#"
+# Vendored crates
+[submodule "vendor/foo"]
+	path = vendor/foo
+	url = https://example.com/foo.git
+[submodule "spaced"]
+	path = "third party/bar" ; where it used to live
+	url = https://example.com/bar.git
+[core]
+	path = not/a/submodule
+[submodule "no-path"]
+	url = https://example.com/baz.git
+[Submodule "escaped"]
+	PATH = "odd\"name"
+"#;
+        assert_eq!(
+            parse_gitmodules(contents),
+            [
+                PathBuf::from("vendor/foo"),
+                PathBuf::from("third party/bar"),
+                PathBuf::from("odd\"name"),
+            ]
+        );
+        assert!(parse_gitmodules("").is_empty());
+    }
+
+    #[test]
+    fn test_submodule_targets() {
+        let temp_dir = TempDir::new().unwrap();
+        let project = temp_dir.path().join("super");
+        fs::create_dir_all(project.join("libs/core/target")).unwrap();
+        fs::create_dir_all(project.join("libs/core/nested/target")).unwrap();
+        fs::create_dir_all(project.join("libs/core/nested/deeper/target")).unwrap();
+        fs::write(
+            project.join(".gitmodules"),
+            "[submodule \"core\"]\n\tpath = libs/core\n\
+             [submodule \"missing\"]\n\tpath = libs/uninitialized\n\
+             [submodule \"escape\"]\n\tpath = ../elsewhere\n",
+        )
+        .unwrap();
+        fs::write(
+            project.join("libs/core/.gitmodules"),
+            "[submodule \"nested\"]\n\tpath = nested\n",
+        )
+        .unwrap();
+        // Two levels down is further than submodules are followed
+        fs::write(
+            project.join("libs/core/nested/.gitmodules"),
+            "[submodule \"deeper\"]\n\tpath = deeper\n",
+        )
+        .unwrap();
+        fs::create_dir_all(temp_dir.path().join("elsewhere/target")).unwrap();
+
+        let mut targets = find_submodule_targets(temp_dir.path(), false, false, &IoStats::new());
+        targets.sort();
+        assert_eq!(
+            targets,
+            [
+                project.join("libs/core/nested/target"),
+                project.join("libs/core/target"),
+            ]
+        );
+    }
+}
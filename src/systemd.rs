@@ -0,0 +1,298 @@
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use regex::Regex;
+
+use crate::{Cli, paths::config_home, resolve_roots};
+
+pub const UNIT_NAME: &str = "clean-big-targets";
+
+/// Shorthand schedules understood by `OnCalendar=`.
+const SHORTHANDS: &[&str] = &[
+    "minutely",
+    "hourly",
+    "daily",
+    "weekly",
+    "monthly",
+    "quarterly",
+    "semiannually",
+    "yearly",
+    "annually",
+];
+
+/// Checks `schedule` looks like a systemd calendar expression.
+///
+/// This accepts the shorthands (`daily`, `weekly`, ...) and expressions made of an optional
+/// weekday list, an optional `YYYY-MM-DD` date and an optional `HH:MM[:SS]` time, each part
+/// allowing `*`, lists, ranges and repetitions. It's a sanity check rather than a full parser,
+/// `systemd-analyze calendar` has the final say.
+pub fn validate_calendar(schedule: &str) -> Result<(), String> {
+    let schedule = schedule.trim();
+    if SHORTHANDS.contains(&schedule.to_ascii_lowercase().as_str()) {
+        return Ok(());
+    }
+
+    let weekday = "(Mon|Tue|Wed|Thu|Fri|Sat|Sun)[a-z]*";
+    let weekdays = Regex::new(&format!(
+        "^{weekday}(\\.\\.{weekday})?(,{weekday}(\\.\\.{weekday})?)*$"
+    ))
+    .map_err(|e| e.to_string())?;
+    let value = "[0-9*][0-9*,./~]*";
+    let date = Regex::new(&format!("^({value}-)?{value}-{value}$")).map_err(|e| e.to_string())?;
+    let time = Regex::new(&format!("^{value}:{value}(:{value})?$")).map_err(|e| e.to_string())?;
+
+    let mut parts = schedule.split_whitespace().peekable();
+    if parts.peek().is_none() {
+        return Err("empty schedule".to_string());
+    }
+    if parts.peek().is_some_and(|part| weekdays.is_match(part)) {
+        parts.next();
+    }
+    if parts.peek().is_some_and(|part| date.is_match(part)) {
+        parts.next();
+    }
+    if parts.peek().is_some_and(|part| time.is_match(part)) {
+        parts.next();
+    }
+    match parts.next() {
+        None => Ok(()),
+        Some(part) => Err(format!(
+            "unrecognised part of calendar expression: '{part}'"
+        )),
+    }
+}
+
+/// Quotes an argument for `ExecStart=`, escaping systemd's specifiers and variables.
+fn quote_exec_arg(arg: &str) -> String {
+    let escaped = arg.replace('%', "%%").replace('$', "$$");
+    if !escaped.is_empty()
+        && !escaped
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ';'))
+    {
+        return escaped;
+    }
+    format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A generated service and timer unit pair.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Units {
+    pub service: String,
+    pub timer: String,
+}
+
+/// Generates units running `binary` with `args` on `schedule`.
+///
+/// The arguments are checked by parsing them as our own command line, and the scan roots
+/// they name are the only paths the service may write to. The service runs in the directory
+/// this is called from, so a relative root, or the default of `.`, is the one it may write to.
+pub fn generate_units(binary: &Path, args: &[String], schedule: &str) -> Result<Units, String> {
+    validate_calendar(schedule)?;
+    let working_dir = std::env::current_dir()
+        .map_err(|e| format!("failed to read the current directory: {e}"))?;
+    let cli =
+        Cli::try_parse_from(std::iter::once(UNIT_NAME.to_string()).chain(args.iter().cloned()))
+            .map_err(|e| format!("invalid arguments for the unit: {e}"))?;
+    let roots: Vec<PathBuf> = resolve_roots(&cli.target_dirs, None, None)
+        .into_iter()
+        .map(|root| {
+            let root = working_dir.join(root);
+            std::path::absolute(&root).unwrap_or(root)
+        })
+        .collect();
+
+    let exec_start = std::iter::once(binary.display().to_string())
+        .chain(args.iter().cloned())
+        .map(|arg| quote_exec_arg(&arg))
+        .collect::<Vec<String>>()
+        .join(" ");
+    let read_write_paths = roots
+        .iter()
+        .map(|root| quote_exec_arg(&root.display().to_string()))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    let service = format!(
+        "[Unit]
+Description=Clean up large Rust target directories
+
+[Service]
+Type=oneshot
+WorkingDirectory={}
+ExecStart={exec_start}
+Nice=10
+IOSchedulingClass=idle
+NoNewPrivileges=yes
+PrivateTmp=yes
+ProtectSystem=strict
+ProtectHome=read-only
+ReadWritePaths={read_write_paths}
+",
+        working_dir.display().to_string().replace('%', "%%")
+    );
+    let timer = format!(
+        "[Unit]
+Description=Scheduled clean up of large Rust target directories
+
+[Timer]
+OnCalendar={}
+Persistent=true
+
+[Install]
+WantedBy=timers.target
+",
+        schedule.trim()
+    );
+    Ok(Units { service, timer })
+}
+
+/// Where user units live, `$XDG_CONFIG_HOME/systemd/user` or `~/.config/systemd/user`.
+pub fn user_unit_dir(env: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    config_home(env).map(|config| config.join("systemd").join("user"))
+}
+
+/// Writes the units into `dir`, returning the paths written.
+pub fn install_units(units: &Units, dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    let service = dir.join(format!("{UNIT_NAME}.service"));
+    let timer = dir.join(format!("{UNIT_NAME}.timer"));
+    std::fs::write(&service, &units.service)?;
+    std::fs::write(&timer, &units.timer)?;
+    Ok(vec![service, timer])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_validate_calendar() {
+        for valid in [
+            "weekly",
+            "Daily",
+            "Mon *-*-* 03:00:00",
+            "Mon..Fri 22:30",
+            "Sat,Sun *-*-01 04:00",
+            "*-*-* 00/6:00",
+            "2030-01-01",
+        ] {
+            assert!(validate_calendar(valid).is_ok(), "{valid} should be valid");
+        }
+        for invalid in ["", "fortnightly", "Mon 25", "*-*-* 03:00 extra"] {
+            assert!(
+                validate_calendar(invalid).is_err(),
+                "{invalid} should be invalid"
+            );
+        }
+    }
+
+    /// The `WorkingDirectory=` the units get when generated from here.
+    fn working_dir() -> String {
+        std::env::current_dir().unwrap().display().to_string()
+    }
+
+    #[test]
+    fn test_generate_units_weekly() {
+        let units = generate_units(
+            Path::new("/usr/local/bin/clean-big-targets"),
+            &args(&["--delete", "--force", "/srv/builds"]),
+            "weekly",
+        )
+        .unwrap();
+        assert_eq!(
+            units.service,
+            format!(
+                "[Unit]
+Description=Clean up large Rust target directories
+
+[Service]
+Type=oneshot
+WorkingDirectory={}
+ExecStart=/usr/local/bin/clean-big-targets --delete --force /srv/builds
+Nice=10
+IOSchedulingClass=idle
+NoNewPrivileges=yes
+PrivateTmp=yes
+ProtectSystem=strict
+ProtectHome=read-only
+ReadWritePaths=/srv/builds
+",
+                working_dir()
+            )
+        );
+        assert_eq!(
+            units.timer,
+            "[Unit]
+Description=Scheduled clean up of large Rust target directories
+
+[Timer]
+OnCalendar=weekly
+Persistent=true
+
+[Install]
+WantedBy=timers.target
+"
+        );
+    }
+
+    #[test]
+    fn test_generate_units_calendar_and_quoting() {
+        let units = generate_units(
+            Path::new("/opt/my tools/clean-big-targets"),
+            &args(&["-D", "--force", "/srv/100% builds", "/srv/other"]),
+            "Mon *-*-* 03:00:00",
+        )
+        .unwrap();
+        assert!(units.service.contains(
+            "ExecStart=\"/opt/my tools/clean-big-targets\" -D --force \"/srv/100%% builds\" /srv/other\n"
+        ));
+        assert!(
+            units
+                .service
+                .contains("ReadWritePaths=\"/srv/100%% builds\" /srv/other\n")
+        );
+        assert!(units.timer.contains("OnCalendar=Mon *-*-* 03:00:00\n"));
+    }
+
+    #[test]
+    fn test_generate_units_without_root() {
+        let units = generate_units(
+            Path::new("/usr/bin/clean-big-targets"),
+            &args(&["--delete", "--force"]),
+            "daily",
+        )
+        .unwrap();
+        // The service scans where it runs, and that's the root it may write to
+        let dir = working_dir();
+        assert!(units.service.contains(&format!("WorkingDirectory={dir}\n")));
+        assert!(
+            units
+                .service
+                .contains("ExecStart=/usr/bin/clean-big-targets --delete --force\n")
+        );
+        let read_write_paths = format!("ReadWritePaths={}\n", quote_exec_arg(&dir));
+        assert!(units.service.contains(&read_write_paths));
+
+        let units = generate_units(
+            Path::new("/usr/bin/clean-big-targets"),
+            &args(&["projects"]),
+            "daily",
+        )
+        .unwrap();
+        let projects = Path::new(&dir).join("projects").display().to_string();
+        let read_write_paths = format!("ReadWritePaths={}\n", quote_exec_arg(&projects));
+        assert!(units.service.contains(&read_write_paths));
+    }
+
+    #[test]
+    fn test_generate_units_rejects_bad_input() {
+        let binary = Path::new("/usr/bin/clean-big-targets");
+        assert!(generate_units(binary, &args(&["/srv"]), "sometimes").is_err());
+        assert!(generate_units(binary, &args(&["--no-such-flag"]), "daily").is_err());
+    }
+}
@@ -0,0 +1,40 @@
+use std::ffi::OsString;
+
+/// The sizes of the entries under a target directory, kept down to a fixed depth.
+///
+/// Directories at the depth limit have no children, their `size` is everything under them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SizeTree {
+    pub name: OsString,
+    /// Size of this entry alone, or of everything under a directory cut off at the depth limit.
+    pub size: u64,
+    pub is_dir: bool,
+    /// Sorted by name.
+    pub children: Vec<SizeTree>,
+}
+
+impl SizeTree {
+    pub fn file(name: OsString, size: u64) -> Self {
+        Self {
+            name,
+            size,
+            is_dir: false,
+            children: Vec::new(),
+        }
+    }
+
+    /// A directory past the depth limit, with `size` standing in for its contents.
+    pub fn collapsed_dir(name: OsString, size: u64) -> Self {
+        Self {
+            name,
+            size,
+            is_dir: true,
+            children: Vec::new(),
+        }
+    }
+
+    /// The size of this entry and everything under it.
+    pub fn total(&self) -> u64 {
+        self.size + self.children.iter().map(SizeTree::total).sum::<u64>()
+    }
+}
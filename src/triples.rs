@@ -0,0 +1,154 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{TargetDirInfo, calculate_dir_size};
+
+/// Whether `name` looks like a target triple such as `wasm32-unknown-unknown` or
+/// `aarch64-unknown-linux-musl`, which cargo uses for `--target` builds.
+///
+/// A pattern rather than a list, so triples rustc adds later are recognized: three or four
+/// components separated by dashes, made of lowercase letters, digits, `_` and `.`.
+pub fn is_triple(name: &OsStr) -> bool {
+    let Some(name) = name.to_str() else {
+        return false;
+    };
+    let components: Vec<&str> = name.split('-').collect();
+    (3..=4).contains(&components.len())
+        && components.iter().all(|component| {
+            !component.is_empty()
+                && component
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '.')
+        })
+}
+
+/// Checks a `--triple` value looks like a triple, so it can't point outside the target.
+pub fn parse_triple(value: &str) -> Result<String, String> {
+    if is_triple(OsStr::new(value)) {
+        Ok(value.to_string())
+    } else {
+        Err(format!("'{value}' doesn't look like a target triple"))
+    }
+}
+
+/// The triple subdirectories of the target directory at `target`, sorted by name.
+pub fn triple_dirs(target: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(target)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() && is_triple(&entry.file_name()) {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// How much one triple takes up across every target directory, see [`summarize_triples`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TripleSummary {
+    pub triple: String,
+    /// How many target directories have a subdirectory for it.
+    pub targets: usize,
+    pub size: u64,
+}
+
+/// Finds and sizes the triple subdirectories of each target, for `--list-triples`.
+///
+/// Sorted by size, largest first.
+pub fn summarize_triples(target_info: &[TargetDirInfo]) -> std::io::Result<Vec<TripleSummary>> {
+    let mut triples: BTreeMap<String, TripleSummary> = BTreeMap::new();
+    for info in target_info {
+        for dir in triple_dirs(&info.path)? {
+            let triple = dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let size = calculate_dir_size(&dir)?;
+            let summary = triples
+                .entry(triple.clone())
+                .or_insert_with(|| TripleSummary {
+                    triple,
+                    targets: 0,
+                    size: 0,
+                });
+            summary.targets += 1;
+            summary.size += size;
+        }
+    }
+    let mut triples: Vec<TripleSummary> = triples.into_values().collect();
+    triples.sort_by_key(|summary| Reverse(summary.size));
+    Ok(triples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_is_triple() {
+        for name in [
+            "wasm32-unknown-unknown",
+            "aarch64-unknown-linux-musl",
+            "x86_64-pc-windows-msvc",
+            "thumbv7em-none-eabihf",
+            "armv7-linux-androideabi",
+            "some_new-vendor-os.2",
+        ] {
+            assert!(is_triple(OsStr::new(name)), "{name}");
+        }
+        for name in [
+            "debug",
+            "release",
+            "release-lto",
+            "a-b-c-d-e",
+            "x86_64--linux",
+            "Wasm32-unknown-unknown",
+            "flycheck0",
+        ] {
+            assert!(!is_triple(OsStr::new(name)), "{name}");
+        }
+        assert!(parse_triple("wasm32-unknown-unknown").is_ok());
+        assert!(parse_triple("../../etc-x-y").is_err());
+    }
+
+    #[test]
+    fn test_summarize_triples() {
+        let temp_dir = TempDir::new().unwrap();
+        let write = |project: &str, dir: &str, size: usize| {
+            let dir = temp_dir.path().join(project).join("target").join(dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("artifact"), vec![0u8; size]).unwrap();
+        };
+        write("a", "debug", 1000);
+        write("a", "wasm32-unknown-unknown", 10);
+        write("b", "wasm32-unknown-unknown", 20);
+        write("b", "aarch64-unknown-linux-musl", 50);
+        let target_info: Vec<TargetDirInfo> = ["a", "b"]
+            .iter()
+            .map(|project| TargetDirInfo::new(temp_dir.path().join(project).join("target"), 0))
+            .collect();
+
+        assert_eq!(
+            summarize_triples(&target_info).unwrap(),
+            [
+                TripleSummary {
+                    triple: "aarch64-unknown-linux-musl".to_string(),
+                    targets: 1,
+                    size: 50
+                },
+                TripleSummary {
+                    triple: "wasm32-unknown-unknown".to_string(),
+                    targets: 2,
+                    size: 30
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Windows path spellings. Canonicalizing gives the extended-length `\\?\C:\x` and
+//! `\\?\UNC\server\share\x` forms, which every path is kept in internally so long and network
+//! paths work, while people are shown the `C:\x` and `\\server\share\x` they'd type.
+//!
+//! Elsewhere paths have a single form and these leave them alone.
+
+use std::path::{Path, PathBuf};
+
+/// `path` as it'd be typed, without a `\\?\` prefix. Unprefixed spellings are already that.
+pub fn display_str(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix(r"\\?\") {
+        if let Some(share) = rest
+            .get(..4)
+            .filter(|unc| unc.eq_ignore_ascii_case(r"UNC\"))
+        {
+            return format!(r"\\{}", &rest[share.len()..]);
+        }
+        if is_drive_absolute(rest) {
+            return rest.to_string();
+        }
+    }
+    path.to_string()
+}
+
+/// `path`, which should be absolute and normalized, in the extended-length form: `\\?\UNC\`
+/// for a share and `\\?\` before a drive. Anything else, relative or already prefixed, is left
+/// as it is.
+pub fn extended_str(path: &str) -> String {
+    if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        return path.to_string();
+    }
+    // The extended form is taken literally, so `/` has to be a separator already
+    let path = path.replace('/', r"\");
+    if let Some(share) = path.strip_prefix(r"\\") {
+        format!(r"\\?\UNC\{share}")
+    } else if is_drive_absolute(&path) {
+        format!(r"\\?\{path}")
+    } else {
+        path
+    }
+}
+
+/// Returns true for `C:\...`, a drive letter followed by a separator.
+fn is_drive_absolute(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\'
+}
+
+/// How to show `path`, see [`display_str`].
+pub fn display_form(path: &Path) -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(display_str(&path.to_string_lossy()))
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// [`display_form`] as a string, in place of `path.display()` in messages.
+pub fn shown(path: &Path) -> String {
+    display_form(path).display().to_string()
+}
+
+/// `path` in the form kept internally, see [`extended_str`].
+pub fn extended_form(path: &Path) -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(extended_str(&path.to_string_lossy()))
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Canonicalizes `path`, which on Windows gives the extended form.
+///
+/// Some network filesystems can't say what a path's final name is, so canonicalizing fails on
+/// them even though the path is there. Then it's made absolute and put in the extended form
+/// instead, which is as canonical as they allow.
+pub fn canonicalize(path: &Path) -> std::io::Result<PathBuf> {
+    match path.canonicalize() {
+        Ok(canonical) => Ok(canonical),
+        Err(e) if cfg!(windows) && path.symlink_metadata().is_ok() => std::path::absolute(path)
+            .map(|absolute| extended_form(&absolute))
+            .map_err(|_| e),
+        Err(e) => Err(e),
+    }
+}
+
+/// Like [`canonicalize`], leaving `path` as it is if it can't be.
+pub fn canonical_or_same(path: &Path) -> PathBuf {
+    canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_forms_round_trip() {
+        for (typed, extended) in [
+            (
+                r"\\fileserver\dev\projects",
+                r"\\?\UNC\fileserver\dev\projects",
+            ),
+            (r"Z:\x", r"\\?\Z:\x"),
+            (r"C:\code\app\target", r"\\?\C:\code\app\target"),
+        ] {
+            assert_eq!(extended_str(typed), extended);
+            assert_eq!(display_str(extended), typed);
+            // Neither changes what's already in its form
+            assert_eq!(extended_str(extended), extended);
+            assert_eq!(display_str(typed), typed);
+        }
+        assert_eq!(display_str(r"\\?\unc\server\share"), r"\\server\share");
+        assert_eq!(extended_str("//server/share/x"), r"\\?\UNC\server\share\x");
+        // Relative paths and devices have no extended form
+        assert_eq!(extended_str(r"code\target"), r"code\target");
+        assert_eq!(extended_str(r"\\.\pipe\x"), r"\\.\pipe\x");
+        assert_eq!(display_str(r"\\?\Volume{1234}\x"), r"\\?\Volume{1234}\x");
+    }
+}
+
+#[cfg(all(test, windows))]
+mod windows_tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_path_forms() {
+        for (typed, extended) in [
+            (r"\\server\share\x", r"\\?\UNC\server\share\x"),
+            (r"Z:\x", r"\\?\Z:\x"),
+        ] {
+            assert_eq!(extended_form(Path::new(typed)), PathBuf::from(extended));
+            assert_eq!(display_form(Path::new(extended)), PathBuf::from(typed));
+            assert_eq!(shown(Path::new(extended)), typed);
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_is_extended() {
+        let temp_dir = TempDir::new().unwrap();
+        let canonical = canonicalize(temp_dir.path()).unwrap();
+        assert!(canonical.to_string_lossy().starts_with(r"\\?\"));
+        assert_eq!(canonicalize(&display_form(&canonical)).unwrap(), canonical);
+        assert!(canonicalize(&temp_dir.path().join("missing")).is_err());
+    }
+}
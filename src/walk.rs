@@ -0,0 +1,432 @@
+use std::{
+    collections::HashSet,
+    fs,
+    iter::Peekable,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    CASE_INSENSITIVE_DEFAULT, child_target_dir,
+    deadline::Deadline,
+    report::{Event, NoopReporter, PruneReason, Reporter},
+    stats::IoStats,
+    unc,
+};
+
+/// Options controlling how target directories are discovered.
+///
+/// The default matches the command line without any flags, adjust it with the setters:
+///
+/// ```
+/// use clean_big_targets::walk::ScanOptions;
+///
+/// let opts = ScanOptions::new().max_results(Some(10)).case_insensitive(true);
+/// assert_eq!(opts.max_results, Some(10));
+/// ```
+#[derive(Clone, Debug)]
+pub struct ScanOptions {
+    /// Match `target` names ignoring ASCII case.
+    pub case_insensitive: bool,
+    /// Stop once this many target directories have been found.
+    pub max_results: Option<usize>,
+    /// Look in directories whose names start with a dot, which are skipped otherwise. The root
+    /// itself is always looked in.
+    pub hidden: bool,
+    /// Stop once this has passed.
+    pub deadline: Deadline,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            case_insensitive: CASE_INSENSITIVE_DEFAULT,
+            max_results: None,
+            hidden: false,
+            deadline: Deadline::default(),
+        }
+    }
+}
+
+impl ScanOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    pub fn max_results(mut self, max_results: Option<usize>) -> Self {
+        self.max_results = max_results;
+        self
+    }
+
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    pub fn deadline(mut self, deadline: Deadline) -> Self {
+        self.deadline = deadline;
+        self
+    }
+}
+
+/// Returns true if `path` names a hidden directory, one starting with a dot.
+pub fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+}
+
+/// A filesystem error hit while walking, along with the path it happened at.
+#[derive(Debug)]
+pub struct ScanError {
+    pub path: PathBuf,
+    pub source: std::io::Error,
+}
+
+impl std::fmt::Display for ScanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.source)
+    }
+}
+
+impl std::error::Error for ScanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<ScanError> for std::io::Error {
+    fn from(error: ScanError) -> Self {
+        std::io::Error::new(error.source.kind(), error)
+    }
+}
+
+/// Lazily yields the target directories under `base`, without sizing them, counting the
+/// directories listed and looked at in `stats`.
+///
+/// This is the sequential walker: nothing happens until the iterator is advanced and no
+/// threads are involved, so it can be dropped at any point.
+pub fn walk_targets<'a>(
+    base: &Path,
+    opts: &ScanOptions,
+    stats: &'a IoStats,
+) -> impl Iterator<Item = Result<PathBuf, ScanError>> + use<'a> {
+    TargetWalker::new(base, opts, &NoopReporter, stats)
+}
+
+enum State {
+    Start,
+    Walking {
+        root: PathBuf,
+        // Boxed, as `ReadDir` is large on Windows
+        entries: Box<Peekable<fs::ReadDir>>,
+    },
+    Done,
+}
+
+/// The iterator behind [`walk_targets`], which also reports events and counts calls.
+pub(crate) struct TargetWalker<'a> {
+    base: PathBuf,
+    case_insensitive: bool,
+    hidden: bool,
+    /// How many more targets may be yielded before stopping, `None` for no limit.
+    remaining: Option<usize>,
+    deadline: Deadline,
+    /// Set once the walk stopped because the deadline passed.
+    timed_out: bool,
+    reporter: &'a dyn Reporter,
+    stats: &'a IoStats,
+    visited: HashSet<PathBuf>,
+    state: State,
+}
+
+impl<'a> TargetWalker<'a> {
+    pub(crate) fn new(
+        base: &Path,
+        opts: &ScanOptions,
+        reporter: &'a dyn Reporter,
+        stats: &'a IoStats,
+    ) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            case_insensitive: opts.case_insensitive,
+            hidden: opts.hidden,
+            remaining: opts.max_results,
+            deadline: opts.deadline,
+            timed_out: false,
+            reporter,
+            stats,
+            visited: HashSet::new(),
+            state: State::Start,
+        }
+    }
+
+    /// Returns true if there are directory entries the walk hasn't looked at yet.
+    ///
+    /// This ignores `max_results`, so after hitting the limit it says whether results were cut off.
+    pub(crate) fn has_remaining(&mut self) -> bool {
+        match &mut self.state {
+            State::Start => true,
+            State::Walking { entries, .. } => entries.peek().is_some(),
+            State::Done => false,
+        }
+    }
+
+    /// Returns true if the walk stopped because the deadline passed.
+    pub(crate) fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+
+    fn fail(
+        &mut self,
+        path: PathBuf,
+        source: std::io::Error,
+    ) -> Option<Result<PathBuf, ScanError>> {
+        self.state = State::Done;
+        Some(Err(ScanError { path, source }))
+    }
+
+    /// Opens the root, returning the root's own target directory if it is itself a project.
+    fn start(&mut self) -> Result<Option<PathBuf>, ScanError> {
+        let root = unc::canonicalize(&self.base).map_err(|source| ScanError {
+            path: self.base.clone(),
+            source,
+        })?;
+
+        if let Some(target) = child_target_dir(&root, self.case_insensitive, self.stats)
+            && unc::canonicalize(&target).is_ok_and(|c| c.starts_with(&root))
+        {
+            self.state = State::Done;
+            return Ok(Some(target));
+        }
+
+        self.stats.count_read_dir();
+        let entries = fs::read_dir(&root)
+            .map_err(|source| ScanError {
+                path: root.clone(),
+                source,
+            })?
+            .peekable();
+        self.state = State::Walking {
+            root,
+            entries: Box::new(entries),
+        };
+        Ok(None)
+    }
+}
+
+impl Iterator for TargetWalker<'_> {
+    type Item = Result<PathBuf, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+        let next = self.next_unlimited();
+        if let (Some(Ok(_)), Some(remaining)) = (&next, &mut self.remaining) {
+            *remaining -= 1;
+        }
+        next
+    }
+}
+
+impl TargetWalker<'_> {
+    fn next_unlimited(&mut self) -> Option<Result<PathBuf, ScanError>> {
+        if let State::Start = self.state {
+            match self.start() {
+                Ok(Some(target)) => {
+                    self.reporter.report(Event::TargetFound(target.clone()));
+                    return Some(Ok(target));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    self.state = State::Done;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        loop {
+            let State::Walking { root, entries } = &mut self.state else {
+                return None;
+            };
+            if self.deadline.passed() {
+                self.timed_out = entries.peek().is_some();
+                self.state = State::Done;
+                return None;
+            }
+            let entry = match entries.next()? {
+                Ok(entry) => entry,
+                Err(source) => {
+                    let root = root.clone();
+                    return self.fail(root, source);
+                }
+            };
+            let path = entry.path();
+            if !self.hidden && is_hidden(&path) {
+                self.reporter
+                    .report(Event::DirPruned(path, PruneReason::Hidden));
+                continue;
+            }
+
+            self.stats.count_metadata();
+            if !path.is_dir() {
+                self.reporter
+                    .report(Event::DirPruned(path, PruneReason::NotADirectory));
+                continue;
+            }
+            // Symlinks could point anywhere, only follow them if they stay inside the root
+            let canonical = match unc::canonicalize(&path) {
+                Ok(canonical) => canonical,
+                Err(source) => return self.fail(path, source),
+            };
+            if !canonical.starts_with(root.as_path()) {
+                self.reporter
+                    .report(Event::DirPruned(path, PruneReason::OutsideRoot));
+                continue;
+            }
+            if !self.visited.insert(canonical) {
+                self.reporter
+                    .report(Event::DirPruned(path, PruneReason::AlreadyVisited));
+                continue;
+            }
+
+            match child_target_dir(&path, self.case_insensitive, self.stats) {
+                Some(target_path) => {
+                    self.reporter
+                        .report(Event::TargetFound(target_path.clone()));
+                    return Some(Ok(target_path));
+                }
+                None => {
+                    self.reporter
+                        .report(Event::DirPruned(path, PruneReason::NoTargetDir));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_walk_targets_stops_early() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..2000 {
+            fs::create_dir_all(temp_dir.path().join(format!("project{i}")).join("target")).unwrap();
+        }
+
+        let stats = IoStats::default();
+        let mut walker = walk_targets(temp_dir.path(), &ScanOptions::default(), &stats);
+        let first = walker.next().unwrap().unwrap();
+        assert!(first.ends_with("target"));
+        drop(walker);
+
+        // Only the root has been listed and only the first project looked at
+        assert_eq!(stats.read_dir_calls(), 1);
+        assert!(stats.metadata_calls() <= 3);
+
+        // Another walk is counted on its own
+        let again = IoStats::default();
+        assert!(
+            walk_targets(temp_dir.path(), &ScanOptions::default(), &again)
+                .next()
+                .is_some()
+        );
+        assert_eq!(again.read_dir_calls(), 1);
+        assert_eq!(stats.read_dir_calls(), 1);
+    }
+
+    #[test]
+    fn test_walk_targets_collects_all() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..5 {
+            fs::create_dir_all(temp_dir.path().join(format!("project{i}")).join("target")).unwrap();
+        }
+        let found: Vec<PathBuf> = walk_targets(
+            temp_dir.path(),
+            &ScanOptions::default(),
+            &IoStats::default(),
+        )
+        .collect::<Result<_, _>>()
+        .unwrap();
+        assert_eq!(found.len(), 5);
+    }
+
+    #[test]
+    fn test_walk_targets_missing_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("missing");
+        let results: Vec<Result<PathBuf, ScanError>> =
+            walk_targets(&missing, &ScanOptions::default(), &IoStats::default()).collect();
+        assert_eq!(results.len(), 1);
+        let error = results.into_iter().next().unwrap().unwrap_err();
+        assert_eq!(error.path, missing);
+    }
+
+    #[test]
+    fn test_walk_targets_honors_max_results() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..5 {
+            fs::create_dir_all(temp_dir.path().join(format!("project{i}")).join("target")).unwrap();
+        }
+        let opts = ScanOptions::new().max_results(Some(2));
+        assert_eq!(
+            walk_targets(temp_dir.path(), &opts, &IoStats::default()).count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_scan_options_setters() {
+        let opts = ScanOptions::new();
+        assert_eq!(opts.case_insensitive, CASE_INSENSITIVE_DEFAULT);
+        assert_eq!(opts.max_results, None);
+
+        let opts = opts.case_insensitive(!CASE_INSENSITIVE_DEFAULT);
+        assert_eq!(opts.case_insensitive, !CASE_INSENSITIVE_DEFAULT);
+        assert_eq!(opts.max_results, None);
+
+        let opts = ScanOptions::new().max_results(Some(3));
+        assert_eq!(opts.case_insensitive, CASE_INSENSITIVE_DEFAULT);
+        assert_eq!(opts.max_results, Some(3));
+        assert!(!opts.hidden);
+        assert!(opts.hidden(true).hidden);
+    }
+
+    #[test]
+    fn test_walk_targets_hidden_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("visible").join("target")).unwrap();
+        let dot_dir = temp_dir.path().join(".backup");
+        fs::create_dir_all(dot_dir.join("old").join("target")).unwrap();
+        fs::create_dir_all(dot_dir.join("target")).unwrap();
+
+        let found = |root: &Path, opts: &ScanOptions| -> Vec<PathBuf> {
+            walk_targets(root, opts, &IoStats::default())
+                .collect::<Result<_, _>>()
+                .unwrap()
+        };
+        let skipping = found(temp_dir.path(), &ScanOptions::default());
+        assert_eq!(skipping.len(), 1);
+        assert!(skipping[0].ends_with("visible/target"));
+        assert_eq!(
+            found(temp_dir.path(), &ScanOptions::new().hidden(true)).len(),
+            2
+        );
+
+        // Pointing straight at a hidden directory looks inside it anyway
+        let explicit = found(&dot_dir, &ScanOptions::default());
+        assert_eq!(explicit.len(), 1);
+        assert!(explicit[0].ends_with(".backup/target"));
+        assert_eq!(
+            found(&dot_dir.join("old"), &ScanOptions::default()),
+            [dot_dir.join("old").join("target").canonicalize().unwrap()]
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::TargetKind;
+
+fn file_names(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect()
+}
+
+/// Whether `dir` is wasm-pack output: a generated `package.json` with a `files` list that
+/// names a `.wasm` file that's actually there.
+pub fn is_wasm_pack_pkg(dir: &Path) -> bool {
+    let Ok(package) = fs::read_to_string(dir.join("package.json")) else {
+        return false;
+    };
+    package.contains("\"files\"")
+        && file_names(dir)
+            .iter()
+            .any(|name| name.ends_with(".wasm") && package.contains(&format!("\"{name}\"")))
+}
+
+/// Splits trunk's `<name>-<hash>` naming, returning the name with the hash if there is one.
+fn hashed_stem(file_name: &str, extension: &str) -> Option<String> {
+    let stem = file_name.strip_suffix(extension)?;
+    let stem = stem.strip_suffix("_bg").unwrap_or(stem);
+    let (_, hash) = stem.rsplit_once('-')?;
+    (hash.len() >= 8 && hash.chars().all(|c| c.is_ascii_hexdigit())).then(|| stem.to_string())
+}
+
+/// Whether `dir` is trunk output: an `index.html` that loads a hashed `.js` file, next to the
+/// `.wasm` file with the same hash.
+///
+/// Requiring the wasm artifact keeps this from matching a hand-written `dist` folder.
+pub fn is_trunk_dist(dir: &Path) -> bool {
+    let Ok(index) = fs::read_to_string(dir.join("index.html")) else {
+        return false;
+    };
+    let names = file_names(dir);
+    names.iter().any(|name| {
+        hashed_stem(name, ".wasm").is_some_and(|stem| {
+            let js = format!("{stem}.js");
+            names.contains(&js) && index.contains(&js)
+        })
+    })
+}
+
+/// The wasm-pack `pkg` and trunk `dist` directories next to the target directory at `target`.
+pub fn wasm_output_dirs(target: &Path) -> Vec<(PathBuf, TargetKind)> {
+    let Some(project) = target.parent() else {
+        return Vec::new();
+    };
+    let mut dirs = Vec::new();
+    let pkg = project.join("pkg");
+    if is_wasm_pack_pkg(&pkg) {
+        dirs.push((pkg, TargetKind::WasmPack));
+    }
+    let dist = project.join("dist");
+    if is_trunk_dist(&dist) {
+        dirs.push((dist, TargetKind::Trunk));
+    }
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_files(dir: &Path, files: &[(&str, &str)]) {
+        fs::create_dir_all(dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+    }
+
+    const PACKAGE_JSON: &str = r#"{
+  "name": "hello-wasm",
+  "version": "0.1.0",
+  "files": [
+    "hello_wasm_bg.wasm",
+    "hello_wasm.js",
+    "hello_wasm.d.ts"
+  ],
+  "module": "hello_wasm.js",
+  "types": "hello_wasm.d.ts"
+}"#;
+
+    #[test]
+    fn test_is_wasm_pack_pkg() {
+        let temp_dir = TempDir::new().unwrap();
+        let pkg = temp_dir.path().join("pkg");
+        write_files(
+            &pkg,
+            &[
+                ("package.json", PACKAGE_JSON),
+                ("hello_wasm_bg.wasm", "\0asm"),
+                ("hello_wasm.js", ""),
+            ],
+        );
+        assert!(is_wasm_pack_pkg(&pkg));
+
+        // An npm package without the wasm isn't wasm-pack output
+        let npm = temp_dir.path().join("npm");
+        write_files(
+            &npm,
+            &[("package.json", PACKAGE_JSON), ("hello_wasm.js", "")],
+        );
+        assert!(!is_wasm_pack_pkg(&npm));
+
+        let unlisted = temp_dir.path().join("unlisted");
+        write_files(
+            &unlisted,
+            &[("package.json", "{\"name\": \"x\"}"), ("other.wasm", "")],
+        );
+        assert!(!is_wasm_pack_pkg(&unlisted));
+        assert!(!is_wasm_pack_pkg(&temp_dir.path().join("missing")));
+    }
+
+    #[test]
+    fn test_is_trunk_dist() {
+        let temp_dir = TempDir::new().unwrap();
+        let dist = temp_dir.path().join("dist");
+        write_files(
+            &dist,
+            &[
+                (
+                    "index.html",
+                    "<script type=\"module\">import init from '/app-9d6e0b1f2a3c4d5e.js';</script>",
+                ),
+                ("app-9d6e0b1f2a3c4d5e_bg.wasm", "\0asm"),
+                ("app-9d6e0b1f2a3c4d5e.js", ""),
+                ("styles-0123456789abcdef.css", ""),
+            ],
+        );
+        assert!(is_trunk_dist(&dist));
+
+        // A hand-written site with scripts but no wasm
+        let site = temp_dir.path().join("site");
+        write_files(
+            &site,
+            &[
+                ("index.html", "<script src=\"main-1234abcd.js\"></script>"),
+                ("main-1234abcd.js", ""),
+            ],
+        );
+        assert!(!is_trunk_dist(&site));
+
+        // Unhashed wasm, as a hand-built demo would have
+        let demo = temp_dir.path().join("demo");
+        write_files(
+            &demo,
+            &[
+                ("index.html", "<script src=\"app.js\"></script>"),
+                ("app.wasm", ""),
+                ("app.js", ""),
+            ],
+        );
+        assert!(!is_trunk_dist(&demo));
+    }
+
+    #[test]
+    fn test_wasm_output_dirs() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target");
+        fs::create_dir_all(&target).unwrap();
+        assert!(wasm_output_dirs(&target).is_empty());
+
+        write_files(
+            &temp_dir.path().join("pkg"),
+            &[("package.json", PACKAGE_JSON), ("hello_wasm_bg.wasm", "")],
+        );
+        write_files(&temp_dir.path().join("dist"), &[("index.html", "")]);
+        assert_eq!(
+            wasm_output_dirs(&target),
+            [(temp_dir.path().join("pkg"), TargetKind::WasmPack)]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! The `CLEAN_BIG_TARGETS_*` environment variables, checked against the built binary so the
+//! real names are read by the real process rather than set in the one running the tests.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use tempfile::TempDir;
+
+/// Two projects, `small` with a 10 byte target and `big` with a 2000 byte one.
+fn fixture() -> TempDir {
+    let temp_dir = TempDir::new().unwrap();
+    for (project, size) in [("small", 10), ("big", 2000)] {
+        let target = temp_dir.path().join(project).join("target");
+        fs::create_dir_all(&target).unwrap();
+        fs::write(target.join("artifact"), vec![0u8; size]).unwrap();
+    }
+    temp_dir
+}
+
+/// Runs the binary on `root` with `args` and only the `envs` of its own variables set.
+fn run(root: &Path, args: &[&str], envs: &[(&str, &str)]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_clean-big-targets"));
+    for (name, _) in std::env::vars_os() {
+        if name.to_string_lossy().starts_with("CLEAN_BIG_TARGETS_") {
+            command.env_remove(name);
+        }
+    }
+    command.args(args).arg(root).envs(envs.iter().copied());
+    command.output().unwrap()
+}
+
+/// The target paths listed by `--porcelain`, in order.
+fn listed(output: &Output) -> Vec<PathBuf> {
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout.clone())
+        .unwrap()
+        .lines()
+        .map(|line| PathBuf::from(line.rsplit('\t').next().unwrap()))
+        .collect()
+}
+
+fn target(root: &Path, project: &str) -> PathBuf {
+    root.canonicalize().unwrap().join(project).join("target")
+}
+
+#[test]
+fn test_env_value_used_when_option_not_given() {
+    let root = fixture();
+    let root = root.path();
+    let output = run(
+        root,
+        &["--porcelain"],
+        &[("CLEAN_BIG_TARGETS_MIN_SIZE", "1kB")],
+    );
+    assert_eq!(listed(&output), [target(root, "big")]);
+
+    // The option wins over the variable
+    let output = run(
+        root,
+        &["--porcelain", "--min-size", "0", "--sort", "size-asc"],
+        &[("CLEAN_BIG_TARGETS_MIN_SIZE", "1kB")],
+    );
+    assert_eq!(
+        listed(&output),
+        [target(root, "small"), target(root, "big")]
+    );
+}
+
+#[test]
+fn test_env_value_parsed_like_option() {
+    let root = fixture();
+    let root = root.path();
+    for (sort, expected) in [
+        ("size-asc", ["small", "big"]),
+        ("size-desc", ["big", "small"]),
+    ] {
+        let output = run(root, &["--porcelain"], &[("CLEAN_BIG_TARGETS_SORT", sort)]);
+        let expected = expected.map(|project| target(root, project));
+        assert_eq!(listed(&output), expected, "{sort}");
+    }
+
+    let output = run(root, &[], &[("CLEAN_BIG_TARGETS_MAX_RESULTS", "lots")]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("lots"));
+}
+
+#[test]
+fn test_env_flags_accept_boolish_values() {
+    let root = fixture();
+    let root = root.path();
+    for (value, expected) in [("1", true), ("yes", true), ("false", false), ("off", false)] {
+        let output = run(root, &[], &[("CLEAN_BIG_TARGETS_STATS", value)]);
+        assert!(output.status.success(), "{output:?}");
+        let shown = String::from_utf8(output.stdout).unwrap().contains("Median");
+        assert_eq!(shown, expected, "{value}");
+    }
+
+    let output = run(root, &[], &[("CLEAN_BIG_TARGETS_REPORT_EMPTY", "maybe")]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_env_flags_turned_off_dont_conflict() {
+    let root = fixture();
+    let root = root.path();
+    // Each with a variable for an option it can't be used with, turned off
+    let cases: &[(&[&str], (&str, &str))] = &[
+        (&["--docs-only"], ("CLEAN_BIG_TARGETS_WASM", "0")),
+        (&["--title", "Builds"], ("CLEAN_BIG_TARGETS_NO_HEADER", "0")),
+        (
+            &["--older-than-file", "Cargo.toml"],
+            ("CLEAN_BIG_TARGETS_APPROXIMATE", "0"),
+        ),
+        (&["--approximate"], ("CLEAN_BIG_TARGETS_FAST_SCAN", "0")),
+        (&["--size-cache"], ("CLEAN_BIG_TARGETS_APPROXIMATE", "0")),
+        (
+            &["--hide-kept"],
+            ("CLEAN_BIG_TARGETS_IGNORE_KEEP_MARKERS", "0"),
+        ),
+    ];
+    for &(args, env) in cases {
+        let output = run(root, args, &[env]);
+        assert!(output.status.success(), "{args:?} {env:?}: {output:?}");
+        let (name, _) = env;
+        let output = run(root, args, &[(name, "1")]);
+        assert_eq!(output.status.code(), Some(2), "{args:?} {name}: {output:?}");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+    }
+}